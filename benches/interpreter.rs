@@ -0,0 +1,89 @@
+//! Benchmarks for each phase of running a Lox program, over programs that stress different parts
+//! of the interpreter. Run with `cargo bench`
+
+use codecrafters_interpreter::{interpreter::Interpreter, parser::Parser, resolver::Resolver, scanner::{token::Token, Scanner}, statement::Statement};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// How deep the nesting program nests its blocks and parentheses
+const NESTING_DEPTH: usize = 100;
+
+/// The programs benchmarked, by name
+fn programs() -> Vec<(&'static str, String)> {
+	vec![
+		("fib", include_str!("programs/fib.lox").to_string()),
+		("strings", include_str!("programs/strings.lox").to_string()),
+		("classes", include_str!("programs/classes.lox").to_string()),
+		("nesting", nesting()),
+	]
+}
+
+/// Blocks nested inside each other, each adding to a variable declared in the one around it with a
+/// deeply parenthesized expression
+fn nesting() -> String {
+	let expression = format!("{}x{}", "(".repeat(NESTING_DEPTH), " + 1)".repeat(NESTING_DEPTH));
+	let mut source = String::from("var x = 0;\n");
+
+	for _ in 0..NESTING_DEPTH {
+		source.push_str(&format!("{{ var y = {}; x = y;\n", expression));
+	}
+
+	source.push_str(&"}\n".repeat(NESTING_DEPTH));
+	source
+}
+
+fn scan(source: &str) -> Vec<Token> {
+	let (tokens, errors) = Scanner::new(source.to_string()).scan_tokens();
+	assert!(errors.is_empty(), "Benchmark programs scan");
+	tokens
+}
+
+fn parse(tokens: Vec<Token>) -> Vec<Statement> {
+	Parser::new(tokens).parse_statement().unwrap_or_else(|_| panic!("Benchmark programs parse"))
+}
+
+/// Resolve and run a parsed program in a fresh interpreter
+fn run(statements: &[Statement]) {
+	let mut resolver = Resolver::new(Interpreter::new());
+	assert!(resolver.resolve_statements(statements).is_ok(), "Benchmark programs resolve");
+
+	let mut interpreter = resolver.interpreter;
+
+	for statement in statements {
+		assert!(interpreter.interpret_statement(statement).is_ok(), "Benchmark programs run");
+	}
+}
+
+fn scanner(c: &mut Criterion) {
+	let mut group = c.benchmark_group("scan");
+
+	for (name, source) in programs() {
+		group.bench_function(name, |b| b.iter(|| scan(black_box(&source))));
+	}
+
+	group.finish();
+}
+
+fn parser(c: &mut Criterion) {
+	let mut group = c.benchmark_group("parse");
+
+	for (name, source) in programs() {
+		let tokens = scan(&source);
+		group.bench_function(name, |b| b.iter_batched(|| tokens.clone(), parse, criterion::BatchSize::SmallInput));
+	}
+
+	group.finish();
+}
+
+fn interpreter(c: &mut Criterion) {
+	let mut group = c.benchmark_group("interpret");
+
+	for (name, source) in programs() {
+		let statements = parse(scan(&source));
+		group.bench_function(name, |b| b.iter(|| run(black_box(&statements))));
+	}
+
+	group.finish();
+}
+
+criterion_group!(benches, scanner, parser, interpreter);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+use std::fmt;
+
+#[derive(Debug)]
+/// Errors arising from command-line parsing, reported with the usage of the offending command
+pub struct CliError {
+	pub message: String,
+	pub usage: String
+}
+
+/// Wrapper type for `Result<T, CliError>`
+pub type CliResult<T> = Result<T, CliError>;
+
+impl fmt::Display for CliError {
+	/// The error followed by the usage of the command it is about
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Error: {}\n\n{}", self.message, self.usage)
+	}
+}
@@ -0,0 +1,476 @@
+use error::{CliError, CliResult};
+
+use crate::{interpreter::trace::Trace, linter::lint::{Level, Rule}, log::Verbosity, optimizer};
+
+pub mod error;
+
+/// The command-line subcommands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+	Tokenize,
+	Parse,
+	Evaluate,
+	Run,
+	Repl,
+	Ast,
+	Fmt,
+	Lint,
+	Dot,
+	Coverage,
+	Bench,
+	Debug,
+	Compile,
+	Exec,
+	Build,
+}
+
+impl Command {
+	fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"tokenize" => Some(Self::Tokenize),
+			"parse" => Some(Self::Parse),
+			"evaluate" => Some(Self::Evaluate),
+			"run" => Some(Self::Run),
+			"repl" => Some(Self::Repl),
+			"ast" => Some(Self::Ast),
+			"fmt" => Some(Self::Fmt),
+			"lint" => Some(Self::Lint),
+			"dot" => Some(Self::Dot),
+			"coverage" => Some(Self::Coverage),
+			"bench" => Some(Self::Bench),
+			"debug" => Some(Self::Debug),
+			"compile" => Some(Self::Compile),
+			"exec" => Some(Self::Exec),
+			"build" => Some(Self::Build),
+			_ => None
+		}
+	}
+
+	fn name(&self) -> &'static str {
+		match self {
+			Self::Tokenize => "tokenize",
+			Self::Parse => "parse",
+			Self::Evaluate => "evaluate",
+			Self::Run => "run",
+			Self::Repl => "repl",
+			Self::Ast => "ast",
+			Self::Fmt => "fmt",
+			Self::Lint => "lint",
+			Self::Dot => "dot",
+			Self::Coverage => "coverage",
+			Self::Bench => "bench",
+			Self::Debug => "debug",
+			Self::Compile => "compile",
+			Self::Exec => "exec",
+			Self::Build => "build",
+		}
+	}
+
+	fn synopsis(&self) -> &'static str {
+		match self {
+			Self::Tokenize => "tokenize [options] <file>",
+			Self::Parse => "parse <file>",
+			Self::Evaluate => "evaluate <file>",
+			Self::Run => "run [options] <file>... [-- <script args>...]\n       run [options] -e <source> [-- <script args>...]",
+			Self::Repl => "repl",
+			Self::Ast => "ast [options] <file>",
+			Self::Fmt => "fmt [options] <file>",
+			Self::Lint => "lint [options] <file>",
+			Self::Dot => "dot [options] <file>",
+			Self::Coverage => "coverage [options] <file>... [-- <script args>...]",
+			Self::Bench => "bench [options] <file> [-- <script args>...]",
+			Self::Debug => "debug [options] <file> [-- <script args>...]",
+			Self::Compile => "compile [options] <file>",
+			Self::Exec => "exec [options] <file.loxc> [-- <script args>...]",
+			Self::Build => "build [options] <file>",
+		}
+	}
+
+	fn summary(&self) -> &'static str {
+		match self {
+			Self::Tokenize => "Print the tokens of a file",
+			Self::Parse => "Print the syntax tree of an expression",
+			Self::Evaluate => "Evaluate an expression and print its value",
+			Self::Run => "Run one or more files in a single interpreter",
+			Self::Repl => "Start an interactive session",
+			Self::Ast => "Print the syntax tree of a program as JSON",
+			Self::Fmt => "Print a file in canonical formatting",
+			Self::Lint => "Report suspicious code in a file",
+			Self::Dot => "Print the syntax tree of a program as a GraphViz graph",
+			Self::Coverage => "Run files and report which lines were executed",
+			Self::Bench => "Run a file repeatedly and report how long it takes",
+			Self::Debug => "Run a file under an interactive debugger",
+			Self::Compile => "Compile a file to bytecode",
+			Self::Exec => "Run a file compiled to bytecode",
+			Self::Build => "Compile a file into a standalone executable",
+		}
+	}
+
+	/// What the command can't do, printed under its summary
+	fn limitations(&self) -> Option<&'static str> {
+		match self {
+			Self::Compile | Self::Build => Some(BYTECODE_LIMITATIONS),
+			_ => None
+		}
+	}
+
+	/// Whether the command takes files as its positional arguments
+	fn takes_files(&self) -> bool {
+		*self != Self::Repl
+	}
+}
+
+/// The output format of commands that print data
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Format {
+	#[default]
+	Text,
+	Json,
+}
+
+/// How `run` and `bench` execute a program
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+	/// Walk the syntax tree
+	#[default]
+	Tree,
+	/// Compile to bytecode and run it on the VM
+	Vm,
+}
+
+/// The constructs the bytecode compiler rejects, so programs using them only run when tree-walked
+const BYTECODE_LIMITATIONS: &str = "Classes, 'this', setting properties, 'for in' loops, and importing and exporting modules\ncan't be compiled to bytecode yet. Programs using them are rejected before anything runs,\nand must be run with 'run' instead.";
+
+/// A command-line flag. Flags with a `value` take the next argument as their value
+struct Flag {
+	long: &'static str,
+	short: Option<&'static str>,
+	value: Option<&'static str>,
+	help: &'static str,
+	commands: &'static [Command]
+}
+
+const FLAGS: &[Flag] = &[
+	Flag { long: "--format", short: None, value: Some("text|json"), help: "Print tokens as text lines or as JSON records", commands: &[Command::Tokenize] },
+	Flag { long: "--eval", short: Some("-e"), value: Some("source"), help: "Run the given source instead of a file", commands: &[Command::Run] },
+	Flag { long: "--optional-semicolons", short: None, value: None, help: "Treat line breaks as statement terminators", commands: &[Command::Run, Command::Ast, Command::Fmt, Command::Lint, Command::Dot, Command::Coverage, Command::Bench, Command::Debug, Command::Compile, Command::Build] },
+	Flag { long: "--allow-exec", short: None, value: None, help: "Allow scripts to run shell commands with exec()", commands: &[Command::Run, Command::Coverage, Command::Bench, Command::Debug, Command::Exec, Command::Build] },
+	Flag { long: "--backend", short: None, value: Some("tree|vm"), help: "Walk the syntax tree, or compile to bytecode and run that, which doesn't support classes, 'for in' or modules", commands: &[Command::Run, Command::Bench] },
+	Flag { long: "--jit", short: None, value: None, help: "Compile numeric functions to native code, experimental", commands: &[Command::Run, Command::Bench] },
+	Flag { long: "--no-tier-up", short: None, value: None, help: "Keep walking the syntax tree of functions called often, instead of compiling them to bytecode", commands: &[Command::Run, Command::Bench] },
+	Flag { long: "--opt", short: Some("-O"), value: None, help: "Optimize the program before running it, the same as --opt-level=2", commands: &[Command::Run, Command::Coverage, Command::Bench, Command::Debug, Command::Compile, Command::Build] },
+	Flag { long: "--opt-level", short: None, value: Some("0-2"), help: "0 runs the program as written, 1 folds constants and removes dead code, 2 also simplifies", commands: &[Command::Run, Command::Coverage, Command::Bench, Command::Debug, Command::Compile, Command::Build] },
+	Flag { long: "--max-depth", short: None, value: Some("count"), help: "How deep calls may nest before a stack overflow error, 10000 by default", commands: &[Command::Run, Command::Coverage, Command::Bench, Command::Debug, Command::Exec] },
+	Flag { long: "--trace", short: None, value: None, help: "Print each statement as it runs, with the values produced", commands: &[Command::Run] },
+	Flag { long: "--trace-expressions", short: None, value: None, help: "Like --trace, also printing every expression evaluated", commands: &[Command::Run] },
+	Flag { long: "--time", short: None, value: None, help: "Report the time spent scanning, parsing, resolving and running", commands: &[Command::Run] },
+	Flag { long: "--stats", short: None, value: None, help: "Report the statements run, calls made, environments and values created, and how deep environments nested", commands: &[Command::Run] },
+	Flag { long: "--lcov", short: None, value: Some("path"), help: "Also write the coverage to a file in LCOV format", commands: &[Command::Coverage] },
+	Flag { long: "--iterations", short: Some("-n"), value: Some("count"), help: "Number of measured runs, 10 by default", commands: &[Command::Bench] },
+	Flag { long: "--warmup", short: None, value: Some("count"), help: "Number of unmeasured runs first, 1 by default", commands: &[Command::Bench] },
+	Flag { long: "--output", short: Some("-o"), value: Some("path"), help: "Where to write the output, the file with a .loxc extension, or none for 'build', by default", commands: &[Command::Compile, Command::Build] },
+	Flag { long: "--strict", short: None, value: None, help: "Treat lint warnings as errors, exiting with 65 before running", commands: &[Command::Run, Command::Compile, Command::Build] },
+	Flag { long: "--check", short: None, value: None, help: "Print nothing, exit with 1 if the file isn't formatted", commands: &[Command::Fmt] },
+	Flag { long: "--deny", short: None, value: Some("rule|all"), help: "Report a lint rule as an error", commands: &[Command::Lint] },
+	Flag { long: "--allow", short: None, value: Some("rule|all"), help: "Turn a lint rule off", commands: &[Command::Lint] },
+];
+
+/// Parsed command-line arguments
+#[derive(Debug, Default)]
+pub struct Cli {
+	/// The subcommand, `None` when only printing help or the version
+	pub command: Option<Command>,
+	pub files: Vec<String>,
+	/// Inline source given with `-e`
+	pub eval: Option<String>,
+	pub optional_semicolons: bool,
+	pub allow_exec: bool,
+	pub backend: Backend,
+	/// Compile what functions can be compiled to native code, needs the `jit` feature
+	pub jit: bool,
+	/// Never move hot functions from the tree-walker to the VM
+	pub no_tier_up: bool,
+	/// Which optimization passes run after parsing, 0 for none
+	pub opt_level: usize,
+	pub trace: Trace,
+	/// Report per-phase timings after `run`
+	pub time: bool,
+	/// Report execution statistics after `run`
+	pub stats: bool,
+	/// Where `coverage` writes an LCOV report
+	pub lcov: Option<String>,
+	/// Measured and warmup runs of `bench`, defaulted when not given
+	pub iterations: Option<usize>,
+	pub warmup: Option<usize>,
+	/// The call depth limit, defaulted when not given
+	pub max_depth: Option<usize>,
+	/// Where `compile` and `build` write their output
+	pub output: Option<String>,
+	/// Reject programs that have any lint findings
+	pub strict: bool,
+	/// Only check whether `fmt` would change the file
+	pub check: bool,
+	/// Lint rule levels in the order given, later ones win
+	pub lint_levels: Vec<(Rule, Level)>,
+	pub format: Format,
+	/// Arguments after `--`, passed on to the script
+	pub script_args: Vec<String>,
+	/// Set by `--verbose` and `--quiet`, which may come before or after the command
+	pub verbosity: Verbosity,
+	pub help: bool,
+	pub version: bool,
+}
+
+impl Cli {
+	/// Parse the arguments the program was started with, including the program name
+	pub fn parse(args: &[String]) -> CliResult<Self> {
+		let program = args.first().map(|p| p.as_str()).unwrap_or("lox");
+		let mut cli = Cli::default();
+		let mut rest = args.iter().skip(1);
+
+		// Global flags may come before the subcommand
+		let command = loop {
+			match rest.next().map(|a| a.as_str()) {
+				Some("-h" | "--help") => cli.help = true,
+				Some("-V" | "--version") => cli.version = true,
+				Some("-v" | "--verbose") => cli.verbosity = Verbosity::Verbose,
+				Some("-q" | "--quiet") => cli.verbosity = Verbosity::Quiet,
+				Some("help") => {
+					cli.help = true;
+					cli.command = rest.next().and_then(|name| Command::from_name(name));
+					return Ok(cli)
+				},
+				Some(name) if name.starts_with('-') => {
+					return Err(CliError { message: format!("Unknown option '{}'.", name), usage: usage(program) })
+				},
+				Some(name) => match Command::from_name(name) {
+					Some(command) => break command,
+					None => return Err(CliError { message: format!("Unknown command '{}'.", name), usage: usage(program) })
+				},
+				// Started without a command, open the repl unless only asked for help or the version
+				None => {
+					if !cli.help && !cli.version {
+						cli.command = Some(Command::Repl);
+					}
+					return Ok(cli)
+				}
+			}
+		};
+
+		cli.command = Some(command);
+		let error = |message: String| CliError { message, usage: command_usage(program, command) };
+
+		while let Some(arg) = rest.next() {
+			if arg == "--" {
+				cli.script_args = rest.cloned().collect();
+				break;
+			}
+
+			if arg == "-h" || arg == "--help" {
+				cli.help = true;
+				continue;
+			}
+
+			if arg == "-v" || arg == "--verbose" {
+				cli.verbosity = Verbosity::Verbose;
+				continue;
+			}
+
+			if arg == "-q" || arg == "--quiet" {
+				cli.verbosity = Verbosity::Quiet;
+				continue;
+			}
+
+			if !arg.starts_with('-') || arg == "-" {
+				if !command.takes_files() {
+					return Err(error(format!("Unexpected argument '{}'.", arg)))
+				}
+
+				cli.files.push(arg.clone());
+				continue;
+			}
+
+			// Long flags may be given as `--flag=value`
+			let (name, inline_value) = match arg.split_once('=') {
+				Some((name, value)) if arg.starts_with("--") => (name, Some(value.to_string())),
+				_ => (arg.as_str(), None)
+			};
+
+			let flag = FLAGS.iter()
+				.find(|f| f.long == name || f.short == Some(name))
+				.filter(|f| f.commands.contains(&command))
+				.ok_or_else(|| error(format!("Unknown option '{}' for '{}'.", name, command.name())))?;
+
+			let value = match (flag.value, inline_value) {
+				(Some(_), Some(v)) => Some(v),
+				(Some(v), None) => Some(rest.next().cloned().ok_or_else(|| error(format!("Option '{}' expects a <{}>.", name, v)))?),
+				(None, Some(_)) => return Err(error(format!("Option '{}' doesn't take a value.", name))),
+				(None, None) => None
+			};
+
+			cli.set(flag.long, value).map_err(error)?;
+		}
+
+		if cli.help || cli.version {
+			return Ok(cli)
+		}
+
+		match command {
+			Command::Run if cli.eval.is_some() && !cli.files.is_empty() => {
+				Err(error("Can't run files together with '-e'.".to_string()))
+			},
+			Command::Run if cli.eval.is_none() && cli.files.is_empty() => Err(error("Expect at least one file to run.".to_string())),
+			Command::Run if cli.backend == Backend::Vm && cli.trace != Trace::Off => {
+				Err(error("Tracing needs '--backend=tree'.".to_string()))
+			},
+			Command::Run | Command::Bench if cli.jit && !cfg!(feature = "jit") => {
+				Err(error("This build has no JIT, rebuild with '--features jit'.".to_string()))
+			},
+			Command::Run | Command::Bench if cli.jit && cli.backend == Backend::Vm => {
+				Err(error("'--jit' needs '--backend=tree'.".to_string()))
+			},
+			Command::Run if cli.jit && cli.trace != Trace::Off => {
+				Err(error("Tracing can't follow functions compiled by '--jit'.".to_string()))
+			},
+			Command::Coverage if cli.files.is_empty() => Err(error("Expect at least one file to run.".to_string())),
+			Command::Tokenize | Command::Parse | Command::Evaluate | Command::Ast | Command::Fmt | Command::Lint | Command::Dot | Command::Bench | Command::Debug | Command::Compile | Command::Exec | Command::Build if cli.files.len() != 1 => {
+				Err(error(format!("'{}' expects exactly one file.", command.name())))
+			},
+			_ => Ok(cli)
+		}
+	}
+
+	/// Apply a flag, identified by its long name, reporting invalid values
+	fn set(&mut self, flag: &str, value: Option<String>) -> Result<(), String> {
+		match flag {
+			"--eval" => self.eval = value,
+			"--format" => {
+				self.format = match value.as_deref() {
+					Some("text") => Format::Text,
+					Some("json") => Format::Json,
+					v => return Err(format!("Unknown format '{}', expected 'text' or 'json'.", v.unwrap_or_default()))
+				}
+			},
+			"--backend" => {
+				self.backend = match value.as_deref() {
+					Some("tree") => Backend::Tree,
+					Some("vm") => Backend::Vm,
+					v => return Err(format!("Unknown backend '{}', expected 'tree' or 'vm'.", v.unwrap_or_default()))
+				}
+			},
+			"--optional-semicolons" => self.optional_semicolons = true,
+			"--allow-exec" => self.allow_exec = true,
+			"--check" => self.check = true,
+			"--strict" => self.strict = true,
+			"--lcov" => self.lcov = value,
+			"--output" => self.output = value,
+			"--iterations" | "--warmup" => {
+				let value = value.unwrap_or_default();
+				let count = value.parse::<usize>().map_err(|_| format!("Expect a count for '{}', got '{}'.", flag, value))?;
+
+				if flag == "--iterations" {
+					if count == 0 {
+						return Err("Expect at least one iteration.".to_string())
+					}
+
+					self.iterations = Some(count);
+				} else {
+					self.warmup = Some(count);
+				}
+			},
+			"--max-depth" => {
+				let value = value.unwrap_or_default();
+
+				self.max_depth = Some(value.parse::<usize>().ok()
+					.filter(|depth| *depth > 0)
+					.ok_or_else(|| format!("Expect a positive depth for '{}', got '{}'.", flag, value))?);
+			},
+			"--trace" => self.trace = self.trace.max(Trace::Statements),
+			"--trace-expressions" => self.trace = Trace::Expressions,
+			"--time" => self.time = true,
+			"--stats" => self.stats = true,
+			"--jit" => self.jit = true,
+			"--no-tier-up" => self.no_tier_up = true,
+			"--opt" => self.opt_level = optimizer::MAX_LEVEL,
+			"--opt-level" => {
+				let value = value.unwrap_or_default();
+
+				self.opt_level = value.parse::<usize>().ok()
+					.filter(|level| *level <= optimizer::MAX_LEVEL)
+					.ok_or_else(|| format!("Expect an optimization level from 0 to {}, got '{}'.", optimizer::MAX_LEVEL, value))?;
+			},
+			"--deny" | "--allow" => {
+				let level = if flag == "--deny" { Level::Deny } else { Level::Allow };
+				let name = value.unwrap_or_default();
+
+				let rules = match Rule::from_name(&name) {
+					Some(rule) => vec![rule],
+					None if name == "all" => Rule::ALL.to_vec(),
+					None => {
+						let names: Vec<&str> = Rule::ALL.iter().map(|r| r.name()).collect();
+						return Err(format!("Unknown lint rule '{}', expected one of: {}.", name, names.join(", ")))
+					}
+				};
+
+				self.lint_levels.extend(rules.into_iter().map(|rule| (rule, level)));
+			},
+			_ => unreachable!("Flag '{}' is declared but never applied", flag)
+		}
+
+		Ok(())
+	}
+}
+
+/// The general usage, listing every command
+pub fn usage(program: &str) -> String {
+	let commands = [Command::Tokenize, Command::Parse, Command::Evaluate, Command::Run, Command::Repl, Command::Ast, Command::Fmt, Command::Lint, Command::Dot, Command::Coverage, Command::Bench, Command::Debug, Command::Compile, Command::Exec, Command::Build];
+
+	let mut out = format!("Usage: {} [--help | --version] [--verbose | --quiet] [<command> [<args>]]\n\nCommands:\n", program);
+
+	for command in commands {
+		out.push_str(&format!("  {:<10} {}\n", command.name(), command.summary()));
+	}
+
+	out.push_str(&format!("\nRun '{} help <command>' for the options of a command. With no command, starts the repl.", program));
+	out
+}
+
+/// The usage of a single command, listing its options
+pub fn command_usage(program: &str, command: Command) -> String {
+	let synopsis = command.synopsis().replace("\n       ", &format!("\n       {} ", program));
+	let mut out = format!("Usage: {} {}\n\n{}.\n", program, synopsis, command.summary());
+
+	if let Some(limitations) = command.limitations() {
+		out.push_str(&format!("\n{}\n", limitations));
+	}
+
+	out.push_str("\nOptions:\n");
+
+	for flag in FLAGS.iter().filter(|f| f.commands.contains(&command)) {
+		let mut name = match flag.short {
+			Some(short) => format!("{}, {}", short, flag.long),
+			None => format!("    {}", flag.long)
+		};
+
+		if let Some(value) = flag.value {
+			name.push_str(&format!(" <{}>", value));
+		}
+
+		out.push_str(&format!("  {:<28} {}\n", name, flag.help));
+	}
+
+	if command == Command::Lint {
+		let names: Vec<&str> = Rule::ALL.iter().map(|r| r.name()).collect();
+		out.push_str(&format!("  {:<28} Rules: {}\n", "", names.join(", ")));
+	}
+
+	out.push_str(&format!("  {:<28} {}\n", "-v, --verbose", "Report what the interpreter is doing"));
+	out.push_str(&format!("  {:<28} {}\n", "-q, --quiet", "Print only the program's output and errors"));
+	out.push_str(&format!("  {:<28} {}", "-h, --help", "Print this help"));
+	out
+}
+
+/// The version line printed by `--version`
+pub fn version() -> String {
+	format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
@@ -1,21 +1,182 @@
-/// Errors from the lox programming languagr
-pub struct LoxError {
-	pub line: usize,
-	pub message: String
+use std::{error, fmt};
+
+use crate::{interpreter::error::StackTrace, scanner::token::{Location, Token, TokenType}};
+
+/// How serious a diagnostic is. Errors stop the program from running, warnings don't
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+	Error,
+	Warning,
+}
+
+impl fmt::Display for Severity {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Error => write!(f, "Error"),
+			Self::Warning => write!(f, "Warning"),
+		}
+	}
+}
+
+/// An error, or a lint finding, from any stage of running a Lox program
+#[derive(Debug)]
+pub enum LoxError {
+	/// Text that doesn't scan, like an unexpected character or an unterminated string. `line` is
+	/// where scanning was when the error was found, which for an unterminated string or comment is
+	/// lines after where it starts
+	Lexical {line: usize, message: String, location: Location},
+	/// Tokens that don't parse
+	Syntax {token: Token, message: String},
+	/// A program that parses, but misuses a name or keyword where the resolver can tell
+	Resolution {token: Token, message: String},
+	/// A program the bytecode compiler can't compile
+	Compile {token: Token, message: String},
+	/// An error raised running the program, with the stack trace of the calls it was raised in once
+	/// it leaves the innermost of them
+	Runtime {location: Location, message: String, trace: StackTrace},
+	/// What a lint rule found, reported at the level the rule is set to
+	Lint {token: Token, message: String, rule: &'static str, severity: Severity},
 }
 
 /// A wrapper type for a generic result and a Lox error.
 pub type LoxResult<T> = Result<T, LoxError>;
 
 impl LoxError {
-	/// Create a new LoxError instance
-	pub fn new(line: usize, message: String) -> Self {
-		Self { line, message }
+	pub fn syntax(token: Token, message: &str) -> Self {
+		Self::Syntax {token, message: message.to_string()}
 	}
-	
-	/// Display error to stderr
-	pub fn report(&self, where_: &str) {
-		eprintln!("[line {}] Error{}: {}", self.line, where_, self.message);
+
+	pub fn resolution(token: Token, message: &str) -> Self {
+		Self::Resolution {token, message: message.to_string()}
+	}
+
+	pub fn compile(token: Token, message: &str) -> Self {
+		Self::Compile {token, message: message.to_string()}
+	}
+
+	pub fn runtime(location: Location, message: &str) -> Self {
+		Self::Runtime {location, message: message.to_string(), trace: StackTrace::default()}
+	}
+
+	/// The line reported with the error
+	pub fn line(&self) -> usize {
+		match self {
+			Self::Lexical { line, .. } => *line,
+			Self::Runtime { location, .. } => location.line,
+			Self::Syntax { token, .. } | Self::Resolution { token, .. } | Self::Compile { token, .. } | Self::Lint { token, .. } => token.line,
+		}
+	}
+
+	pub fn message(&self) -> &str {
+		match self {
+			Self::Lexical { message, .. } | Self::Syntax { message, .. } | Self::Resolution { message, .. }
+			| Self::Compile { message, .. } | Self::Runtime { message, .. } | Self::Lint { message, .. } => message,
+		}
+	}
+
+	pub fn severity(&self) -> Severity {
+		match self {
+			Self::Lint { severity, .. } => *severity,
+			_ => Severity::Error,
+		}
+	}
+
+	/// A short code for the kind of error, which stays the same when messages are reworded. Lint
+	/// findings use the name of their rule
+	pub fn code(&self) -> &'static str {
+		match self {
+			Self::Lexical { .. } => "E001",
+			Self::Syntax { .. } => "E002",
+			Self::Resolution { .. } => "E003",
+			Self::Compile { .. } => "E004",
+			Self::Runtime { .. } => "E005",
+			Self::Lint { rule, .. } => rule,
+		}
+	}
+}
+
+impl fmt::Display for LoxError {
+	/// The error as reported, with the line and, for errors about a token, the token it was found at
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Lexical { line, message, .. } => write!(f, "[line {}] Error: {}", line, message),
+			Self::Syntax { token, message } | Self::Resolution { token, message } | Self::Compile { token, message } => {
+				if token.token_type == TokenType::EOF {
+					write!(f, "[line {}] Error at end: {}", token.line, message)
+				} else {
+					write!(f, "[line {}] Error at '{}': {}", token.line, token.lexeme, message)
+				}
+			},
+			Self::Runtime { location, message, trace } => write!(f, "[line {}] Error: {}{}", location.line, message, trace),
+			Self::Lint { token, message, rule, severity } => write!(f, "[line {}] {}: {} [{}]", token.line, severity, message, rule),
+		}
+	}
+}
+
+impl error::Error for LoxError {}
+
+impl Diagnostic for LoxError {
+	fn location(&self) -> Option<Location> {
+		match self {
+			Self::Lexical { location, .. } | Self::Runtime { location, .. } => Some(*location),
+			Self::Syntax { token, .. } | Self::Resolution { token, .. } | Self::Compile { token, .. } | Self::Lint { token, .. } => Some(token.location()),
+		}
+	}
+}
+
+/// An error as reported to the user, which may point at the part of the source it is about
+pub trait Diagnostic: fmt::Display {
+	/// Where in the source the error is, if anywhere
+	fn location(&self) -> Option<Location>;
+
+	/// The error followed by a [`snippet`] of the source it points at, or just the error when it
+	/// points nowhere in `source`
+	fn render(&self, filename: &str, source: &str) -> String {
+		match self.location().and_then(|location| snippet(filename, source, location)) {
+			Some(snippet) => format!("{}\n{}", self, snippet),
+			None => self.to_string()
+		}
 	}
 }
 
+/// The line of `source` a location is on, with the span underlined, in the style of rustc:
+///
+/// ```text
+///   --> main.lox:3:9
+///    |
+///  3 | print a + nil;
+///    |         ^
+/// ```
+///
+/// Only the line is shown when the column isn't known. `None` if the location doesn't fit the
+/// source, as when it comes from another file
+pub fn snippet(filename: &str, source: &str, location: Location) -> Option<String> {
+	let (text, underline) = if location.column == 0 {
+		(source.lines().nth(location.line.checked_sub(1)?)?, String::new())
+	} else {
+		let start = location.span.start;
+		let line_start = source.get(..start)?.rfind('\n').map_or(0, |i| i + 1);
+		let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+		let before = &source[line_start..start];
+
+		if before.chars().count() + 1 != location.column {
+			return None
+		}
+
+		// Spans running over several lines, like unterminated strings, are underlined to the end of
+		// the first. Tabs are kept so the carets line up however wide they are shown
+		let width = source.get(start..location.span.end().clamp(start, line_end))?.chars().count().max(1);
+		let indent: String = before.chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+
+		(&source[line_start..line_end], format!(" {}{}", indent, "^".repeat(width)))
+	};
+
+	let number = location.line.to_string();
+	let gutter = " ".repeat(number.len());
+	let position = match location.column {
+		0 => format!("{}:{}", filename, location.line),
+		column => format!("{}:{}:{}", filename, location.line, column)
+	};
+
+	Some(format!("{gutter}--> {position}\n{gutter} |\n{number} | {text}\n{gutter} |{underline}", text = text.trim_end_matches('\r')))
+}
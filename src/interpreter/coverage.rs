@@ -0,0 +1,243 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use crate::{parser::expr::Expr, statement::Statement};
+
+/// The lines executed in each file while running under `coverage`. A line counts as executable when
+/// a statement starts on it
+#[derive(Default)]
+pub struct Coverage {
+	/// Hit counts by line, by file
+	files: BTreeMap<PathBuf, BTreeMap<usize, usize>>
+}
+
+impl Coverage {
+	/// Record the executable lines of a program, so lines that never run are reported
+	pub fn register(&mut self, file: &Path, statements: &[Statement]) {
+		let lines = self.files.entry(file.to_path_buf()).or_default();
+		register_statements(lines, statements);
+	}
+
+	/// Count an execution of the statement starting on a line
+	pub fn hit(&mut self, file: &Path, line: usize) {
+		let lines = self.files.entry(file.to_path_buf()).or_default();
+		*lines.entry(line).or_default() += 1;
+	}
+
+	/// A summary of each file, with the ranges of lines that never ran
+	pub fn report(&self) -> String {
+		let mut out = String::from("Coverage:\n");
+		let (mut total, mut total_hit) = (0, 0);
+
+		for (file, lines) in &self.files {
+			let hit = lines.values().filter(|hits| **hits > 0).count();
+			let missed: Vec<usize> = lines.iter().filter(|(_, hits)| **hits == 0).map(|(line, _)| *line).collect();
+
+			out.push_str(&format!("  {}: {}/{} lines ({})", file.display(), hit, lines.len(), percent(hit, lines.len())));
+
+			if !missed.is_empty() {
+				out.push_str(&format!(", missed {}", ranges(&missed)));
+			}
+
+			out.push('\n');
+			total += lines.len();
+			total_hit += hit;
+		}
+
+		out.push_str(&format!("  total: {}/{} lines ({})", total_hit, total, percent(total_hit, total)));
+		out
+	}
+
+	/// The coverage in LCOV tracefile format
+	pub fn lcov(&self) -> String {
+		let mut out = String::from("TN:\n");
+
+		for (file, lines) in &self.files {
+			out.push_str(&format!("SF:{}\n", file.display()));
+
+			for (line, hits) in lines {
+				out.push_str(&format!("DA:{},{}\n", line, hits));
+			}
+
+			let hit = lines.values().filter(|hits| **hits > 0).count();
+			out.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", lines.len(), hit));
+		}
+
+		out
+	}
+}
+
+fn percent(hit: usize, total: usize) -> String {
+	if total == 0 {
+		return "100.0%".to_string()
+	}
+
+	format!("{:.1}%", hit as f64 * 100.0 / total as f64)
+}
+
+/// Format sorted line numbers as comma separated ranges, e.g. `3, 7-9`
+fn ranges(lines: &[usize]) -> String {
+	let mut out: Vec<String> = Vec::new();
+	let mut i = 0;
+
+	while i < lines.len() {
+		let start = lines[i];
+
+		while i + 1 < lines.len() && lines[i + 1] == lines[i] + 1 {
+			i += 1;
+		}
+
+		if lines[i] == start {
+			out.push(start.to_string());
+		} else {
+			out.push(format!("{}-{}", start, lines[i]));
+		}
+
+		i += 1;
+	}
+
+	out.join(", ")
+}
+
+fn register_statements(lines: &mut BTreeMap<usize, usize>, statements: &[Statement]) {
+	for statement in statements {
+		register_statement(lines, statement);
+	}
+}
+
+fn register_statement(lines: &mut BTreeMap<usize, usize>, statement: &Statement) {
+	if let Some(line) = statement.line() {
+		lines.entry(line).or_default();
+	}
+
+	match statement {
+		Statement::Print(p) => register_expr(lines, &p.0),
+		Statement::Expression(e) => register_expr(lines, &e.0),
+		Statement::Function(f) => register_statements(lines, &f.body),
+		Statement::Class(c) => {
+			for method in &c.methods {
+				register_statements(lines, &method.body);
+			}
+		},
+		Statement::Return(r) => {
+			if let Some(value) = &r.value {
+				register_expr(lines, value);
+			}
+		},
+		Statement::If(i) => {
+			register_expr(lines, &i.condition);
+			register_statement(lines, &i.then_branch);
+
+			if let Some(else_branch) = &i.else_branch {
+				register_statement(lines, else_branch);
+			}
+		},
+		Statement::While(w) => {
+			register_expr(lines, &w.condition);
+			register_statement(lines, &w.body);
+		},
+		Statement::For(f) => {
+			if let Some(initializer) = &f.initializer {
+				register_statement(lines, initializer);
+			}
+
+			if let Some(condition) = &f.condition {
+				register_expr(lines, condition);
+			}
+
+			if let Some(increment) = &f.increment {
+				register_expr(lines, increment);
+			}
+
+			register_statement(lines, &f.body);
+		},
+		Statement::ForIn(f) => {
+			register_expr(lines, &f.iterable);
+			register_statement(lines, &f.body);
+		},
+		Statement::Switch(s) => {
+			register_expr(lines, &s.subject);
+
+			for case in &s.cases {
+				register_statements(lines, &case.body.statements);
+			}
+
+			if let Some(default) = &s.default {
+				register_statements(lines, &default.statements);
+			}
+		},
+		Statement::Var(v) => {
+			if let Some(initializer) = &v.initializer {
+				register_expr(lines, initializer);
+			}
+		},
+		Statement::MultiVar(declarations) => {
+			for v in declarations {
+				if let Some(initializer) = &v.initializer {
+					register_expr(lines, initializer);
+				}
+			}
+		},
+		Statement::Block(b) => register_statements(lines, &b.statements),
+		Statement::Export(e) => register_statement(lines, &e.declaration),
+		Statement::Break() | Statement::Continue() | Statement::Import(_) => {},
+	}
+}
+
+/// Register the statements nested in block and if expressions
+fn register_expr(lines: &mut BTreeMap<usize, usize>, expr: &Expr) {
+	match expr {
+		Expr::Block(b) => {
+			register_statements(lines, &b.statements);
+
+			if let Some(value) = &b.value {
+				register_expr(lines, value);
+			}
+		},
+		Expr::If(i) => {
+			register_expr(lines, &i.condition);
+			register_expr(lines, &i.then_branch);
+
+			if let Some(else_branch) = &i.else_branch {
+				register_expr(lines, else_branch);
+			}
+		},
+		Expr::Call(c) => {
+			register_expr(lines, &c.callee);
+
+			for argument in &c.arguments {
+				register_expr(lines, argument);
+			}
+		},
+		Expr::Binary(b) => {
+			register_expr(lines, &b.left);
+			register_expr(lines, &b.right);
+		},
+		Expr::Logical(l) => {
+			register_expr(lines, &l.left);
+			register_expr(lines, &l.right);
+		},
+		Expr::Unary(u) => register_expr(lines, &u.right),
+		Expr::Grouping(g) => register_expr(lines, &g.0),
+		Expr::Assignment(a) => register_expr(lines, &a.value),
+		Expr::Get(g) => register_expr(lines, &g.object),
+		Expr::Set(s) => {
+			register_expr(lines, &s.object);
+			register_expr(lines, &s.value);
+		},
+		Expr::Array(a) => {
+			for element in &a.elements {
+				register_expr(lines, element);
+			}
+		},
+		Expr::Index(i) => {
+			register_expr(lines, &i.object);
+			register_expr(lines, &i.index);
+		},
+		Expr::IndexSet(i) => {
+			register_expr(lines, &i.object);
+			register_expr(lines, &i.index);
+			register_expr(lines, &i.value);
+		},
+		Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) => {},
+	}
+}
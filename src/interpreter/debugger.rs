@@ -0,0 +1,247 @@
+use std::{collections::BTreeSet, io::{self, BufRead, Write}, path::PathBuf, rc::Rc};
+
+use crate::{scanner::token::Symbol, statement::{environment::EnvCell, Statement}};
+
+use super::{error::{ValueError, ValueResult}, values::{Value, ValueCell}, Interpreter};
+
+/// When the debugger next pauses, besides at breakpoints
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+	/// Only at breakpoints
+	Continue,
+	/// At the next statement
+	Step,
+	/// At the next statement at most this many calls deep, stepping over calls
+	Next(usize),
+	/// At the next statement fewer than this many calls deep, once the current call returns
+	Finish(usize),
+}
+
+/// The state of `lox debug`, consulted before every statement runs
+pub struct Debugger {
+	/// The file being debugged, breakpoints and listings refer to its lines
+	file: PathBuf,
+	source: Vec<String>,
+	breakpoints: BTreeSet<usize>,
+	mode: Mode,
+	/// The last command entered, repeated by an empty line
+	last_command: String,
+}
+
+impl Debugger {
+	/// A debugger that pauses before the first statement of a file
+	pub fn new(file: PathBuf, source: &str) -> Self {
+		Self {
+			file,
+			source: source.lines().map(|l| l.to_string()).collect(),
+			breakpoints: BTreeSet::new(),
+			mode: Mode::Step,
+			last_command: String::new()
+		}
+	}
+
+	fn should_pause(&self, depth: usize, line: usize, in_file: bool) -> bool {
+		let at_breakpoint = in_file && self.breakpoints.contains(&line);
+
+		at_breakpoint || match self.mode {
+			Mode::Continue => false,
+			Mode::Step => true,
+			Mode::Next(d) => depth <= d,
+			Mode::Finish(d) => depth < d,
+		}
+	}
+
+	/// Print the source around a line of the debugged file, marking the line itself
+	fn list(&self, line: usize) {
+		let first = line.saturating_sub(3).max(1);
+		let last = (line + 3).min(self.source.len());
+
+		for n in first..=last {
+			let marker = if n == line { "->" } else if self.breakpoints.contains(&n) { " *" } else { "  " };
+			eprintln!("{} {:>4} {}", marker, n, self.source[n - 1]);
+		}
+	}
+}
+
+const HELP: &str = "Commands:
+  s, step             Run to the next statement, entering calls
+  n, next             Run to the next statement, stepping over calls
+  f, finish           Run until the current call returns
+  c, continue         Run until the next breakpoint
+  b, break <line>     Pause before the statement on a line
+  d, delete <line>    Remove a breakpoint
+  l, list             Show the source around the current line
+  bt, backtrace       Show the active calls
+  p, print <name>     Print a variable visible from the current scope
+  v, vars             Print the variables of the current scopes
+  q, quit             Stop the program
+An empty line repeats the last command.";
+
+impl Interpreter {
+	/// Pause before a statement if a breakpoint or the step mode says so, then read debugger
+	/// commands until told to resume. Blocks are containers, so only their contents pause. Quitting
+	/// raises [`ValueError::Quit`] to stop the program
+	pub fn debug_statement(&mut self, statement: &Statement) -> ValueResult<()> {
+		let Some(mut debugger) = self.debugger.take() else { return Ok(()) };
+		let mut result = Ok(());
+
+		let line = match (statement, statement.line()) {
+			(Statement::Block(_), _) | (_, None) => None,
+			(_, Some(line)) => Some(line),
+		};
+
+		if let Some(line) = line {
+			let in_file = self.current_file.as_ref() == Some(&debugger.file);
+
+			if debugger.should_pause(self.frames.len(), line, in_file) {
+				result = self.debug_prompt(&mut debugger, line, in_file);
+			}
+		}
+
+		self.debugger = Some(debugger);
+		result
+	}
+
+	fn debug_prompt(&mut self, debugger: &mut Debugger, line: usize, in_file: bool) -> ValueResult<()> {
+		let file = self.current_file.as_ref().map(|f| f.display().to_string()).unwrap_or_default();
+		eprintln!("Paused at {}:{}", file, line);
+
+		if in_file {
+			if let Some(text) = debugger.source.get(line - 1) {
+				eprintln!("{:>4} {}", line, text.trim());
+			}
+		}
+
+		let stdin = io::stdin();
+
+		loop {
+			eprint!("(debug) ");
+			io::stderr().flush().unwrap();
+
+			let mut input = String::new();
+
+			// Without input there is no one to debug for, so run the rest of the program undisturbed
+			if matches!(stdin.lock().read_line(&mut input), Ok(0) | Err(_)) {
+				eprintln!();
+				debugger.breakpoints.clear();
+				debugger.mode = Mode::Continue;
+				return Ok(())
+			}
+
+			let mut input = input.trim().to_string();
+
+			if input.is_empty() {
+				input = debugger.last_command.clone();
+			} else {
+				debugger.last_command = input.clone();
+			}
+
+			let (command, argument) = match input.split_once(char::is_whitespace) {
+				Some((command, argument)) => (command, argument.trim()),
+				None => (input.as_str(), "")
+			};
+
+			match command {
+				"" => {},
+				"s" | "step" => { debugger.mode = Mode::Step; return Ok(()) },
+				"n" | "next" => { debugger.mode = Mode::Next(self.frames.len()); return Ok(()) },
+				"f" | "finish" => {
+					if self.frames.is_empty() {
+						eprintln!("Not inside a call.");
+					} else {
+						debugger.mode = Mode::Finish(self.frames.len());
+						return Ok(())
+					}
+				},
+				"c" | "continue" => { debugger.mode = Mode::Continue; return Ok(()) },
+				"b" | "break" => match argument.parse::<usize>() {
+					Ok(n) if n >= 1 && n <= debugger.source.len() => {
+						debugger.breakpoints.insert(n);
+						eprintln!("Breakpoint at line {}.", n);
+					},
+					_ => eprintln!("Expect a line number of the file, got '{}'.", argument),
+				},
+				"d" | "delete" => match argument.parse::<usize>() {
+					Ok(n) if debugger.breakpoints.remove(&n) => eprintln!("Removed the breakpoint at line {}.", n),
+					_ => eprintln!("No breakpoint at line '{}'.", argument),
+				},
+				"l" | "list" => {
+					if in_file {
+						debugger.list(line);
+					} else {
+						eprintln!("Can only list {}.", debugger.file.display());
+					}
+				},
+				"bt" | "backtrace" => self.debug_backtrace(line),
+				"p" | "print" => match self.debug_lookup(argument) {
+					Some(value) => eprintln!("{} = {}", argument, debug_repr(&value)),
+					None => eprintln!("Undefined variable '{}'.", argument),
+				},
+				"v" | "vars" => self.debug_vars(),
+				"q" | "quit" => return Err(ValueError::Quit),
+				"h" | "help" => eprintln!("{}", HELP),
+				_ => eprintln!("Unknown command '{}', 'help' lists the commands.", command),
+			}
+		}
+	}
+
+	/// List the active calls, innermost first, with the line each is at
+	fn debug_backtrace(&self, line: usize) {
+		let mut line = line;
+
+		for (i, frame) in self.frames.iter().rev().enumerate() {
+			eprintln!("#{} {} at line {}", i, frame.name, line);
+			line = frame.line;
+		}
+
+		eprintln!("#{} <script> at line {}", self.frames.len(), line);
+	}
+
+	/// Find a variable in the current environment chain
+	fn debug_lookup(&self, name: &str) -> Option<Value> {
+		let mut environment = Some(self.environment.clone());
+
+		while let Some(EnvCell(e)) = environment {
+			if let Some(value) = e.borrow().lookup(Symbol::intern(name)) {
+				return Some(value.value())
+			}
+
+			environment = e.borrow().enclosing.clone();
+		}
+
+		None
+	}
+
+	/// Print the variables of each scope from the innermost out. Natives are left out of the globals
+	fn debug_vars(&self) {
+		let mut environment = Some(self.environment.clone());
+		let mut depth = 0;
+
+		while let Some(EnvCell(e)) = environment {
+			let is_globals = Rc::ptr_eq(&e, &self.globals.0);
+			let scope = e.borrow();
+
+			let mut entries: Vec<(Symbol, &ValueCell)> = scope.entries()
+				.filter(|(_, value)| !matches!(value.value(), Value::NativeFn(_)))
+				.collect();
+			entries.sort_by_key(|(name, _)| *name);
+
+			eprintln!("{}:", if is_globals { "globals".to_string() } else { format!("scope {}", depth) });
+
+			for (name, value) in entries {
+				eprintln!("  {} = {}", name, debug_repr(&value.value()));
+			}
+
+			environment = scope.enclosing.clone();
+			depth += 1;
+		}
+	}
+}
+
+/// A value as the debugger shows it, with strings quoted so they stand out from other values
+fn debug_repr(value: &Value) -> String {
+	match value {
+		Value::String(s) => format!("\"{}\"", s),
+		v => v.to_string()
+	}
+}
@@ -1,37 +1,97 @@
-use crate::{scanner::token::Token, statement::environment::error::EnvironmentError};
+use std::fmt;
+
+use crate::{error::{Diagnostic, LoxError}, scanner::token::{Location, Token}};
 
 use super::values::Value;
 
+/// An error raised running a program. Control flow leaving a loop or call travels separately, as a
+/// [`super::flow::ControlFlow`]
 pub enum ValueError {
-	Break,
-	Continue,
-	Return(Value),
-	Std {token: Token, message: String},
+	/// A runtime error, always a [`LoxError::Runtime`]. Boxed, as results carry errors everywhere
+	Error(Box<LoxError>),
+	/// An error raised by a native function, which has no token of its own. The interpreter
+	/// converts it into a [`ValueError::Error`] at the call site
+	Native {message: String},
+	/// The debugger was told to quit. It unwinds the program like an error, leaving whoever runs the
+	/// program to stop it
+	Quit,
 }
 
 impl ValueError {
 	pub fn new(token: Token, message: &str) -> Self {
-		Self::Std {token, message: message.to_string()}
+		Self::Error(Box::new(LoxError::runtime(token.location(), message)))
+	}
+
+	/// Create an error from within a native function
+	pub fn native(message: &str) -> Self {
+		Self::Native {message: message.to_string()}
+	}
+
+	/// Attach a token to an error raised by a native function
+	pub fn at(self, token: &Token) -> Self {
+		match self {
+			Self::Native { message } => Self::Error(Box::new(LoxError::runtime(token.location(), &message))),
+			e => e
+		}
+	}
+}
+
+impl Diagnostic for ValueError {
+	/// Where the error was raised, unless it is yet to be placed
+	fn location(&self) -> Option<Location> {
+		match self {
+			Self::Error(e) => e.location(),
+			Self::Native { .. } | Self::Quit => None
+		}
 	}
+}
 
-	pub fn error(&self) {
+impl fmt::Display for ValueError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		match self {
-			Self::Std { token, message } => eprintln!("[line {}] Error: {}", token.line, message),
-			Self::Break => eprintln!("'BREAK' value error detected"),
-			Self::Continue => eprintln!("'CONTINUE' value error detected"),
-			Self::Return(v) => eprintln!("'RETURN' value error detected, value {}", v),
+			Self::Error(e) => write!(f, "{}", e),
+			Self::Native { message } => write!(f, "Error: {}", message),
+			Self::Quit => write!(f, "Quit."),
 		}
 	}
 }
 
-impl From<EnvironmentError> for ValueError {
-	fn from(value: EnvironmentError) -> Self {
-		Self::Std {token: value.token, message: value.message}
+impl From<LoxError> for ValueError {
+	fn from(value: LoxError) -> Self {
+		Self::Error(Box::new(value))
 	}
 }
 
 pub type ValueResult<T> = Result<T, ValueError>;
 
+/// The calls a runtime error was raised in, innermost first, one line each. Empty until the error
+/// leaves the innermost of them. Boxed, as results carry errors everywhere and few errors get one
+#[derive(Debug, Default)]
+pub struct StackTrace(Option<Box<[String]>>);
+
+impl StackTrace {
+	pub fn is_empty(&self) -> bool {
+		self.0.is_none()
+	}
+}
+
+impl From<Vec<String>> for StackTrace {
+	fn from(lines: Vec<String>) -> Self {
+		Self((!lines.is_empty()).then(|| lines.into_boxed_slice()))
+	}
+}
+
+impl fmt::Display for StackTrace {
+	/// The trace as written below its error, each line on its own
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for line in self.0.iter().flat_map(|lines| lines.iter()) {
+			write!(f, "\n    {}", line)?;
+		}
+
+		Ok(())
+	}
+}
+
 pub fn check_number_operand(operator: Token, operand: &Value) -> ValueResult<f64> {
 	match operand {
 		Value::Double(n) => Ok(*n),
@@ -44,4 +104,29 @@ pub fn check_number_operands(operator: &Token, left: &Value, right: &Value) -> V
 		(Value::Double(l), Value::Double(r)) => Ok((*l, *r)),
 		_ => Err(ValueError::new(operator.clone(), "Operands must be a numbers."))
 	}
-}
\ No newline at end of file
+}
+
+/// Check that a value is a valid index into a sequence of the given length. Negative indices
+/// count back from the end, so `-1` is the last element
+pub fn check_index(bracket: &Token, index: &Value, len: usize) -> ValueResult<usize> {
+	let n = match index {
+		Value::Double(n) if n.fract() == 0.0 => *n,
+		_ => return Err(ValueError::new(bracket.clone(), "Index must be an integer."))
+	};
+
+	let position = if n < 0.0 { n + len as f64 } else { n };
+
+	if position < 0.0 || position >= len as f64 {
+		return Err(ValueError::new(bracket.clone(), &format!("Index {} out of range for length {}.", n, len)))
+	}
+
+	Ok(position as usize)
+}
+
+/// Check that a map key is a string
+pub fn check_key(bracket: &Token, key: &Value) -> ValueResult<String> {
+	match key {
+		Value::String(k) => Ok(k.to_string()),
+		_ => Err(ValueError::new(bracket.clone(), "Map keys must be strings."))
+	}
+}
@@ -0,0 +1,70 @@
+use crate::{error::LoxError, scanner::token::Token};
+
+use super::{error::ValueError, values::{Arguments, Value}};
+
+/// A statement leaving the code it runs in for somewhere other than the next statement
+pub enum ControlFlow {
+	Break,
+	Continue,
+	Return(Value),
+	/// A function returning the result of a call, which its caller makes in its place
+	TailCall(Box<TailCall>),
+}
+
+/// A call in tail position, evaluated up to the point of calling
+pub struct TailCall {
+	pub callee: Value,
+	pub arguments: Arguments,
+	pub paren: Token,
+}
+
+/// Why running statements or evaluating an expression stopped early: an error, or control flow on
+/// its way to the loop or call it leaves. Block expressions hold statements, so control flow passes
+/// through expressions as well
+pub enum Unwind {
+	Error(ValueError),
+	Flow(ControlFlow),
+}
+
+/// A wrapper result type for running statements and evaluating expressions
+pub type ExecResult<T> = Result<T, Unwind>;
+
+impl Unwind {
+	/// The error that stopped running, where control flow can go no further. The parser and resolver
+	/// reject a `break`, `continue` or `return` with nowhere to go, so one reaching a call or the top
+	/// level is a bug, reported rather than dropped
+	pub fn into_error(self) -> ValueError {
+		match self {
+			Self::Error(e) => e,
+			Self::Flow(ControlFlow::Break) => ValueError::native("Unexpected 'break' outside a loop."),
+			Self::Flow(ControlFlow::Continue) => ValueError::native("Unexpected 'continue' outside a loop."),
+			Self::Flow(ControlFlow::Return(_) | ControlFlow::TailCall(_)) => ValueError::native("Unexpected 'return' outside a function."),
+		}
+	}
+
+	/// Attach a token to an error raised by a native function, see [`ValueError::at`]
+	pub fn at(self, token: &Token) -> Self {
+		match self {
+			Self::Error(e) => Self::Error(e.at(token)),
+			flow => flow
+		}
+	}
+}
+
+impl From<ValueError> for Unwind {
+	fn from(value: ValueError) -> Self {
+		Self::Error(value)
+	}
+}
+
+impl From<LoxError> for Unwind {
+	fn from(value: LoxError) -> Self {
+		Self::Error(value.into())
+	}
+}
+
+impl From<ControlFlow> for Unwind {
+	fn from(value: ControlFlow) -> Self {
+		Self::Flow(value)
+	}
+}
@@ -0,0 +1,225 @@
+use std::{cell::RefCell, collections::{BTreeMap, HashMap}, hash::{BuildHasherDefault, Hasher}, rc::Rc};
+
+use crate::statement::environment::{self, Environment};
+
+use super::{values::{LoxClass, LoxFunction, LoxInstance, Value}, Interpreter};
+
+/// How many environments may be captured by functions before the first collection
+pub const INITIAL_THRESHOLD: usize = 1024;
+
+/// Something reference counted that can hold on to an environment, directly or through its values
+enum Node {
+	Environment(Rc<RefCell<Environment>>),
+	Cell(Rc<RefCell<Value>>),
+	Array(Rc<RefCell<Vec<Value>>>),
+	Map(Rc<RefCell<BTreeMap<String, Value>>>),
+	Instance(Rc<RefCell<LoxInstance>>),
+	Class(Rc<LoxClass>),
+	Function(Rc<LoxFunction>),
+}
+
+/// Hashes node addresses, which are already spread out, far faster than the default hasher
+#[derive(Default)]
+struct AddressHasher(u64);
+
+impl Hasher for AddressHasher {
+	fn finish(&self) -> u64 {
+		self.0
+	}
+
+	fn write(&mut self, _: &[u8]) {
+		unreachable!("Only addresses are hashed")
+	}
+
+	fn write_usize(&mut self, address: usize) {
+		self.0 = (address as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+	}
+}
+
+/// A node found by the collector
+struct Entry {
+	node: Node,
+	/// Whether the node was borrowed and couldn't be read, so must be kept along with all it holds
+	pinned: bool,
+	/// The number of references to the node from other nodes
+	internal: usize,
+}
+
+impl Node {
+	/// The address of the shared allocation, identifying the node
+	fn id(&self) -> usize {
+		match self {
+			Node::Environment(e) => Rc::as_ptr(e) as *const () as usize,
+			Node::Cell(c) => Rc::as_ptr(c) as *const () as usize,
+			Node::Array(a) => Rc::as_ptr(a) as *const () as usize,
+			Node::Map(m) => Rc::as_ptr(m) as *const () as usize,
+			Node::Instance(i) => Rc::as_ptr(i) as *const () as usize,
+			Node::Class(c) => Rc::as_ptr(c) as *const () as usize,
+			Node::Function(f) => Rc::as_ptr(f) as *const () as usize,
+		}
+	}
+
+	fn strong_count(&self) -> usize {
+		match self {
+			Node::Environment(e) => Rc::strong_count(e),
+			Node::Cell(c) => Rc::strong_count(c),
+			Node::Array(a) => Rc::strong_count(a),
+			Node::Map(m) => Rc::strong_count(m),
+			Node::Instance(i) => Rc::strong_count(i),
+			Node::Class(c) => Rc::strong_count(c),
+			Node::Function(f) => Rc::strong_count(f),
+		}
+	}
+
+	/// Add the nodes this one holds a reference to, returning `None` if it is mutably borrowed and
+	/// can't be read
+	fn children(&self, children: &mut Vec<Node>) -> Option<()> {
+		match self {
+			Node::Environment(e) => {
+				let e = e.try_borrow().ok()?;
+				// Cells holding plain values can't be part of a cycle, so they are left out
+				children.extend(e.values.iter().flatten()
+					.filter(|cell| cell.0.try_borrow().map_or(true, |v| holds_references(&v)))
+					.map(|cell| Node::Cell(cell.0.clone())));
+				children.extend(e.enclosing.iter().map(|enclosing| Node::Environment(enclosing.0.clone())));
+			},
+			Node::Cell(c) => value_children(&*c.try_borrow().ok()?, children),
+			Node::Array(a) => a.try_borrow().ok()?.iter().for_each(|v| value_children(v, children)),
+			Node::Map(m) => m.try_borrow().ok()?.values().for_each(|v| value_children(v, children)),
+			Node::Instance(i) => {
+				let i = i.try_borrow().ok()?;
+				children.push(Node::Class(i.class.clone()));
+				i.fields.values().for_each(|v| value_children(v, children));
+			},
+			Node::Class(c) => children.extend(c.methods.values().map(|m| Node::Environment(m.closure.0.clone()))),
+			Node::Function(f) => children.push(Node::Environment(f.closure.0.clone())),
+		}
+
+		Some(())
+	}
+
+	/// Drop everything the node holds, breaking the cycles it is part of
+	fn clear(&self) {
+		match self {
+			Node::Environment(e) => if let Ok(mut e) = e.try_borrow_mut() {
+				*e = Environment::default();
+			},
+			Node::Cell(c) => if let Ok(mut c) = c.try_borrow_mut() {
+				*c = Value::Nil;
+			},
+			Node::Array(a) => if let Ok(mut a) = a.try_borrow_mut() {
+				a.clear();
+			},
+			Node::Map(m) => if let Ok(mut m) = m.try_borrow_mut() {
+				m.clear();
+			},
+			Node::Instance(i) => if let Ok(mut i) = i.try_borrow_mut() {
+				i.fields.clear();
+			},
+			// Classes and functions can't change, the environments they close over are cleared instead
+			Node::Class(_) | Node::Function(_) => {},
+		}
+	}
+}
+
+/// The nodes a value refers to. Bytecode closures are left out, which only keeps more alive.
+/// Functions are nodes of their own, so one held only by a Rust temporary, such as an argument of
+/// a call still being evaluated, is a root rather than garbage
+fn value_children(value: &Value, children: &mut Vec<Node>) {
+	match value {
+		Value::Function(f) => children.push(Node::Function(f.clone())),
+		Value::Class(c) => children.push(Node::Class(c.clone())),
+		Value::Instance(i) => children.push(Node::Instance(i.clone())),
+		Value::Module(m) => children.push(Node::Environment(m.environment.0.clone())),
+		Value::Array(a) => children.push(Node::Array(a.clone())),
+		Value::Map(m) => children.push(Node::Map(m.clone())),
+		_ => {}
+	}
+}
+
+/// Whether a value can refer to a node
+fn holds_references(value: &Value) -> bool {
+	matches!(value, Value::Function(_) | Value::Class(_) | Value::Instance(_) | Value::Module(_) | Value::Array(_) | Value::Map(_))
+}
+
+impl Interpreter {
+	/// Collect garbage if functions have captured enough environments since the last collection
+	pub fn collect_garbage_if_needed(&mut self) {
+		if environment::tracked_count() >= self.gc_threshold {
+			self.collect_garbage();
+		}
+	}
+
+	/// Free the environments that are only kept alive by reference cycles among themselves, along
+	/// with the values they hold, returning how many environments were freed.
+	///
+	/// Anything referenced from outside what the collector finds, such as the interpreter's current
+	/// environment or a value on the Rust stack, has more strong references than the collector finds
+	/// inside them. Those are the roots, and whatever can't be reached from a root is garbage
+	pub fn collect_garbage(&mut self) -> usize {
+		let mut entries: Vec<Entry> = Vec::new();
+		let mut index: HashMap<usize, usize, BuildHasherDefault<AddressHasher>> = HashMap::default();
+		// The references between nodes, as the entries of the holder and the node held
+		let mut references: Vec<(usize, usize)> = Vec::new();
+		let mut children = Vec::new();
+
+		// Nodes still to visit, with the entry of the node holding the reference
+		let mut pending: Vec<(Node, Option<usize>)> = environment::tracked().into_iter()
+			.map(|e| (Node::Environment(e), None))
+			.collect();
+
+		// Find every node reachable from the tracked environments, counting the references between
+		// them. Every cycle passes through an environment captured by a function, so this finds all
+		// of them
+		while let Some((node, holder)) = pending.pop() {
+			let entry = match index.get(&node.id()) {
+				Some(&entry) => entry,
+				None => {
+					let entry = entries.len();
+					let pinned = node.children(&mut children).is_none();
+					pending.extend(children.drain(..).map(|child| (child, Some(entry))));
+
+					index.insert(node.id(), entry);
+					entries.push(Entry { node, pinned, internal: 0 });
+					entry
+				}
+			};
+
+			if let Some(holder) = holder {
+				entries[entry].internal += 1;
+				references.push((holder, entry));
+			}
+		}
+
+		// The collector holds one reference to each node. Any beyond that and those found inside other
+		// nodes come from outside, making the node a root, as is one that couldn't be read
+		let mut reachable: Vec<usize> = (0..entries.len())
+			.filter(|&i| entries[i].pinned || entries[i].node.strong_count() > 1 + entries[i].internal)
+			.collect();
+
+		references.sort_unstable();
+		let mut marked = vec![false; entries.len()];
+
+		while let Some(i) = reachable.pop() {
+			if !marked[i] {
+				marked[i] = true;
+				let start = references.partition_point(|&(holder, _)| holder < i);
+				reachable.extend(references[start..].iter().take_while(|&&(holder, _)| holder == i).map(|&(_, held)| held));
+			}
+		}
+
+		let garbage: Vec<Node> = entries.into_iter().zip(marked)
+			.filter(|(_, marked)| !marked)
+			.map(|(entry, _)| entry.node)
+			.collect();
+
+		let freed = garbage.iter().filter(|node| matches!(node, Node::Environment(_))).count();
+		garbage.iter().for_each(Node::clear);
+		drop(garbage);
+
+		let live = environment::tracked().len();
+		self.gc_threshold = INITIAL_THRESHOLD.max(live * 2);
+
+		freed
+	}
+}
@@ -1,38 +1,147 @@
-use std::{collections::HashMap, time::UNIX_EPOCH};
+use std::{cell::RefCell, collections::{HashMap, HashSet}, fmt, path::PathBuf, rc::Rc};
 
-use error::{check_number_operand, check_number_operands, ValueError, ValueResult};
-use values::{Callable, Native, Value, ValueCell};
+use coverage::Coverage;
+use debugger::Debugger;
 
-use crate::{parser::expr::{Expr, ExprAssignment, ExprBinary, ExprCall, ExprGet, ExprGrouping, ExprLiteral, ExprLogical, ExprSet, ExprThis, ExprUnary, ExprVariable}, scanner::token::{Token, TokenType}, statement::environment::EnvCell};
+use error::{check_index, check_key, check_number_operand, check_number_operands, ValueError, ValueResult};
+use flow::{ExecResult, Unwind};
+use module::Modules;
+use natives::random::Rng;
+use stats::Stats;
+use trace::Trace;
+use values::{Arguments, Callable, LoxInstance, Value, ValueCell};
+
+use crate::{error::LoxError, parser::expr::{AstPrinter, Expr, ExprArray, ExprAssignment, ExprBinary, ExprBlock, ExprCall, ExprGet, ExprGrouping, ExprIf, ExprIndex, ExprIndexSet, ExprLiteral, ExprLogical, ExprSet, ExprThis, ExprUnary, ExprVariable, NodeId}, scanner::token::{Symbol, Token, TokenType}, statement::environment::{pool::EnvironmentPool, EnvCell}, visitor::ExprVisitor, vm::{Stack, Vm}};
 
 pub mod values;
 pub mod error;
+pub mod flow;
+pub mod module;
+pub mod natives;
+pub mod trace;
+pub mod coverage;
+pub mod debugger;
+pub mod gc;
+pub mod stats;
+pub mod string;
+pub mod tier;
 
 /// A Lox interpreter
 pub struct Interpreter {
 	pub environment: EnvCell,
 	pub globals: EnvCell,
-	pub locals: HashMap<Expr, usize>
+	/// Where each resolved variable lives, by the id of the expression referring to it
+	pub resolved: HashMap<NodeId, Resolved>,
+	/// Calls whose result a function returns directly, which run without growing the Rust stack
+	pub tail_calls: HashSet<NodeId>,
+	/// Blocks that declare nothing, which run in the environment around them instead of their own
+	pub flat_blocks: HashSet<NodeId>,
+	/// The file currently executing, imports are resolved relative to it
+	pub current_file: Option<PathBuf>,
+	/// Modules imported so far, keyed by canonical path
+	pub modules: Modules,
+	/// The generator behind `random`, `randomInt` and `randomSeed`
+	pub rng: Rng,
+	/// The command-line arguments passed to the script, exposed by `args()`
+	pub script_args: Vec<String>,
+	/// Whether scripts may run shell commands through `exec`, set by `--allow-exec`
+	pub allow_exec: bool,
+	/// The exit code of the last command run by `exec`, exposed by `exitCode()`
+	pub last_exit_code: Option<i32>,
+	/// The active Lox calls, outermost first
+	pub frames: Vec<CallFrame>,
+	/// The deepest calls may nest before raising a stack overflow error, set by `--max-depth`
+	pub max_call_depth: usize,
+	/// What `--trace` reports as the program runs
+	pub trace: Trace,
+	/// The lines executed so far, when running under `coverage`
+	pub coverage: Option<Coverage>,
+	/// What has run so far, with `--stats`
+	pub stats: Option<Stats>,
+	/// The breakpoints and stepping state of `debug`
+	pub debugger: Option<Debugger>,
+	/// The line of the last traced statement or expression, for those that don't keep one
+	trace_line: usize,
+	/// How many environments captured by functions may be tracked before the next garbage collection
+	pub gc_threshold: usize,
+	/// How much imported modules are optimized, like the program, set by `--opt-level`
+	pub opt_level: usize,
+	/// Environments of finished blocks and calls, ready to be reused
+	pub environment_pool: EnvironmentPool,
+	/// Whether top-level functions called often are compiled to bytecode and run by the VM
+	pub tier_up: bool,
+	/// The bytecode VM's stack, kept here while no VM holds it so that nested runs share it
+	pub vm_stack: Stack,
+	/// Native code for top-level functions, with `--jit`
+	#[cfg(feature = "jit")]
+	pub jit: Option<crate::jit::Jit>,
+}
+
+/// How deep calls may nest by default. The main thread's stack is sized to fit this many calls
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// Where the resolver found a variable
+#[derive(Clone, Copy)]
+pub enum Resolved {
+	/// A local, this many scopes out from the reference
+	Local(usize),
+	/// A global, by its slot in the global environment
+	Global(usize),
+}
+
+/// A single active call, for stack traces
+#[derive(Clone)]
+pub struct CallFrame {
+	/// The name of the function, class or native being called
+	pub name: String,
+	pub kind: CallKind,
+	/// The line of the call site
+	pub line: usize,
+}
+
+/// What a call frame is running, as named in stack traces
+#[derive(Clone, Copy, PartialEq)]
+pub enum CallKind {
+	Function,
+	Method,
+	Class,
+	Native,
+}
+
+impl CallFrame {
+	/// The frame for calling a value from the given line
+	pub fn new(callee: &Value, line: usize) -> Self {
+		let (name, kind) = match callee {
+			Value::NativeFn(n) => (n.name.clone(), CallKind::Native),
+			Value::Function(f) if f.is_method() => (f.name().to_string(), CallKind::Method),
+			Value::Function(f) => (f.name().to_string(), CallKind::Function),
+			Value::Closure(c) => (c.function.name.clone(), CallKind::Function),
+			Value::Class(c) => (c.name.clone(), CallKind::Class),
+			_ => (String::new(), CallKind::Function)
+		};
+
+		Self { name, kind, line }
+	}
+}
+
+impl fmt::Display for CallKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			CallKind::Function => "fn",
+			CallKind::Method => "method",
+			CallKind::Class => "class",
+			CallKind::Native => "native fn",
+		})
+	}
 }
 
 impl Interpreter {
 	/// Initialize a new interpreter
 	pub fn new() -> Self {
 		let globals = EnvCell::new();
-		let mut new = Self {environment: EnvCell::with_enclosing(&globals), globals, locals: HashMap::new()};
-		
-		fn get_curr_time() -> Value {
-			let v = std::time::SystemTime::now()
-			.duration_since(UNIX_EPOCH)
-			.expect("Time went backwards")
-			.as_millis();
+		let mut new = Self {environment: EnvCell::with_enclosing(&globals), globals, resolved: HashMap::new(), tail_calls: HashSet::new(), flat_blocks: HashSet::new(), current_file: None, modules: HashMap::new(), rng: Rng::from_time(), script_args: Vec::new(), allow_exec: false, last_exit_code: None, frames: Vec::new(), max_call_depth: MAX_CALL_DEPTH, trace: Trace::Off, coverage: None, stats: None, debugger: None, trace_line: 0, gc_threshold: gc::INITIAL_THRESHOLD, opt_level: 0, environment_pool: EnvironmentPool::default(), tier_up: false, vm_stack: Stack::default(), #[cfg(feature = "jit")] jit: None};
 
-			return Value::Double(v as f64);
-		}
-	
-		let clock = Native::new(0, get_curr_time);
-		
-		new.globals.define("clock".to_string(), Value::NativeFn(clock));
+		new.define_natives();
 		new.environment = new.globals.clone();
 		
 		new
@@ -42,43 +151,84 @@ impl Interpreter {
 
 impl Interpreter {
 	/// Begin interpretation
-	pub fn interpret(&mut self, expr: Expr) -> Option<Value>{
-		let res = self.interpret_expr(expr);
-
-		match res {
-			Ok(e) => {Some(e.value())},
-			Err(e) => {e.error(); None}
-		}
+	pub fn interpret(&mut self, expr: Expr) -> ValueResult<Value> {
+		self.interpret_expr(&expr).map_err(Unwind::into_error)
 	}
 
 	/// Interpret an expression
-	pub fn interpret_expr(&mut self, expr: Expr) -> ValueResult<ValueCell> {
-		match expr {
-			Expr::Assignment(x) => {self.interpret_expr_assignment(x)}
-			Expr::Binary(x) => {self.interpret_expr_binary(x)},
-			Expr::Literal(x) => {self.interpret_expr_literal(x)},
-			Expr::Unary(x) => {self.interpret_expr_unary(x)},
-			Expr::Call(c) => {self.interpret_expr_call(c)},
-			Expr::Get(g) => {self.interpret_expr_get(g)},
-			Expr::Set(s) => {self.interpret_expr_set(s)},
-			Expr::This(t) => {self.interpret_expr_this(t)},
-			Expr::Grouping(x) => {self.interpret_expr_grouping(x)},
-			Expr::Logical(x) => {self.interpret_expr_logical(x)},
-			Expr::Variable(x) => {Ok(self.environment.get(x.name)?)},
+	pub fn interpret_expr(&mut self, expr: &Expr) -> ExecResult<Value> {
+		if self.trace == Trace::Expressions {
+			let line = expr.line();
+			let description = AstPrinter::print(expr);
+			let v = self.evaluate(expr)?;
+
+			self.trace_expr(line, &description, &v);
+			return Ok(v)
 		}
+
+		self.evaluate(expr)
+	}
+
+	fn evaluate(&mut self, expr: &Expr) -> ExecResult<Value> {
+		expr.accept(self)
 	}
 }
 
-impl Interpreter {
+impl ExprVisitor for Interpreter {
+	type Output = ExecResult<Value>;
+
+	/// Interpret a literal expression
+	fn visit_literal_expr(&mut self, expr: &ExprLiteral) -> ExecResult<Value> {
+		let v = match expr {
+			ExprLiteral::True => Value::Boolean(true),
+			ExprLiteral::False => Value::Boolean(false),
+			ExprLiteral::NUMBER(n) => Value::Double(*n),
+			ExprLiteral::STRING(s) => {
+				self.count_value();
+				Value::String((*s).into())
+			},
+			ExprLiteral::Null => Value::Nil,
+		};
+
+		Ok(v)
+	}
+
+	/// Interpret a call expression
+	fn visit_call_expr(&mut self, expr: &ExprCall) -> ExecResult<Value> {
+		let callee = self.interpret_expr(&expr.callee)?;
+		let mut arguments = Arguments::new();
+
+		for argument in &expr.arguments {
+			arguments.push(self.interpret_expr(argument)?);
+		}
+
+		let paren = &expr.paren;
+
+		if self.frames.len() >= self.max_call_depth {
+			return Err(ValueError::new(paren.clone(), "Stack overflow.").into())
+		}
+
+		// A call that can't be made fails at the call site, not inside the callee
+		check_call(&callee, &arguments).map_err(|e| self.with_trace(e.at(paren)))?;
+
+		self.frames.push(CallFrame::new(&callee, paren.line));
+		self.count_call();
+
+		let result = self.call_value(callee, arguments).map_err(|e| self.with_trace(e.at(paren)).into());
+		self.frames.pop();
+
+		return result
+	}
+
 	/// Interpret a Binary expression
-	pub fn interpret_expr_binary(&mut self, expr: ExprBinary) -> ValueResult<ValueCell> {
-		let left = self.interpret_expr(*expr.left)?.value();
-		let right = self.interpret_expr(*expr.right)?.value();
-		let o = expr.operator;
+	fn visit_binary_expr(&mut self, expr: &ExprBinary) -> ExecResult<Value> {
+		let left = self.interpret_expr(&expr.left)?;
+		let right = self.interpret_expr(&expr.right)?;
+		let o = &expr.operator;
 
 		let v = match o.token_type {
 			TokenType::MINUS => {
-				let (l, r) = check_number_operands(&o, &left, &right)?;
+				let (l, r) = check_number_operands(o, &left, &right)?;
 				Value::Double(l - r)
 			},
 			TokenType::PLUS => {
@@ -86,33 +236,36 @@ impl Interpreter {
 					(Value::Double(l), Value::Double(r)) => Value::Double(l + r),
 					// (Value::Double(l), Value::String(r)) => Value::String(l.to_string() + &r),
 					// (Value::String(l), Value::Double(r)) => Value::String(l + &r.to_string()),
-					(Value::String(l), Value::String(r)) => Value::String(l + &r),
-					_ => return Err(ValueError::new(o, "Operands can only be numbers or strings"))
+					(Value::String(l), Value::String(r)) => {
+						self.count_value();
+						Value::String(l.concat(&r))
+					},
+					_ => return Err(ValueError::new(o.clone(), "Operands can only be numbers or strings").into())
 				}
 			},
 			TokenType::STAR => {
-				let (l, r) = check_number_operands(&o, &left, &right)?;
+				let (l, r) = check_number_operands(o, &left, &right)?;
 				Value::Double(l * r)
 			},
 			TokenType::SLASH => {
-				let (l, r) = check_number_operands(&o, &left, &right)?;
-				if r == 0.0 { return Err(ValueError::new(o, "Denominator cannot be 0"))}
+				let (l, r) = check_number_operands(o, &left, &right)?;
+				if r == 0.0 { return Err(ValueError::new(o.clone(), "Denominator cannot be 0").into()) }
 				Value::Double(l/r)
 			},
 			TokenType::GREATER => {
-				let (l, r) = check_number_operands(&o, &left, &right)?;
+				let (l, r) = check_number_operands(o, &left, &right)?;
 				Value::Boolean(l > r)
 			},
 			TokenType::GREATER_EQUAL => {
-				let (l, r) = check_number_operands(&o, &left, &right)?;
+				let (l, r) = check_number_operands(o, &left, &right)?;
 				Value::Boolean(l >= r)
 			},
 			TokenType::LESS => {
-				let (l, r) = check_number_operands(&o, &left, &right)?;
+				let (l, r) = check_number_operands(o, &left, &right)?;
 				Value::Boolean(l < r)
 			},
 			TokenType::LESS_EQUAL => {
-				let (l, r) = check_number_operands(&o, &left, &right)?;
+				let (l, r) = check_number_operands(o, &left, &right)?;
 				Value::Boolean(l <= r)
 			},
 			TokenType::BANG_EQUAL => Value::Boolean(!left.eq(&right)),
@@ -120,176 +273,348 @@ impl Interpreter {
 			_ => Value::Nil
 		};
 
-		Ok(ValueCell::new(v))
+		Ok(v)
 	}
-}
 
+	/// Interpret a grouping expression
+	fn visit_grouping_expr(&mut self, expr: &ExprGrouping) -> ExecResult<Value> {
+		return self.interpret_expr(&expr.0);
+	}
 
-impl Interpreter{
-	/// Interpret a literal expression
-	pub fn interpret_expr_literal(&mut self, expr: ExprLiteral) -> ValueResult<ValueCell> {
-		let v = match expr {
-			ExprLiteral::True => Value::Boolean(true),
-			ExprLiteral::False => Value::Boolean(false),
-			ExprLiteral::NUMBER(n) => Value::Double(n),
-			ExprLiteral::STRING(s) => Value::String(s),
-			ExprLiteral::Null => Value::Nil,
-		};
+	/// Interpret a variable expression
+	fn visit_variable_expr(&mut self, expr: &ExprVariable) -> ExecResult<Value> {
+		Ok(self.look_up_variable(&expr.name, expr.id)?.value())
+	}
 
-		Ok(ValueCell::new(v))
+	/// Interpret an assignment expression
+	fn visit_assignment_expr(&mut self, expr: &ExprAssignment) -> ExecResult<Value> {
+		let value = self.interpret_expr(&expr.value)?;
+
+		match self.resolved.get(&expr.id) {
+			Some(&Resolved::Local(distance)) => self.environment.assign_at(distance, &expr.name, value.clone()),
+			Some(&Resolved::Global(slot)) => self.globals.assign_slot(slot, &expr.name, value.clone())?,
+			None => self.environment.assign(expr.name.clone(), value.clone())?
+		}
+
+		Ok(value)
 	}
-}
 
-impl Interpreter {
-	/// Interpret a grouping expression
-	pub fn interpret_expr_grouping(&mut self, expr: ExprGrouping) -> ValueResult<ValueCell> {
-		return self.interpret_expr(*expr.0);
+	/// Interpret a get expression
+	fn visit_get_expr(&mut self, expr: &ExprGet) -> ExecResult<Value> {
+		let object = self.interpret_expr(&expr.object)?;
+
+		match object {
+			Value::Instance(object) => {
+				return Ok(LoxInstance::get(&object, expr.name.clone())?)
+			},
+			Value::Module(module) => {
+				return Ok(module.get(&expr.name)?)
+			},
+			Value::Array(array) if expr.name.lexeme == "length" => {
+				return Ok(Value::Double(array.borrow().len() as f64))
+			},
+			Value::Map(map) if expr.name.lexeme == "length" => {
+				return Ok(Value::Double(map.borrow().len() as f64))
+			},
+			Value::String(s) if expr.name.lexeme == "length" => {
+				return Ok(Value::Double(s.chars().count() as f64))
+			},
+			_ => Err(self.error(expr.name.clone(), "Only instances have properties").into())
+		}
+
+	}
+
+	/// Interpret a Logical expression
+	fn visit_logical_expr(&mut self, expr: &ExprLogical) -> ExecResult<Value> {
+		let left = self.interpret_expr(&expr.left)?;
+
+		if expr.operator.token_type == TokenType::OR {
+			if left.is_truthy() {return Ok(left)}
+		} else {
+			if !left.is_truthy() {return Ok(left)}
+		}
+
+		return self.interpret_expr(&expr.right);
+	}
+
+	/// Interpret a set expression
+	fn visit_set_expr(&mut self, expr: &ExprSet) -> ExecResult<Value> {
+		let object = self.interpret_expr(&expr.object)?;
+		let value = self.interpret_expr(&expr.value)?;
+
+		match object {
+			Value::Instance(object) => {
+				object.borrow_mut().set(&expr.name, value.clone());
+				Ok(value)
+			},
+			_ => Err(self.error(expr.name.clone(), "Only instances have fields").into())
+		}
+	}
+
+	/// Interpret a set expression
+	fn visit_this_expr(&mut self, expr: &ExprThis) -> ExecResult<Value> {
+		Ok(self.look_up_variable(&expr.keyword, expr.id)?.value())
 	}
-}
 
-impl Interpreter {
 	/// Interpret a unary expression
-	pub fn interpret_expr_unary(&mut self, expr: ExprUnary) -> ValueResult<ValueCell> {
-		let right = self.interpret_expr(*expr.right)?.value();
-		let o = expr.operator;
+	fn visit_unary_expr(&mut self, expr: &ExprUnary) -> ExecResult<Value> {
+		let right = self.interpret_expr(&expr.right)?;
+		let o = &expr.operator;
 
 		let v = match o.token_type {
 			TokenType::MINUS=> {
-				let n = check_number_operand(o, &right)?;
+				let n = check_number_operand(o.clone(), &right)?;
 				Value::Double(-n)
 			},
 			TokenType::BANG => { Value::Boolean(!right.is_truthy()) }
 			_ => Value::Nil
 		};
 
-		Ok(ValueCell::new(v))
+		Ok(v)
 	}
-}
 
-impl Interpreter {
-	/// Interpret a call expression
-	pub fn interpret_expr_call(&mut self, expr: ExprCall) -> ValueResult<ValueCell> {
-		let callee = self.interpret_expr(*expr.callee)?.value();
-		let mut arguments = Vec::new();
+	/// Interpret a block expression, producing the value of its trailing expression
+	fn visit_block_expr(&mut self, expr: &ExprBlock) -> ExecResult<Value> {
+		let previous = self.begin_environment();
 
-		for argument in expr.arguments {
-			arguments.push(self.interpret_expr(argument)?.value());
+		let result = self.execute_statements(&expr.statements).and_then(|_| {
+			match &expr.value {
+				Some(value) => self.interpret_expr(value),
+				None => Ok(Value::Nil)
+			}
+		});
+
+		self.end_environment(previous);
+		result
+	}
+
+	/// Interpret an if expression, producing the value of the branch taken
+	fn visit_if_expr(&mut self, expr: &ExprIf) -> ExecResult<Value> {
+		if self.interpret_expr(&expr.condition)?.is_truthy() {
+			self.interpret_expr(&expr.then_branch)
+		} else if let Some(else_branch) = &expr.else_branch {
+			self.interpret_expr(else_branch)
+		} else {
+			Ok(Value::Nil)
 		}
+	}
 
-		let mut function: Box<dyn Callable> = match callee {
-			Value::NativeFn(x) => Box::new(x),
-			Value::Function(f) => Box::new(f),
-			Value::Class(c) => Box::new(c),
-			_ => return Err(ValueError::Std { token: expr.paren, message: "Can only call functions and classes".to_string() })
-		};
+	/// Interpret an array literal
+	fn visit_array_expr(&mut self, expr: &ExprArray) -> ExecResult<Value> {
+		let mut elements = Vec::new();
 
-		if arguments.len() != function.arity() {
-			return Err(ValueError::Std { token: expr.paren, message: format!("Expected {} arguments but got {}.", function.arity(), arguments.len()) })
+		for element in &expr.elements {
+			elements.push(self.interpret_expr(element)?);
 		}
 
-		return Ok(ValueCell::new(function.call(self, arguments)?))
+		self.count_value();
+		Ok(Value::Array(Rc::new(RefCell::new(elements))))
 	}
-}
 
+	/// Interpret a subscript read on an array or string
+	fn visit_index_expr(&mut self, expr: &ExprIndex) -> ExecResult<Value> {
+		let object = self.interpret_expr(&expr.object)?;
+		let index = self.interpret_expr(&expr.index)?;
 
-impl Interpreter {
-	/// Interpret a get expression
-	pub fn interpret_expr_get(&mut self, expr: ExprGet) -> ValueResult<ValueCell> {
-		let object = self.interpret_expr(*expr.object)?.value();
+		let v = match object {
+			Value::Array(array) => {
+				let array = array.borrow();
+				let i = check_index(&expr.bracket, &index, array.len())?;
+				array[i].clone()
+			},
+			Value::String(s) => {
+				let chars: Vec<char> = s.chars().collect();
+				let i = check_index(&expr.bracket, &index, chars.len())?;
+				self.count_value();
+				Value::String(chars[i].to_string().into())
+			},
+			// Missing keys read as nil
+			Value::Map(map) => {
+				let key = check_key(&expr.bracket, &index)?;
+				map.borrow().get(&key).cloned().unwrap_or(Value::Nil)
+			},
+			_ => return Err(ValueError::new(expr.bracket.clone(), "Only arrays, maps and strings can be indexed.").into())
+		};
+
+		Ok(v)
+	}
+
+	/// Interpret a subscript write on an array or map
+	fn visit_index_set_expr(&mut self, expr: &ExprIndexSet) -> ExecResult<Value> {
+		let object = self.interpret_expr(&expr.object)?;
+		let index = self.interpret_expr(&expr.index)?;
+		let value = self.interpret_expr(&expr.value)?;
 
 		match object {
-			Value::Instance(object) => {
-				return Ok(ValueCell::new(object.get(expr.name)?))
+			Value::Array(array) => {
+				let mut array = array.borrow_mut();
+				let i = check_index(&expr.bracket, &index, array.len())?;
+				array[i] = value.clone();
+			},
+			Value::Map(map) => {
+				let key = check_key(&expr.bracket, &index)?;
+				map.borrow_mut().insert(key, value.clone());
 			},
-			_ => Err(self.error(expr.name, "Only instances have properties"))
+			Value::String(_) => return Err(ValueError::new(expr.bracket.clone(), "Strings are immutable.").into()),
+			_ => return Err(ValueError::new(expr.bracket.clone(), "Only arrays and maps can be assigned by index.").into())
 		}
 
+		Ok(value)
 	}
 }
 
 impl Interpreter {
-	/// Interpret an assignment expression
-	pub fn interpret_expr_assignment(&mut self, expr: ExprAssignment) -> ValueResult<ValueCell> {
-		let value = self.interpret_expr(*expr.value.clone())?;
-		let name = expr.name.clone();
+	/// Render the active calls, innermost first, each with the line it is currently executing
+	pub fn stack_trace(&self) -> String {
+		let mut lines = Vec::new();
+
+		for (i, frame) in self.frames.iter().enumerate().rev() {
+			// A frame is executing the line its innermost callee was called from
+			if let Some(callee) = self.frames.get(i + 1) {
+				lines.push(format!("at {} (line {})", frame.name, callee.line));
+			}
+		}
 
-		if let Some(&distance) = self.locals.get(&Expr::Assignment(expr)) {
-			self.environment.assign_at(distance, &name, value.value().clone());
-		} else {
-			self.globals.assign(name, value.value().clone())?;
+		if let Some(first) = self.frames.first() {
+			lines.push(format!("at <script> (line {})", first.line));
 		}
 
-		Ok(value)
+		lines.join("\n")
 	}
 
+	/// The active Lox calls as a stack trace, innermost first, for an error raised at `line`. Each
+	/// call is shown with the line it is executing, and a call repeating the one before, as in deep
+	/// recursion, is counted rather than shown again. Empty outside any Lox call, where the line of
+	/// the error says it all
+	pub fn error_trace(&self, line: usize) -> Vec<String> {
+		let mut trace: Vec<String> = Vec::new();
+		let mut repeated = 0;
+		let mut line = line;
+
+		for frame in self.frames.iter().rev() {
+			// Natives are left out, but the calls they make run at their call site
+			if frame.kind != CallKind::Native {
+				let call = format!("in {} {}, line {}", frame.kind, frame.name, line);
+
+				if trace.last() == Some(&call) {
+					repeated += 1;
+				} else {
+					if repeated > 0 {
+						trace.push(format!("(the call above repeated {} more times)", repeated));
+						repeated = 0;
+					}
+
+					trace.push(call);
+				}
+			}
 
-}
+			line = frame.line;
+		}
 
-impl Interpreter {
-	/// Interpret a variable expression
-	pub fn interpret_expr_variable(&mut self, expr: ExprVariable) -> ValueResult<ValueCell> {
-		self.look_up_variable(expr.name.clone(), Expr::Variable(expr))
+		if repeated > 0 {
+			trace.push(format!("(the call above repeated {} more times)", repeated));
+		}
+
+		if !trace.is_empty() {
+			trace.push(format!("at top level, line {}", line));
+		}
+
+		trace
 	}
 
-	pub fn look_up_variable(&mut self, name: Token, expr: Expr) -> ValueResult<ValueCell> {
-		if let Some(&distance) = self.locals.get(&expr) {
-			return Ok(self.environment.get_at(distance, name.lexeme.clone()))
-		} else {
-			return Ok(self.globals.get(name)?)
+	/// Attach the active calls to an error leaving the innermost of them, as its stack trace. An
+	/// error that already has one keeps it, it was raised deeper
+	pub fn with_trace(&self, mut error: ValueError) -> ValueError {
+		if let ValueError::Error(e) = &mut error {
+			if let LoxError::Runtime { location, trace, .. } = e.as_mut() {
+				if trace.is_empty() {
+					*trace = self.error_trace(location.line).into();
+				}
+			}
+		}
+
+		error
+	}
+
+	/// Call a Lox value with the given arguments. Errors that aren't tied to a token, such as an
+	/// arity mismatch, are raised as [`ValueError::Native`] for the caller to place
+	pub fn call_value(&mut self, callee: Value, arguments: Arguments) -> ValueResult<Value> {
+		// Compiled functions run in a VM of their own, sharing the globals
+		if let Value::Closure(closure) = callee {
+			return Vm::new(self).call(closure, arguments).map_err(|e| ValueError::native(e.message()))
 		}
+
+		let function: &dyn Callable = match &callee {
+			Value::NativeFn(x) => &**x,
+			Value::Function(f) => &**f,
+			Value::Class(c) => c,
+			_ => return Err(ValueError::native("Can only call functions and classes"))
+		};
+
+		check_arity(function, &arguments)?;
+		function.call(self, arguments)
 	}
 }
 
-impl Interpreter {
-	/// Interpret a Logical expression
-	pub fn interpret_expr_logical(&mut self, expr: ExprLogical) -> ValueResult<ValueCell> {
-		let left = self.interpret_expr(*expr.left)?.value();
+/// Check that a value can be called with the arguments it is given
+pub fn check_call(callee: &Value, arguments: &[Value]) -> ValueResult<()> {
+	match callee {
+		Value::NativeFn(x) => check_arity(&**x, arguments),
+		Value::Function(f) => check_arity(&**f, arguments),
+		Value::Class(c) => check_arity(c, arguments),
+		Value::Closure(c) if arguments.len() != c.function.arity => Err(ValueError::native(&format!("Expected {} arguments but got {}.", c.function.arity, arguments.len()))),
+		Value::Closure(_) => Ok(()),
+		_ => Err(ValueError::native("Can only call functions and classes"))
+	}
+}
 
-		if expr.operator.token_type == TokenType::OR {
-			if left.is_truthy() {return Ok(ValueCell::new(left))}
-		} else {
-			if !left.is_truthy() {return Ok(ValueCell::new(left))}
-		}
+/// Check that a callable can take the arguments it is given
+pub fn check_arity(function: &dyn Callable, arguments: &[Value]) -> ValueResult<()> {
+	if function.variadic() && arguments.len() < function.arity() {
+		return Err(ValueError::native(&format!("Expected at least {} arguments but got {}.", function.arity(), arguments.len())))
+	}
 
-		return self.interpret_expr(*expr.right);
+	if !function.variadic() && arguments.len() != function.arity() {
+		return Err(ValueError::native(&format!("Expected {} arguments but got {}.", function.arity(), arguments.len())))
 	}
+
+	Ok(())
 }
 
 impl Interpreter {
-	/// Interpret a set expression
-	pub fn interpret_expr_set(&mut self, expr: ExprSet) -> ValueResult<ValueCell> {
-		let object = self.interpret_expr(*expr.object)?;
-		let mut v = object.0.borrow_mut();
-		// let e = object.0.borrow().clone();
-		// let  = object.0.borrow_mut();
-
-		match &mut *v {
-			Value::Instance(ref mut object) => {
-				let value = self.interpret_expr(*expr.value)?;
-				object.set(&expr.name, value.value());
-				Ok(value)
-			},
-			_ => Err(self.error(expr.name, "Only instances have fields"))
+	/// The cell a variable is stored in
+	pub fn look_up_variable(&mut self, name: &Token, id: NodeId) -> ValueResult<ValueCell> {
+		// Code run without resolving it first, such as by `evaluate`, looks its names up as it goes
+		match self.resolved.get(&id) {
+			Some(&Resolved::Local(distance)) => Ok(self.environment.get_at(distance, name.lexeme)),
+			Some(&Resolved::Global(slot)) => Ok(self.globals.get_slot(slot, name)?),
+			None => Ok(self.environment.get(name.clone())?)
 		}
 	}
 }
 
 impl Interpreter {
-	/// Interpret a set expression
-	pub fn interpret_expr_this(&mut self, expr: ExprThis) -> ValueResult<ValueCell> {
-		let name = expr.keyword.clone();
-		self.look_up_variable(name, Expr::This(expr))
+	pub fn resolve_expr_depth(&mut self, id: NodeId, depth: usize) {
+		self.resolved.insert(id, Resolved::Local(depth));
 	}
-}
 
-impl Interpreter {
-	pub fn resolve_expr_depth(&mut self, expr: Expr, depth: usize) {
-		self.locals.insert(expr, depth);
+	/// Record that a call is in tail position, so a function can return its result without calling it
+	pub fn resolve_tail_call(&mut self, id: NodeId) {
+		self.tail_calls.insert(id);
+	}
+
+	/// Record that a block declares nothing, so it can run without an environment of its own
+	pub fn resolve_flat_block(&mut self, id: NodeId) {
+		self.flat_blocks.insert(id);
+	}
+
+	/// Record that an expression refers to a global, reserving a slot for it if it isn't defined yet
+	pub fn resolve_expr_global(&mut self, id: NodeId, name: Symbol) {
+		let slot = self.globals.slot(name);
+		self.resolved.insert(id, Resolved::Global(slot));
 	}
 
 	pub fn error(&mut self, token: Token, message: &str) -> ValueError {
-		let e = ValueError::new(token, message);
-		e.error();
-		e
+		ValueError::new(token, message)
 	}
 }
\ No newline at end of file
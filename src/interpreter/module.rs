@@ -0,0 +1,140 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use crate::{log, optimizer, parser::Parser, resolver::Resolver, scanner::{token::{Literal, Symbol, Token}, Scanner}, statement::{environment::EnvCell, Statement}};
+
+use super::{error::{ValueError, ValueResult}, flow::Unwind, values::Value, Interpreter};
+
+/// The load state of a module, keyed by canonical path in the interpreter's module cache
+#[derive(Clone)]
+pub enum ModuleState {
+	/// The module is currently executing, importing it again means there is an import cycle
+	Loading,
+	/// The module has finished executing
+	Loaded(LoxModule)
+}
+
+/// A struct representing a loaded Lox module, the namespace value bound by an import
+#[derive(Clone)]
+pub struct LoxModule {
+	pub name: String,
+	/// The environment holding the module's top-level bindings
+	pub environment: EnvCell,
+	/// The names of the bindings the module exports
+	pub exports: Vec<Symbol>
+}
+
+impl PartialEq for LoxModule {
+	/// Modules are equal only if they are the same loaded module
+	fn eq(&self, other: &Self) -> bool {
+		std::rc::Rc::ptr_eq(&self.environment.0, &other.environment.0)
+	}
+}
+
+impl LoxModule {
+	/// Get an exported binding from the module
+	pub fn get(&self, name: &Token) -> ValueResult<Value> {
+		if !self.exports.contains(&name.lexeme) {
+			return Err(ValueError::new(name.clone(), &format!("Module '{}' has no export '{}'.", self.name, name.lexeme)))
+		}
+
+		Ok(self.environment.get(name.clone())?.value())
+	}
+}
+
+impl std::fmt::Display for LoxModule {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "<module {}>", self.name)
+	}
+}
+
+/// The module cache of an interpreter
+pub type Modules = HashMap<PathBuf, ModuleState>;
+
+impl Interpreter {
+	/// Load, parse, resolve and execute the module at the given path, relative to the file currently
+	/// executing. Each module is executed only once, later imports share the same namespace
+	pub fn import_module(&mut self, path_token: &Token) -> ValueResult<LoxModule> {
+		let relative = match &path_token.literal {
+			Literal::String(p) => PathBuf::from(p.as_str()),
+			_ => return Err(ValueError::new(path_token.clone(), "Module path must be a string."))
+		};
+
+		let path = match self.current_file.as_ref().and_then(|f| f.parent()) {
+			Some(dir) => dir.join(&relative),
+			None => relative.clone()
+		};
+
+		let path = path.canonicalize().map_err(|_| {
+			ValueError::new(path_token.clone(), &format!("Could not find module '{}'.", relative.display()))
+		})?;
+
+		match self.modules.get(&path) {
+			Some(ModuleState::Loaded(module)) => return Ok(module.clone()),
+			Some(ModuleState::Loading) => {
+				return Err(ValueError::new(path_token.clone(), &format!("Circular import of module '{}'.", relative.display())))
+			},
+			None => {}
+		}
+
+		// The errors that stopped the module loading are given as the reason it failed
+		let failed = |reason: String| ValueError::new(path_token.clone(), &format!("Failed to load module '{}': {}", relative.display(), reason));
+		let reasons = |errors: Vec<String>| failed(errors.join("\n"));
+
+		let source = fs::read_to_string(&path).map_err(|e| failed(e.to_string()))?;
+
+		let mut parser = Parser::streaming(Scanner::new(source));
+		let parsed = parser.parse_statement();
+
+		if !parser.scan_errors.is_empty() {
+			return Err(reasons(parser.scan_errors.iter().map(|e| e.to_string()).collect()))
+		}
+
+		let mut statements = parsed.map_err(|errors| reasons(errors.iter().map(|e| e.to_string()).collect()))?;
+
+		statements = optimizer::optimize(statements, self.opt_level);
+
+		// Globals the module refers to get their slots in this interpreter's globals
+		let mut resolver = Resolver::new(Interpreter::new());
+		resolver.interpreter.globals = self.globals.clone();
+		resolver.resolve_module(&statements).map_err(|errors| reasons(errors.iter().map(|e| e.to_string()).collect()))?;
+		self.resolved.extend(resolver.interpreter.resolved);
+		self.tail_calls.extend(resolver.interpreter.tail_calls);
+		self.flat_blocks.extend(resolver.interpreter.flat_blocks);
+
+		if let Some(coverage) = &mut self.coverage {
+			coverage.register(&path, &statements);
+		}
+
+		let exports = statements.iter()
+			.filter_map(|s| match s {
+				Statement::Export(e) => Some(e.names()),
+				_ => None
+			})
+			.flatten()
+			.map(|name| name.lexeme)
+			.collect();
+
+		let name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+		let module = LoxModule { name, environment: EnvCell::with_enclosing(&self.globals), exports };
+
+		log::verbose(&format!("Importing {}", path.display()));
+		self.modules.insert(path.clone(), ModuleState::Loading);
+
+		let previous_environment = std::mem::replace(&mut self.environment, module.environment.clone());
+		let previous_file = self.current_file.replace(path.clone());
+
+		let result = self.execute_statements(&statements).map_err(Unwind::into_error);
+
+		self.environment = previous_environment;
+		self.current_file = previous_file;
+
+		if let Err(e) = result {
+			self.modules.remove(&path);
+			return Err(e)
+		}
+
+		self.modules.insert(path, ModuleState::Loaded(module.clone()));
+
+		Ok(module)
+	}
+}
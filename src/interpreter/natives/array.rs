@@ -0,0 +1,200 @@
+use std::{cell::RefCell, cmp::Ordering, rc::Rc};
+
+use smallvec::smallvec;
+
+use crate::interpreter::{error::{ValueError, ValueResult}, values::{Arguments, LoxRange, Value}, Interpreter};
+
+use super::expect_number;
+
+type Array = Rc<RefCell<Vec<Value>>>;
+
+impl Interpreter {
+	/// Define the array natives in the global environment
+	pub fn define_array_natives(&mut self) {
+		self.define_native("push", 2, push);
+		self.define_native("pop", 1, pop);
+		self.define_native("insert", 3, insert);
+		self.define_native("remove", 2, remove);
+		self.define_native("len", 1, len);
+		self.define_native("sort", 1, sort);
+		self.define_native("map", 2, map);
+		self.define_native("filter", 2, filter);
+		self.define_native("reduce", 3, reduce);
+		self.define_native("range", 2, range);
+		self.define_native("lazyRange", 2, lazy_range);
+	}
+}
+
+/// Check that an argument to a native is an array
+fn expect_array(native: &str, value: &Value) -> ValueResult<Array> {
+	match value {
+		Value::Array(array) => Ok(array.clone()),
+		_ => Err(ValueError::native(&format!("First argument to '{}' must be an array.", native)))
+	}
+}
+
+/// Check that an argument to a native is an integer position in `0..=max` of an array of length `len`,
+/// counting from the end if negative
+fn expect_position(native: &str, value: &Value, len: usize, max: usize) -> ValueResult<usize> {
+	let n = expect_number(native, value)?;
+
+	if n.fract() != 0.0 {
+		return Err(ValueError::native(&format!("Index passed to '{}' must be an integer.", native)))
+	}
+
+	let position = if n < 0.0 { n + len as f64 } else { n };
+
+	if position < 0.0 || position > max as f64 {
+		return Err(ValueError::native(&format!("Index {} out of range in '{}'.", n, native)))
+	}
+
+	Ok(position as usize)
+}
+
+/// Append a value to the end of an array
+fn push(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	expect_array("push", &args[0])?.borrow_mut().push(args[1].clone());
+
+	Ok(Value::Nil)
+}
+
+/// Remove and return the last element of an array
+fn pop(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	expect_array("pop", &args[0])?.borrow_mut().pop()
+		.ok_or_else(|| ValueError::native("Can't pop from an empty array."))
+}
+
+/// Insert a value before the given index, an index equal to the length appends
+fn insert(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let array = expect_array("insert", &args[0])?;
+	let mut array = array.borrow_mut();
+	let i = expect_position("insert", &args[1], array.len(), array.len())?;
+
+	array.insert(i, args[2].clone());
+
+	Ok(Value::Nil)
+}
+
+/// Remove and return the element at the given index
+fn remove(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let array = expect_array("remove", &args[0])?;
+	let mut array = array.borrow_mut();
+
+	if array.is_empty() {
+		return Err(ValueError::native("Can't remove from an empty array."))
+	}
+
+	let i = expect_position("remove", &args[1], array.len(), array.len() - 1)?;
+
+	Ok(array.remove(i))
+}
+
+/// The number of elements in an array or map, or characters in a string
+fn len(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let n = match &args[0] {
+		Value::Array(array) => array.borrow().len(),
+		Value::Map(map) => map.borrow().len(),
+		Value::String(s) => s.chars().count(),
+		_ => return Err(ValueError::native("Argument to 'len' must be an array, map or string."))
+	};
+
+	Ok(Value::Double(n as f64))
+}
+
+/// Sort an array of numbers or an array of strings in place
+fn sort(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let array = expect_array("sort", &args[0])?;
+	let mut array = array.borrow_mut();
+
+	let all_numbers = array.iter().all(|v| matches!(v, Value::Double(_)));
+	let all_strings = array.iter().all(|v| matches!(v, Value::String(_)));
+
+	if !all_numbers && !all_strings {
+		return Err(ValueError::native("Can only sort arrays of numbers or arrays of strings."))
+	}
+
+	array.sort_by(|a, b| match (a, b) {
+		(Value::Double(a), Value::Double(b)) => a.total_cmp(b),
+		(Value::String(a), Value::String(b)) => a.cmp(b),
+		_ => Ordering::Equal
+	});
+
+	Ok(Value::Nil)
+}
+
+/// A new array holding the result of calling the function on each element
+fn map(interpreter: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	// Copy the elements out so the callback is free to mutate the array
+	let elements = expect_array("map", &args[0])?.borrow().clone();
+	let mut result = Vec::with_capacity(elements.len());
+
+	for element in elements {
+		result.push(interpreter.call_value(args[1].clone(), smallvec![element])?);
+	}
+
+	Ok(Value::Array(Rc::new(RefCell::new(result))))
+}
+
+/// A new array holding the elements for which the function returns a truthy value
+fn filter(interpreter: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let elements = expect_array("filter", &args[0])?.borrow().clone();
+	let mut result = Vec::new();
+
+	for element in elements {
+		if interpreter.call_value(args[1].clone(), smallvec![element.clone()])?.is_truthy() {
+			result.push(element);
+		}
+	}
+
+	Ok(Value::Array(Rc::new(RefCell::new(result))))
+}
+
+/// Fold the array into a single value, calling the function with the accumulator and each element
+fn reduce(interpreter: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let elements = expect_array("reduce", &args[0])?.borrow().clone();
+	let mut accumulator = args[2].clone();
+
+	for element in elements {
+		accumulator = interpreter.call_value(args[1].clone(), smallvec![accumulator, element])?;
+	}
+
+	Ok(accumulator)
+}
+
+/// The most numbers `range` puts in an array, so that a huge range fails rather than exhausting memory
+const MAX_RANGE: usize = 1 << 24;
+
+/// An array of the numbers counting up by one from the start, up to but not including the end, so
+/// that `for (var i in range(0, n))` runs `n` times
+fn range(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let start = expect_number("range", &args[0])?;
+	let end = expect_number("range", &args[1])?;
+
+	if !start.is_finite() || !end.is_finite() {
+		return Err(ValueError::native("Arguments to 'range' must be finite."))
+	}
+
+	let count = (end - start).ceil().max(0.0);
+
+	if count > MAX_RANGE as f64 {
+		return Err(ValueError::native(&format!("'range' can't make an array of more than {} numbers, use 'lazyRange' to loop over more.", MAX_RANGE)))
+	}
+
+	let count = count as usize;
+	let result = (0..count).map(|i| Value::Double(start + i as f64)).collect();
+
+	Ok(Value::Array(Rc::new(RefCell::new(result))))
+}
+
+/// The same numbers as `range`, counted out one at a time as a `for-in` loop asks for them rather
+/// than all put in an array up front
+fn lazy_range(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let start = expect_number("lazyRange", &args[0])?;
+	let end = expect_number("lazyRange", &args[1])?;
+
+	if !start.is_finite() || !end.is_finite() {
+		return Err(ValueError::native("Arguments to 'lazyRange' must be finite."))
+	}
+
+	Ok(Value::Range(Rc::new(LoxRange { start, end })))
+}
@@ -0,0 +1,66 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::interpreter::{error::ValueResult, values::{Arguments, LoxInstance, Value}, Interpreter};
+
+impl Interpreter {
+	/// Define the copying natives in the global environment
+	pub fn define_copy_natives(&mut self) {
+		self.define_native("deepCopy", 1, deep_copy);
+	}
+}
+
+/// Recursively copy arrays, maps and instances, so that the copy shares no mutable state with the original
+fn deep_copy(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(copy_value(&args[0], &mut HashMap::new()))
+}
+
+/// Copy a value, `copies` maps the address of each array, map and instance already copied to its copy, so that
+/// shared and cyclic references are preserved rather than recursed into forever
+fn copy_value(value: &Value, copies: &mut HashMap<usize, Value>) -> Value {
+	match value {
+		Value::Array(array) => {
+			let key = Rc::as_ptr(array) as usize;
+			if let Some(copy) = copies.get(&key) {
+				return copy.clone()
+			}
+
+			let copy = Rc::new(RefCell::new(Vec::new()));
+			copies.insert(key, Value::Array(copy.clone()));
+
+			let elements = array.borrow().iter().map(|v| copy_value(v, copies)).collect();
+			*copy.borrow_mut() = elements;
+
+			Value::Array(copy)
+		},
+		Value::Map(map) => {
+			let key = Rc::as_ptr(map) as usize;
+			if let Some(copy) = copies.get(&key) {
+				return copy.clone()
+			}
+
+			let copy = Rc::new(RefCell::new(Default::default()));
+			copies.insert(key, Value::Map(copy.clone()));
+
+			let entries = map.borrow().iter().map(|(k, v)| (k.clone(), copy_value(v, copies))).collect();
+			*copy.borrow_mut() = entries;
+
+			Value::Map(copy)
+		},
+		Value::Instance(instance) => {
+			let key = Rc::as_ptr(instance) as usize;
+			if let Some(copy) = copies.get(&key) {
+				return copy.clone()
+			}
+
+			let copy = Rc::new(RefCell::new(LoxInstance::new(instance.borrow().class.clone())));
+			copies.insert(key, Value::Instance(copy.clone()));
+
+			let fields = instance.borrow().fields.iter().map(|(k, v)| (*k, copy_value(v, copies))).collect();
+			copy.borrow_mut().fields = fields;
+
+			Value::Instance(copy)
+		},
+		// Everything else is immutable, so sharing it is indistinguishable from copying it
+		v => v.clone()
+	}
+}
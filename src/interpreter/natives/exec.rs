@@ -0,0 +1,42 @@
+use std::process::Command;
+
+use crate::interpreter::{error::{ValueError, ValueResult}, values::{Arguments, Value}, Interpreter};
+
+impl Interpreter {
+	/// Define the process natives in the global environment
+	pub fn define_exec_natives(&mut self) {
+		self.define_native("exec", 1, exec);
+		self.define_native("exitCode", 0, exit_code);
+	}
+}
+
+/// Run a command through the system shell and return its standard output. Only available when the
+/// interpreter was started with `--allow-exec`
+fn exec(interpreter: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	if !interpreter.allow_exec {
+		return Err(ValueError::native("'exec' is disabled, run with --allow-exec to enable it."))
+	}
+
+	let command = match &args[0] {
+		Value::String(c) => c,
+		_ => return Err(ValueError::native("Argument to 'exec' must be a string."))
+	};
+
+	let output = if cfg!(windows) {
+		Command::new("cmd").args(["/C", command]).output()
+	} else {
+		Command::new("sh").args(["-c", command]).output()
+	};
+
+	let output = output.map_err(|e| ValueError::native(&format!("Could not run command: {}.", e)))?;
+
+	interpreter.last_exit_code = output.status.code();
+
+	Ok(Value::String(String::from_utf8_lossy(&output.stdout).into_owned().into()))
+}
+
+/// The exit code of the last command run by `exec`. Nil if no command has run, or if the last one
+/// was killed by a signal
+fn exit_code(interpreter: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	Ok(interpreter.last_exit_code.map(|c| Value::Double(c as f64)).unwrap_or(Value::Nil))
+}
@@ -0,0 +1,163 @@
+use std::{iter::Peekable, str::Chars};
+
+use crate::interpreter::{error::{ValueError, ValueResult}, values::{Arguments, Value}, Interpreter};
+
+impl Interpreter {
+	/// Define the string formatting natives in the global environment
+	pub fn define_format_natives(&mut self) {
+		self.define_variadic_native("format", 1, format);
+	}
+}
+
+/// How a formatted value is padded out to its width
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+	Left,
+	Right,
+	Center
+}
+
+/// A parsed `{:[align][0][width][.precision]}` placeholder specification
+struct Spec {
+	align: Option<Align>,
+	zero: bool,
+	width: usize,
+	precision: Option<usize>
+}
+
+/// Substitute the arguments into the `{}` placeholders of a format string. Placeholders take an
+/// optional spec, e.g. `{:>8}`, `{:<8}`, `{:^8}`, `{:08.2}`, and `{{`/`}}` are literal braces
+fn format(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let template = match &args[0] {
+		Value::String(s) => s,
+		_ => return Err(ValueError::native("First argument to 'format' must be a string."))
+	};
+
+	let mut values = args[1..].iter();
+	let mut chars = template.chars().peekable();
+	let mut out = String::new();
+
+	while let Some(c) = chars.next() {
+		match c {
+			'{' if chars.peek() == Some(&'{') => {
+				chars.next();
+				out.push('{');
+			},
+			'{' => {
+				let spec = parse_spec(&mut chars)?;
+				let value = values.next()
+					.ok_or_else(|| ValueError::native("Too few arguments for format string."))?;
+
+				out.push_str(&apply_spec(&spec, value));
+			},
+			'}' if chars.peek() == Some(&'}') => {
+				chars.next();
+				out.push('}');
+			},
+			'}' => return Err(ValueError::native("Unmatched '}' in format string.")),
+			c => out.push(c)
+		}
+	}
+
+	if values.next().is_some() {
+		return Err(ValueError::native("Too many arguments for format string."))
+	}
+
+	Ok(Value::String(out.into()))
+}
+
+/// The largest width or precision a placeholder may ask for, so that padding can't exhaust memory
+const MAX_WIDTH: usize = 4096;
+
+/// Parse the spec of a placeholder, the opening `{` has already been consumed
+fn parse_spec(chars: &mut Peekable<Chars>) -> ValueResult<Spec> {
+	let mut text = String::new();
+
+	loop {
+		match chars.next() {
+			Some('}') => break,
+			Some(c) => text.push(c),
+			None => return Err(ValueError::native("Unterminated '{' in format string."))
+		}
+	}
+
+	let invalid = || ValueError::native(&format!("Invalid format specifier '{{{}}}'.", text));
+
+	let mut spec = Spec { align: None, zero: false, width: 0, precision: None };
+
+	if text.is_empty() {
+		return Ok(spec)
+	}
+
+	let mut rest = text.strip_prefix(':').ok_or_else(invalid)?;
+
+	spec.align = match rest.chars().next() {
+		Some('<') => Some(Align::Left),
+		Some('>') => Some(Align::Right),
+		Some('^') => Some(Align::Center),
+		_ => None
+	};
+
+	if spec.align.is_some() {
+		rest = &rest[1..];
+	}
+
+	if let Some(stripped) = rest.strip_prefix('0') {
+		spec.zero = true;
+		rest = stripped;
+	}
+
+	let (width, precision) = match rest.split_once('.') {
+		Some((w, p)) => (w, Some(p)),
+		None => (rest, None)
+	};
+
+	if !width.is_empty() {
+		spec.width = width.parse().ok().filter(|w| *w <= MAX_WIDTH).ok_or_else(invalid)?;
+	}
+
+	if let Some(p) = precision {
+		spec.precision = Some(p.parse().ok().filter(|p| *p <= MAX_WIDTH).ok_or_else(invalid)?);
+	}
+
+	Ok(spec)
+}
+
+/// Render a value according to a spec. Numbers align right by default, everything else aligns left
+fn apply_spec(spec: &Spec, value: &Value) -> String {
+	let text = match (value, spec.precision) {
+		(Value::Double(n), Some(p)) => format!("{:.*}", p, n),
+		(Value::String(s), Some(p)) => s.chars().take(p).collect(),
+		(v, _) => v.to_string()
+	};
+
+	let len = text.chars().count();
+
+	if len >= spec.width {
+		return text
+	}
+
+	let is_number = matches!(value, Value::Double(_));
+	let padding = spec.width - len;
+
+	// Zero padding goes between the sign and the digits
+	if spec.zero && is_number && spec.align.is_none() {
+		let (sign, digits) = match text.strip_prefix('-') {
+			Some(digits) => ("-", digits),
+			None => ("", text.as_str())
+		};
+
+		return format!("{}{}{}", sign, "0".repeat(padding), digits)
+	}
+
+	let align = spec.align.unwrap_or(if is_number { Align::Right } else { Align::Left });
+
+	match align {
+		Align::Left => format!("{}{}", text, " ".repeat(padding)),
+		Align::Right => format!("{}{}", " ".repeat(padding), text),
+		Align::Center => {
+			let left = padding / 2;
+			format!("{}{}{}", " ".repeat(left), text, " ".repeat(padding - left))
+		}
+	}
+}
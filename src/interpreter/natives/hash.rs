@@ -0,0 +1,80 @@
+use std::rc::Rc;
+
+use crate::interpreter::{error::ValueResult, values::{Arguments, Value}, Interpreter};
+
+impl Interpreter {
+	/// Define the hashing natives in the global environment
+	pub fn define_hash_natives(&mut self) {
+		self.define_native("hash", 1, hash);
+	}
+}
+
+/// 32-bit FNV-1a, small enough that every hash is exactly representable as a Lox number
+fn fnv1a(tag: u8, bytes: &[u8]) -> u32 {
+	let mut hash: u32 = 0x811c9dc5;
+
+	for byte in std::iter::once(&tag).chain(bytes) {
+		hash ^= *byte as u32;
+		hash = hash.wrapping_mul(0x01000193);
+	}
+
+	hash
+}
+
+/// How deep into nested arrays and maps a hash looks. Comparing self-containing arrays takes the
+/// cycle to be equal, so equal values unroll into the same elements to any depth and hashing only so
+/// far keeps equal values hashing the same while still ending on cycles
+const MAX_DEPTH: usize = 4;
+
+/// A hash that is stable across runs for strings, numbers, ranges, booleans and nil, and follows
+/// `==`: arrays and maps hash by contents, instances, classes and functions by identity
+fn hash(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(hash_value(&args[0], MAX_DEPTH) as f64))
+}
+
+/// Hash a value, looking `depth` levels further into the arrays and maps it holds
+fn hash_value(value: &Value, depth: usize) -> u32 {
+	// Each kind of value is tagged so that, e.g., `"1"` and `1` don't collide by construction
+	match value {
+		Value::Nil => fnv1a(0, &[]),
+		Value::Boolean(b) => fnv1a(1, &[*b as u8]),
+		Value::Double(n) => fnv1a(2, &number_bytes(*n)),
+		Value::String(s) => fnv1a(3, s.as_bytes()),
+		Value::Array(a) => {
+			let a = a.borrow();
+			let mut bytes = a.len().to_le_bytes().to_vec();
+			if depth > 0 {
+				a.iter().for_each(|v| bytes.extend(hash_value(v, depth - 1).to_le_bytes()));
+			}
+			fnv1a(4, &bytes)
+		},
+		Value::Map(m) => {
+			let m = m.borrow();
+			let mut bytes = m.len().to_le_bytes().to_vec();
+			if depth > 0 {
+				m.iter().for_each(|(k, v)| {
+					bytes.extend(fnv1a(3, k.as_bytes()).to_le_bytes());
+					bytes.extend(hash_value(v, depth - 1).to_le_bytes());
+				});
+			}
+			fnv1a(5, &bytes)
+		},
+		Value::NativeFn(n) => fnv1a(6, n.name.as_bytes()),
+		Value::Function(f) => fnv1a(7, &address(Rc::as_ptr(f))),
+		Value::Closure(c) => fnv1a(7, &address(Rc::as_ptr(c))),
+		Value::Class(c) => fnv1a(8, &address(Rc::as_ptr(c))),
+		Value::Instance(i) => fnv1a(9, &address(Rc::as_ptr(i))),
+		Value::Module(m) => fnv1a(10, &address(Rc::as_ptr(&m.environment.0))),
+		Value::Range(r) => fnv1a(11, &[number_bytes(r.start), number_bytes(r.end)].concat()),
+	}
+}
+
+/// The bytes of a number, the same for `0` and `-0` since they are equal
+fn number_bytes(n: f64) -> [u8; 8] {
+	(if n == 0.0 { 0.0f64 } else { n }).to_bits().to_le_bytes()
+}
+
+/// The bytes of the address of a shared allocation, identifying it for as long as it is alive
+fn address<T>(pointer: *const T) -> [u8; std::mem::size_of::<usize>()] {
+	(pointer as *const () as usize).to_le_bytes()
+}
@@ -0,0 +1,287 @@
+use std::{cell::RefCell, collections::{BTreeMap, HashSet}, iter::Peekable, rc::Rc, str::Chars};
+
+use crate::{interpreter::{error::{ValueError, ValueResult}, values::{Arguments, Value}, Interpreter}, printer::json::write_string, utils::number};
+
+impl Interpreter {
+	/// Define the JSON natives in the global environment
+	pub fn define_json_natives(&mut self) {
+		self.define_native("jsonParse", 1, json_parse);
+		self.define_native("jsonStringify", 1, json_stringify);
+	}
+}
+
+/// Parse a JSON document. Objects become maps, arrays become arrays and null becomes nil
+fn json_parse(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let source = match &args[0] {
+		Value::String(s) => s,
+		_ => return Err(ValueError::native("Argument to 'jsonParse' must be a string."))
+	};
+
+	let mut parser = JsonParser { chars: source.chars().peekable(), position: 0 };
+
+	let value = parser.value()?;
+	parser.skip_whitespace();
+
+	if parser.chars.peek().is_some() {
+		return Err(parser.error("Unexpected trailing characters"))
+	}
+
+	Ok(value)
+}
+
+/// Convert a value to a JSON string
+fn json_stringify(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let mut out = String::new();
+	stringify(&args[0], &mut out, &mut HashSet::new())?;
+
+	Ok(Value::String(out.into()))
+}
+
+/// A recursive descent parser over the characters of a JSON document
+struct JsonParser<'a> {
+	chars: Peekable<Chars<'a>>,
+	/// The character offset, used in error messages
+	position: usize
+}
+
+impl<'a> JsonParser<'a> {
+	fn error(&self, message: &str) -> ValueError {
+		ValueError::native(&format!("Invalid JSON: {} at position {}.", message, self.position))
+	}
+
+	fn advance(&mut self) -> Option<char> {
+		self.position += 1;
+		self.chars.next()
+	}
+
+	fn skip_whitespace(&mut self) {
+		while let Some(' ' | '\t' | '\n' | '\r') = self.chars.peek() {
+			self.advance();
+		}
+	}
+
+	fn expect(&mut self, expected: char) -> ValueResult<()> {
+		match self.advance() {
+			Some(c) if c == expected => Ok(()),
+			_ => Err(self.error(&format!("Expect '{}'", expected)))
+		}
+	}
+
+	fn value(&mut self) -> ValueResult<Value> {
+		self.skip_whitespace();
+
+		match self.chars.peek() {
+			Some('{') => self.object(),
+			Some('[') => self.array(),
+			Some('"') => Ok(Value::String(self.string()?.into())),
+			Some('-' | '0'..='9') => self.number(),
+			Some('t') => self.keyword("true", Value::Boolean(true)),
+			Some('f') => self.keyword("false", Value::Boolean(false)),
+			Some('n') => self.keyword("null", Value::Nil),
+			Some(_) => Err(self.error("Unexpected character")),
+			None => Err(self.error("Unexpected end of input"))
+		}
+	}
+
+	fn keyword(&mut self, word: &str, value: Value) -> ValueResult<Value> {
+		for expected in word.chars() {
+			if self.advance() != Some(expected) {
+				return Err(self.error(&format!("Expect '{}'", word)))
+			}
+		}
+
+		Ok(value)
+	}
+
+	fn object(&mut self) -> ValueResult<Value> {
+		self.advance();
+		let mut map = BTreeMap::new();
+
+		self.skip_whitespace();
+		if self.chars.peek() == Some(&'}') {
+			self.advance();
+			return Ok(Value::Map(Rc::new(RefCell::new(map))))
+		}
+
+		loop {
+			self.skip_whitespace();
+			if self.chars.peek() != Some(&'"') {
+				return Err(self.error("Expect string key"))
+			}
+
+			let key = self.string()?;
+			self.skip_whitespace();
+			self.expect(':')?;
+
+			let value = self.value()?;
+			map.insert(key, value);
+
+			self.skip_whitespace();
+			match self.advance() {
+				Some(',') => continue,
+				Some('}') => break,
+				_ => return Err(self.error("Expect ',' or '}' after object member"))
+			}
+		}
+
+		Ok(Value::Map(Rc::new(RefCell::new(map))))
+	}
+
+	fn array(&mut self) -> ValueResult<Value> {
+		self.advance();
+		let mut elements = Vec::new();
+
+		self.skip_whitespace();
+		if self.chars.peek() == Some(&']') {
+			self.advance();
+			return Ok(Value::Array(Rc::new(RefCell::new(elements))))
+		}
+
+		loop {
+			elements.push(self.value()?);
+
+			self.skip_whitespace();
+			match self.advance() {
+				Some(',') => continue,
+				Some(']') => break,
+				_ => return Err(self.error("Expect ',' or ']' after array element"))
+			}
+		}
+
+		Ok(Value::Array(Rc::new(RefCell::new(elements))))
+	}
+
+	fn string(&mut self) -> ValueResult<String> {
+		self.advance();
+		let mut s = String::new();
+
+		loop {
+			match self.advance() {
+				Some('"') => return Ok(s),
+				Some('\\') => {
+					let c = match self.advance() {
+						Some('"') => '"',
+						Some('\\') => '\\',
+						Some('/') => '/',
+						Some('b') => '\u{8}',
+						Some('f') => '\u{c}',
+						Some('n') => '\n',
+						Some('r') => '\r',
+						Some('t') => '\t',
+						Some('u') => self.unicode_escape()?,
+						_ => return Err(self.error("Invalid escape sequence"))
+					};
+					s.push(c);
+				},
+				Some(c) if (c as u32) < 0x20 => return Err(self.error("Control character in string")),
+				Some(c) => s.push(c),
+				None => return Err(self.error("Unterminated string"))
+			}
+		}
+	}
+
+	/// Decode the digits of a `\u` escape, combining a surrogate pair if one follows
+	fn unicode_escape(&mut self) -> ValueResult<char> {
+		let high = self.hex4()?;
+
+		if !(0xD800..0xDC00).contains(&high) {
+			return char::from_u32(high).ok_or_else(|| self.error("Invalid unicode escape"))
+		}
+
+		if self.advance() != Some('\\') || self.advance() != Some('u') {
+			return Err(self.error("Expect low surrogate"))
+		}
+
+		let low = self.hex4()?;
+
+		if !(0xDC00..0xE000).contains(&low) {
+			return Err(self.error("Invalid low surrogate"))
+		}
+
+		let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+		char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+	}
+
+	fn hex4(&mut self) -> ValueResult<u32> {
+		let mut code = 0;
+
+		for _ in 0..4 {
+			let digit = self.advance()
+				.and_then(|c| c.to_digit(16))
+				.ok_or_else(|| self.error("Expect four hex digits"))?;
+			code = code * 16 + digit;
+		}
+
+		Ok(code)
+	}
+
+	fn number(&mut self) -> ValueResult<Value> {
+		let mut text = String::new();
+
+		while let Some(c @ ('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) = self.chars.peek() {
+			text.push(*c);
+			self.advance();
+		}
+
+		// Rust's float grammar is looser than JSON's, so check the shape by hand
+		let unsigned = text.strip_prefix('-').unwrap_or(&text);
+		let valid = match unsigned.chars().next() {
+			Some('0') => !unsigned[1..].starts_with(|c: char| c.is_ascii_digit()),
+			Some(c) => c.is_ascii_digit(),
+			None => false
+		} && !unsigned.contains(".e") && !unsigned.contains(".E") && !unsigned.ends_with('.');
+
+		match text.parse::<f64>() {
+			Ok(n) if valid => Ok(Value::Double(n)),
+			_ => Err(self.error(&format!("Invalid number '{}'", text)))
+		}
+	}
+}
+
+/// Write the JSON form of a value to `out`, `visiting` holding the addresses of the arrays and maps
+/// being written further up, which JSON has no way to refer back to
+fn stringify(value: &Value, out: &mut String, visiting: &mut HashSet<usize>) -> ValueResult<()> {
+	match value {
+		Value::Nil => out.push_str("null"),
+		Value::Boolean(b) => out.push_str(&b.to_string()),
+		Value::Double(n) if n.is_finite() => out.push_str(&number::format(*n)),
+		Value::Double(_) => return Err(ValueError::native("Can't convert a non-finite number to JSON.")),
+		Value::String(s) => write_string(out, s),
+		Value::Array(array) => {
+			let address = Rc::as_ptr(array) as usize;
+			enter(address, visiting)?;
+			out.push('[');
+			for (i, element) in array.borrow().iter().enumerate() {
+				if i > 0 { out.push(','); }
+				stringify(element, out, visiting)?;
+			}
+			out.push(']');
+			visiting.remove(&address);
+		},
+		Value::Map(map) => {
+			let address = Rc::as_ptr(map) as usize;
+			enter(address, visiting)?;
+			out.push('{');
+			for (i, (key, element)) in map.borrow().iter().enumerate() {
+				if i > 0 { out.push(','); }
+				write_string(out, key);
+				out.push(':');
+				stringify(element, out, visiting)?;
+			}
+			out.push('}');
+			visiting.remove(&address);
+		},
+		v => return Err(ValueError::native(&format!("Can't convert '{}' to JSON.", v)))
+	}
+
+	Ok(())
+}
+
+/// Start writing the array or map at `address`, failing if it is already being written
+fn enter(address: usize, visiting: &mut HashSet<usize>) -> ValueResult<()> {
+	if !visiting.insert(address) {
+		return Err(ValueError::native("Can't convert a cyclic structure to JSON."))
+	}
+
+	Ok(())
+}
@@ -0,0 +1,92 @@
+use crate::interpreter::{error::ValueResult, values::{Arguments, Value}, Interpreter};
+
+use super::expect_number;
+
+impl Interpreter {
+	/// Define the math natives in the global environment
+	pub fn define_math_natives(&mut self) {
+		self.define_native("sqrt", 1, sqrt);
+		self.define_native("abs", 1, abs);
+		self.define_native("floor", 1, floor);
+		self.define_native("ceil", 1, ceil);
+		self.define_native("round", 1, round);
+		self.define_native("pow", 2, pow);
+		self.define_native("min", 2, min);
+		self.define_native("max", 2, max);
+
+		self.define_native("sin", 1, sin);
+		self.define_native("cos", 1, cos);
+		self.define_native("tan", 1, tan);
+		self.define_native("log", 1, log);
+		self.define_native("exp", 1, exp);
+
+		self.globals.define_constant("PI".into(), Value::Double(std::f64::consts::PI));
+		self.globals.define_constant("E".into(), Value::Double(std::f64::consts::E));
+	}
+}
+
+fn sqrt(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(expect_number("sqrt", &args[0])?.sqrt()))
+}
+
+fn abs(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(expect_number("abs", &args[0])?.abs()))
+}
+
+fn floor(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(expect_number("floor", &args[0])?.floor()))
+}
+
+fn ceil(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(expect_number("ceil", &args[0])?.ceil()))
+}
+
+/// Round to the nearest integer, halfway cases round away from zero
+fn round(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(expect_number("round", &args[0])?.round()))
+}
+
+fn pow(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let base = expect_number("pow", &args[0])?;
+	let exponent = expect_number("pow", &args[1])?;
+
+	Ok(Value::Double(base.powf(exponent)))
+}
+
+fn min(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let a = expect_number("min", &args[0])?;
+	let b = expect_number("min", &args[1])?;
+
+	Ok(Value::Double(a.min(b)))
+}
+
+fn max(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let a = expect_number("max", &args[0])?;
+	let b = expect_number("max", &args[1])?;
+
+	Ok(Value::Double(a.max(b)))
+}
+
+/// Sine of an angle in radians
+fn sin(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(expect_number("sin", &args[0])?.sin()))
+}
+
+/// Cosine of an angle in radians
+fn cos(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(expect_number("cos", &args[0])?.cos()))
+}
+
+/// Tangent of an angle in radians
+fn tan(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(expect_number("tan", &args[0])?.tan()))
+}
+
+/// Natural logarithm
+fn log(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(expect_number("log", &args[0])?.ln()))
+}
+
+fn exp(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(expect_number("exp", &args[0])?.exp()))
+}
@@ -0,0 +1,64 @@
+use std::{alloc::{GlobalAlloc, Layout, System}, sync::atomic::{AtomicUsize, Ordering}};
+
+use crate::interpreter::{error::ValueResult, values::{Arguments, Value}, Interpreter};
+
+/// The system allocator, counting the bytes and blocks currently allocated so that scripts can
+/// observe the interpreter's memory use. A program using the interpreter installs it as its
+/// `#[global_allocator]`, without it the memory natives report nothing allocated
+pub struct CountingAllocator;
+
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BLOCKS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		let ptr = System.alloc(layout);
+		if !ptr.is_null() {
+			ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+			ALLOCATED_BLOCKS.fetch_add(1, Ordering::Relaxed);
+		}
+		ptr
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout);
+		ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+		ALLOCATED_BLOCKS.fetch_sub(1, Ordering::Relaxed);
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		let new = System.realloc(ptr, layout, new_size);
+		if !new.is_null() {
+			ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+			ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+		}
+		new
+	}
+}
+
+impl Interpreter {
+	/// Define the memory introspection natives in the global environment
+	pub fn define_memory_natives(&mut self) {
+		self.define_native("gc", 0, gc);
+		self.define_native("memoryUsage", 0, memory_usage);
+		self.define_native("objectCount", 0, object_count);
+	}
+}
+
+/// Force a collection. Values are reference counted and freed as soon as they become unreachable,
+/// a collection frees those kept alive by reference cycles, such as a closure stored in the
+/// environment it captured
+fn gc(interpreter: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	interpreter.collect_garbage();
+	Ok(Value::Nil)
+}
+
+/// The number of bytes the interpreter currently has allocated on the heap
+fn memory_usage(_: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(ALLOCATED_BYTES.load(Ordering::Relaxed) as f64))
+}
+
+/// The number of live heap allocations, covering Lox values as well as the interpreter's own data
+fn object_count(_: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(ALLOCATED_BLOCKS.load(Ordering::Relaxed) as f64))
+}
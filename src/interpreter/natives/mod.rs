@@ -0,0 +1,161 @@
+use std::{cell::RefCell, io::BufRead, rc::Rc, time::{Duration, UNIX_EPOCH}};
+
+use super::{error::{ValueError, ValueResult}, values::{Arguments, Native, NativeFn, Value}, Interpreter};
+
+pub mod array;
+pub mod copy;
+pub mod exec;
+pub mod format;
+pub mod hash;
+pub mod json;
+pub mod math;
+pub mod memory;
+pub mod random;
+pub mod reflect;
+pub mod regex;
+pub mod time;
+
+impl Interpreter {
+	/// Define every native function in the global environment
+	pub fn define_natives(&mut self) {
+		self.define_native("clock", 0, clock);
+		self.define_native("millis", 0, millis);
+		self.define_native("nanos", 0, nanos);
+		self.define_native("readLine", 0, read_line);
+		self.define_native("eprint", 1, eprint);
+		self.define_native("sleep", 1, sleep);
+		self.define_native("assert", 2, assert);
+		self.define_native("error", 1, error);
+		self.define_native("args", 0, args);
+		self.define_native("stackTrace", 0, stack_trace);
+		self.define_math_natives();
+		self.define_random_natives();
+		self.define_json_natives();
+		self.define_regex_natives();
+		self.define_array_natives();
+		self.define_format_natives();
+		self.define_exec_natives();
+		self.define_time_natives();
+		self.define_hash_natives();
+		self.define_copy_natives();
+		self.define_memory_natives();
+		self.define_reflect_natives();
+	}
+
+	/// Define a single native function in the global environment
+	pub fn define_native(&mut self, name: &str, arity: usize, fn_call: NativeFn) {
+		self.globals.define(name.into(), Value::NativeFn(Rc::new(Native::new(name, arity, fn_call))));
+	}
+
+	/// Define a single native function taking at least `arity` arguments in the global environment
+	pub fn define_variadic_native(&mut self, name: &str, arity: usize, fn_call: NativeFn) {
+		self.globals.define(name.into(), Value::NativeFn(Rc::new(Native::variadic(name, arity, fn_call))));
+	}
+}
+
+/// Check that an argument to a native is a number
+pub fn expect_number(native: &str, value: &Value) -> ValueResult<f64> {
+	match value {
+		Value::Double(n) => Ok(*n),
+		_ => Err(ValueError::native(&format!("Argument to '{}' must be a number.", native)))
+	}
+}
+
+/// Check that an argument to a native is a string
+pub fn expect_string<'a>(native: &str, value: &'a Value) -> ValueResult<&'a str> {
+	match value {
+		Value::String(s) => Ok(s),
+		_ => Err(ValueError::native(&format!("Argument to '{}' must be a string.", native)))
+	}
+}
+
+/// The time elapsed since the unix epoch
+fn since_epoch() -> Duration {
+	std::time::SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.expect("Time went backwards")
+}
+
+/// The time elapsed since the unix epoch, in fractional seconds
+fn clock(_: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(since_epoch().as_secs_f64()))
+}
+
+/// The time elapsed since the unix epoch, in whole milliseconds
+fn millis(_: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(since_epoch().as_millis() as f64))
+}
+
+/// The time elapsed since the unix epoch, in nanoseconds. Lox numbers are doubles, so differences
+/// between two readings are only exact to a few hundred nanoseconds
+fn nanos(_: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(since_epoch().as_nanos() as f64))
+}
+
+/// Read a single line from standard input, without its line ending. Returns nil at the end of input
+fn read_line(_: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	let mut line = String::new();
+
+	let read = std::io::stdin().lock().read_line(&mut line)
+		.map_err(|e| ValueError::native(&format!("Could not read from standard input: {}.", e)))?;
+
+	if read == 0 {
+		return Ok(Value::Nil)
+	}
+
+	let trimmed = line.strip_suffix('\n').unwrap_or(&line);
+	let trimmed = trimmed.strip_suffix('\r').unwrap_or(trimmed);
+
+	Ok(Value::String(trimmed.into()))
+}
+
+/// Print a value to standard error, the diagnostic counterpart of the `print` statement
+fn eprint(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	eprintln!("{}", args[0]);
+
+	Ok(Value::Nil)
+}
+
+/// Block for the given number of milliseconds
+fn sleep(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let ms = expect_number("sleep", &args[0])?;
+
+	if ms < 0.0 || !ms.is_finite() {
+		return Err(ValueError::native("Argument to 'sleep' must be a finite, non-negative number."))
+	}
+
+	let duration = Duration::try_from_secs_f64(ms / 1000.0)
+		.map_err(|_| ValueError::native("Argument to 'sleep' is too long a time to sleep for."))?;
+
+	std::thread::sleep(duration);
+
+	Ok(Value::Nil)
+}
+
+/// Raise a runtime error with the given message if the condition is falsey
+fn assert(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	if !args[0].is_truthy() {
+		return Err(ValueError::native(&args[1].to_string()))
+	}
+
+	Ok(Value::Nil)
+}
+
+/// Raise a runtime error with the given message
+fn error(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	Err(ValueError::native(&args[0].to_string()))
+}
+
+/// The command-line arguments passed to the script, as an array of strings
+fn args(interpreter: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	let args = interpreter.script_args.iter()
+		.map(|a| Value::String(a.as_str().into()))
+		.collect();
+
+	Ok(Value::Array(Rc::new(RefCell::new(args))))
+}
+
+/// The active Lox calls as a string, one `at name (line n)` per line with the innermost call first
+fn stack_trace(interpreter: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	Ok(Value::String(interpreter.stack_trace().into()))
+}
@@ -0,0 +1,87 @@
+use std::time::UNIX_EPOCH;
+
+use crate::interpreter::{error::{ValueError, ValueResult}, values::{Arguments, Value}, Interpreter};
+
+use super::expect_number;
+
+/// A xorshift64* pseudo-random number generator. Not suitable for anything security related
+pub struct Rng(u64);
+
+impl Rng {
+	/// Create a generator from a seed. Any seed is accepted, zero is remapped since xorshift can't leave it
+	pub fn new(seed: u64) -> Self {
+		Self(if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed })
+	}
+
+	/// Create a generator seeded from the system clock
+	pub fn from_time() -> Self {
+		let nanos = std::time::SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_nanos() as u64)
+			.unwrap_or_default();
+
+		Self::new(nanos)
+	}
+
+	/// Generate the next 64 random bits
+	pub fn next_u64(&mut self) -> u64 {
+		self.0 ^= self.0 >> 12;
+		self.0 ^= self.0 << 25;
+		self.0 ^= self.0 >> 27;
+
+		self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+	}
+
+	/// Generate a number in `[0, 1)`
+	pub fn next_f64(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+}
+
+impl Interpreter {
+	/// Define the random number natives in the global environment
+	pub fn define_random_natives(&mut self) {
+		self.define_native("random", 0, random);
+		self.define_native("randomInt", 2, random_int);
+		self.define_native("randomSeed", 1, random_seed);
+	}
+}
+
+/// A random number in `[0, 1)`
+fn random(interpreter: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	Ok(Value::Double(interpreter.rng.next_f64()))
+}
+
+/// The largest integer up to which every integer is exactly representable as a Lox number
+const MAX_SAFE_INTEGER: f64 = 9007199254740992.0;
+
+/// A random integer between `lo` and `hi`, both inclusive
+fn random_int(interpreter: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let lo = expect_number("randomInt", &args[0])?;
+	let hi = expect_number("randomInt", &args[1])?;
+
+	if lo.fract() != 0.0 || hi.fract() != 0.0 {
+		return Err(ValueError::native("Arguments to 'randomInt' must be integers."))
+	}
+
+	// Past 2^53 not every integer is a number, and the span between the bounds could overflow
+	if lo.abs() > MAX_SAFE_INTEGER || hi.abs() > MAX_SAFE_INTEGER {
+		return Err(ValueError::native("Arguments to 'randomInt' must be between -2^53 and 2^53."))
+	}
+
+	if lo > hi {
+		return Err(ValueError::native("Lower bound of 'randomInt' must not exceed the upper bound."))
+	}
+
+	let span = (hi - lo) as u64 + 1;
+
+	Ok(Value::Double(lo + (interpreter.rng.next_u64() % span) as f64))
+}
+
+/// Reseed the generator, so that the following random numbers are reproducible
+fn random_seed(interpreter: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let seed = expect_number("randomSeed", &args[0])?;
+	interpreter.rng = Rng::new(seed.to_bits());
+
+	Ok(Value::Nil)
+}
@@ -0,0 +1,49 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::interpreter::{error::{ValueError, ValueResult}, values::{Arguments, Callable, Value}, Interpreter};
+
+impl Interpreter {
+	/// Define the reflection natives in the global environment
+	pub fn define_reflect_natives(&mut self) {
+		self.define_native("fields", 1, fields);
+		self.define_native("methods", 1, methods);
+		self.define_native("arity", 1, arity);
+	}
+}
+
+/// Sort a list of names into an array of strings, so that results don't depend on hash order
+fn names(mut names: Vec<String>) -> Value {
+	names.sort();
+	Value::Array(Rc::new(RefCell::new(names.into_iter().map(|name| Value::String(name.into())).collect())))
+}
+
+/// The names of an instance's fields, in sorted order
+fn fields(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	match &args[0] {
+		Value::Instance(instance) => Ok(names(instance.borrow().fields.keys().map(|k| k.to_string()).collect())),
+		_ => Err(ValueError::native("Argument to 'fields' must be an instance."))
+	}
+}
+
+/// The names of the methods of a class, or of an instance's class, in sorted order
+fn methods(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let class = match &args[0] {
+		Value::Class(class) => class.clone(),
+		Value::Instance(instance) => instance.borrow().class.clone(),
+		_ => return Err(ValueError::native("Argument to 'methods' must be a class or an instance."))
+	};
+
+	Ok(names(class.methods.keys().map(|k| k.to_string()).collect()))
+}
+
+/// The number of arguments a function, class or native takes, the minimum for variadic natives
+fn arity(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let n = match &args[0] {
+		Value::NativeFn(n) => n.arity(),
+		Value::Function(f) => f.arity(),
+		Value::Class(c) => c.arity(),
+		_ => return Err(ValueError::native("Argument to 'arity' must be a function or class."))
+	};
+
+	Ok(Value::Double(n as f64))
+}
@@ -0,0 +1,58 @@
+use std::{cell::RefCell, rc::Rc};
+
+use regex::Regex;
+
+use crate::interpreter::{error::{ValueError, ValueResult}, values::{Arguments, Value}, Interpreter};
+
+use super::expect_string;
+
+impl Interpreter {
+	/// Define the regular expression natives in the global environment
+	pub fn define_regex_natives(&mut self) {
+		self.define_native("regexMatch", 2, regex_match);
+		self.define_native("regexFind", 2, regex_find);
+		self.define_native("regexReplace", 3, regex_replace);
+	}
+}
+
+/// Compile the pattern argument of a regex native
+fn compile(native: &str, pattern: &Value) -> ValueResult<Regex> {
+	let pattern = expect_string(native, pattern)?;
+
+	Regex::new(pattern).map_err(|e| ValueError::native(&format!("Invalid pattern passed to '{}': {}", native, e)))
+}
+
+/// Whether the pattern matches anywhere in the string
+fn regex_match(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let regex = compile("regexMatch", &args[0])?;
+	let s = expect_string("regexMatch", &args[1])?;
+
+	Ok(Value::Boolean(regex.is_match(s)))
+}
+
+/// The captures of the first match as an array, the whole match first and nil for groups that didn't
+/// participate. Returns nil if there is no match
+fn regex_find(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let regex = compile("regexFind", &args[0])?;
+	let s = expect_string("regexFind", &args[1])?;
+
+	let captures = match regex.captures(s) {
+		Some(c) => c,
+		None => return Ok(Value::Nil)
+	};
+
+	let groups = captures.iter()
+		.map(|group| group.map(|m| Value::String(m.as_str().into())).unwrap_or(Value::Nil))
+		.collect();
+
+	Ok(Value::Array(Rc::new(RefCell::new(groups))))
+}
+
+/// Replace every match of the pattern. The replacement may refer to groups as `$1` or `${name}`
+fn regex_replace(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let regex = compile("regexReplace", &args[0])?;
+	let s = expect_string("regexReplace", &args[1])?;
+	let replacement = expect_string("regexReplace", &args[2])?;
+
+	Ok(Value::String(regex.replace_all(s, replacement).into_owned().into()))
+}
@@ -0,0 +1,220 @@
+use std::time::UNIX_EPOCH;
+
+use crate::interpreter::{error::{ValueError, ValueResult}, values::{Arguments, Value}, Interpreter};
+
+use super::{expect_number, expect_string};
+
+const DAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+const MONTHS: [&str; 12] = [
+	"January", "February", "March", "April", "May", "June",
+	"July", "August", "September", "October", "November", "December"
+];
+
+impl Interpreter {
+	/// Define the date and time natives in the global environment
+	pub fn define_time_natives(&mut self) {
+		self.define_native("now", 0, now);
+		self.define_native("formatTime", 2, format_time);
+		self.define_native("parseTime", 2, parse_time);
+	}
+}
+
+/// A broken-down UTC date and time
+struct DateTime {
+	year: i64,
+	month: u32,
+	day: u32,
+	hour: u32,
+	minute: u32,
+	second: u32
+}
+
+impl DateTime {
+	/// Break down a count of seconds since the unix epoch
+	fn from_timestamp(ts: i64) -> Self {
+		let days = ts.div_euclid(86400);
+		let secs = ts.rem_euclid(86400) as u32;
+		let (year, month, day) = civil_from_days(days);
+
+		Self { year, month, day, hour: secs / 3600, minute: secs / 60 % 60, second: secs % 60 }
+	}
+
+	fn timestamp(&self) -> i64 {
+		days_from_civil(self.year, self.month, self.day) * 86400
+			+ (self.hour * 3600 + self.minute * 60 + self.second) as i64
+	}
+
+	/// Days since the first of January, starting from zero
+	fn ordinal(&self) -> i64 {
+		days_from_civil(self.year, self.month, self.day) - days_from_civil(self.year, 1, 1)
+	}
+
+	/// Days since Monday, starting from zero
+	fn weekday(&self) -> usize {
+		// The epoch was a Thursday
+		(days_from_civil(self.year, self.month, self.day) + 3).rem_euclid(7) as usize
+	}
+}
+
+/// Convert days since the unix epoch to a (year, month, day) in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+	let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+	(year, month, day)
+}
+
+/// Convert a date in the proleptic Gregorian calendar to days since the unix epoch
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+	let y = if month <= 2 { year - 1 } else { year };
+	let era = y.div_euclid(400);
+	let yoe = y.rem_euclid(400);
+	let m = month as i64;
+	let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+	era * 146097 + doe - 719468
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+	match month {
+		2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+		2 => 28,
+		4 | 6 | 9 | 11 => 30,
+		_ => 31
+	}
+}
+
+/// The current time in seconds since the unix epoch
+fn now(_: &mut Interpreter, _: Arguments) -> ValueResult<Value> {
+	let v = std::time::SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.expect("Time went backwards")
+		.as_secs_f64();
+
+	Ok(Value::Double(v))
+}
+
+/// Format a timestamp as UTC with strftime-style directives: `%Y %y %m %d %e %H %M %S %j %a %A
+/// %b %B %s %%`
+fn format_time(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let ts = expect_number("formatTime", &args[0])?;
+	let fmt = expect_string("formatTime", &args[1])?;
+
+	if !ts.is_finite() {
+		return Err(ValueError::native("Timestamp passed to 'formatTime' must be finite."))
+	}
+
+	let ts = ts.floor() as i64;
+	let t = DateTime::from_timestamp(ts);
+
+	let mut out = String::new();
+	let mut chars = fmt.chars();
+
+	while let Some(c) = chars.next() {
+		if c != '%' {
+			out.push(c);
+			continue;
+		}
+
+		match chars.next() {
+			Some('Y') => out.push_str(&t.year.to_string()),
+			Some('y') => out.push_str(&format!("{:02}", t.year.rem_euclid(100))),
+			Some('m') => out.push_str(&format!("{:02}", t.month)),
+			Some('d') => out.push_str(&format!("{:02}", t.day)),
+			Some('e') => out.push_str(&format!("{:2}", t.day)),
+			Some('H') => out.push_str(&format!("{:02}", t.hour)),
+			Some('M') => out.push_str(&format!("{:02}", t.minute)),
+			Some('S') => out.push_str(&format!("{:02}", t.second)),
+			Some('j') => out.push_str(&format!("{:03}", t.ordinal() + 1)),
+			Some('a') => out.push_str(&DAYS[t.weekday()][..3]),
+			Some('A') => out.push_str(DAYS[t.weekday()]),
+			Some('b') => out.push_str(&MONTHS[t.month as usize - 1][..3]),
+			Some('B') => out.push_str(MONTHS[t.month as usize - 1]),
+			Some('s') => out.push_str(&ts.to_string()),
+			Some('%') => out.push('%'),
+			Some(d) => return Err(ValueError::native(&format!("Unknown time directive '%{}'.", d))),
+			None => return Err(ValueError::native("Incomplete time directive at end of format."))
+		}
+	}
+
+	Ok(Value::String(out.into()))
+}
+
+/// Parse a UTC time with strftime-style directives, `%Y %m %d %H %M %S %%`, into seconds since the
+/// unix epoch. Fields missing from the format default to the start of 1970
+fn parse_time(_: &mut Interpreter, args: Arguments) -> ValueResult<Value> {
+	let s = expect_string("parseTime", &args[0])?;
+	let fmt = expect_string("parseTime", &args[1])?;
+
+	let failed = || ValueError::native(&format!("Could not parse '{}' with format '{}'.", s, fmt));
+
+	let mut t = DateTime { year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0 };
+	let mut input = s.chars().peekable();
+	let mut directives = fmt.chars();
+
+	while let Some(c) = directives.next() {
+		if c != '%' {
+			if input.next() != Some(c) {
+				return Err(failed())
+			}
+			continue;
+		}
+
+		let directive = match directives.next() {
+			Some('%') => {
+				if input.next() != Some('%') {
+					return Err(failed())
+				}
+				continue;
+			},
+			Some(d @ ('Y' | 'm' | 'd' | 'H' | 'M' | 'S')) => d,
+			Some(d) => return Err(ValueError::native(&format!("Unknown time directive '%{}'.", d))),
+			None => return Err(ValueError::native("Incomplete time directive at end of format."))
+		};
+
+		// Years may be negative and take up to four digits, every other field takes up to two
+		let negative = directive == 'Y' && input.next_if_eq(&'-').is_some();
+		let max_digits = if directive == 'Y' { 4 } else { 2 };
+		let mut digits = String::new();
+
+		while digits.len() < max_digits {
+			match input.next_if(|c| c.is_ascii_digit()) {
+				Some(d) => digits.push(d),
+				None => break
+			}
+		}
+
+		let n: i64 = digits.parse().map_err(|_| failed())?;
+
+		match directive {
+			'Y' => t.year = if negative { -n } else { n },
+			'm' => t.month = n as u32,
+			'd' => t.day = n as u32,
+			'H' => t.hour = n as u32,
+			'M' => t.minute = n as u32,
+			_ => t.second = n as u32
+		}
+	}
+
+	if input.next().is_some() {
+		return Err(failed())
+	}
+
+	let valid = (1..=12).contains(&t.month)
+		&& t.day >= 1 && t.day <= days_in_month(t.year, t.month)
+		&& t.hour < 24 && t.minute < 60 && t.second < 60;
+
+	if !valid {
+		return Err(failed())
+	}
+
+	Ok(Value::Double(t.timestamp() as f64))
+}
@@ -0,0 +1,55 @@
+use super::Interpreter;
+
+/// What the interpreter did while running under `--stats`
+#[derive(Default)]
+pub struct Stats {
+	statements: usize,
+	calls: usize,
+	environments: usize,
+	/// Strings, arrays, instances, functions and classes made by the program's own expressions and
+	/// declarations, leaving out those returned by natives
+	values: usize,
+	/// The most environments enclosing one another, the globals included
+	peak_depth: usize,
+}
+
+impl Stats {
+	pub fn report(&self) -> String {
+		let mut out = String::from("Statistics:\n");
+
+		out.push_str(&format!("  statements executed:    {}\n", self.statements));
+		out.push_str(&format!("  function calls:         {}\n", self.calls));
+		out.push_str(&format!("  environments created:   {}\n", self.environments));
+		out.push_str(&format!("  values allocated:       {}\n", self.values));
+		out.push_str(&format!("  peak environment depth: {}", self.peak_depth));
+		out
+	}
+}
+
+impl Interpreter {
+	pub fn count_statement(&mut self) {
+		if let Some(stats) = &mut self.stats {
+			stats.statements += 1;
+		}
+	}
+
+	pub fn count_call(&mut self) {
+		if let Some(stats) = &mut self.stats {
+			stats.calls += 1;
+		}
+	}
+
+	pub fn count_value(&mut self) {
+		if let Some(stats) = &mut self.stats {
+			stats.values += 1;
+		}
+	}
+
+	/// Count the environment just made the current one
+	pub fn count_environment(&mut self) {
+		if let Some(stats) = &mut self.stats {
+			stats.environments += 1;
+			stats.peak_depth = stats.peak_depth.max(self.environment.depth());
+		}
+	}
+}
@@ -0,0 +1,177 @@
+use std::{cell::{OnceCell, RefCell}, fmt, hash::{Hash, Hasher}, ops::Deref, rc::Rc};
+
+use crate::scanner::token::Symbol;
+
+/// Joined strings shorter than this are copied right away, as the copy costs less than a rope node
+const ROPE_THRESHOLD: usize = 64;
+
+/// A Lox string. Cloning one only copies a pointer, a thin one so that a [`Value`] stays two words.
+///
+/// Concatenating long strings makes a rope, a node holding both sides, which is only copied into one
+/// string the first time it is read. Building a string piece by piece in a loop then copies each piece
+/// once, instead of copying everything built so far on every `+`
+///
+/// [`Value`]: super::values::Value
+#[derive(Clone)]
+pub struct LoxString(Rc<Repr>);
+
+enum Repr {
+	Flat(Box<str>),
+	/// A string literal, whose text the program already holds on to
+	Interned(Symbol),
+	Rope(Rope),
+}
+
+/// Two strings joined, see [`LoxString`]
+struct Rope {
+	len: usize,
+	/// The joined string, once it has been read
+	flat: OnceCell<Box<str>>,
+	/// The two sides, until the rope is flattened
+	parts: RefCell<Option<(LoxString, LoxString)>>,
+}
+
+impl LoxString {
+	/// The length in bytes
+	pub fn len(&self) -> usize {
+		match &*self.0 {
+			Repr::Flat(s) => s.len(),
+			Repr::Interned(s) => s.len(),
+			Repr::Rope(r) => r.len,
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn as_str(&self) -> &str {
+		match &*self.0 {
+			Repr::Flat(s) => s,
+			Repr::Interned(s) => s.as_str(),
+			Repr::Rope(r) => r.flat.get_or_init(|| r.flatten()),
+		}
+	}
+
+	/// Join two strings
+	pub fn concat(&self, other: &LoxString) -> LoxString {
+		let len = self.len() + other.len();
+
+		if other.is_empty() {
+			return self.clone()
+		}
+
+		if self.is_empty() {
+			return other.clone()
+		}
+
+		if len < ROPE_THRESHOLD {
+			let mut joined = String::with_capacity(len);
+			joined.push_str(self);
+			joined.push_str(other);
+			return joined.into()
+		}
+
+		LoxString(Rc::new(Repr::Rope(Rope { len, flat: OnceCell::new(), parts: RefCell::new(Some((self.clone(), other.clone()))) })))
+	}
+}
+
+impl Rope {
+	/// Copy the pieces of the rope into one string, dropping them once done. Ropes nest as deep as the
+	/// number of joins that built them, so they are walked without recursion
+	fn flatten(&self) -> Box<str> {
+		let mut joined = String::with_capacity(self.len);
+		let mut pending: Vec<LoxString> = self.parts.borrow().iter().flat_map(|(l, r)| [r.clone(), l.clone()]).collect();
+
+		while let Some(s) = pending.pop() {
+			match &*s.0 {
+				Repr::Flat(s) => joined.push_str(s),
+				Repr::Interned(s) => joined.push_str(s),
+				Repr::Rope(r) => match r.flat.get() {
+					Some(flat) => joined.push_str(flat),
+					None => pending.extend(r.parts.borrow().iter().flat_map(|(l, r)| [r.clone(), l.clone()])),
+				},
+			}
+		}
+
+		self.parts.borrow_mut().take();
+		joined.into()
+	}
+}
+
+impl Drop for Rope {
+	/// Drop the pieces one at a time, as dropping a deeply nested rope recursively overflows the stack
+	fn drop(&mut self) {
+		let mut pending: Vec<LoxString> = self.parts.get_mut().take().into_iter().flat_map(|(l, r)| [l, r]).collect();
+
+		while let Some(s) = pending.pop() {
+			if let Ok(Repr::Rope(mut r)) = Rc::try_unwrap(s.0) {
+				pending.extend(r.parts.get_mut().take().into_iter().flat_map(|(l, r)| [l, r]));
+			}
+		}
+	}
+}
+
+impl Deref for LoxString {
+	type Target = str;
+
+	fn deref(&self) -> &str {
+		self.as_str()
+	}
+}
+
+impl From<String> for LoxString {
+	fn from(s: String) -> Self {
+		LoxString(Rc::new(Repr::Flat(s.into())))
+	}
+}
+
+impl From<&str> for LoxString {
+	fn from(s: &str) -> Self {
+		LoxString(Rc::new(Repr::Flat(s.into())))
+	}
+}
+
+impl From<Symbol> for LoxString {
+	fn from(s: Symbol) -> Self {
+		LoxString(Rc::new(Repr::Interned(s)))
+	}
+}
+
+impl PartialEq for LoxString {
+	fn eq(&self, other: &Self) -> bool {
+		self.len() == other.len() && self.as_str() == other.as_str()
+	}
+}
+
+impl Eq for LoxString {}
+
+impl PartialOrd for LoxString {
+	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for LoxString {
+	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+		self.as_str().cmp(other.as_str())
+	}
+}
+
+impl Hash for LoxString {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.as_str().hash(state)
+	}
+}
+
+impl fmt::Display for LoxString {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self)
+	}
+}
+
+impl fmt::Debug for LoxString {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Debug::fmt(self.as_str(), f)
+	}
+}
@@ -0,0 +1,60 @@
+use std::{cell::{Cell, OnceCell}, rc::Rc};
+
+use crate::{log, statement::FunctionDecl, vm::{chunk::{Closure, Constant, Function}, compiler::Compiler}};
+
+/// How many times a function is tree-walked before it is compiled to bytecode
+pub const TIER_UP_CALLS: usize = 1000;
+
+/// A top-level function's way to the VM. It is tree-walked at first, and once called often enough is
+/// compiled, then run by the VM from then on. A function the compiler can't handle stays tree-walked
+#[derive(Default)]
+pub struct Tier {
+	calls: Cell<usize>,
+	/// Set when the function is compiled, to `None` if it couldn't be
+	compiled: OnceCell<Option<Rc<Closure>>>,
+}
+
+impl Tier {
+	/// Count a call of the function, compiling it if it has become hot. Returns the compiled function
+	/// to run in place of walking it, if there is one
+	pub fn enter(&self, declaration: &FunctionDecl) -> Option<Rc<Closure>> {
+		if let Some(compiled) = self.compiled.get() {
+			return compiled.clone()
+		}
+
+		let calls = self.calls.get() + 1;
+		self.calls.set(calls);
+
+		if calls < TIER_UP_CALLS {
+			return None
+		}
+
+		let compiled = match Compiler::compile_function(declaration) {
+			Ok(function) if function.upvalue_count == 0 && !creates_capturing_closures(&function) => {
+				log::verbose(&format!("Compiled '{}' to bytecode after {} calls", declaration.name.lexeme, calls));
+				Some(Rc::new(Closure { function: Rc::new(function), upvalues: Vec::new() }))
+			},
+			_ => {
+				log::verbose(&format!("Keeping '{}' tree-walked, it can't be compiled to bytecode", declaration.name.lexeme));
+				None
+			}
+		};
+
+		self.compiled.get_or_init(|| compiled).clone()
+	}
+
+	/// The compiled function, if the function has been compiled
+	pub fn compiled(&self) -> Option<Rc<Closure>> {
+		self.compiled.get().cloned().flatten()
+	}
+}
+
+/// Whether running a function can create a closure that captures variables. The collector doesn't
+/// trace the VM's closures, so one kept alive by a cycle through what it captured would never be
+/// freed, and such functions are left tree-walked
+fn creates_capturing_closures(function: &Function) -> bool {
+	function.chunk.constants.iter().any(|constant| match constant {
+		Constant::Function(f) => f.upvalue_count > 0 || creates_capturing_closures(f),
+		_ => false
+	})
+}
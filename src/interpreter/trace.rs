@@ -0,0 +1,89 @@
+use crate::{parser::expr::{AstPrinter, Expr}, statement::Statement};
+
+use super::{values::Value, Interpreter};
+
+/// How much of a run `--trace` reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Trace {
+	#[default]
+	Off,
+	/// Every statement as it starts, and the values of expression, print and var statements
+	Statements,
+	/// Statements, plus every expression with its value once evaluated
+	Expressions,
+}
+
+impl Interpreter {
+	/// Report a statement that is about to run
+	pub fn trace_statement(&mut self, statement: &Statement) {
+		if let Some(line) = statement.line() {
+			self.trace_line = line;
+		}
+
+		self.trace(&describe(statement));
+	}
+
+	/// Report the value a statement on the given line produced
+	pub fn trace_value(&mut self, line: Option<usize>, value: &Value) {
+		if let Some(line) = line {
+			self.trace_line = line;
+		}
+
+		self.trace(&format!("=> {}", value));
+	}
+
+	/// Report an expression and the value it evaluated to
+	pub fn trace_expr(&mut self, line: Option<usize>, description: &str, value: &Value) {
+		if let Some(line) = line {
+			self.trace_line = line;
+		}
+
+		self.trace(&format!("{} => {}", description, value));
+	}
+
+	/// Write a trace line to stderr, indented by the depth of the call stack
+	fn trace(&self, message: &str) {
+		eprintln!("[trace] [line {}] {}{}", self.trace_line, "  ".repeat(self.frames.len()), message);
+	}
+}
+
+/// A one-line summary of a statement, with expressions in prefix form
+fn describe(statement: &Statement) -> String {
+	let expr = |e: &Expr| AstPrinter::print(e);
+
+	match statement {
+		Statement::Print(p) => format!("print {}", expr(&p.0)),
+		Statement::Expression(e) => expr(&e.0),
+		Statement::Function(f) => format!("fun {}", f.name.lexeme),
+		Statement::Class(c) => format!("class {}", c.name.lexeme),
+		Statement::Return(r) => match &r.value {
+			Some(value) => format!("return {}", expr(value)),
+			None => "return".to_string()
+		},
+		Statement::If(i) => format!("if {}", expr(&i.condition)),
+		Statement::While(w) => format!("while {}", expr(&w.condition)),
+		Statement::For(f) => match &f.condition {
+			Some(condition) => format!("for {}", expr(condition)),
+			None => "for".to_string()
+		},
+		Statement::ForIn(f) => format!("for {} in {}", f.name.lexeme, expr(&f.iterable)),
+		Statement::Switch(s) => format!("switch {}", expr(&s.subject)),
+		Statement::Break() => "break".to_string(),
+		Statement::Continue() => "continue".to_string(),
+		Statement::Var(v) => {
+			let keyword = if v.is_const { "const" } else { "var" };
+
+			match &v.initializer {
+				Some(initializer) => format!("{} {} = {}", keyword, v.name.lexeme, expr(initializer)),
+				None => format!("{} {}", keyword, v.name.lexeme)
+			}
+		},
+		Statement::MultiVar(v) => {
+			let names: Vec<&str> = v.iter().map(|d| d.name.lexeme.as_str()).collect();
+			format!("var {}", names.join(", "))
+		},
+		Statement::Block(_) => "block".to_string(),
+		Statement::Import(i) => format!("import {} from {}", i.name.lexeme, i.path.lexeme),
+		Statement::Export(e) => format!("export {}", describe(&e.declaration)),
+	}
+}
@@ -1,11 +1,13 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::{BTreeMap, HashMap, HashSet}, rc::Rc, sync::Arc};
 
-use crate::{scanner::token::Token, statement::{environment::{EnvCell, Environment}, FunctionDecl}};
+use smallvec::SmallVec;
 
-use super::{error::{ValueError, ValueResult}, Interpreter};
+use crate::{scanner::token::{Symbol, Token}, utils::number, statement::{environment::{EnvCell, Environment}, FunctionDecl}, vm::{chunk::Closure, Vm}};
+
+use super::{check_call, error::{ValueError, ValueResult}, flow::{ControlFlow, ExecResult, TailCall, Unwind}, module::LoxModule, string::LoxString, tier::Tier, CallFrame, Interpreter};
 
 /// An enum representing all possible Lox values
-#[derive(PartialEq, Clone)]
+#[derive(Clone)]
 pub enum Value {
 	/// Lox Number
 	Double(f64),
@@ -13,18 +15,32 @@ pub enum Value {
 	Nil,
 	/// Lox Boolean
 	Boolean(bool),
-	/// Lox String
-	String(String),
+	/// Lox String, see [`LoxString`]
+	String(LoxString),
 	/// Lox Native Function/ In-built functions
-	NativeFn(Native),
+	NativeFn(Rc<Native>),
 	/// Lox user-defined functions
-	Function(LoxFunction),
-	/// Lox class
-	Class(LoxClass),
+	Function(Rc<LoxFunction>),
 	/// Lox class
-	Instance(LoxInstance)
+	Class(Rc<LoxClass>),
+	/// Lox class instance, shared by reference
+	Instance(Rc<RefCell<LoxInstance>>),
+	/// Lox module namespace
+	Module(Rc<LoxModule>),
+	/// Lox array, shared by reference
+	Array(Rc<RefCell<Vec<Value>>>),
+	/// Lox map from string keys to values, shared by reference
+	Map(Rc<RefCell<BTreeMap<String, Value>>>),
+	/// Lox range of numbers, counted out lazily as it is iterated
+	Range(Rc<LoxRange>),
+	/// Lox function compiled to bytecode, run by the VM
+	Closure(Rc<Closure>)
 }
 
+// Values are copied constantly, so every payload larger than a word sits behind a thin pointer,
+// strings included, leaving a value as a tag and a word
+const _: () = assert!(std::mem::size_of::<Value>() <= 16);
+
 #[derive(PartialEq, Clone)]
 pub struct ValueCell(pub Rc<RefCell<Value>>);
 
@@ -42,44 +58,72 @@ impl ValueCell {
 /// A trait to be implemented for any call-able Lox value
 pub trait Callable {
 	/// This defines the result of a Lox Value call
-	fn call(&mut self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> ValueResult<Value>;
+	fn call(&self, interpreter: &mut Interpreter, arguments: Arguments) -> ValueResult<Value>;
 	/// This defines the number of arguments, taken by a Lox Callable
 	fn arity(&self) -> usize;
+	/// Whether the callable accepts any number of arguments beyond its arity
+	fn variadic(&self) -> bool {
+		false
+	}
 	/// This defines the printed result of a Lox Callable Value
 	fn to_string(&self) -> String;
 }
 
-/// A struct representing Lox Native/ In-built functions
+/// The arguments of a call. Most calls pass only a few, which are kept inline instead of allocated
+pub type Arguments = SmallVec<[Value; 4]>;
 
-#[derive(PartialEq, Clone)]
+/// The signature of the Rust function backing a native. Errors raised without a token, see
+/// [`ValueError::native`], are reported at the call site
+pub type NativeFn = fn(&mut Interpreter, Arguments) -> ValueResult<Value>;
+
+/// A struct representing Lox Native/ In-built functions
+#[derive(Clone)]
 pub struct Native {
+	pub name: String,
 	arity: usize,
-	to_string: String,
-	fn_call: fn() -> Value
+	variadic: bool,
+	fn_call: NativeFn
+}
+
+impl PartialEq for Native {
+	/// Natives are equal if they are the same built-in
+	fn eq(&self, other: &Self) -> bool {
+		self.name == other.name
+	}
 }
 
 impl Native {
 	/// Create a new Native function
-	pub fn new(arity: usize, fn_call: fn() -> Value) -> Self{
+	pub fn new(name: &str, arity: usize, fn_call: NativeFn) -> Self{
 		Self {
+			name: name.to_string(),
 			arity,
+			variadic: false,
 			fn_call,
-			to_string: "<native fn>".to_string(),
 		}
 	}
+
+	/// Create a new Native function taking at least `arity` arguments
+	pub fn variadic(name: &str, arity: usize, fn_call: NativeFn) -> Self {
+		Self { variadic: true, ..Self::new(name, arity, fn_call) }
+	}
 }
 
 impl Callable for Native {
-	fn call(&mut self, _: &mut Interpreter, _: Vec<Value>) -> ValueResult<Value> {
-		Ok((self.fn_call)())
+	fn call(&self, interpreter: &mut Interpreter, arguments: Arguments) -> ValueResult<Value> {
+		(self.fn_call)(interpreter, arguments)
 	}
 
 	fn arity(&self) -> usize {
 		self.arity
 	}
 
+	fn variadic(&self) -> bool {
+		self.variadic
+	}
+
 	fn to_string(&self) -> String {
-		self.to_string.clone()
+		"<native fn>".to_string()
 	}
 }
 
@@ -87,10 +131,15 @@ impl Callable for Native {
 #[derive(Clone)]
 pub struct LoxFunction {
 	/// The associated function statement
-	declaration: FunctionDecl,
+	declaration: Arc<FunctionDecl>,
 	/// The closure/environment of the function
 	pub closure: EnvCell,
-	is_initializer: bool
+	is_initializer: bool,
+	/// The call count and bytecode of a top-level function, when hot functions are moved to the VM
+	pub tier: Option<Rc<Tier>>,
+	/// The function's native code, when `--jit` compiled it
+	#[cfg(feature = "jit")]
+	pub compiled: Option<crate::jit::Compiled>,
 }
 
 impl PartialEq for LoxFunction {
@@ -107,13 +156,29 @@ impl PartialEq for LoxFunction {
 
 impl LoxFunction {
 	/// Initialize a user-defined function
-	pub fn new(declaration: FunctionDecl, closure: EnvCell, is_initializer: bool) -> Self {
-		Self {declaration, closure, is_initializer}
+	pub fn new(declaration: Arc<FunctionDecl>, closure: EnvCell, is_initializer: bool) -> Self {
+		closure.track();
+		Self {declaration, closure, is_initializer, tier: None, #[cfg(feature = "jit")] compiled: None}
+	}
+
+	/// The function's bytecode, once it has been called often enough to be compiled
+	pub fn tiered(&self) -> Option<Rc<Closure>> {
+		self.tier.as_ref().and_then(|tier| tier.compiled())
+	}
+
+	/// Whether the function is a method bound to an instance, whose closure holds `this`
+	pub fn is_method(&self) -> bool {
+		self.closure.0.borrow().lookup(Symbol::this()).is_some()
+	}
+
+	/// The declared name of the function
+	pub fn name(&self) -> &str {
+		&self.declaration.name.lexeme
 	}
 
-	pub fn bind(&mut self, instance: LoxInstance) -> Self {
+	pub fn bind(&self, instance: Rc<RefCell<LoxInstance>>) -> Self {
 		let mut environment = Environment::with_enclosing(self.closure.clone());
-		environment.define("this".to_string(), Value::Instance(instance));
+		environment.define(Symbol::this(), Value::Instance(instance));
 		
 		return LoxFunction::new(self.declaration.clone(), EnvCell::with_environment(environment) , self.is_initializer)
 	}
@@ -130,83 +195,124 @@ impl Callable for LoxFunction {
 	}
 
 
-	fn call(&mut self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> ValueResult<Value> {
-		let mut environment = EnvCell::with_enclosing(&self.closure);
+	/// Run the function, then any functions it returns tail calls to in a loop, so that tail recursion
+	/// runs in constant Rust stack. A Lox function tail called takes over the caller's frame
+	fn call(&self, interpreter: &mut Interpreter, arguments: Arguments) -> ValueResult<Value> {
+		let mut result = self.execute(interpreter, arguments);
 
-		self.declaration.params.iter().zip(arguments.iter())
-			.map(|(param, arg)| {
-				(param.lexeme.clone(), arg.clone())
-			})
-			.for_each(|(k, v)| {
-				environment.define(k, v);
-			})
-		;
+		while let Err(Unwind::Flow(ControlFlow::TailCall(call))) = result {
+			let TailCall { callee, arguments, paren } = *call;
+
+			if let Err(e) = check_call(&callee, &arguments) {
+				result = Err(Unwind::from(e).at(&paren));
+				break
+			}
+
+			if let (Some(frame), Value::Function(_)) = (interpreter.frames.last_mut(), &callee) {
+				*frame = CallFrame::new(&callee, frame.line);
+			}
+
+			interpreter.count_call();
+			result = match &callee {
+				Value::Function(f) => f.execute(interpreter, arguments),
+				// Anything else runs in a frame of its own above the caller's, as it would if not tail called
+				_ => {
+					interpreter.frames.push(CallFrame::new(&callee, paren.line));
+					let result = interpreter.call_value(callee, arguments).map_err(Unwind::from);
+					interpreter.frames.pop();
+					result
+				},
+			}.map_err(|e| e.at(&paren));
+		}
+
+		result.map_err(Unwind::into_error)
+	}
+}
+
+impl LoxFunction {
+	/// Run the function's body once, leaving a tail call it returns for the caller to make
+	fn execute(&self, interpreter: &mut Interpreter, arguments: Arguments) -> ExecResult<Value> {
+		#[cfg(feature = "jit")]
+		if let Some(value) = self.compiled.and_then(|c| c.call(&arguments)) {
+			return Ok(value)
+		}
+
+		if let Some(closure) = self.tier.as_ref().and_then(|tier| tier.enter(&self.declaration)) {
+			return Vm::new(interpreter).call_tiered(closure, arguments).map_err(Unwind::from)
+		}
 
 		let previous = interpreter.environment.clone();
-		interpreter.environment = environment;
+		interpreter.environment = interpreter.environment_pool.take(&self.closure);
+		interpreter.count_environment();
+
+		{
+			let mut environment = interpreter.environment.0.borrow_mut();
+			environment.reserve(self.declaration.params.len());
+
+			for (param, argument) in self.declaration.params.iter().zip(arguments) {
+				environment.define(param.lexeme, argument);
+			}
+		}
 
-		let result = match interpreter.execute_statements(self.declaration.body.clone()) {
+		let result = match interpreter.execute_statements(&self.declaration.body) {
 			Err(value) => {
 				match value {
-					ValueError::Return(v) => {
+					Unwind::Flow(ControlFlow::Return(v)) => {
 						if self.is_initializer {
-							Ok(self.closure.get_at(0, "this".to_string()).value())
+							Ok(self.closure.get_at(0, Symbol::this()).value())
 						} else {
 							Ok(v)
 						}
 					},
-					k => {
-						// Ideally this should never happen but just in case it somehow does
-						k.error();
-						Err(ValueError::new(self.declaration.name.clone(), "Non-return value error detected in function call", ))
-					}
+					// Runtime errors raised in the body propagate to the caller unchanged
+					k => Err(k)
 				}
 			},
 			_ => {
 				if self.is_initializer {
-					Ok(self.closure.get_at(0, "this".to_string()).value())
+					Ok(self.closure.get_at(0, Symbol::this()).value())
 				} else {
 					Ok(Value::Nil)
 				}
 			}
 		};
 
-
-
-		interpreter.environment = previous;
+		interpreter.end_environment(previous);
 		result
 	}
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(Clone)]
 pub struct LoxClass {
 	pub name: String,
-	pub methods: HashMap<String, LoxFunction>
+	pub methods: HashMap<Symbol, LoxFunction>
 }
 
 impl LoxClass {
-	pub fn new(name: String, methods: HashMap<String, LoxFunction>) -> Self {
+	pub fn new(name: String, methods: HashMap<Symbol, LoxFunction>) -> Self {
 		Self { name, methods }
 	}
 
-	pub fn find_method(&self, name: &str) -> Option<LoxFunction> {
-		self.methods.get(name)
+	pub fn find_method(&self, name: Symbol) -> Option<LoxFunction> {
+		self.methods.get(&name)
 			.map(|m| m.clone())
 	}
 }
 
-impl Callable for LoxClass {
+/// Classes are called through the shared handle their values hold, which their instances keep
+impl Callable for Rc<LoxClass> {
 	fn arity(&self) -> usize {
-		self.find_method("init")
+		self.find_method(Symbol::init())
 			.map(|m| m.arity())
 			.unwrap_or(0)
 	}
 
-	fn call(&mut self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> ValueResult<Value> {
-		let instance = LoxInstance::new(self.clone());
+	fn call(&self, interpreter: &mut Interpreter, arguments: Arguments) -> ValueResult<Value> {
+		let instance = Rc::new(RefCell::new(LoxInstance::new(self.clone())));
+		interpreter.count_value();
 
-		if let Some(initializer) = self.methods.get_mut("init") {
-			return initializer.bind(instance.clone()).call(interpreter, arguments)
+		if let Some(initializer) = self.methods.get(&Symbol::init()) {
+			return initializer.bind(instance).call(interpreter, arguments)
 		}
 
 		// if let Some(mut initializer) = self.find_method("init") {
@@ -221,26 +327,28 @@ impl Callable for LoxClass {
 	}
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(Clone)]
 pub struct LoxInstance {
-	class: LoxClass,
-	fields: HashMap<String, Value>
+	pub class: Rc<LoxClass>,
+	pub fields: HashMap<Symbol, Value>
 }
 
 impl LoxInstance {
-	pub fn new(class: LoxClass) -> Self {
+	pub fn new(class: Rc<LoxClass>) -> Self {
 		Self { class, fields: HashMap::new() }
 	}
 
-	pub fn get(&self, name: Token) -> ValueResult<Value> {
-		let l = name.lexeme.clone();
+	/// Read a field of an instance, or one of its methods bound to it
+	pub fn get(instance: &Rc<RefCell<LoxInstance>>, name: Token) -> ValueResult<Value> {
+		let l = name.lexeme;
+		let this = instance.borrow();
 
-		match self.fields.get(&l) {
+		match this.fields.get(&l) {
 			Some(v) => return Ok(v.clone()),
 			_ => {
-				if let Some(mut method) = self.class.find_method(&name.lexeme) {
-					let v = method.bind(self.clone());
-					return Ok(Value::Function(v));
+				if let Some(method) = this.class.find_method(name.lexeme) {
+					let v = method.bind(instance.clone());
+					return Ok(Value::Function(Rc::new(v)));
 				}
 
 				Err(ValueError::new(name, &format!("Undefined property '{}'.", l)))
@@ -252,18 +360,82 @@ impl LoxInstance {
 	}
 
 	pub fn set(&mut self, name: &Token, value: Value){
-		let l = name.lexeme.clone();
+		let l = name.lexeme;
 		self.fields.insert(l, value);
 	}
 }
 
+impl PartialEq for LoxInstance {
+	/// An instance is only equal to itself
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self, other)
+	}
+}
+
 impl ToString for LoxInstance {
 	fn to_string(&self) -> String {
 		format!("{} instance", self.class.name)
 	}
 }
 
+/// The numbers counting up by one from the start, up to but not including the end
+#[derive(PartialEq, Clone)]
+pub struct LoxRange {
+	pub start: f64,
+	pub end: f64,
+}
+
+impl std::fmt::Display for LoxRange {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "<range {}..{}>", number::format(self.start), number::format(self.end))
+	}
+}
+
+/// The iteration protocol driving `for-in` loops, yielding the elements of an iterable Lox value
+pub enum LoxIterator {
+	/// The characters of a string, each yielded as a one-character string
+	Chars(std::vec::IntoIter<char>),
+	/// The elements of an array, read by position so that mutations during the loop are observed
+	Array(Rc<RefCell<Vec<Value>>>, usize),
+	/// The keys of a map in order, as they were when the loop started
+	Keys(std::vec::IntoIter<String>),
+	/// The numbers of a range, the next one to yield and the end
+	Range(f64, f64),
+}
+
+impl Iterator for LoxIterator {
+	type Item = Value;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		match self {
+			LoxIterator::Chars(chars) => chars.next().map(|c| Value::String(c.to_string().into())),
+			LoxIterator::Array(array, index) => {
+				let v = array.borrow().get(*index).cloned();
+				*index += 1;
+				v
+			},
+			LoxIterator::Keys(keys) => keys.next().map(|k| Value::String(k.into())),
+			LoxIterator::Range(next, end) => {
+				let v = (*next < *end).then_some(Value::Double(*next));
+				*next += 1.0;
+				v
+			},
+		}
+	}
+}
+
 impl Value {
+	/// Get an iterator over the elements of this value, or `None` if it is not iterable
+	pub fn iterate(&self) -> Option<LoxIterator> {
+		match self {
+			Value::String(s) => Some(LoxIterator::Chars(s.chars().collect::<Vec<_>>().into_iter())),
+			Value::Array(a) => Some(LoxIterator::Array(a.clone(), 0)),
+			Value::Map(m) => Some(LoxIterator::Keys(m.borrow().keys().cloned().collect::<Vec<_>>().into_iter())),
+			Value::Range(r) => Some(LoxIterator::Range(r.start, r.end)),
+			_ => None
+		}
+	}
+
 	/// Only the Lox boolean [`Value`] false and the Lox null/nill are falsy, every other is truthy
 	pub fn is_truthy(&self) -> bool {
 		match self {
@@ -273,16 +445,112 @@ impl Value {
 	}
 }
 
+impl PartialEq for Value {
+	/// Arrays and maps compare by contents, unless they are the same one, with the pairs already being
+	/// compared taken as equal so that self-containing ones don't recurse forever
+	fn eq(&self, other: &Self) -> bool {
+		equal(self, other, &mut HashSet::new())
+	}
+}
+
+/// Compare two values, `comparing` holding the addresses of the arrays and maps whose comparison is
+/// in progress further up
+fn equal(a: &Value, b: &Value, comparing: &mut HashSet<(usize, usize)>) -> bool {
+	match (a, b) {
+		(Value::Array(x), Value::Array(y)) => {
+			let pair = (Rc::as_ptr(x) as usize, Rc::as_ptr(y) as usize);
+			if Rc::ptr_eq(x, y) || !comparing.insert(pair) {
+				return true
+			}
+
+			let (x, y) = (x.borrow(), y.borrow());
+			let result = x.len() == y.len() && x.iter().zip(y.iter()).all(|(a, b)| equal(a, b, comparing));
+			comparing.remove(&pair);
+
+			result
+		},
+		(Value::Map(x), Value::Map(y)) => {
+			let pair = (Rc::as_ptr(x) as usize, Rc::as_ptr(y) as usize);
+			if Rc::ptr_eq(x, y) || !comparing.insert(pair) {
+				return true
+			}
+
+			let (x, y) = (x.borrow(), y.borrow());
+			let result = x.len() == y.len() && x.iter().zip(y.iter()).all(|((k, a), (l, b))| k == l && equal(a, b, comparing));
+			comparing.remove(&pair);
+
+			result
+		},
+		(Value::Double(x), Value::Double(y)) => x == y,
+		(Value::Nil, Value::Nil) => true,
+		(Value::Boolean(x), Value::Boolean(y)) => x == y,
+		(Value::String(x), Value::String(y)) => x == y,
+		(Value::NativeFn(x), Value::NativeFn(y)) => x == y,
+		(Value::Function(x), Value::Function(y)) => Rc::ptr_eq(x, y),
+		(Value::Class(x), Value::Class(y)) => Rc::ptr_eq(x, y),
+		(Value::Instance(x), Value::Instance(y)) => x == y,
+		(Value::Module(x), Value::Module(y)) => x == y,
+		(Value::Closure(x), Value::Closure(y)) => x == y,
+		(Value::Range(x), Value::Range(y)) => x == y,
+		_ => false
+	}
+}
+
+impl Value {
+	/// Write the value, `visiting` holding the addresses of the arrays and maps being written further
+	/// up, which are written as `[...]` and `{...}` when they contain themselves
+	fn write(&self, f: &mut std::fmt::Formatter<'_>, visiting: &mut HashSet<usize>) -> std::fmt::Result {
+		match self {
+			Value::Array(x) => {
+				let address = Rc::as_ptr(x) as usize;
+				if !visiting.insert(address) {
+					return write!(f, "[...]")
+				}
+
+				write!(f, "[")?;
+				for (i, v) in x.borrow().iter().enumerate() {
+					if i > 0 { write!(f, ", ")?; }
+					v.write(f, visiting)?;
+				}
+				visiting.remove(&address);
+
+				write!(f, "]")
+			},
+			Value::Map(x) => {
+				let address = Rc::as_ptr(x) as usize;
+				if !visiting.insert(address) {
+					return write!(f, "{{...}}")
+				}
+
+				write!(f, "{{")?;
+				for (i, (k, v)) in x.borrow().iter().enumerate() {
+					if i > 0 { write!(f, ", ")?; }
+					write!(f, "{}: ", k)?;
+					v.write(f, visiting)?;
+				}
+				visiting.remove(&address);
+
+				write!(f, "}}")
+			},
+			v => write!(f, "{}", v),
+		}
+	}
+}
+
 impl std::fmt::Display for Value {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		let as_str = match self {
 			Value::Boolean(x) => &x.to_string(),
-			Value::Double(x) => &format!("{}", x),
+			Value::Double(x) => &number::format(*x),
 			Value::Nil => "nil",
 			Value::NativeFn(x) => &format!("{}", x.to_string()),
 			Value::Function(x) => &format!("{}", x.to_string()),
 			Value::Class(x) => &x.to_string(),
-			Value::Instance(x) => &x.to_string(),
+			Value::Instance(x) => &x.borrow().to_string(),
+			Value::Module(x) => &x.to_string(),
+			Value::Closure(x) => &format!("<fn {}>", x.function.name),
+			Value::Range(x) => &x.to_string(),
+			Value::Array(_) | Value::Map(_) => return self.write(f, &mut HashSet::new()),
 			Value::String(x) => &x,
 		};
 
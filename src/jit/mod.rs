@@ -0,0 +1,707 @@
+use std::{collections::{HashMap, HashSet}, sync::Arc};
+
+use cranelift_codegen::{entity::EntityRef, ir::{condcodes::{FloatCC, IntCC}, types, AbiParam, Block, InstBuilder, MemFlags, StackSlotData, StackSlotKind, Value as IrValue}, settings::{self, Configurable}};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{FuncId, Linkage, Module};
+
+use crate::{interpreter::values::Value, log, parser::expr::{Expr, ExprLiteral}, scanner::token::{Symbol, TokenType}, statement::{FunctionDecl, Statement}};
+
+/// How deep compiled functions may call each other before handing the call back to the interpreter
+const MAX_DEPTH: i64 = 10_000;
+
+/// The signature shared by every compiled function: a pointer to the arguments, the depth of the
+/// call and a pointer to a status byte, set when the function bails out
+type Entry = unsafe extern "C" fn(*const f64, i64, *mut u8) -> f64;
+
+/// The type of a value in compiled code, which only handles numbers and booleans
+#[derive(Clone, Copy, PartialEq)]
+enum Type {
+	Number,
+	Bool,
+}
+
+/// A function compiled to native code
+#[derive(Clone, Copy)]
+pub struct Compiled {
+	code: *const u8,
+	arity: usize,
+}
+
+impl Compiled {
+	/// Run the native code. `None` when an argument isn't a number or the code bailed out, such as
+	/// on a division by zero, for the interpreter to run the call instead. Compiled functions have
+	/// no side effects, so running a call again is safe
+	pub fn call(&self, arguments: &[Value]) -> Option<Value> {
+		let args = arguments.iter()
+			.map(|a| match a { Value::Double(n) => Some(*n), _ => None })
+			.collect::<Option<Vec<f64>>>()?;
+
+		if args.len() != self.arity {
+			return None
+		}
+
+		let mut status = 0u8;
+
+		// SAFETY: the code was compiled with the `Entry` signature, and the module that owns it
+		// leaks its memory when dropped, so the pointer stays valid
+		let result = unsafe {
+			let entry: Entry = std::mem::transmute(self.code);
+			entry(args.as_ptr(), 0, &mut status)
+		};
+
+		(status == 0).then_some(Value::Double(result))
+	}
+}
+
+/// The functions of a program compiled to native code, keyed by their declaration.
+///
+/// Only top-level functions are compiled, and only when they work on numbers and booleans alone:
+/// parameters, locals, arithmetic, comparisons, `if`, `while`, `return`, and calls to themselves or
+/// to functions compiled before them. A function returning a call to itself jumps back to its start
+/// instead, so tail recursion never runs out of depth. A function is skipped if its name is declared more than once
+/// or assigned anywhere, since calls between compiled functions are bound when compiling
+#[derive(Clone, Default)]
+pub struct Jit {
+	functions: HashMap<Arc<FunctionDecl>, Compiled>,
+}
+
+impl Jit {
+	/// Compile what can be compiled of a program, in order. Fails only if the host isn't supported
+	pub fn compile(statements: &[Statement]) -> Result<Self, String> {
+		let mut flags = settings::builder();
+		flags.set("use_colocated_libcalls", "false").map_err(|e| e.to_string())?;
+		flags.set("is_pic", "false").map_err(|e| e.to_string())?;
+		flags.set("opt_level", "speed").map_err(|e| e.to_string())?;
+
+		let isa = cranelift_native::builder()
+			.map_err(|e| e.to_string())?
+			.finish(settings::Flags::new(flags))
+			.map_err(|e| e.to_string())?;
+
+		let mut module = JITModule::new(JITBuilder::with_isa(isa, cranelift_module::default_libcall_names()));
+		let pointer = module.target_config().pointer_type();
+
+		let mut signature = module.make_signature();
+		signature.params.push(AbiParam::new(pointer));
+		signature.params.push(AbiParam::new(types::I64));
+		signature.params.push(AbiParam::new(pointer));
+		signature.returns.push(AbiParam::new(types::F64));
+
+		let declared = top_level_names(statements);
+		let mut assigned = HashSet::new();
+		assigned_names(statements, &mut assigned);
+
+		let mut callees: HashMap<Symbol, (FuncId, usize)> = HashMap::new();
+		let mut compiled = Vec::new();
+		let mut context = module.make_context();
+
+		for statement in statements {
+			let Statement::Function(f) = statement else { continue };
+			let name = f.name.lexeme;
+
+			if declared.get(&name).copied().unwrap_or(0) > 1 || assigned.contains(&name) {
+				log::verbose(&format!("Interpreting {}, it is declared more than once or reassigned", name));
+				continue;
+			}
+
+			let id = module.declare_function(&format!("lox_{}_{}", name, compiled.len()), Linkage::Local, &signature)
+				.map_err(|e| e.to_string())?;
+
+			// Declared before the body, so the function can call itself
+			callees.insert(name, (id, f.params.len()));
+			context.func.signature = signature.clone();
+
+			let mut builder_context = FunctionBuilderContext::new();
+			let result = FunctionCompiler::compile(f, &mut module, &callees, &mut context.func, &mut builder_context, pointer);
+
+			match result.and_then(|_| module.define_function(id, &mut context).map_err(|e| e.to_string())) {
+				Ok(_) => {
+					log::verbose(&format!("Compiled {} to native code", name));
+					compiled.push((f.clone(), id));
+				},
+				Err(reason) => {
+					log::verbose(&format!("Interpreting {}, {}", name, reason));
+					callees.remove(&name);
+				}
+			}
+
+			module.clear_context(&mut context);
+		}
+
+		module.finalize_definitions().map_err(|e| e.to_string())?;
+
+		let functions = compiled.into_iter()
+			.map(|(f, id)| {
+				let arity = f.params.len();
+				(f, Compiled { code: module.get_finalized_function(id), arity })
+			})
+			.collect();
+
+		Ok(Self { functions })
+	}
+
+	/// The native code for a function declaration, if it was compiled
+	pub fn get(&self, declaration: &FunctionDecl) -> Option<Compiled> {
+		self.functions.get(declaration).copied()
+	}
+}
+
+/// How many times each name is declared at the top level
+fn top_level_names(statements: &[Statement]) -> HashMap<Symbol, usize> {
+	let mut names = HashMap::new();
+
+	for statement in statements {
+		let tokens = match statement {
+			Statement::Var(v) => vec![v.name.clone()],
+			Statement::MultiVar(v) => v.iter().map(|d| d.name.clone()).collect(),
+			Statement::Function(f) => vec![f.name.clone()],
+			Statement::Class(c) => vec![c.name.clone()],
+			Statement::Import(i) => vec![i.name.clone()],
+			Statement::Export(e) => e.names(),
+			_ => vec![]
+		};
+
+		for token in tokens {
+			*names.entry(token.lexeme).or_insert(0) += 1;
+		}
+	}
+
+	names
+}
+
+/// Collect every name assigned anywhere in the program, whatever scope it is in
+fn assigned_names(statements: &[Statement], names: &mut HashSet<Symbol>) {
+	for statement in statements {
+		match statement {
+			Statement::Print(p) => assigned_in(&p.0, names),
+			Statement::Expression(e) => assigned_in(&e.0, names),
+			Statement::Function(f) => assigned_names(&f.body, names),
+			Statement::Class(c) => c.methods.iter().for_each(|m| assigned_names(&m.body, names)),
+			Statement::Return(r) => if let Some(value) = &r.value { assigned_in(value, names) },
+			Statement::If(i) => {
+				assigned_in(&i.condition, names);
+				assigned_names(std::slice::from_ref(&*i.then_branch), names);
+
+				if let Some(else_branch) = &i.else_branch {
+					assigned_names(std::slice::from_ref(&**else_branch), names);
+				}
+			},
+			Statement::While(w) => {
+				assigned_in(&w.condition, names);
+				assigned_names(std::slice::from_ref(&*w.body), names);
+			},
+			Statement::For(f) => {
+				if let Some(initializer) = &f.initializer {
+					assigned_names(std::slice::from_ref(&**initializer), names);
+				}
+
+				f.condition.iter().chain(&f.increment).for_each(|e| assigned_in(e, names));
+				assigned_names(std::slice::from_ref(&*f.body), names);
+			},
+			Statement::ForIn(f) => {
+				assigned_in(&f.iterable, names);
+				assigned_names(std::slice::from_ref(&*f.body), names);
+			},
+			Statement::Switch(s) => {
+				assigned_in(&s.subject, names);
+
+				for case in &s.cases {
+					case.values.iter().for_each(|v| assigned_in(v, names));
+					assigned_names(&case.body.statements, names);
+				}
+
+				if let Some(default) = &s.default {
+					assigned_names(&default.statements, names);
+				}
+			},
+			Statement::Var(v) => if let Some(initializer) = &v.initializer { assigned_in(initializer, names) },
+			Statement::MultiVar(v) => v.iter().filter_map(|d| d.initializer.as_ref()).for_each(|i| assigned_in(i, names)),
+			Statement::Block(b) => assigned_names(&b.statements, names),
+			Statement::Export(e) => assigned_names(std::slice::from_ref(&*e.declaration), names),
+			Statement::Break() | Statement::Continue() | Statement::Import(_) => {}
+		}
+	}
+}
+
+fn assigned_in(expr: &Expr, names: &mut HashSet<Symbol>) {
+	match expr {
+		Expr::Assignment(a) => {
+			names.insert(a.name.lexeme);
+			assigned_in(&a.value, names);
+		},
+		Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) => {},
+		Expr::Call(c) => {
+			assigned_in(&c.callee, names);
+			c.arguments.iter().for_each(|a| assigned_in(a, names));
+		},
+		Expr::Binary(b) => {
+			assigned_in(&b.left, names);
+			assigned_in(&b.right, names);
+		},
+		Expr::Logical(l) => {
+			assigned_in(&l.left, names);
+			assigned_in(&l.right, names);
+		},
+		Expr::Grouping(g) => assigned_in(&g.0, names),
+		Expr::Unary(u) => assigned_in(&u.right, names),
+		Expr::Get(g) => assigned_in(&g.object, names),
+		Expr::Set(s) => {
+			assigned_in(&s.object, names);
+			assigned_in(&s.value, names);
+		},
+		Expr::Block(b) => {
+			assigned_names(&b.statements, names);
+
+			if let Some(value) = &b.value {
+				assigned_in(value, names);
+			}
+		},
+		Expr::If(i) => {
+			assigned_in(&i.condition, names);
+			assigned_in(&i.then_branch, names);
+
+			if let Some(else_branch) = &i.else_branch {
+				assigned_in(else_branch, names);
+			}
+		},
+		Expr::Array(a) => a.elements.iter().for_each(|e| assigned_in(e, names)),
+		Expr::Index(i) => {
+			assigned_in(&i.object, names);
+			assigned_in(&i.index, names);
+		},
+		Expr::IndexSet(i) => {
+			assigned_in(&i.object, names);
+			assigned_in(&i.index, names);
+			assigned_in(&i.value, names);
+		},
+	}
+}
+
+/// Translates the body of one function to cranelift IR. Anything outside the supported subset fails
+/// with the reason, and the function is left to the interpreter
+struct FunctionCompiler<'a> {
+	builder: FunctionBuilder<'a>,
+	module: &'a mut JITModule,
+	callees: &'a HashMap<Symbol, (FuncId, usize)>,
+	/// The name of the function being compiled, and the variables of its parameters
+	name: Symbol,
+	params: Vec<Variable>,
+	scopes: Vec<HashMap<Symbol, (Variable, Type)>>,
+	variables: usize,
+	depth: IrValue,
+	status: IrValue,
+	/// Where the function bails out, setting the status byte
+	bail: Block,
+	/// The start of the body, where self tail calls jump back to
+	body: Block,
+	pointer: types::Type,
+}
+
+impl<'a> FunctionCompiler<'a> {
+	fn compile(
+		f: &FunctionDecl,
+		module: &'a mut JITModule,
+		callees: &'a HashMap<Symbol, (FuncId, usize)>,
+		func: &'a mut cranelift_codegen::ir::Function,
+		builder_context: &'a mut FunctionBuilderContext,
+		pointer: types::Type,
+	) -> Result<(), String> {
+		let mut builder = FunctionBuilder::new(func, builder_context);
+
+		let entry = builder.create_block();
+		builder.append_block_params_for_function_params(entry);
+		builder.switch_to_block(entry);
+		builder.seal_block(entry);
+
+		let params = builder.block_params(entry).to_vec();
+		let (args, depth, status) = (params[0], params[1], params[2]);
+		let bail = builder.create_block();
+		let body = builder.create_block();
+
+		let mut compiler = Self { builder, module, callees, name: f.name.lexeme, params: Vec::new(), scopes: vec![HashMap::new()], variables: 0, depth, status, bail, body, pointer };
+
+		for (i, param) in f.params.iter().enumerate() {
+			let value = compiler.builder.ins().load(types::F64, MemFlags::trusted(), args, (i * 8) as i32);
+			let variable = compiler.declare(param.lexeme, value, Type::Number);
+			compiler.params.push(variable);
+		}
+
+		let too_deep = compiler.builder.ins().icmp_imm(IntCC::SignedGreaterThanOrEqual, depth, MAX_DEPTH);
+		compiler.builder.ins().brif(too_deep, bail, &[], body, &[]);
+		compiler.builder.switch_to_block(body);
+
+		for statement in &f.body {
+			compiler.statement(statement)?;
+		}
+
+		// Only sealed now that every self tail call jumping back to it is known
+		compiler.builder.seal_block(body);
+
+		// Falling off the end returns nil, which only the interpreter can produce
+		compiler.builder.ins().jump(bail, &[]);
+
+		compiler.builder.switch_to_block(bail);
+		compiler.builder.seal_block(bail);
+		let one = compiler.builder.ins().iconst(types::I8, 1);
+		compiler.builder.ins().store(MemFlags::trusted(), one, status, 0);
+		let zero = compiler.builder.ins().f64const(0.0);
+		compiler.builder.ins().return_(&[zero]);
+
+		compiler.builder.finalize();
+		Ok(())
+	}
+
+	fn declare(&mut self, name: Symbol, value: IrValue, ty: Type) -> Variable {
+		let variable = Variable::new(self.variables);
+		self.variables += 1;
+
+		let ir_type = match ty {
+			Type::Number => types::F64,
+			Type::Bool => types::I8,
+		};
+
+		self.builder.declare_var(variable, ir_type);
+		self.builder.def_var(variable, value);
+		self.scopes.last_mut().expect("A scope is always open").insert(name, (variable, ty));
+		variable
+	}
+
+	fn lookup(&self, name: Symbol) -> Option<(Variable, Type)> {
+		self.scopes.iter().rev().find_map(|scope| scope.get(&name).copied())
+	}
+
+	/// Continue in a fresh block after an instruction that ends one, the code that follows is
+	/// unreachable
+	fn after_terminator(&mut self) {
+		let block = self.builder.create_block();
+		self.builder.switch_to_block(block);
+		self.builder.seal_block(block);
+	}
+
+	/// Bail out when a condition holds
+	fn bail_if(&mut self, condition: IrValue) {
+		let next = self.builder.create_block();
+		self.builder.ins().brif(condition, self.bail, &[], next, &[]);
+		self.builder.switch_to_block(next);
+		self.builder.seal_block(next);
+	}
+
+	fn condition(&mut self, expr: &Expr) -> Result<IrValue, String> {
+		match self.expr(expr)? {
+			(value, Type::Bool) => Ok(value),
+			(_, Type::Number) => Err("a condition isn't a comparison".to_string())
+		}
+	}
+
+	fn number(&mut self, expr: &Expr) -> Result<IrValue, String> {
+		match self.expr(expr)? {
+			(value, Type::Number) => Ok(value),
+			(_, Type::Bool) => Err("a boolean is used as a number".to_string())
+		}
+	}
+
+	fn statement(&mut self, statement: &Statement) -> Result<(), String> {
+		match statement {
+			Statement::Expression(e) => {
+				self.expr(&e.0)?;
+			},
+			Statement::Var(v) => {
+				let initializer = v.initializer.as_ref().ok_or("a variable starts as nil")?;
+				let (value, ty) = self.expr(initializer)?;
+				self.declare(v.name.lexeme, value, ty);
+			},
+			Statement::MultiVar(declarations) => {
+				for v in declarations {
+					self.statement(&Statement::Var(v.clone()))?;
+				}
+			},
+			Statement::Block(b) => {
+				self.scopes.push(HashMap::new());
+
+				for statement in &b.statements {
+					self.statement(statement)?;
+				}
+
+				self.scopes.pop();
+			},
+			Statement::If(i) => {
+				let condition = self.condition(&i.condition)?;
+				let then_block = self.builder.create_block();
+				let else_block = self.builder.create_block();
+				let merge = self.builder.create_block();
+
+				self.builder.ins().brif(condition, then_block, &[], else_block, &[]);
+				self.builder.seal_block(then_block);
+				self.builder.seal_block(else_block);
+
+				self.builder.switch_to_block(then_block);
+				self.statement(&i.then_branch)?;
+				self.builder.ins().jump(merge, &[]);
+
+				self.builder.switch_to_block(else_block);
+
+				if let Some(else_branch) = &i.else_branch {
+					self.statement(else_branch)?;
+				}
+
+				self.builder.ins().jump(merge, &[]);
+				self.builder.switch_to_block(merge);
+				self.builder.seal_block(merge);
+			},
+			Statement::While(w) => {
+				let header = self.builder.create_block();
+				let body = self.builder.create_block();
+				let exit = self.builder.create_block();
+
+				self.builder.ins().jump(header, &[]);
+				self.builder.switch_to_block(header);
+
+				let condition = self.condition(&w.condition)?;
+				self.builder.ins().brif(condition, body, &[], exit, &[]);
+				self.builder.seal_block(body);
+
+				self.builder.switch_to_block(body);
+				self.statement(&w.body)?;
+				self.builder.ins().jump(header, &[]);
+				self.builder.seal_block(header);
+
+				self.builder.switch_to_block(exit);
+				self.builder.seal_block(exit);
+			},
+			Statement::For(f) => {
+				self.scopes.push(HashMap::new());
+
+				if let Some(initializer) = &f.initializer {
+					self.statement(initializer)?;
+				}
+
+				let header = self.builder.create_block();
+				let body = self.builder.create_block();
+				let exit = self.builder.create_block();
+
+				self.builder.ins().jump(header, &[]);
+				self.builder.switch_to_block(header);
+
+				match &f.condition {
+					Some(condition) => {
+						let condition = self.condition(condition)?;
+						self.builder.ins().brif(condition, body, &[], exit, &[]);
+					},
+					None => {
+						self.builder.ins().jump(body, &[]);
+					}
+				}
+
+				self.builder.seal_block(body);
+
+				self.builder.switch_to_block(body);
+				self.statement(&f.body)?;
+
+				if let Some(increment) = &f.increment {
+					self.expr(increment)?;
+				}
+
+				self.builder.ins().jump(header, &[]);
+				self.builder.seal_block(header);
+
+				self.builder.switch_to_block(exit);
+				self.builder.seal_block(exit);
+				self.scopes.pop();
+			},
+			Statement::Return(r) => {
+				match &r.value {
+					Some(Expr::Call(c)) if self.is_self_call(c) => {
+						let mut arguments = Vec::new();
+
+						for argument in &c.arguments {
+							arguments.push(self.number(argument)?);
+						}
+
+						// Every argument is evaluated before any parameter is rebound
+						for (variable, argument) in self.params.clone().into_iter().zip(arguments) {
+							self.builder.def_var(variable, argument);
+						}
+
+						self.builder.ins().jump(self.body, &[]);
+					},
+					Some(value) => {
+						let value = self.number(value)?;
+						self.builder.ins().return_(&[value]);
+					},
+					None => {
+						self.builder.ins().jump(self.bail, &[]);
+					}
+				}
+
+				self.after_terminator();
+			},
+			Statement::Print(_) => return Err("'print' has side effects".to_string()),
+			Statement::Function(_) => return Err("it declares a function".to_string()),
+			Statement::Class(_) => return Err("it declares a class".to_string()),
+			Statement::ForIn(_) => return Err("'for in' isn't supported".to_string()),
+			Statement::Switch(_) => return Err("'switch' isn't supported".to_string()),
+			Statement::Break() | Statement::Continue() => return Err("'break' and 'continue' aren't supported".to_string()),
+			Statement::Import(_) | Statement::Export(_) => return Err("it uses modules".to_string()),
+		}
+
+		Ok(())
+	}
+
+	fn expr(&mut self, expr: &Expr) -> Result<(IrValue, Type), String> {
+		let result = match expr {
+			Expr::Literal(ExprLiteral::NUMBER(n)) => (self.builder.ins().f64const(*n), Type::Number),
+			Expr::Literal(ExprLiteral::True) => (self.builder.ins().iconst(types::I8, 1), Type::Bool),
+			Expr::Literal(ExprLiteral::False) => (self.builder.ins().iconst(types::I8, 0), Type::Bool),
+			Expr::Literal(_) => return Err("it uses strings or nil".to_string()),
+			Expr::Grouping(g) => self.expr(&g.0)?,
+			Expr::Unary(u) => match u.operator.token_type {
+				TokenType::MINUS => {
+					let right = self.number(&u.right)?;
+					(self.builder.ins().fneg(right), Type::Number)
+				},
+				_ => {
+					let right = self.condition(&u.right)?;
+					(self.builder.ins().icmp_imm(IntCC::Equal, right, 0), Type::Bool)
+				}
+			},
+			Expr::Binary(b) => {
+				let (left, left_type) = self.expr(&b.left)?;
+				let (right, right_type) = self.expr(&b.right)?;
+
+				if left_type != right_type {
+					return Err("an operator mixes numbers and booleans".to_string())
+				}
+
+				let comparison = match b.operator.token_type {
+					TokenType::GREATER => Some(FloatCC::GreaterThan),
+					TokenType::GREATER_EQUAL => Some(FloatCC::GreaterThanOrEqual),
+					TokenType::LESS => Some(FloatCC::LessThan),
+					TokenType::LESS_EQUAL => Some(FloatCC::LessThanOrEqual),
+					_ => None
+				};
+
+				match (&b.operator.token_type, left_type) {
+					(TokenType::EQUAL_EQUAL, Type::Bool) => (self.builder.ins().icmp(IntCC::Equal, left, right), Type::Bool),
+					(TokenType::BANG_EQUAL, Type::Bool) => (self.builder.ins().icmp(IntCC::NotEqual, left, right), Type::Bool),
+					(TokenType::EQUAL_EQUAL, Type::Number) => (self.builder.ins().fcmp(FloatCC::Equal, left, right), Type::Bool),
+					(TokenType::BANG_EQUAL, Type::Number) => (self.builder.ins().fcmp(FloatCC::NotEqual, left, right), Type::Bool),
+					(_, Type::Bool) => return Err("arithmetic on booleans".to_string()),
+					_ if comparison.is_some() => {
+						let cc = comparison.expect("Checked by the guard");
+						(self.builder.ins().fcmp(cc, left, right), Type::Bool)
+					},
+					(TokenType::PLUS, _) => (self.builder.ins().fadd(left, right), Type::Number),
+					(TokenType::MINUS, _) => (self.builder.ins().fsub(left, right), Type::Number),
+					(TokenType::STAR, _) => (self.builder.ins().fmul(left, right), Type::Number),
+					(TokenType::SLASH, _) => {
+						// The interpreter reports the division by zero
+						let zero = self.builder.ins().f64const(0.0);
+						let is_zero = self.builder.ins().fcmp(FloatCC::Equal, right, zero);
+						self.bail_if(is_zero);
+						(self.builder.ins().fdiv(left, right), Type::Number)
+					},
+					_ => return Err(format!("the operator '{}' isn't supported", b.operator.lexeme))
+				}
+			},
+			Expr::Logical(l) => {
+				let left = self.condition(&l.left)?;
+				let right_block = self.builder.create_block();
+				let merge = self.builder.create_block();
+				self.builder.append_block_param(merge, types::I8);
+
+				if l.operator.token_type == TokenType::OR {
+					self.builder.ins().brif(left, merge, &[left], right_block, &[]);
+				} else {
+					self.builder.ins().brif(left, right_block, &[], merge, &[left]);
+				}
+
+				self.builder.seal_block(right_block);
+				self.builder.switch_to_block(right_block);
+				let right = self.condition(&l.right)?;
+				self.builder.ins().jump(merge, &[right]);
+
+				self.builder.switch_to_block(merge);
+				self.builder.seal_block(merge);
+				(self.builder.block_params(merge)[0], Type::Bool)
+			},
+			Expr::Variable(v) => {
+				let (variable, ty) = self.lookup(v.name.lexeme)
+					.ok_or_else(|| format!("it reads the global '{}'", v.name.lexeme))?;
+
+				(self.builder.use_var(variable), ty)
+			},
+			Expr::Assignment(a) => {
+				let (variable, ty) = self.lookup(a.name.lexeme)
+					.ok_or_else(|| format!("it assigns the global '{}'", a.name.lexeme))?;
+
+				let (value, value_type) = self.expr(&a.value)?;
+
+				if value_type != ty {
+					return Err(format!("'{}' changes type", a.name.lexeme))
+				}
+
+				self.builder.def_var(variable, value);
+				(value, ty)
+			},
+			Expr::Call(c) => self.call(c)?,
+			Expr::Get(_) | Expr::Set(_) | Expr::This(_) => return Err("it uses objects".to_string()),
+			Expr::Array(_) | Expr::Index(_) | Expr::IndexSet(_) => return Err("it uses arrays".to_string()),
+			Expr::Block(_) | Expr::If(_) => return Err("block and 'if' expressions aren't supported".to_string()),
+		};
+
+		Ok(result)
+	}
+
+	/// Whether a call is to the function being compiled, with an argument for each parameter
+	fn is_self_call(&self, c: &crate::parser::expr::ExprCall) -> bool {
+		matches!(&*c.callee, Expr::Variable(callee) if callee.name.lexeme == self.name && self.lookup(self.name).is_none())
+			&& c.arguments.len() == self.params.len()
+	}
+
+	/// Call another compiled function, bailing out too if it bails out
+	fn call(&mut self, c: &crate::parser::expr::ExprCall) -> Result<(IrValue, Type), String> {
+		let Expr::Variable(callee) = &*c.callee else {
+			return Err("it calls something other than a function by name".to_string())
+		};
+
+		let name = callee.name.lexeme;
+
+		let (id, arity) = match self.lookup(name) {
+			Some(_) => None,
+			None => self.callees.get(&name).copied()
+		}.ok_or_else(|| format!("it calls '{}', which isn't compiled", name))?;
+
+		if arity != c.arguments.len() {
+			return Err(format!("it calls '{}' with the wrong number of arguments", name))
+		}
+
+		let mut arguments = Vec::new();
+
+		for argument in &c.arguments {
+			arguments.push(self.number(argument)?);
+		}
+
+		let args = if arguments.is_empty() {
+			self.builder.ins().iconst(self.pointer, 0)
+		} else {
+			let slot = self.builder.create_sized_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, (arguments.len() * 8) as u32, 3));
+
+			for (i, argument) in arguments.into_iter().enumerate() {
+				self.builder.ins().stack_store(argument, slot, (i * 8) as i32);
+			}
+
+			self.builder.ins().stack_addr(self.pointer, slot, 0)
+		};
+
+		let function = self.module.declare_func_in_func(id, self.builder.func);
+		let depth = self.builder.ins().iadd_imm(self.depth, 1);
+		let call = self.builder.ins().call(function, &[args, depth, self.status]);
+		let result = self.builder.inst_results(call)[0];
+
+		let status = self.builder.ins().load(types::I8, MemFlags::trusted(), self.status, 0);
+		self.bail_if(status);
+
+		Ok((result, Type::Number))
+	}
+}
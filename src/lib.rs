@@ -0,0 +1,19 @@
+//! The Lox interpreter as a library, used by the `codecrafters-interpreter` binary and the
+//! benchmarks in `benches/`
+
+pub mod cli;
+pub mod printer;
+pub mod scanner;
+pub mod utils;
+pub mod parser;
+pub mod error;
+pub mod interpreter;
+pub mod statement;
+pub mod resolver;
+pub mod linter;
+pub mod optimizer;
+pub mod visitor;
+pub mod vm;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod log;
@@ -0,0 +1,72 @@
+use crate::{error::{LoxError, Severity}, scanner::token::Token};
+
+/// A check the linter runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+	UnusedVariable,
+	Shadowing,
+	EmptyBlock,
+	ConstantCondition,
+	SelfAssignment,
+	UnreachableCode,
+}
+
+impl Rule {
+	pub const ALL: [Rule; 6] = [
+		Rule::UnusedVariable, Rule::Shadowing, Rule::EmptyBlock, Rule::ConstantCondition, Rule::SelfAssignment,
+		Rule::UnreachableCode
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::UnusedVariable => "unused-variable",
+			Self::Shadowing => "shadowing",
+			Self::EmptyBlock => "empty-block",
+			Self::ConstantCondition => "constant-condition",
+			Self::SelfAssignment => "self-assignment",
+			Self::UnreachableCode => "unreachable-code",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|rule| rule.name() == name)
+	}
+
+	/// The level of the rule given overrides in the order they were set, every rule warns by default
+	pub fn level(&self, overrides: &[(Rule, Level)]) -> Level {
+		overrides.iter().rev()
+			.find(|(rule, _)| rule == self)
+			.map(|(_, level)| *level)
+			.unwrap_or(Level::Warn)
+	}
+}
+
+/// What happens when a rule finds something
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+	/// The rule doesn't run
+	Allow,
+	/// Findings are reported
+	Warn,
+	/// Findings are reported as errors, and fail the lint
+	Deny,
+}
+
+/// A finding of a rule
+pub struct Lint {
+	pub rule: Rule,
+	pub token: Token,
+	pub message: String
+}
+
+impl Lint {
+	pub fn new(rule: Rule, token: Token, message: String) -> Self {
+		Self { rule, token, message }
+	}
+
+	/// The finding as a warning or, if its rule is denied, an error
+	pub fn report(&self, level: Level) -> LoxError {
+		let severity = if level == Level::Deny { Severity::Error } else { Severity::Warning };
+		LoxError::Lint { token: self.token.clone(), message: self.message.clone(), rule: self.rule.name(), severity }
+	}
+}
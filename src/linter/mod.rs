@@ -0,0 +1,386 @@
+use std::{collections::HashMap, sync::Arc};
+
+use lint::{Lint, Rule};
+
+use crate::{parser::expr::{Expr, ExprArray, ExprAssignment, ExprBinary, ExprBlock, ExprCall, ExprGet, ExprGrouping, ExprIf, ExprIndex, ExprIndexSet, ExprLiteral, ExprLogical, ExprSet, ExprThis, ExprUnary, ExprVariable}, scanner::token::{Literal, Symbol, Token, TokenType}, statement::{BlockStatement, ClassDecl, ExportStatement, ExprStatement, ForInStatement, ForStatement, FunctionDecl, IfStatement, ImportStatement, PrintStatement, ReturnStatement, Statement, SwitchStatement, VarDeclaration, WhileStatement}, visitor::{ExprVisitor, StmtVisitor}};
+
+pub mod lint;
+
+/// A name declared in a scope
+struct Local {
+	token: Token,
+	/// Whether the name has been read. Names that aren't variables, like functions and parameters,
+	/// start out used so they are never reported
+	used: bool
+}
+
+/// Walks a resolved program looking for code that is valid but probably wrong. Scopes are tracked
+/// the same way as in the resolver, with the globals as the outermost scope
+pub struct Linter {
+	scopes: Vec<HashMap<Symbol, Local>>,
+	lints: Vec<Lint>
+}
+
+impl Linter {
+	/// Run every rule over a program, returning the findings in source order
+	pub fn lint(statements: &[Statement]) -> Vec<Lint> {
+		let mut linter = Self { scopes: vec![HashMap::new()], lints: Vec::new() };
+
+		linter.statements(statements);
+		linter.lints.sort_by_key(|lint| (lint.token.line, lint.token.column));
+
+		linter.lints
+	}
+
+	fn statements(&mut self, statements: &[Statement]) {
+		for statement in statements {
+			self.statement(statement);
+		}
+
+		self.unreachable(statements);
+	}
+
+	/// Report the first statement after a `return`, `break` or `continue` in the same list
+	fn unreachable(&mut self, statements: &[Statement]) {
+		let Some(exit) = statements.iter().position(|s| matches!(s, Statement::Return(_) | Statement::Break() | Statement::Continue())) else { return };
+		let Some(next) = statements.get(exit + 1) else { return };
+
+		let keyword = match &statements[exit] {
+			Statement::Return(_) => "return",
+			Statement::Break() => "break",
+			_ => "continue"
+		};
+
+		if let Some(line) = next.line() {
+			let token = Token::new(TokenType::IDENTIFIER, "", Literal::Null, line);
+			self.report(Rule::UnreachableCode, &token, format!("Code after '{}' is unreachable.", keyword));
+		}
+	}
+
+	fn statement(&mut self, statement: &Statement) {
+		statement.accept(self)
+	}
+
+	fn block(&mut self, block: &BlockStatement) {
+		self.begin_scope();
+		self.statements(&block.statements);
+		self.end_scope();
+	}
+
+	fn function(&mut self, function: &FunctionDecl) {
+		self.begin_scope();
+
+		for param in &function.params {
+			self.declare(param, false);
+		}
+
+		self.statements(&function.body);
+		self.end_scope();
+	}
+
+	fn var(&mut self, v: &VarDeclaration) {
+		if let Some(initializer) = &v.initializer {
+			self.expr(initializer);
+		}
+
+		self.declare(&v.name, true);
+	}
+
+	fn expr(&mut self, expr: &Expr) {
+		expr.accept(self)
+	}
+
+	/// Check the condition of an `if` or `while`, reporting it if it is a literal
+	fn condition(&mut self, keyword: &Token, condition: &Expr, allow_true: bool) {
+		if let Some(truthy) = literal_truthiness(condition) {
+			if !(allow_true && truthy) {
+				self.report(Rule::ConstantCondition, keyword, format!("Condition is always {}.", truthy));
+			}
+		}
+
+		self.expr(condition);
+	}
+
+	fn begin_scope(&mut self) {
+		self.scopes.push(HashMap::new());
+	}
+
+	/// Close a local scope, reporting the variables that were never read
+	fn end_scope(&mut self) {
+		let Some(scope) = self.scopes.pop() else { return };
+
+		for (name, local) in scope {
+			if !local.used && !name.starts_with('_') {
+				self.report(Rule::UnusedVariable, &local.token, format!("Variable '{}' is never used.", name));
+			}
+		}
+	}
+
+	/// Declare a name in the innermost scope, reporting it if it hides one from an enclosing scope.
+	/// Only variables are tracked for use
+	fn declare(&mut self, name: &Token, variable: bool) {
+		let outer = self.scopes.iter().rev().skip(1).find_map(|scope| scope.get(&name.lexeme));
+
+		if let Some(outer) = outer {
+			let message = format!("'{}' shadows a declaration on line {}.", name.lexeme, outer.token.line);
+			self.report(Rule::Shadowing, name, message);
+		}
+
+		// Globals may be read by code the linter doesn't see, so they are never reported as unused
+		let used = !variable || self.scopes.len() == 1;
+
+		if let Some(scope) = self.scopes.last_mut() {
+			scope.insert(name.lexeme, Local { token: name.clone(), used });
+		}
+	}
+
+	fn use_name(&mut self, name: &Token) {
+		if let Some(local) = self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(&name.lexeme)) {
+			local.used = true;
+		}
+	}
+
+	fn report(&mut self, rule: Rule, token: &Token, message: String) {
+		self.lints.push(Lint::new(rule, token.clone(), message));
+	}
+}
+
+impl StmtVisitor for Linter {
+	type Output = ();
+
+	fn visit_print_statement(&mut self, statement: &PrintStatement) {
+		self.expr(&statement.0);
+	}
+
+	fn visit_expression_statement(&mut self, statement: &ExprStatement) {
+		self.expr(&statement.0);
+	}
+
+	fn visit_function_statement(&mut self, statement: &Arc<FunctionDecl>) {
+		self.declare(&statement.name, false);
+		self.function(statement);
+	}
+
+	fn visit_class_statement(&mut self, statement: &ClassDecl) {
+		self.declare(&statement.name, false);
+
+		for method in &statement.methods {
+			self.function(method);
+		}
+	}
+
+	fn visit_return_statement(&mut self, statement: &ReturnStatement) {
+		if let Some(value) = &statement.value {
+			self.expr(value);
+		}
+	}
+
+	fn visit_if_statement(&mut self, statement: &IfStatement) {
+		self.condition(&statement.keyword, &statement.condition, false);
+		self.statement(&statement.then_branch);
+
+		if let Some(else_branch) = &statement.else_branch {
+			self.statement(else_branch);
+		}
+	}
+
+	fn visit_while_statement(&mut self, statement: &WhileStatement) {
+		// `while (true)` is how an endless loop is written
+		self.condition(&statement.keyword, &statement.condition, true);
+		self.statement(&statement.body);
+	}
+
+	fn visit_for_statement(&mut self, statement: &ForStatement) {
+		self.begin_scope();
+
+		if let Some(initializer) = &statement.initializer {
+			self.statement(initializer);
+		}
+
+		if let Some(condition) = &statement.condition {
+			self.condition(&statement.keyword, condition, false);
+		}
+
+		self.statement(&statement.body);
+
+		if let Some(increment) = &statement.increment {
+			self.expr(increment);
+		}
+
+		self.end_scope();
+	}
+
+	fn visit_for_in_statement(&mut self, statement: &ForInStatement) {
+		self.expr(&statement.iterable);
+
+		self.begin_scope();
+		self.declare(&statement.name, true);
+		self.statement(&statement.body);
+		self.end_scope();
+	}
+
+	fn visit_switch_statement(&mut self, statement: &SwitchStatement) {
+		self.expr(&statement.subject);
+
+		for case in &statement.cases {
+			for value in &case.values {
+				self.expr(value);
+			}
+
+			self.block(&case.body);
+		}
+
+		if let Some(default) = &statement.default {
+			self.block(default);
+		}
+	}
+
+	fn visit_break_statement(&mut self) {}
+
+	fn visit_continue_statement(&mut self) {}
+
+	fn visit_var_statement(&mut self, statement: &VarDeclaration) {
+		self.var(statement);
+	}
+
+	fn visit_multi_var_statement(&mut self, statement: &[VarDeclaration]) {
+		for v in statement {
+			self.var(v);
+		}
+	}
+
+	fn visit_block_statement(&mut self, statement: &BlockStatement) {
+		if statement.statements.is_empty() {
+			self.report(Rule::EmptyBlock, &statement.brace, "Empty block.".to_string());
+		}
+
+		self.block(statement);
+	}
+
+	fn visit_import_statement(&mut self, statement: &ImportStatement) {
+		self.declare(&statement.name, false);
+	}
+
+	fn visit_export_statement(&mut self, statement: &ExportStatement) {
+		self.statement(&statement.declaration);
+	}
+}
+
+impl ExprVisitor for Linter {
+	type Output = ();
+
+	fn visit_literal_expr(&mut self, _expr: &ExprLiteral) {}
+
+	fn visit_this_expr(&mut self, _expr: &ExprThis) {}
+
+	fn visit_variable_expr(&mut self, expr: &ExprVariable) {
+		self.use_name(&expr.name);
+	}
+
+	fn visit_assignment_expr(&mut self, expr: &ExprAssignment) {
+		if let Expr::Variable(v) = expr.value.as_ref() {
+			if v.name.lexeme == expr.name.lexeme {
+				self.report(Rule::SelfAssignment, &expr.name, format!("'{}' is assigned to itself.", expr.name.lexeme));
+			}
+		}
+
+		self.expr(&expr.value);
+	}
+
+	fn visit_set_expr(&mut self, expr: &ExprSet) {
+		if let Expr::Get(g) = expr.value.as_ref() {
+			if g.name.lexeme == expr.name.lexeme && same_object(&g.object, &expr.object) {
+				self.report(Rule::SelfAssignment, &expr.name, format!("'{}' is assigned to itself.", expr.name.lexeme));
+			}
+		}
+
+		self.expr(&expr.object);
+		self.expr(&expr.value);
+	}
+
+	fn visit_call_expr(&mut self, expr: &ExprCall) {
+		self.expr(&expr.callee);
+
+		for argument in &expr.arguments {
+			self.expr(argument);
+		}
+	}
+
+	fn visit_binary_expr(&mut self, expr: &ExprBinary) {
+		self.expr(&expr.left);
+		self.expr(&expr.right);
+	}
+
+	fn visit_logical_expr(&mut self, expr: &ExprLogical) {
+		self.expr(&expr.left);
+		self.expr(&expr.right);
+	}
+
+	fn visit_unary_expr(&mut self, expr: &ExprUnary) {
+		self.expr(&expr.right);
+	}
+
+	fn visit_grouping_expr(&mut self, expr: &ExprGrouping) {
+		self.expr(&expr.0);
+	}
+
+	fn visit_get_expr(&mut self, expr: &ExprGet) {
+		self.expr(&expr.object);
+	}
+
+	fn visit_block_expr(&mut self, expr: &ExprBlock) {
+		self.begin_scope();
+		self.statements(&expr.statements);
+
+		if let Some(value) = &expr.value {
+			self.expr(value);
+		}
+
+		self.end_scope();
+	}
+
+	fn visit_if_expr(&mut self, expr: &ExprIf) {
+		self.condition(&expr.keyword, &expr.condition, false);
+		self.expr(&expr.then_branch);
+
+		if let Some(else_branch) = &expr.else_branch {
+			self.expr(else_branch);
+		}
+	}
+
+	fn visit_array_expr(&mut self, expr: &ExprArray) {
+		for element in &expr.elements {
+			self.expr(element);
+		}
+	}
+
+	fn visit_index_expr(&mut self, expr: &ExprIndex) {
+		self.expr(&expr.object);
+		self.expr(&expr.index);
+	}
+
+	fn visit_index_set_expr(&mut self, expr: &ExprIndexSet) {
+		self.expr(&expr.object);
+		self.expr(&expr.index);
+		self.expr(&expr.value);
+	}
+}
+
+/// The truthiness of a condition that is a literal, possibly in parentheses
+fn literal_truthiness(expr: &Expr) -> Option<bool> {
+	match expr {
+		Expr::Literal(ExprLiteral::False | ExprLiteral::Null) => Some(false),
+		Expr::Literal(_) => Some(true),
+		Expr::Grouping(g) => literal_truthiness(&g.0),
+		_ => None
+	}
+}
+
+/// Whether two expressions name the same object, `this` or the same variable
+fn same_object(a: &Expr, b: &Expr) -> bool {
+	match (a, b) {
+		(Expr::This(_), Expr::This(_)) => true,
+		(Expr::Variable(a), Expr::Variable(b)) => a.name.lexeme == b.name.lexeme,
+		_ => false
+	}
+}
@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much the interpreter says about itself on stderr, set once from `--verbose` or `--quiet`.
+/// Diagnostics and the program's own output are never affected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Verbosity {
+	/// Only diagnostics
+	Quiet,
+	/// Diagnostics and notes, such as which files `fmt --check` would change
+	#[default]
+	Normal,
+	/// Also what each phase is doing
+	Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+	VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+	match VERBOSITY.load(Ordering::Relaxed) {
+		0 => Verbosity::Quiet,
+		1 => Verbosity::Normal,
+		_ => Verbosity::Verbose,
+	}
+}
+
+/// Report something the user asked about, hidden by `--quiet`
+pub fn info(message: &str) {
+	if verbosity() >= Verbosity::Normal {
+		eprintln!("{}", message);
+	}
+}
+
+/// Report the interpreter's progress, shown only with `--verbose`
+pub fn verbose(message: &str) {
+	if verbosity() >= Verbosity::Verbose {
+		eprintln!("[verbose] {}", message);
+	}
+}
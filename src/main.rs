@@ -1,24 +1,53 @@
 use std::env;
 use std::fs;
-use std::io::{self, Write};
-
-use interpreter::Interpreter;
+use std::io::{self, BufRead, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use codecrafters_interpreter::{cli, error, interpreter, linter, log, optimizer, parser, printer, resolver, scanner, statement, vm};
+#[cfg(feature = "jit")]
+use codecrafters_interpreter::jit;
+
+use cli::{Backend, Cli, Command, Format};
+use error::Diagnostic;
+use interpreter::{coverage::Coverage, debugger::Debugger, error::ValueError, flow::Unwind, natives::memory::CountingAllocator, stats::Stats, trace::Trace, Interpreter, MAX_CALL_DEPTH};
+use linter::{lint::{Level, Rule}, Linter};
 use parser::expr::AstPrinter;
 use parser::Parser;
+use printer::{ast::JsonAstPrinter, dot::DotAstPrinter, source::SourcePrinter, tokens::JsonTokenPrinter};
 use resolver::Resolver;
-use scanner::Scanner;
+use scanner::{token::Token, Scanner};
+use statement::Statement;
+use vm::{compiler::Compiler, file, Vm};
+
+/// The stack size of the thread running Lox. Lox calls recurse on the Rust stack, so it must fit
+/// [`MAX_CALL_DEPTH`] calls with room to spare, even in debug builds
+const STACK_SIZE: usize = 1 << 30;
+
+/// What errors in the REPL's input say they are in
+const REPL_NAME: &str = "<repl>";
 
-pub mod scanner;
-pub mod utils;
-pub mod parser;
-pub mod error;
-pub mod interpreter;
-pub mod statement;
-pub mod resolver;
+/// Counts allocations for the memory natives, `memoryUsage()` and `objectCount()`
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    Lox::main(args);
+
+    let runner = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+        // An executable made by `build` runs its program, passing every argument on to it
+        if let Some((program, flags)) = Lox::embedded() {
+            return Lox::run_embedded(program, flags, args.into_iter().skip(1).collect());
+        }
+
+        Lox::main(args);
+    }).expect("Failed to start the interpreter thread");
+
+    // The panic has already been reported, exit the way a panic on the main thread would
+    if runner.join().is_err() {
+        std::process::exit(101);
+    }
 }
 
 
@@ -26,163 +55,919 @@ pub struct Lox;
 
 impl Lox { 
     pub fn main(args: Vec<String>){
-        
-        if args.len() < 3 {
-            writeln!(io::stderr(), "Usage: {} tokenize <filename>", args[0]).unwrap();
+        let program = args.first().map(|p| p.as_str()).unwrap_or("lox");
+
+        let cli = match Cli::parse(&args) {
+            Ok(cli) => cli,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(64);
+            }
+        };
+
+        log::set_verbosity(cli.verbosity);
+
+        if cli.help {
+            match cli.command {
+                Some(command) => println!("{}", cli::command_usage(program, command)),
+                None => println!("{}", cli::usage(program))
+            }
             return;
         }
 
-        let command = &args[1];
-        let filename = &args[2];
+        if cli.version {
+            println!("{}", cli::version());
+            return;
+        }
 
-        
-        // You can use print statements as follows for debugging, they'll be visible when running tests.
-        writeln!(io::stderr(), "Logs from your program will appear here!").unwrap();
+        let command = match cli.command {
+            Some(command) => command,
+            None => return
+        };
 
-        match command.as_str() {
-            "tokenize" => {
-                let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                    writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-                    String::new()
-                });
+        if command == Command::Repl {
+            return Self::repl();
+        }
 
-                Self::tokenize(file_contents.to_string());
+        match command {
+            Command::Tokenize => Self::tokenize(Self::read_file(&cli.files[0]), &cli.files[0], cli.format),
+            Command::Parse => Self::parse(Self::read_file(&cli.files[0]), &cli.files[0]),
+            Command::Evaluate => Self::evaluate(Self::read_file(&cli.files[0]), &cli.files[0]),
+            Command::Ast => Self::ast(Self::read_file(&cli.files[0]), &cli.files[0], cli.optional_semicolons),
+            Command::Dot => Self::dot(Self::read_file(&cli.files[0]), &cli.files[0], cli.optional_semicolons),
+            Command::Fmt => Self::fmt(Self::read_file(&cli.files[0]), &cli.files[0], cli.optional_semicolons, cli.check),
+            Command::Lint => Self::lint(Self::read_file(&cli.files[0]), &cli.files[0], cli.optional_semicolons, &cli.lint_levels),
+            Command::Run | Command::Coverage | Command::Debug => {
+                // `run -e <source>` runs an inline snippet under a synthetic filename
+                let files = match &cli.eval {
+                    Some(source) => vec![("<eval>".to_string(), source.clone())],
+                    None => cli.files.iter().map(|f| (f.clone(), Self::read_file(f))).collect()
+                };
+
+                Self::run(files, &cli)
             },
-            "parse" => {
-                let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                    writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-                    String::new()
-                });
-                
-                Self::parse(file_contents.to_string())
-            },
-            "evaluate" => {
-                let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                    writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-                    String::new()
-                });
-                
-                Self::evaluate(file_contents.to_string())
+            Command::Bench => Self::bench(Self::read_file(&cli.files[0]), &cli),
+            Command::Compile => Self::compile(Self::read_file(&cli.files[0]), &cli),
+            Command::Exec => Self::exec(&cli),
+            Command::Build => Self::build(Self::read_file(&cli.files[0]), &cli),
+            Command::Repl => unreachable!()
+        }
+    }
+
+    /// Scan all tokens of a source, reporting the errors found on the way
+    fn scan(scanner: &mut Scanner, filename: &str) -> Vec<Token> {
+        let (tokens, errors) = scanner.scan_tokens();
+        Self::report(&errors, filename, scanner.source());
+        tokens
+    }
+
+    /// Report errors in a file to stderr, one after another
+    fn report<E: Diagnostic>(errors: &[E], filename: &str, source: &str) {
+        for e in errors {
+            Self::report_error(e, filename, source);
+        }
+    }
+
+    /// Report an error in a file to stderr. On a terminal it is followed by the source it points at,
+    /// while anything reading the output gets just the one line error
+    fn report_error(error: &impl Diagnostic, filename: &str, source: &str) {
+        if io::stderr().is_terminal() {
+            eprintln!("{}", error.render(filename, source));
+        } else {
+            eprintln!("{}", error);
+        }
+    }
 
+    /// Report an error raised running a file. The error may have been raised in code from another
+    /// file, so the source is only shown when `single` says there was one file and no module was
+    /// imported
+    fn report_runtime_error(error: &impl Diagnostic, interpreter: &Interpreter, single: bool, filename: &str, source: &str) {
+        if single && interpreter.modules.is_empty() {
+            Self::report_error(error, filename, source);
+        } else {
+            eprintln!("{}", error);
+        }
+    }
+
+    /// Read a source file, reporting a failure and continuing with empty source
+    fn read_file(filename: &str) -> String {
+        fs::read_to_string(filename).unwrap_or_else(|_| {
+            writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
+            String::new()
+        })
+    }
+
+    pub fn tokenize(source: String, filename: &str, format: Format) {
+        let mut scanner = Scanner::new(source);
+        let tokens = Self::scan(&mut scanner, filename);
+
+        match format {
+            Format::Text => {
+                for token in tokens {
+                    println!("{}", token);
+                }
             },
-            "run" => {
-                let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                    writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-                    String::new()
-                });
-                
-                Self::run(file_contents.to_string())
-            }
-            _ => {
-                writeln!(io::stderr(), "Unknown command: {}", command).unwrap();
-                return;
-            }
+            Format::Json => println!("{}", JsonTokenPrinter::print(&tokens))
         }
 
+        if scanner.had_error {
+            std::process::exit(65);
+        }
     }
 
-    pub fn tokenize(source: String) {
+    pub fn parse(source: String, filename: &str) {
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens().expect("Failed to scan tokens");
+        let tokens = Self::scan(&mut scanner, filename);
 
-        for token in tokens {
-            println!("{}", token);
+        if scanner.had_error {
+            std::process::exit(65);
         }
 
+        let mut parser = Parser::new(tokens);
+
+        match parser.parse() {
+            Ok(e) => println!("{}", AstPrinter::print(&e)),
+            Err(errors) => {
+                Self::report(&errors, filename, scanner.source());
+                std::process::exit(65);
+            }
+        }
+    }
+
+    /// Print the syntax tree of a whole program as JSON
+    pub fn ast(source: String, filename: &str, optional_semicolons: bool) {
+        let mut scanner = Scanner::new(source);
+        let tokens = Self::scan(&mut scanner, filename);
+
         if scanner.had_error {
             std::process::exit(65);
         }
+
+        let mut parser = Parser::new(tokens);
+        parser.optional_semicolons = optional_semicolons;
+
+        match parser.parse_statement() {
+            Ok(statements) => println!("{}", JsonAstPrinter::print(&statements)),
+            Err(errors) => {
+                Self::report(&errors, filename, scanner.source());
+                std::process::exit(65);
+            }
+        }
     }
 
-    pub fn parse(source: String) {
+    /// Print the syntax tree of a whole program as a GraphViz DOT graph
+    pub fn dot(source: String, filename: &str, optional_semicolons: bool) {
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens().expect("Failed to scan tokens");
+        let tokens = Self::scan(&mut scanner, filename);
 
         if scanner.had_error {
             std::process::exit(65);
         }
 
         let mut parser = Parser::new(tokens);
-        let expression = parser.parse();
+        parser.optional_semicolons = optional_semicolons;
 
-        if expression.is_none() {
+        match parser.parse_statement() {
+            Ok(statements) => println!("{}", DotAstPrinter::print(&statements)),
+            Err(errors) => {
+                Self::report(&errors, filename, scanner.source());
+                std::process::exit(65);
+            }
+        }
+    }
+
+    /// Print a file in canonical formatting. With `check`, print nothing and exit with 1 if the
+    /// formatting would change the file. Files that don't parse are left alone
+    pub fn fmt(source: String, filename: &str, optional_semicolons: bool, check: bool) {
+        let mut scanner = Scanner::new(source.clone());
+        let tokens = Self::scan(&mut scanner, filename);
+
+        if scanner.had_error {
             std::process::exit(65);
         }
 
-        if let Some(e) = expression {
-            println!("{}", AstPrinter::print(e));
+        let mut parser = Parser::new(tokens);
+        parser.optional_semicolons = optional_semicolons;
+
+        if let Err(errors) = parser.parse_statement() {
+            Self::report(&errors, filename, scanner.source());
+            std::process::exit(65);
+        }
+
+        // Scan again, this time keeping the comments
+        let mut scanner = Scanner::new(source.clone());
+        scanner.keep_comments = true;
+        let tokens = Self::scan(&mut scanner, filename);
+
+        let formatted = SourcePrinter::print(&tokens, scanner.source(), optional_semicolons);
+
+        if !check {
+            print!("{}", formatted);
+        } else if formatted != source {
+            log::info(&format!("Would reformat {}", filename));
+            std::process::exit(1);
         }
     }
 
-    pub fn evaluate(source: String) {
+    /// Resolve a program and report what the lint rules find. Exits with 65 if a denied rule finds
+    /// anything
+    pub fn lint(source: String, filename: &str, optional_semicolons: bool, levels: &[(Rule, Level)]) {
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens().expect("Failed to scan tokens");
+        let tokens = Self::scan(&mut scanner, filename);
 
         if scanner.had_error {
             std::process::exit(65);
         }
 
         let mut parser = Parser::new(tokens);
-        let expression = parser.parse();
+        parser.optional_semicolons = optional_semicolons;
 
-        if expression.is_none() {
+        let statements = match parser.parse_statement() {
+            Ok(statements) => statements,
+            Err(errors) => {
+                Self::report(&errors, filename, scanner.source());
+                std::process::exit(65);
+            }
+        };
+
+        if let Err(errors) = Resolver::new(Interpreter::new()).resolve_statements(&statements) {
+            Self::report(&errors, filename, scanner.source());
+            std::process::exit(65);
+        }
+
+        let mut denied = false;
+
+        for lint in Linter::lint(&statements) {
+            let level = lint.rule.level(levels);
+
+            if level != Level::Allow {
+                Self::report_error(&lint.report(level), filename, scanner.source());
+                denied |= level == Level::Deny;
+            }
+        }
+
+        if denied {
+            std::process::exit(65);
+        }
+    }
+
+    /// Report every lint finding of a program as an error, for `--strict`. Returns whether there were any
+    fn strict_lints(statements: &[Statement], filename: &str, source: &str) -> bool {
+        let lints = Linter::lint(statements);
+
+        for lint in &lints {
+            Self::report_error(&lint.report(Level::Deny), filename, source);
+        }
+
+        !lints.is_empty()
+    }
+
+    pub fn evaluate(source: String, filename: &str) {
+        let mut scanner = Scanner::new(source);
+        let tokens = Self::scan(&mut scanner, filename);
+
+        if scanner.had_error {
             std::process::exit(65);
         }
 
+        let mut parser = Parser::new(tokens);
+
+        let expression = match parser.parse() {
+            Ok(expression) => expression,
+            Err(errors) => {
+                Self::report(&errors, filename, scanner.source());
+                std::process::exit(65);
+            }
+        };
+
+        match Interpreter::new().interpret(expression) {
+            Ok(v) => println!("{}", v),
+            Err(e) => {
+                Self::report_error(&e, filename, scanner.source());
+                std::process::exit(70);
+            }
+        }
+    }
+
+    /// Run an interactive session. Definitions persist across inputs, the values of bare expressions
+    /// are printed, and errors are reported without ending the session
+    pub fn repl() {
+        let mut resolver = Resolver::new(Interpreter::new());
+        let mut line = 1;
+        let stdin = io::stdin();
+
+        loop {
+            print!("> ");
+            io::stdout().flush().unwrap();
+
+            let mut input = String::new();
+            match stdin.lock().read_line(&mut input) {
+                Ok(0) | Err(_) => { println!(); return },
+                Ok(_) => {}
+            }
+
+            // Keep numbering lines across inputs, so errors point somewhere meaningful and identical
+            // expressions on different inputs stay distinct
+            let mut scanner = Scanner::new(input);
+            scanner.line = line;
+            let tokens = Self::scan(&mut scanner, REPL_NAME);
+            line = scanner.line;
+
+            if scanner.had_error {
+                continue;
+            }
+
+            let mut parser = Parser::new(tokens);
+            parser.optional_semicolons = true;
+
+            let statements = match parser.parse_statement() {
+                Ok(s) => s,
+                Err(errors) => {
+                    Self::report(&errors, REPL_NAME, scanner.source());
+                    continue;
+                }
+            };
+
+            if let Err(errors) = resolver.resolve_statements(&statements) {
+                Self::report(&errors, REPL_NAME, scanner.source());
+                continue;
+            }
+
+            let interpreter = &mut resolver.interpreter;
+
+            for statement in &statements {
+                let result = match statement {
+                    Statement::Expression(e) => interpreter.interpret_expr(&e.0)
+                        .map(|v| println!("{}", v)),
+                    s => interpreter.interpret_statement(s)
+                }.map_err(Unwind::into_error);
+
+                // Functions from earlier inputs raise errors in source no longer at hand, so runtime
+                // errors are reported without it
+                if let Err(e) = result {
+                    eprintln!("{}", e);
+                    interpreter.environment = interpreter.globals.clone();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Run one or more files, in order, in a single interpreter so that later files see the globals
+    /// of earlier ones. Every file is checked before any of them runs
+    pub fn run(files: Vec<(String, String)>, cli: &Cli) {
         let mut interpreter = Interpreter::new();
+        interpreter.script_args = cli.script_args.clone();
+        interpreter.allow_exec = cli.allow_exec;
+        interpreter.max_call_depth = cli.max_depth.unwrap_or(MAX_CALL_DEPTH);
+        interpreter.trace = cli.trace;
+        interpreter.opt_level = cli.opt_level;
+        // Compiled functions run without the statement by statement hooks of tracing, coverage,
+        // debugging and statistics
+        interpreter.tier_up = !cli.no_tier_up && cli.command == Some(Command::Run) && cli.trace == Trace::Off && !cli.stats;
+
+        if cli.command == Some(Command::Coverage) {
+            interpreter.coverage = Some(Coverage::default());
+        }
+
+        if cli.stats {
+            interpreter.stats = Some(Stats::default());
+        }
+
+        if cli.command == Some(Command::Debug) {
+            if let Some((filename, source)) = files.first() {
+                interpreter.debugger = Some(Debugger::new(filename.into(), source));
+            }
+        }
+
+        let mut resolver = Resolver::new(interpreter);
+        let mut programs = Vec::new();
+        let mut timings = Timings::default();
+        let mut rejected = false;
 
-        let v = interpreter.interpret(expression.unwrap());
+        let Some(parsed) = Self::parse_files(files, cli) else {
+            std::process::exit(65);
+        };
+
+        for Parsed { filename, source, statements, scan, parse, tokens } in parsed {
+            timings.scan += scan;
+            timings.tokens += tokens;
+            timings.parse += parse;
+            timings.statements += statements.len();
+
+            let start = Instant::now();
+            if let Err(errors) = resolver.resolve_statements(&statements) {
+                Self::report(&errors, &filename, &source);
+                std::process::exit(65);
+            }
+            timings.resolve += start.elapsed();
+
+            if cli.strict {
+                rejected |= Self::strict_lints(&statements, &filename, &source);
+            }
+
+            if let Some(coverage) = &mut resolver.interpreter.coverage {
+                coverage.register(Path::new(&filename), &statements);
+            }
+
+            // With the VM backend each file is compiled up front, so no file runs if any fails to compile
+            let compiled = match cli.backend {
+                Backend::Tree => None,
+                Backend::Vm => {
+                    let start = Instant::now();
+                    let function = match Compiler::compile(&statements) {
+                        Ok(function) => function,
+                        Err(e) => {
+                            Self::report_error(&e, &filename, &source);
+                            std::process::exit(65);
+                        }
+                    };
+                    timings.compile = Some(timings.compile.unwrap_or_default() + start.elapsed());
+                    Some(function)
+                }
+            };
+
+            programs.push((filename, source, statements, compiled));
+        }
+
+        if rejected {
+            std::process::exit(65);
+        }
+
+        log::verbose("Resolving complete, now interpreting");
+
+        let mut interpreter = resolver.interpreter;
+
+        #[cfg(feature = "jit")]
+        if cli.jit {
+            let start = Instant::now();
+            let statements: Vec<Statement> = programs.iter().flat_map(|(_, _, statements, _)| statements.iter().cloned()).collect();
+            interpreter.jit = Self::jit(&statements);
+            timings.compile = Some(start.elapsed());
+        }
 
-        if v.is_none() {
+        let start = Instant::now();
+
+        let mut failed = false;
+        let single = programs.len() == 1;
+
+        // Runtime errors stop the run, but the timings and coverage so far are still reported
+        'files: for (filename, source, statements, compiled) in programs {
+            log::verbose(&format!("Running {}", filename));
+            interpreter.current_file = Some(filename.clone().into());
+
+            if let Some(function) = compiled {
+                let result = Vm::new(&mut interpreter).run_script(function);
+
+                if let Err(e) = result {
+                    Self::report_runtime_error(&e, &interpreter, single, &filename, &source);
+                    failed = true;
+                    break 'files;
+                }
+
+                continue;
+            }
+
+            for statement in &statements {
+                match interpreter.interpret_statement(statement).map_err(Unwind::into_error) {
+                    Ok(_) => {},
+                    // Quitting the debugger ends the program early, but not as a failure
+                    Err(ValueError::Quit) => break 'files,
+                    Err(e) => {
+                        Self::report_runtime_error(&e, &interpreter, single, &filename, &source);
+                        failed = true;
+                        break 'files;
+                    }
+                }
+            }
+        }
+
+        timings.interpret = start.elapsed();
+
+        if cli.time {
+            timings.report();
+        }
+
+        if let Some(stats) = &interpreter.stats {
+            eprintln!("{}", stats.report());
+        }
+
+        if let Some(coverage) = &interpreter.coverage {
+            eprintln!("{}", coverage.report());
+
+            if let Some(path) = &cli.lcov {
+                if let Err(e) = fs::write(path, coverage.lcov()) {
+                    eprintln!("Failed to write LCOV report to {}: {}", path, e);
+                }
+            }
+        }
+
+        if failed {
             std::process::exit(70);
         }
+    }
+
+    /// Scan, parse and optimize files ahead of resolving them, several at once when there are
+    /// several. Errors are reported as each file is parsed, and `None` returned if any file had one
+    fn parse_files(files: Vec<(String, String)>, cli: &Cli) -> Option<Vec<Parsed>> {
+        let workers = thread::available_parallelism().map_or(1, |n| n.get()).min(files.len());
+
+        if workers <= 1 {
+            return files.into_iter().map(|(filename, source)| Self::parse_file(filename, source, cli)).collect()
+        }
+
+        // Each worker takes a run of consecutive files, so joining them in turn keeps the files in order
+        let per_worker = files.len().div_ceil(workers);
+        let mut files = files.into_iter();
+        let batches: Vec<Vec<(String, String)>> = (0..workers).map(|_| files.by_ref().take(per_worker).collect()).collect();
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = batches.into_iter().map(|batch| {
+                // Parsing recurses as deep as the code nests, like running it does
+                thread::Builder::new().stack_size(STACK_SIZE).spawn_scoped(scope, move || {
+                    batch.into_iter().map(|(filename, source)| Self::parse_file(filename, source, cli)).collect::<Vec<_>>()
+                }).expect("Failed to start a parsing thread")
+            }).collect();
+
+            let parsed: Vec<Option<Parsed>> = handles.into_iter()
+                .flat_map(|handle| handle.join().unwrap_or_else(|_| std::process::exit(101)))
+                .collect();
+
+            parsed.into_iter().collect()
+        })
+    }
+
+    /// Scan, parse and optimize one file
+    fn parse_file(filename: String, source: String, cli: &Cli) -> Option<Parsed> {
+        let start = Instant::now();
+        let mut scanner = Scanner::new(source.clone());
+        let tokens = Self::scan(&mut scanner, &filename);
+        let scan = start.elapsed();
+        let token_count = tokens.len();
+        log::verbose(&format!("Scanned {} tokens from {}", token_count, filename));
+
+        if scanner.had_error {
+            return None
+        }
+
+        let start = Instant::now();
+        let mut parser = Parser::new(tokens);
+        parser.optional_semicolons = cli.optional_semicolons;
 
-        let v = v.unwrap();
+        let statements = match parser.parse_statement() {
+            Ok(statements) => statements,
+            Err(errors) => {
+                Self::report(&errors, &filename, &source);
+                return None
+            }
+        };
+        let statements = optimizer::optimize(statements, cli.opt_level);
+        let parse = start.elapsed();
+        log::verbose(&format!("Parsed {} statements from {}", statements.len(), filename));
 
-        println!("{}", v);
+        Some(Parsed { filename, source, statements, scan, parse, tokens: token_count })
     }
 
-    pub fn run(source: String) {
+    /// Run a file repeatedly, each time in a fresh interpreter, and report statistics on the time
+    /// spent interpreting. The file is scanned and parsed once, warmup runs aren't measured
+    pub fn bench(source: String, cli: &Cli) {
+        let filename = &cli.files[0];
+        let iterations = cli.iterations.unwrap_or(10);
+        let warmup = cli.warmup.unwrap_or(1);
+
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens().expect("Failed to scan tokens");
+        let tokens = Self::scan(&mut scanner, filename);
 
         if scanner.had_error {
             std::process::exit(65);
         }
 
         let mut parser = Parser::new(tokens);
+        parser.optional_semicolons = cli.optional_semicolons;
 
-        let statements = parser.parse_statement();
+        let mut statements = match parser.parse_statement() {
+            Ok(statements) => statements,
+            Err(errors) => {
+                Self::report(&errors, filename, scanner.source());
+                std::process::exit(65);
+            }
+        };
 
+        statements = optimizer::optimize(statements, cli.opt_level);
 
+        // The program is compiled once, only running the bytecode is measured
+        let compiled = match cli.backend {
+            Backend::Tree => None,
+            Backend::Vm => match Compiler::compile(&statements) {
+                Ok(function) => Some(function),
+                Err(e) => {
+                    Self::report_error(&e, filename, scanner.source());
+                    std::process::exit(65);
+                }
+            }
+        };
 
-        match statements {
-            Ok(statements) => {
-                let mut interpreter = Interpreter::new();
-                let mut resolver = Resolver::new(interpreter);
+        #[cfg(feature = "jit")]
+        let jit = if cli.jit { Self::jit(&statements) } else { None };
 
-                let r = resolver.resolve_statements(statements.clone());
+        let mut samples = Vec::with_capacity(iterations);
 
-                if let Err(_) = r {
-                    // println!("{:?}", resolver.scopes);
-                    std::process::exit(65);
+        for i in 0..warmup + iterations {
+            let mut interpreter = Interpreter::new();
+            interpreter.script_args = cli.script_args.clone();
+            interpreter.allow_exec = cli.allow_exec;
+            interpreter.max_call_depth = cli.max_depth.unwrap_or(MAX_CALL_DEPTH);
+            interpreter.opt_level = cli.opt_level;
+            interpreter.tier_up = !cli.no_tier_up;
+
+            let mut resolver = Resolver::new(interpreter);
+
+            if let Err(errors) = resolver.resolve_statements(&statements) {
+                Self::report(&errors, filename, scanner.source());
+                std::process::exit(65);
+            }
+
+            let mut interpreter = resolver.interpreter;
+            interpreter.current_file = Some(filename.clone().into());
+
+            #[cfg(feature = "jit")]
+            {
+                interpreter.jit = jit.clone();
+            }
+
+            let start = Instant::now();
+
+            if let Some(function) = &compiled {
+                let result = Vm::new(&mut interpreter).run_script(function.clone());
+
+                if let Err(e) = result {
+                    Self::report_runtime_error(&e, &interpreter, true, filename, scanner.source());
+                    std::process::exit(70);
+                }
+            } else {
+                for statement in &statements {
+                    if let Err(e) = interpreter.interpret_statement(statement).map_err(Unwind::into_error) {
+                        Self::report_runtime_error(&e, &interpreter, true, filename, scanner.source());
+                        std::process::exit(70);
+                    }
                 }
+            }
 
-                eprintln!("Resolving complete, now interpreting");
+            if i >= warmup {
+                samples.push(start.elapsed());
+            }
+        }
 
-                interpreter = resolver.interpreter;                
-                interpreter.interpret_statements(statements);
-            },
-            Err(_) => {std::process::exit(65);}
+        Self::report_bench(filename, warmup, &mut samples);
+    }
+
+    /// Compile a file to bytecode and write it out, by default next to the source with a `.loxc`
+    /// extension
+    pub fn compile(source: String, cli: &Cli) {
+        let function = Self::compile_program(source, cli);
+
+        let output = match &cli.output {
+            Some(output) => output.clone(),
+            None => Path::new(&cli.files[0]).with_extension("loxc").to_string_lossy().to_string()
+        };
+
+        if let Err(e) = fs::write(&output, file::encode(&function)) {
+            eprintln!("Failed to write {}: {}", output, e);
+            std::process::exit(74);
+        }
+    }
+
+    /// Compile a file into a copy of this executable, which runs the program instead of taking
+    /// commands. It is written by default next to the source, without its extension
+    pub fn build(source: String, cli: &Cli) {
+        let function = Self::compile_program(source, cli);
+
+        let runtime = env::current_exe().and_then(fs::read).unwrap_or_else(|e| {
+            eprintln!("Failed to read the interpreter's executable: {}", e);
+            std::process::exit(74);
+        });
+
+        let output = match &cli.output {
+            Some(output) => output.clone(),
+            None => Path::new(&cli.files[0]).with_extension(env::consts::EXE_EXTENSION).to_string_lossy().to_string()
+        };
+
+        let flags = if cli.allow_exec { file::EMBED_ALLOW_EXEC } else { 0 };
+
+        if let Err(e) = fs::write(&output, file::embed(&runtime, &file::encode(&function), flags)) {
+            eprintln!("Failed to write {}: {}", output, e);
+            std::process::exit(74);
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            if let Err(e) = fs::set_permissions(&output, fs::Permissions::from_mode(0o755)) {
+                eprintln!("Failed to make {} executable: {}", output, e);
+                std::process::exit(74);
+            }
+        }
+    }
+
+    /// Scan, parse, resolve and compile a file, exiting with 65 if any step fails. The program is
+    /// resolved first, so it gets the same static checks as `run`
+    fn compile_program(source: String, cli: &Cli) -> vm::chunk::Function {
+        let filename = &cli.files[0];
+        let mut scanner = Scanner::new(source);
+        let tokens = Self::scan(&mut scanner, filename);
+
+        if scanner.had_error {
+            std::process::exit(65);
+        }
+
+        let mut parser = Parser::new(tokens);
+        parser.optional_semicolons = cli.optional_semicolons;
+
+        let mut statements = match parser.parse_statement() {
+            Ok(statements) => statements,
+            Err(errors) => {
+                Self::report(&errors, filename, scanner.source());
+                std::process::exit(65);
+            }
+        };
+
+        statements = optimizer::optimize(statements, cli.opt_level);
+
+        let mut resolver = Resolver::new(Interpreter::new());
+
+        if let Err(errors) = resolver.resolve_statements(&statements) {
+            Self::report(&errors, filename, scanner.source());
+            std::process::exit(65);
+        }
+
+        if cli.strict && Self::strict_lints(&statements, filename, scanner.source()) {
+            std::process::exit(65);
+        }
+
+        match Compiler::compile(&statements) {
+            Ok(function) => function,
+            Err(e) => {
+                Self::report_error(&e, filename, scanner.source());
+                std::process::exit(65);
+            }
+        }
+    }
+
+    /// Run a file compiled with `compile`
+    pub fn exec(cli: &Cli) {
+        let filename = &cli.files[0];
+
+        let bytes = fs::read(filename).unwrap_or_else(|_| {
+            eprintln!("Failed to read file {}", filename);
+            std::process::exit(66);
+        });
+
+        let function = match file::decode(&bytes) {
+            Ok(function) => function,
+            Err(message) => {
+                eprintln!("Error: {}: {}", filename, message);
+                std::process::exit(65);
+            }
+        };
+
+        let mut interpreter = Interpreter::new();
+        interpreter.script_args = cli.script_args.clone();
+        interpreter.allow_exec = cli.allow_exec;
+        interpreter.max_call_depth = cli.max_depth.unwrap_or(MAX_CALL_DEPTH);
+        interpreter.current_file = Some(filename.into());
+
+        let result = Vm::new(&mut interpreter).run_script(function);
+
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(70);
+        }
+    }
+
+    /// Compile what functions of a program can be compiled to native code. If the host isn't
+    /// supported, everything is interpreted instead
+    #[cfg(feature = "jit")]
+    fn jit(statements: &[Statement]) -> Option<jit::Jit> {
+        match jit::Jit::compile(statements) {
+            Ok(jit) => Some(jit),
+            Err(e) => {
+                log::info(&format!("JIT unavailable, interpreting everything: {}", e));
+                None
+            }
+        }
+    }
+
+    /// The program appended to this executable by `build`, with its flags. Only the trailer is read
+    /// unless there is one
+    fn embedded() -> Option<(Vec<u8>, u8)> {
+        let mut executable = fs::File::open(env::current_exe().ok()?).ok()?;
+        let size = executable.metadata().ok()?.len();
+
+        let mut trailer = [0; file::EMBED_TRAILER];
+        executable.seek(SeekFrom::Start(size.checked_sub(trailer.len() as u64)?)).ok()?;
+        executable.read_exact(&mut trailer).ok()?;
+
+        let (flags, len) = file::embedded_trailer(&trailer)?;
+        let start = size.checked_sub(trailer.len() as u64)?.checked_sub(len)?;
+
+        let mut program = vec![0; usize::try_from(len).ok()?];
+        executable.seek(SeekFrom::Start(start)).ok()?;
+        executable.read_exact(&mut program).ok()?;
+
+        Some((program, flags))
+    }
+
+    /// Run the program embedded in an executable made by `build`, exiting like `exec`
+    fn run_embedded(program: Vec<u8>, flags: u8, args: Vec<String>) {
+        let function = match file::decode(&program) {
+            Ok(function) => function,
+            Err(message) => {
+                eprintln!("Error: {}", message);
+                std::process::exit(65);
+            }
+        };
+
+        let mut interpreter = Interpreter::new();
+        interpreter.script_args = args;
+        interpreter.allow_exec = flags & file::EMBED_ALLOW_EXEC != 0;
+
+        let result = Vm::new(&mut interpreter).run_script(function);
+
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(70);
         }
     }
 
+    fn report_bench(filename: &str, warmup: usize, samples: &mut [Duration]) {
+        samples.sort();
+
+        let ms: Vec<f64> = samples.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        let n = ms.len();
+        let mean = ms.iter().sum::<f64>() / n as f64;
+
+        let median = if n % 2 == 0 { (ms[n / 2 - 1] + ms[n / 2]) / 2.0 } else { ms[n / 2] };
+
+        // Sample standard deviation, zero for a single run
+        let stddev = if n > 1 {
+            (ms.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        eprintln!("Benchmark: {} ({} runs, {} warmup)", filename, n, warmup);
+        eprintln!("  min    {:>10.3}ms", ms[0]);
+        eprintln!("  mean   {:>10.3}ms", mean);
+        eprintln!("  median {:>10.3}ms", median);
+        eprintln!("  max    {:>10.3}ms", ms[n - 1]);
+        eprintln!("  stddev {:>10.3}ms", stddev);
+    }
+
 
 
     
 }
 
-fn char_at(string: &str, n: usize) -> char {
-    return string.as_bytes()[n] as char;
+/// A file scanned, parsed and optimized, waiting to be resolved
+struct Parsed {
+    filename: String,
+    source: String,
+    statements: Vec<Statement>,
+    scan: Duration,
+    parse: Duration,
+    tokens: usize,
 }
 
+/// Wall-clock time spent in each phase of `run`, summed over every file, reported by `--time`. Files
+/// are scanned and parsed at the same time, so those phases can add up to more than the run took
+#[derive(Default)]
+struct Timings {
+    scan: Duration,
+    parse: Duration,
+    resolve: Duration,
+    /// Only measured with the VM backend
+    compile: Option<Duration>,
+    interpret: Duration,
+    tokens: usize,
+    statements: usize,
+}
+
+impl Timings {
+    fn report(&self) {
+        let total = self.scan + self.parse + self.resolve + self.compile.unwrap_or_default() + self.interpret;
+        let ms = |d: Duration| format!("{:>10.3}ms", d.as_secs_f64() * 1000.0);
+
+        eprintln!("Timings:");
+        eprintln!("  scan      {}  {} tokens", ms(self.scan), self.tokens);
+        eprintln!("  parse     {}  {} statements", ms(self.parse), self.statements);
+        eprintln!("  resolve   {}", ms(self.resolve));
+
+        if let Some(compile) = self.compile {
+            eprintln!("  compile   {}", ms(compile));
+        }
+
+        eprintln!("  interpret {}", ms(self.interpret));
+        eprintln!("  total     {}", ms(total));
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use crate::{parser::expr::NodeId, scanner::token::Token, statement::{BlockStatement, ClassDecl, ExportStatement, ForInStatement, ForStatement, FunctionDecl, IfStatement, Statement, SwitchCase, SwitchStatement, WhileStatement}};
+
+use super::{empty_block, truthiness, Pass};
+
+/// Removes statements that can never run: those after a `return`, `break` or `continue` in the same
+/// block, and branches of `if`s and bodies of `while`s whose condition is a constant that skips them
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+	fn name(&self) -> &'static str {
+		"dead code elimination"
+	}
+
+	fn run(&mut self, statements: Vec<Statement>) -> Vec<Statement> {
+		eliminate_statements(statements)
+	}
+}
+
+/// Keep the statements of a block up to and including the first one that always leaves it
+fn eliminate_statements(statements: Vec<Statement>) -> Vec<Statement> {
+	let mut live = Vec::new();
+
+	for statement in statements {
+		if let Some(statement) = eliminate_statement(statement) {
+			let leaves = always_leaves(&statement);
+			live.push(statement);
+
+			if leaves {
+				break
+			}
+		}
+	}
+
+	live
+}
+
+/// Remove the dead code in a statement, returning `None` if the statement never does anything
+fn eliminate_statement(statement: Statement) -> Option<Statement> {
+	let live = match statement {
+		Statement::Function(f) => Statement::Function(eliminate_function(f)),
+		Statement::Class(c) => Statement::Class(ClassDecl { name: c.name, methods: c.methods.into_iter().map(eliminate_function).collect() }),
+		Statement::If(i) => match truthiness(&i.condition) {
+			Some(true) => return eliminate_statement(*i.then_branch),
+			Some(false) => return i.else_branch.and_then(|e| eliminate_statement(*e)),
+			None => Statement::If(IfStatement {
+				condition: i.condition,
+				then_branch: eliminate_branch(*i.then_branch, &i.keyword),
+				else_branch: i.else_branch.map(|e| eliminate_branch(*e, &i.keyword)),
+				keyword: i.keyword
+			})
+		},
+		Statement::While(w) => match truthiness(&w.condition) {
+			Some(false) => return None,
+			_ => Statement::While(WhileStatement { body: eliminate_branch(*w.body, &w.keyword), keyword: w.keyword, condition: w.condition })
+		},
+		Statement::For(f) => match f.condition.as_ref().and_then(truthiness) {
+			// The initializer still runs, in a block of its own to keep its variables scoped
+			Some(false) => return f.initializer.map(|i| Statement::Block(BlockStatement { id: NodeId::next(), brace: f.keyword, statements: vec![*i] })),
+			_ => Statement::For(ForStatement { body: eliminate_branch(*f.body, &f.keyword), keyword: f.keyword, initializer: f.initializer, condition: f.condition, increment: f.increment })
+		},
+		Statement::ForIn(f) => Statement::ForIn(ForInStatement { body: eliminate_branch(*f.body, &f.keyword), name: f.name, keyword: f.keyword, iterable: f.iterable }),
+		Statement::Switch(s) => Statement::Switch(SwitchStatement {
+			keyword: s.keyword,
+			subject: s.subject,
+			cases: s.cases.into_iter().map(|c| SwitchCase { values: c.values, body: eliminate_block(c.body) }).collect(),
+			default: s.default.map(eliminate_block)
+		}),
+		Statement::Block(b) => Statement::Block(eliminate_block(b)),
+		Statement::Export(e) => Statement::Export(ExportStatement { keyword: e.keyword, declaration: Box::new(eliminate_statement(*e.declaration)?) }),
+		s => s,
+	};
+
+	Some(live)
+}
+
+/// Remove the dead code in a statement that must stay in place, such as a loop body
+fn eliminate_branch(statement: Statement, token: &Token) -> Box<Statement> {
+	Box::new(eliminate_statement(statement).unwrap_or_else(|| empty_block(token)))
+}
+
+fn eliminate_block(block: BlockStatement) -> BlockStatement {
+	BlockStatement { id: block.id, brace: block.brace, statements: eliminate_statements(block.statements) }
+}
+
+fn eliminate_function(f: Arc<FunctionDecl>) -> Arc<FunctionDecl> {
+	let f = Arc::unwrap_or_clone(f);
+	Arc::new(FunctionDecl { name: f.name, params: f.params, body: eliminate_statements(f.body) })
+}
+
+/// Whether control never continues past a statement to the next one in its block
+fn always_leaves(statement: &Statement) -> bool {
+	match statement {
+		Statement::Return(_) | Statement::Break() | Statement::Continue() => true,
+		Statement::Block(b) => b.statements.iter().any(always_leaves),
+		Statement::If(i) => always_leaves(&i.then_branch) && i.else_branch.as_deref().is_some_and(always_leaves),
+		_ => false
+	}
+}
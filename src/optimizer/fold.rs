@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use crate::{parser::expr::{Expr, ExprBlock, ExprIf, ExprLiteral}, scanner::token::{Symbol, Token, TokenType}, statement::{BlockStatement, ClassDecl, ExportStatement, ExprStatement, ForInStatement, ForStatement, FunctionDecl, IfStatement, PrintStatement, ReturnStatement, Statement, SwitchCase, SwitchStatement, VarDeclaration, WhileStatement}};
+
+use super::{empty_block, truthiness, Pass};
+
+/// Folds constant expressions, and `if`s with a constant condition into the branch they take
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+	fn name(&self) -> &'static str {
+		"constant folding"
+	}
+
+	fn run(&mut self, statements: Vec<Statement>) -> Vec<Statement> {
+		fold_statements(statements)
+	}
+}
+
+/// Fold constants in a list of statements, dropping `if`s whose condition is constant and that take
+/// no branch
+fn fold_statements(statements: Vec<Statement>) -> Vec<Statement> {
+	statements.into_iter().filter_map(fold_statement).collect()
+}
+
+/// Fold constants in a statement, returning `None` when nothing would run
+fn fold_statement(statement: Statement) -> Option<Statement> {
+	let folded = match statement {
+		Statement::Print(PrintStatement(e, token)) => Statement::Print(PrintStatement(fold_expr(e), token)),
+		Statement::Expression(ExprStatement(e)) => Statement::Expression(ExprStatement(fold_expr(e))),
+		Statement::Function(f) => Statement::Function(fold_function(f)),
+		Statement::Class(c) => Statement::Class(ClassDecl { name: c.name, methods: c.methods.into_iter().map(fold_function).collect() }),
+		Statement::Return(r) => Statement::Return(ReturnStatement { keyword: r.keyword, value: r.value.map(fold_expr) }),
+		Statement::If(i) => return fold_if(i),
+		Statement::While(w) => Statement::While(WhileStatement { keyword: w.keyword.clone(), condition: fold_expr(w.condition), body: fold_branch(*w.body, &w.keyword) }),
+		Statement::For(f) => Statement::For(ForStatement {
+			keyword: f.keyword.clone(),
+			initializer: f.initializer.and_then(|i| fold_statement(*i)).map(Box::new),
+			condition: f.condition.map(fold_expr),
+			increment: f.increment.map(fold_expr),
+			body: fold_branch(*f.body, &f.keyword)
+		}),
+		Statement::ForIn(f) => Statement::ForIn(ForInStatement { iterable: fold_expr(f.iterable), body: fold_branch(*f.body, &f.keyword), name: f.name, keyword: f.keyword }),
+		Statement::Switch(s) => Statement::Switch(SwitchStatement {
+			keyword: s.keyword,
+			subject: fold_expr(s.subject),
+			cases: s.cases.into_iter().map(|c| SwitchCase { values: c.values.into_iter().map(fold_expr).collect(), body: fold_block(c.body) }).collect(),
+			default: s.default.map(fold_block)
+		}),
+		Statement::Var(v) => Statement::Var(fold_var(v)),
+		Statement::MultiVar(v) => Statement::MultiVar(v.into_iter().map(fold_var).collect()),
+		Statement::Block(b) => Statement::Block(fold_block(b)),
+		Statement::Export(e) => Statement::Export(ExportStatement { keyword: e.keyword, declaration: Box::new(fold_statement(*e.declaration)?) }),
+		s @ (Statement::Break() | Statement::Continue() | Statement::Import(_)) => s,
+	};
+
+	Some(folded)
+}
+
+/// Fold an `if` statement, keeping only the branch taken when the condition is constant. A branch
+/// runs in the scope of the `if`, so it can stand in for it unchanged
+fn fold_if(i: IfStatement) -> Option<Statement> {
+	let condition = fold_expr(i.condition);
+
+	match truthiness(&condition) {
+		Some(true) => fold_statement(*i.then_branch),
+		Some(false) => i.else_branch.and_then(|e| fold_statement(*e)),
+		None => Some(Statement::If(IfStatement {
+			condition,
+			then_branch: fold_branch(*i.then_branch, &i.keyword),
+			else_branch: i.else_branch.map(|e| fold_branch(*e, &i.keyword)),
+			keyword: i.keyword
+		}))
+	}
+}
+
+/// Fold a statement that must stay in place, such as a loop body, using an empty block for one that
+/// would be dropped
+fn fold_branch(statement: Statement, token: &Token) -> Box<Statement> {
+	Box::new(fold_statement(statement).unwrap_or_else(|| empty_block(token)))
+}
+
+fn fold_block(block: BlockStatement) -> BlockStatement {
+	BlockStatement { id: block.id, brace: block.brace, statements: fold_statements(block.statements) }
+}
+
+fn fold_var(v: VarDeclaration) -> VarDeclaration {
+	VarDeclaration { name: v.name, initializer: v.initializer.map(fold_expr), is_const: v.is_const }
+}
+
+fn fold_function(f: Arc<FunctionDecl>) -> Arc<FunctionDecl> {
+	let f = Arc::unwrap_or_clone(f);
+	Arc::new(FunctionDecl { name: f.name, params: f.params, body: fold_statements(f.body) })
+}
+
+/// Fold the constant parts of an expression. Operations that would fail at runtime, like dividing
+/// by zero or adding a number to a string, are left for the runtime to report
+fn fold_expr(expr: Expr) -> Expr {
+	match expr {
+		Expr::Grouping(g) => match fold_expr(*g.0) {
+			Expr::Literal(l) => Expr::Literal(l),
+			e => Expr::new_grouping(e)
+		},
+		Expr::Unary(mut u) => {
+			let right = fold_expr(*u.right);
+
+			match (&u.operator.token_type, &right) {
+				(TokenType::MINUS, Expr::Literal(ExprLiteral::NUMBER(n))) => Expr::Literal(ExprLiteral::NUMBER(-n)),
+				(TokenType::BANG, _) => match truthiness(&right) {
+					Some(truthy) => boolean(!truthy),
+					None => Expr::new_unary(u.operator, right)
+				},
+				_ => {
+					u.right = Box::new(right);
+					Expr::Unary(u)
+				}
+			}
+		},
+		Expr::Binary(b) => {
+			let left = fold_expr(*b.left);
+			let right = fold_expr(*b.right);
+
+			match (&left, &right) {
+				(Expr::Literal(l), Expr::Literal(r)) => match fold_binary(&b.operator, l, r) {
+					Some(v) => Expr::Literal(v),
+					None => Expr::new_binary(left, b.operator, right)
+				},
+				_ => Expr::new_binary(left, b.operator, right)
+			}
+		},
+		Expr::Logical(mut l) => {
+			let left = fold_expr(*l.left);
+			let right = fold_expr(*l.right);
+
+			// `or` keeps a truthy left side and `and` a falsy one, otherwise the result is the right side
+			match truthiness(&left) {
+				Some(truthy) if truthy == (l.operator.token_type == TokenType::OR) => left,
+				Some(_) => right,
+				None => {
+					l.left = Box::new(left);
+					l.right = Box::new(right);
+					Expr::Logical(l)
+				}
+			}
+		},
+		Expr::If(i) => {
+			let condition = fold_expr(*i.condition);
+
+			match truthiness(&condition) {
+				Some(true) => fold_expr(*i.then_branch),
+				Some(false) => i.else_branch.map_or(Expr::Literal(ExprLiteral::Null), |e| fold_expr(*e)),
+				None => Expr::If(ExprIf {
+					keyword: i.keyword,
+					condition: Box::new(condition),
+					then_branch: Box::new(fold_expr(*i.then_branch)),
+					else_branch: i.else_branch.map(|e| Box::new(fold_expr(*e)))
+				})
+			}
+		},
+		Expr::Block(b) => Expr::Block(ExprBlock { statements: fold_statements(b.statements), value: b.value.map(|v| Box::new(fold_expr(*v))) }),
+		Expr::Call(mut c) => {
+			c.callee = Box::new(fold_expr(*c.callee));
+			c.arguments = c.arguments.into_iter().map(fold_expr).collect();
+			Expr::Call(c)
+		},
+		Expr::Assignment(mut a) => {
+			a.value = Box::new(fold_expr(*a.value));
+			Expr::Assignment(a)
+		},
+		Expr::Get(mut g) => {
+			g.object = Box::new(fold_expr(*g.object));
+			Expr::Get(g)
+		},
+		Expr::Set(mut s) => {
+			s.object = Box::new(fold_expr(*s.object));
+			s.value = Box::new(fold_expr(*s.value));
+			Expr::Set(s)
+		},
+		Expr::Array(mut a) => {
+			a.elements = a.elements.into_iter().map(fold_expr).collect();
+			Expr::Array(a)
+		},
+		Expr::Index(mut i) => {
+			i.object = Box::new(fold_expr(*i.object));
+			i.index = Box::new(fold_expr(*i.index));
+			Expr::Index(i)
+		},
+		Expr::IndexSet(mut i) => {
+			i.object = Box::new(fold_expr(*i.object));
+			i.index = Box::new(fold_expr(*i.index));
+			i.value = Box::new(fold_expr(*i.value));
+			Expr::IndexSet(i)
+		},
+		e @ (Expr::Literal(_) | Expr::Variable(_) | Expr::This(_)) => e,
+	}
+}
+
+/// The result of a binary operation on two literals, if it can be computed without an error
+fn fold_binary(operator: &Token, left: &ExprLiteral, right: &ExprLiteral) -> Option<ExprLiteral> {
+	use ExprLiteral::{NUMBER, STRING};
+
+	let folded = match (&operator.token_type, left, right) {
+		(TokenType::PLUS, NUMBER(l), NUMBER(r)) => NUMBER(l + r),
+		(TokenType::PLUS, STRING(l), STRING(r)) => STRING(Symbol::intern(&format!("{}{}", l, r))),
+		(TokenType::MINUS, NUMBER(l), NUMBER(r)) => NUMBER(l - r),
+		(TokenType::STAR, NUMBER(l), NUMBER(r)) => NUMBER(l * r),
+		(TokenType::SLASH, NUMBER(l), NUMBER(r)) if *r != 0.0 => NUMBER(l / r),
+		(TokenType::GREATER, NUMBER(l), NUMBER(r)) => literal_boolean(l > r),
+		(TokenType::GREATER_EQUAL, NUMBER(l), NUMBER(r)) => literal_boolean(l >= r),
+		(TokenType::LESS, NUMBER(l), NUMBER(r)) => literal_boolean(l < r),
+		(TokenType::LESS_EQUAL, NUMBER(l), NUMBER(r)) => literal_boolean(l <= r),
+		(TokenType::EQUAL_EQUAL, l, r) => literal_boolean(literals_equal(l, r)),
+		(TokenType::BANG_EQUAL, l, r) => literal_boolean(!literals_equal(l, r)),
+		_ => return None
+	};
+
+	Some(folded)
+}
+
+/// Whether two literals are equal at runtime. Unlike `ExprLiteral`'s own equality, NaN is never
+/// equal to itself
+fn literals_equal(left: &ExprLiteral, right: &ExprLiteral) -> bool {
+	match (left, right) {
+		(ExprLiteral::NUMBER(l), ExprLiteral::NUMBER(r)) => l == r,
+		(l, r) => l == r
+	}
+}
+
+fn literal_boolean(b: bool) -> ExprLiteral {
+	if b { ExprLiteral::True } else { ExprLiteral::False }
+}
+
+fn boolean(b: bool) -> Expr {
+	Expr::Literal(literal_boolean(b))
+}
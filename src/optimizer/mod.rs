@@ -0,0 +1,75 @@
+use crate::{log, parser::expr::{Expr, ExprLiteral, NodeId}, scanner::token::Token, statement::{BlockStatement, Statement}};
+
+pub mod dce;
+pub mod fold;
+pub mod simplify;
+
+/// The highest optimization level, the one `--opt` asks for
+pub const MAX_LEVEL: usize = 2;
+
+/// A rewrite of a parsed program that does less work when run, without changing what it does.
+/// Passes run before resolution, so both backends get the rewritten program
+pub trait Pass {
+	/// What the pass does, for `--verbose`
+	fn name(&self) -> &'static str;
+
+	fn run(&mut self, statements: Vec<Statement>) -> Vec<Statement>;
+}
+
+/// Passes run one after another, each on the output of the last
+#[derive(Default)]
+pub struct Pipeline {
+	passes: Vec<Box<dyn Pass>>
+}
+
+impl Pipeline {
+	/// The passes for an optimization level. Level 0 does nothing, 1 folds constants and removes dead
+	/// code, and 2 also simplifies what is left
+	pub fn for_level(level: usize) -> Self {
+		let mut pipeline = Self::default();
+
+		if level >= 1 {
+			pipeline.add(fold::ConstantFolding);
+			pipeline.add(dce::DeadCodeElimination);
+		}
+
+		if level >= 2 {
+			pipeline.add(simplify::Simplification);
+		}
+
+		pipeline
+	}
+
+	/// Add a pass to run after the ones already added
+	pub fn add(&mut self, pass: impl Pass + 'static) {
+		self.passes.push(Box::new(pass));
+	}
+
+	pub fn run(&mut self, mut statements: Vec<Statement>) -> Vec<Statement> {
+		for pass in &mut self.passes {
+			log::verbose(&format!("Running the {} pass", pass.name()));
+			statements = pass.run(statements);
+		}
+
+		statements
+	}
+}
+
+/// Optimize a parsed program at the given level
+pub fn optimize(statements: Vec<Statement>, level: usize) -> Vec<Statement> {
+	Pipeline::for_level(level).run(statements)
+}
+
+/// Whether an expression is truthy, if it is a literal
+fn truthiness(expr: &Expr) -> Option<bool> {
+	match expr {
+		Expr::Literal(ExprLiteral::Null | ExprLiteral::False) => Some(false),
+		Expr::Literal(_) => Some(true),
+		_ => None
+	}
+}
+
+/// A block that does nothing, to stand in for a removed statement where one is required
+fn empty_block(token: &Token) -> Statement {
+	Statement::Block(BlockStatement { id: NodeId::next(), brace: token.clone(), statements: Vec::new() })
+}
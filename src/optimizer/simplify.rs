@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use crate::{parser::expr::{Expr, ExprBlock, ExprIf, ExprLiteral}, statement::{declares, BlockStatement, ClassDecl, ExportStatement, ExprStatement, ForInStatement, ForStatement, FunctionDecl, IfStatement, PrintStatement, ReturnStatement, Statement, SwitchCase, SwitchStatement, VarDeclaration, WhileStatement}};
+
+use super::Pass;
+
+/// Removes structure that only costs time when run: blocks that declare nothing, so need no scope of
+/// their own, expression statements that do nothing, and parentheses
+pub struct Simplification;
+
+impl Pass for Simplification {
+	fn name(&self) -> &'static str {
+		"simplification"
+	}
+
+	fn run(&mut self, statements: Vec<Statement>) -> Vec<Statement> {
+		simplify_statements(statements)
+	}
+}
+
+/// Simplify a list of statements, splicing in the statements of blocks that declare nothing
+fn simplify_statements(statements: Vec<Statement>) -> Vec<Statement> {
+	let mut simplified = Vec::with_capacity(statements.len());
+
+	for statement in statements {
+		match simplify_statement(statement) {
+			Statement::Block(b) if !declares(&b.statements) => simplified.extend(b.statements),
+			Statement::Expression(ExprStatement(Expr::Literal(_))) => {},
+			s => simplified.push(s),
+		}
+	}
+
+	simplified
+}
+
+fn simplify_statement(statement: Statement) -> Statement {
+	match statement {
+		Statement::Print(PrintStatement(e, token)) => Statement::Print(PrintStatement(simplify_expr(e), token)),
+		Statement::Expression(ExprStatement(e)) => Statement::Expression(ExprStatement(simplify_expr(e))),
+		Statement::Function(f) => Statement::Function(simplify_function(f)),
+		Statement::Class(c) => Statement::Class(ClassDecl { name: c.name, methods: c.methods.into_iter().map(simplify_function).collect() }),
+		Statement::Return(r) => Statement::Return(ReturnStatement { keyword: r.keyword, value: r.value.map(simplify_expr) }),
+		Statement::If(i) => Statement::If(IfStatement {
+			keyword: i.keyword,
+			condition: simplify_expr(i.condition),
+			then_branch: simplify_branch(*i.then_branch),
+			else_branch: i.else_branch.map(|e| simplify_branch(*e))
+		}),
+		Statement::While(w) => Statement::While(WhileStatement { keyword: w.keyword, condition: simplify_expr(w.condition), body: simplify_branch(*w.body) }),
+		Statement::For(f) => Statement::For(ForStatement {
+			keyword: f.keyword,
+			initializer: f.initializer.map(|i| Box::new(simplify_statement(*i))),
+			condition: f.condition.map(simplify_expr),
+			increment: f.increment.map(simplify_expr),
+			body: simplify_branch(*f.body)
+		}),
+		Statement::ForIn(f) => Statement::ForIn(ForInStatement { iterable: simplify_expr(f.iterable), body: simplify_branch(*f.body), name: f.name, keyword: f.keyword }),
+		Statement::Switch(s) => Statement::Switch(SwitchStatement {
+			keyword: s.keyword,
+			subject: simplify_expr(s.subject),
+			cases: s.cases.into_iter().map(|c| SwitchCase { values: c.values.into_iter().map(simplify_expr).collect(), body: simplify_block(c.body) }).collect(),
+			default: s.default.map(simplify_block)
+		}),
+		Statement::Var(v) => Statement::Var(simplify_var(v)),
+		Statement::MultiVar(v) => Statement::MultiVar(v.into_iter().map(simplify_var).collect()),
+		Statement::Block(b) => Statement::Block(simplify_block(b)),
+		Statement::Export(e) => Statement::Export(ExportStatement { keyword: e.keyword, declaration: Box::new(simplify_statement(*e.declaration)) }),
+		s @ (Statement::Break() | Statement::Continue() | Statement::Import(_)) => s,
+	}
+}
+
+/// Simplify a statement that must stay in place, such as a loop body. A block holding a single
+/// statement that declares nothing is replaced by that statement
+fn simplify_branch(statement: Statement) -> Box<Statement> {
+	match simplify_statement(statement) {
+		Statement::Block(mut b) if b.statements.len() == 1 && !declares(&b.statements) => Box::new(b.statements.remove(0)),
+		s => Box::new(s),
+	}
+}
+
+fn simplify_block(block: BlockStatement) -> BlockStatement {
+	BlockStatement { id: block.id, brace: block.brace, statements: simplify_statements(block.statements) }
+}
+
+fn simplify_var(v: VarDeclaration) -> VarDeclaration {
+	VarDeclaration { name: v.name, initializer: v.initializer.map(simplify_expr), is_const: v.is_const }
+}
+
+fn simplify_function(f: Arc<FunctionDecl>) -> Arc<FunctionDecl> {
+	let f = Arc::unwrap_or_clone(f);
+	Arc::new(FunctionDecl { name: f.name, params: f.params, body: simplify_statements(f.body) })
+}
+
+fn simplify_expr(expr: Expr) -> Expr {
+	match expr {
+		Expr::Grouping(g) => simplify_expr(*g.0),
+		Expr::Block(b) if b.statements.is_empty() => b.value.map_or(Expr::Literal(ExprLiteral::Null), |v| simplify_expr(*v)),
+		Expr::Block(b) => Expr::Block(ExprBlock { statements: simplify_statements(b.statements), value: b.value.map(|v| Box::new(simplify_expr(*v))) }),
+		Expr::Unary(mut u) => {
+			u.right = Box::new(simplify_expr(*u.right));
+			Expr::Unary(u)
+		},
+		Expr::Binary(mut b) => {
+			b.left = Box::new(simplify_expr(*b.left));
+			b.right = Box::new(simplify_expr(*b.right));
+			Expr::Binary(b)
+		},
+		Expr::Logical(mut l) => {
+			l.left = Box::new(simplify_expr(*l.left));
+			l.right = Box::new(simplify_expr(*l.right));
+			Expr::Logical(l)
+		},
+		Expr::If(i) => Expr::If(ExprIf {
+			keyword: i.keyword,
+			condition: Box::new(simplify_expr(*i.condition)),
+			then_branch: Box::new(simplify_expr(*i.then_branch)),
+			else_branch: i.else_branch.map(|e| Box::new(simplify_expr(*e)))
+		}),
+		Expr::Call(mut c) => {
+			c.callee = Box::new(simplify_expr(*c.callee));
+			c.arguments = c.arguments.into_iter().map(simplify_expr).collect();
+			Expr::Call(c)
+		},
+		Expr::Assignment(mut a) => {
+			a.value = Box::new(simplify_expr(*a.value));
+			Expr::Assignment(a)
+		},
+		Expr::Get(mut g) => {
+			g.object = Box::new(simplify_expr(*g.object));
+			Expr::Get(g)
+		},
+		Expr::Set(mut s) => {
+			s.object = Box::new(simplify_expr(*s.object));
+			s.value = Box::new(simplify_expr(*s.value));
+			Expr::Set(s)
+		},
+		Expr::Array(mut a) => {
+			a.elements = a.elements.into_iter().map(simplify_expr).collect();
+			Expr::Array(a)
+		},
+		Expr::Index(mut i) => {
+			i.object = Box::new(simplify_expr(*i.object));
+			i.index = Box::new(simplify_expr(*i.index));
+			Expr::Index(i)
+		},
+		Expr::IndexSet(mut i) => {
+			i.object = Box::new(simplify_expr(*i.object));
+			i.index = Box::new(simplify_expr(*i.index));
+			i.value = Box::new(simplify_expr(*i.value));
+			Expr::IndexSet(i)
+		},
+		e @ (Expr::Literal(_) | Expr::Variable(_) | Expr::This(_)) => e,
+	}
+}
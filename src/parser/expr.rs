@@ -1,6 +1,6 @@
-use std::{cmp::Ordering, hash::Hash};
+use std::{cmp::Ordering, hash::Hash, sync::atomic::{self, AtomicUsize}};
 
-use crate::scanner::token::Token;
+use crate::{scanner::token::{Symbol, Token}, statement::Statement, utils::number, visitor::ExprVisitor};
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub enum Expr {
@@ -15,51 +15,17 @@ pub enum Expr {
 	Set(ExprSet),
 	This(ExprThis),
 	Unary(ExprUnary),
-}
-
-impl ExprAccept for Expr {
-	fn accept(self) -> String {
-		match self {
-			Expr::Literal(x) => x.accept(),
-			Expr::Unary(u) => u.accept(),
-			Expr::Call(c) => c.accept(),
-			Expr::Get(g) => g.accept(),
-			Expr::Set(s) => s.accept(),
-			Expr::This(t) => t.accept(),
-			Expr::Binary(b) => b.accept(),
-			Expr::Grouping(g) => g.accept(),
-			Expr::Variable(v) => v.accept(),
-			Expr::Assignment(a) => a.accept(),
-			Expr::Logical(l) => l.accept(),
-		}
-	}
-}
-
-impl Expr {
-	pub fn parenthesize<T>(name: String, exprs: Vec<T>) -> String
-		where T: ExprAccept
-	{
-		let mut builder = String::new();
-
-		builder.push_str("(");
-		builder.push_str(&name);
-
-		for expr in exprs {
-			builder.push_str(" ");
-			builder.push_str(&expr.accept())
-		}
-
-		builder.push_str(")");
-		
-		builder
-
-	}
+	Block(ExprBlock),
+	If(ExprIf),
+	Array(ExprArray),
+	Index(ExprIndex),
+	IndexSet(ExprIndexSet),
 }
 
 #[derive(Clone)]
 pub enum ExprLiteral {
 	NUMBER(f64),
-	STRING(String),
+	STRING(Symbol),
 	True,
 	False,
 	Null
@@ -102,11 +68,8 @@ impl PartialEq for ExprLiteral {
 			(ExprLiteral::NUMBER(s), ExprLiteral::NUMBER(o)) => {
 				match (s.is_finite(), o.is_finite()) {
 					(true, true) => {
-						let ord = s.partial_cmp(&o).unwrap_or_else(| | {
-							eprintln!("Failed to partially compare two finite f64 values, this should not have happened. Replacing with `Ordering::Equal`");
-							return Ordering::Equal
-						});
-						match ord {
+						// Finite numbers always compare
+						match s.partial_cmp(&o).unwrap_or(Ordering::Equal) {
 							Ordering::Equal => true,
 							_ => false
 						}
@@ -128,6 +91,19 @@ impl PartialEq for ExprLiteral {
 	}
 }
 
+/// Identifies an expression that refers to a variable, so the resolver can record where it resolves
+/// to. Unlike the expression itself, it tells apart identical expressions at different sites
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId(usize);
+
+impl NodeId {
+	/// A fresh id, unique for the whole run, even across files and modules parsed separately
+	pub fn next() -> Self {
+		static NEXT: AtomicUsize = AtomicUsize::new(0);
+		Self(NEXT.fetch_add(1, atomic::Ordering::Relaxed))
+	}
+}
+
 impl Expr {
 	pub fn new_binary(left: Expr, operator: Token, right: Expr) -> Expr {
 		Expr::Binary(ExprBinary {left: Box::new(left), operator, right: Box::new(right)})
@@ -142,25 +118,60 @@ impl Expr {
 	}
 
 	pub fn new_variable(name: Token) -> Expr {
-		Expr::Variable(ExprVariable {name})
+		Expr::Variable(ExprVariable {id: NodeId::next(), name})
 	}
 
 	pub fn new_assignment(name: Token, value: Expr) -> Expr {
-		Expr::Assignment(ExprAssignment {name, value: Box::new(value)})
+		Expr::Assignment(ExprAssignment {id: NodeId::next(), name, value: Box::new(value)})
 	}
 
 	pub fn new_set(object: Expr, name: Token, value: Expr) -> Expr {
 		Expr::Set(ExprSet {name, object: Box::new(object), value: Box::new(value)})
 	}
 
+	pub fn new_index_set(object: Expr, bracket: Token, index: Expr, value: Expr) -> Expr {
+		Expr::IndexSet(ExprIndexSet {object: Box::new(object), bracket, index: Box::new(index), value: Box::new(value)})
+	}
+
+	/// The call this expression evaluates to the result of, looking through parentheses
+	pub fn tail_call(&self) -> Option<&ExprCall> {
+		match self {
+			Expr::Call(c) => Some(c),
+			Expr::Grouping(g) => g.0.tail_call(),
+			_ => None
+		}
+	}
+
+	/// The line of the first token the expression keeps, literals keep none
+	pub fn line(&self) -> Option<usize> {
+		match self {
+			Expr::Literal(_) => None,
+			Expr::Call(c) => c.callee.line().or(Some(c.paren.line)),
+			Expr::Binary(b) => b.left.line().or(Some(b.operator.line)),
+			Expr::Logical(l) => l.left.line().or(Some(l.operator.line)),
+			Expr::Unary(u) => Some(u.operator.line),
+			Expr::Grouping(g) => g.0.line(),
+			Expr::Variable(v) => Some(v.name.line),
+			Expr::Assignment(a) => Some(a.name.line),
+			Expr::Get(g) => g.object.line().or(Some(g.name.line)),
+			Expr::Set(s) => s.object.line().or(Some(s.name.line)),
+			Expr::This(t) => Some(t.keyword.line),
+			Expr::Block(b) => b.statements.iter().find_map(|s| s.line()).or_else(|| b.value.as_ref().and_then(|v| v.line())),
+			Expr::If(i) => Some(i.keyword.line),
+			Expr::Array(a) => Some(a.bracket.line),
+			Expr::Index(i) => i.object.line().or(Some(i.bracket.line)),
+			Expr::IndexSet(i) => i.object.line().or(Some(i.bracket.line)),
+		}
+	}
+
 }
 
 impl ToString for ExprLiteral {
 
 	fn to_string(&self) -> String {
 		match self {
-			ExprLiteral::NUMBER(n) => {format!("{:?}", n)},
-			ExprLiteral::STRING(s) => {s.clone()},
+			ExprLiteral::NUMBER(n) => {number::format_literal(*n)},
+			ExprLiteral::STRING(s) => {s.to_string()},
 			ExprLiteral::True => {"true".to_string()},
 			ExprLiteral::False => {"false".to_string()},
 			ExprLiteral::Null => {"nil".to_string()},
@@ -179,6 +190,7 @@ pub struct ExprUnary {
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ExprCall {
+	pub id: NodeId,
 	pub callee: Box<Expr>,
 	pub paren: Token,
 	pub arguments: Vec<Expr>
@@ -199,6 +211,7 @@ pub struct ExprSet {
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ExprThis {
+	pub id: NodeId,
 	pub keyword: Token,
 }
 
@@ -218,96 +231,152 @@ pub struct ExprLogical {
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ExprVariable {
+	pub id: NodeId,
 	pub name: Token
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ExprAssignment {
+	pub id: NodeId,
 	pub name: Token,
 	pub value: Box<Expr>
 }
 
+/// An array literal, e.g. `[1, 2, 3]`
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ExprArray {
+	pub bracket: Token,
+	pub elements: Vec<Expr>
+}
+
+/// A subscript read, e.g. `a[i]`
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ExprIndex {
+	pub object: Box<Expr>,
+	pub bracket: Token,
+	pub index: Box<Expr>
+}
+
+/// A subscript write, e.g. `a[i] = v`
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ExprIndexSet {
+	pub object: Box<Expr>,
+	pub bracket: Token,
+	pub index: Box<Expr>,
+	pub value: Box<Expr>
+}
+
+/// A block evaluating to the value of its trailing expression, e.g. `{ var a = 1; a + 1 }`
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ExprBlock {
+	pub statements: Vec<Statement>,
+	pub value: Option<Box<Expr>>
+}
+
+/// An if evaluating to the value of the branch taken, or nil when no branch is taken
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ExprIf {
+	pub keyword: Token,
+	pub condition: Box<Expr>,
+	pub then_branch: Box<Expr>,
+	pub else_branch: Option<Box<Expr>>
+}
+
 impl ExprBinary {
 		pub fn new(left: Expr, operator: Token, right: Expr) -> Self {
 			Self { left: Box::new(left), operator, right: Box::new(right) }
 		}
 }
 
-pub trait ExprAccept {
-	fn accept(self) -> String;
-}
+/// Prints an expression as nested parenthesized prefix forms, e.g. `(+ 1.0 (group 2.0))`
+pub struct AstPrinter;
 
-impl ExprAccept for ExprBinary {
-	fn accept(self) -> String {
-		Expr::parenthesize(self.operator.lexeme, vec![*self.left, *self.right])
+impl AstPrinter {
+	pub fn print(expr: &Expr) -> String {
+		expr.accept(&mut AstPrinter)
 	}
-}
 
-impl ExprAccept for ExprUnary {
-	fn accept(self) -> String {
-		Expr::parenthesize(self.operator.lexeme, vec![*self.right])
+	fn parenthesize<'a>(&mut self, name: &str, exprs: impl IntoIterator<Item = &'a Expr>) -> String {
+		let mut builder = String::new();
+
+		builder.push('(');
+		builder.push_str(name);
+
+		for expr in exprs {
+			builder.push(' ');
+			builder.push_str(&expr.accept(self))
+		}
+
+		builder.push(')');
+
+		builder
 	}
 }
 
-impl ExprAccept for ExprCall {
-	fn accept(self) -> String {
-		self.paren.lexeme
+impl ExprVisitor for AstPrinter {
+	type Output = String;
+
+	fn visit_binary_expr(&mut self, expr: &ExprBinary) -> String {
+		self.parenthesize(&expr.operator.lexeme, [&*expr.left, &*expr.right])
 	}
-}
 
-impl ExprAccept for ExprGet {
-	fn accept(self) -> String {
-		Expr::parenthesize(self.name.lexeme, vec![*self.object])
+	fn visit_unary_expr(&mut self, expr: &ExprUnary) -> String {
+		self.parenthesize(&expr.operator.lexeme, [&*expr.right])
 	}
-}
 
-impl ExprAccept for ExprSet {
-	fn accept(self) -> String {
-		Expr::parenthesize(self.name.lexeme, vec![*self.object, *self.value])
+	fn visit_call_expr(&mut self, expr: &ExprCall) -> String {
+		self.parenthesize("call", std::iter::once(&*expr.callee).chain(&expr.arguments))
 	}
-}
 
+	fn visit_get_expr(&mut self, expr: &ExprGet) -> String {
+		self.parenthesize(&expr.name.lexeme, [&*expr.object])
+	}
 
-impl ExprAccept for ExprThis {
-	fn accept(self) -> String {
-		self.keyword.lexeme.clone()
+	fn visit_set_expr(&mut self, expr: &ExprSet) -> String {
+		self.parenthesize(&expr.name.lexeme, [&*expr.object, &*expr.value])
 	}
-}
 
-impl ExprAccept for ExprLiteral {
-	fn accept(self) -> String {
-		self.to_string()
+	fn visit_this_expr(&mut self, expr: &ExprThis) -> String {
+		expr.keyword.lexeme.to_string()
 	}
-}
 
-impl ExprAccept for ExprLogical {
-	fn accept(self) -> String {
-		Expr::parenthesize(self.operator.lexeme, vec![*self.left, *self.right])
+	fn visit_literal_expr(&mut self, expr: &ExprLiteral) -> String {
+		expr.to_string()
 	}
-}
 
-impl ExprAccept for ExprGrouping {
-	fn accept(self) -> String {
-		Expr::parenthesize("group".to_string(), vec![*self.0])
+	fn visit_logical_expr(&mut self, expr: &ExprLogical) -> String {
+		self.parenthesize(&expr.operator.lexeme, [&*expr.left, &*expr.right])
 	}
-}
 
-impl ExprAccept for ExprVariable {
-	fn accept(self) -> String {
-		self.name.to_string()
+	fn visit_grouping_expr(&mut self, expr: &ExprGrouping) -> String {
+		self.parenthesize("group", [&*expr.0])
 	}
-}
 
-impl ExprAccept for ExprAssignment {
-	fn accept(self) -> String {
-		String::new()
+	fn visit_variable_expr(&mut self, expr: &ExprVariable) -> String {
+		expr.name.lexeme.to_string()
 	}
-}
 
-pub struct AstPrinter;
+	fn visit_assignment_expr(&mut self, expr: &ExprAssignment) -> String {
+		self.parenthesize(&format!("= {}", expr.name.lexeme), [&*expr.value])
+	}
 
-impl AstPrinter {
-	pub fn print(expr: Expr) -> String{
-		return expr.accept()
+	fn visit_block_expr(&mut self, expr: &ExprBlock) -> String {
+		self.parenthesize("block", expr.value.as_deref())
+	}
+
+	fn visit_if_expr(&mut self, expr: &ExprIf) -> String {
+		self.parenthesize("if", [&*expr.condition, &*expr.then_branch].into_iter().chain(expr.else_branch.as_deref()))
+	}
+
+	fn visit_array_expr(&mut self, expr: &ExprArray) -> String {
+		self.parenthesize("array", &expr.elements)
 	}
-}
\ No newline at end of file
+
+	fn visit_index_expr(&mut self, expr: &ExprIndex) -> String {
+		self.parenthesize("index", [&*expr.object, &*expr.index])
+	}
+
+	fn visit_index_set_expr(&mut self, expr: &ExprIndexSet) -> String {
+		self.parenthesize("index-set", [&*expr.object, &*expr.index, &*expr.value])
+	}
+}
@@ -1,43 +1,87 @@
-use expr::{Expr, ExprCall, ExprGet, ExprLiteral, ExprLogical, ExprThis};
-use error::{ParserError, ParserResult};
+use expr::{Expr, ExprArray, ExprCall, ExprGet, ExprIndex, ExprLiteral, ExprLogical, ExprThis, NodeId};
 
-use crate::scanner::token::{Literal, Token, TokenType};
+use crate::{error::{LoxError, LoxResult}, scanner::{token::{Literal, Symbol, Token, TokenType}, Scanner}};
 
 pub mod expr;
-pub mod error;
 
 /// A struct representing the parser, moving token by token
 pub struct Parser {
-	pub tokens: Vec<Token>,
-	current: usize,
-	had_error: bool,
+	/// Where tokens are pulled from, one at a time as parsing needs them
+	tokens: Box<dyn Iterator<Item = LoxResult<Token>>>,
+	current: Token,
+	previous: Option<Token>,
+	/// Errors found that didn't stop parsing, returned once it is done
+	pub errors: Vec<LoxError>,
+	/// Scanner errors met while pulling tokens. Parse errors after one are most likely caused by it,
+	/// so callers report these instead when there are any
+	pub scan_errors: Vec<LoxError>,
 	pub loop_depth: usize,
+	/// When set, a line break ends a statement wherever a semicolon is expected
+	pub optional_semicolons: bool,
 }
 
 impl Parser {
-	/// Initialize a new parser
+	/// Initialize a new parser over scanned tokens
 	pub fn new(tokens: Vec<Token>) -> Self {
-		Parser {tokens, current: 0, had_error: false, loop_depth: 0}
+		Self::from_results(Box::new(tokens.into_iter().map(Ok)))
+	}
+
+	/// Initialize a parser that scans the source as it goes, collecting scanner errors when it reaches them
+	pub fn streaming(scanner: Scanner) -> Self {
+		Self::from_results(Box::new(scanner))
+	}
+
+	fn from_results(tokens: Box<dyn Iterator<Item = LoxResult<Token>>>) -> Self {
+		let eof = Token::new(TokenType::EOF, "", Literal::Null, 0);
+		let mut parser = Parser {tokens, current: eof, previous: None, errors: Vec::new(), scan_errors: Vec::new(), loop_depth: 0, optional_semicolons: false};
+
+		parser.current = parser.pull();
+		parser
+	}
+
+	/// Take the next token from the source, collecting any scanner errors on the way. Past the end,
+	/// the EOF token is repeated
+	fn pull(&mut self) -> Token {
+		loop {
+			match self.tokens.next() {
+				Some(Ok(token)) => return token,
+				Some(Err(e)) => self.scan_errors.push(e),
+				None => return Token::new(TokenType::EOF, "", Literal::Null, self.current.line)
+			}
+		}
 	}
 }
 
 impl Parser {
 	/// Begin parsing
-	pub fn parse(&mut self) -> Option<Expr> {
-		self.expression().ok()
+	pub fn parse(&mut self) -> Result<Expr, Vec<LoxError>> {
+		let result = self.expression();
+		self.finish(result)
+	}
+
+	/// The result of parsing, failing with every error found along the way if there were any
+	pub fn finish<T>(&mut self, result: LoxResult<T>) -> Result<T, Vec<LoxError>> {
+		match result {
+			Ok(parsed) if self.errors.is_empty() => Ok(parsed),
+			Ok(_) => Err(std::mem::take(&mut self.errors)),
+			Err(e) => {
+				self.errors.push(e);
+				Err(std::mem::take(&mut self.errors))
+			}
+		}
 	}
 
 	/// Parse an expression
-	pub fn expression(&mut self) -> ParserResult<Expr> {
+	pub fn expression(&mut self) -> LoxResult<Expr> {
 		return self.assignment()
 	}
 
 	/// Parse an assignment
-	pub fn assignment(&mut self) -> ParserResult<Expr> {
+	pub fn assignment(&mut self) -> LoxResult<Expr> {
 		let expr = self.or()?;
 
-		if self.match_next(vec![TokenType::EQUAL]) {
-			let equals = self.previous();
+		if self.match_next(&[TokenType::EQUAL]) {
+			let equals = self.previous().clone();
 			let value = self.assignment()?;
 
 			match expr {
@@ -47,8 +91,11 @@ impl Parser {
 				},
 				Expr::Get(g) => {
 					return Ok(Expr::new_set(*g.object, g.name, value))
+				},
+				Expr::Index(i) => {
+					return Ok(Expr::new_index_set(*i.object, i.bracket, *i.index, value))
 				}
-				_ => return Err(ParserError::new(equals, "Invalid assignment target"))
+				_ => return Err(LoxError::syntax(equals, "Invalid assignment target"))
 			}
 		}
 
@@ -56,11 +103,11 @@ impl Parser {
 	}
 
 	/// Parse a logical or
-	pub fn or(&mut self) -> ParserResult<Expr> {
+	pub fn or(&mut self) -> LoxResult<Expr> {
 		let mut expr = self.and()?;
 
-		while self.match_next(vec![TokenType::OR]) {
-			let operator = self.previous();
+		while self.match_next(&[TokenType::OR]) {
+			let operator = self.previous().clone();
 			let right = Box::new(self.and()?);
 
 			expr = Expr::Logical(ExprLogical { left: Box::new(expr), operator, right});
@@ -70,11 +117,11 @@ impl Parser {
 	}
 
 	/// Parse a Logical and
-	pub fn and(&mut self) -> ParserResult<Expr> {
+	pub fn and(&mut self) -> LoxResult<Expr> {
 		let mut expr = self.equality()?;
 
-		while self.match_next(vec![TokenType::AND]) {
-			let operator = self.previous();
+		while self.match_next(&[TokenType::AND]) {
+			let operator = self.previous().clone();
 			let right = Box::new(self.equality()?);
 
 			expr = Expr::Logical(ExprLogical {left: Box::new(expr), operator, right});
@@ -84,11 +131,11 @@ impl Parser {
 	}
 
 	/// Parse equality
-	pub fn equality(&mut self) -> ParserResult<Expr> {
+	pub fn equality(&mut self) -> LoxResult<Expr> {
 		let mut expr = self.comparison()?;
 
-		while self.match_next(vec![TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL]) {
-			let operator = self.previous();
+		while self.match_next(&[TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL]) {
+			let operator = self.previous().clone();
 			let right = self.comparison()?;
 
 			expr = Expr::new_binary(expr, operator, right);
@@ -99,7 +146,7 @@ impl Parser {
 
 	/// Check if the current token matches at least one in a given token. If true, it advances "current"
 	/// and returns true, returns false otherwise
-	pub fn match_next(&mut self, token_types: Vec<TokenType>) -> bool {
+	pub fn match_next(&mut self, token_types: &[TokenType]) -> bool {
 		for token_type in token_types {
 			if self.check(token_type) {
 				self.advance();
@@ -111,18 +158,19 @@ impl Parser {
 	}
 
 	/// Moves "current" one step forward if not at end of file
-	pub fn advance(&mut self) -> Token {
+	pub fn advance(&mut self) -> &Token {
 		if !self.is_at_end() {
-			self.current += 1;
+			let next = self.pull();
+			self.previous = Some(std::mem::replace(&mut self.current, next));
 		}
 		return self.previous();
 	}
 
 
 	/// Checks if the current token's type matches the given token's type
-	pub fn check(&self, token_type: TokenType) -> bool {
+	pub fn check(&self, token_type: &TokenType) -> bool {
 		if self.is_at_end() {return false}
-		return self.peek().token_type == token_type
+		return self.peek().token_type == *token_type
 	}
 
 	/// Checks if the end of the file has been reached;
@@ -131,22 +179,22 @@ impl Parser {
 	}
 
 	/// Gets the current token;
-	pub fn peek(&self) -> Token {
-		return self.tokens.get(self.current).unwrap().clone()
+	pub fn peek(&self) -> &Token {
+		return &self.current
 	}
 
 	/// Gets the previous token
-	pub fn previous(&self) -> Token {
-		return self.tokens.get(self.current - 1).unwrap().clone()
+	pub fn previous(&self) -> &Token {
+		return self.previous.as_ref().expect("No token has been consumed yet")
 	}
 
 
 	/// Parse comparison
-	pub fn comparison(&mut self) -> ParserResult<Expr> {
+	pub fn comparison(&mut self) -> LoxResult<Expr> {
 		let mut expr = self.term()?;
 
-		while self.match_next(vec![TokenType::GREATER, TokenType::GREATER_EQUAL, TokenType::LESS, TokenType::LESS_EQUAL]) {
-			let operator = self.previous();
+		while self.match_next(&[TokenType::GREATER, TokenType::GREATER_EQUAL, TokenType::LESS, TokenType::LESS_EQUAL]) {
+			let operator = self.previous().clone();
 			let right = self.term()?;
 
 			expr = Expr::new_binary(expr, operator, right);
@@ -156,24 +204,13 @@ impl Parser {
 	}
 
 	/// Parse a term
-	pub fn term(&mut self) -> ParserResult<Expr> {
+	pub fn term(&mut self) -> LoxResult<Expr> {
 		let mut expr = self.factor()?;
 
-		while self.match_next(vec![TokenType::MINUS, TokenType::PLUS]) {
-			// If invalid LHS
-			match expr {
-				Expr::Literal(ExprLiteral::Null) => {return Err(self.error(self.previous(), "Invalid LHS for binary expression"))},
-				_ => {}
-			}
-
-			let operator = self.previous();
+		while self.match_next(&[TokenType::MINUS, TokenType::PLUS]) {
+			let operator = self.previous().clone();
 			let right = self.factor()?;
 
-			match right {
-				Expr::Literal(ExprLiteral::Null) => {return Err(self.error(self.peek(), "Invalid RHS for binary expression"))},
-				_ => {}
-			}
-
 			expr = Expr::new_binary(expr, operator, right);
 		}
 
@@ -181,11 +218,11 @@ impl Parser {
 	}
 
 	/// Parse a factor
-	pub fn factor(&mut self) -> ParserResult<Expr> {
+	pub fn factor(&mut self) -> LoxResult<Expr> {
 		let mut expr = self.unary()?;
 
-		while self.match_next(vec![TokenType::SLASH, TokenType::STAR]) {
-			let operator = self.previous();
+		while self.match_next(&[TokenType::SLASH, TokenType::STAR]) {
+			let operator = self.previous().clone();
 			let right = self.unary()?;
 			
 			expr = Expr::new_binary(expr, operator, right);
@@ -194,9 +231,9 @@ impl Parser {
 		Ok(expr)
 	}
 
-	pub fn unary(&mut self) -> ParserResult<Expr> {
-		if self.match_next(vec![TokenType::BANG, TokenType::MINUS]) {
-			let operator = self.previous();
+	pub fn unary(&mut self) -> LoxResult<Expr> {
+		if self.match_next(&[TokenType::BANG, TokenType::MINUS]) {
+			let operator = self.previous().clone();
 			let right = self.unary()?;
 			return Ok(Expr::new_unary(operator, right))
 		}
@@ -206,15 +243,20 @@ impl Parser {
 	}
 	
 	/// Begin parsing a call
-	pub fn call(&mut self) -> ParserResult<Expr> {
+	pub fn call(&mut self) -> LoxResult<Expr> {
 		let mut expr = self.primary()?;
 
 		loop {
-			if self.match_next(vec![TokenType::LEFT_PAREN]) {
+			if self.match_next(&[TokenType::LEFT_PAREN]) {
 				expr = self.finish_call(expr)?;
-			} else if self.match_next(vec![TokenType::DOT]) {
+			} else if self.match_next(&[TokenType::DOT]) {
 				let name = self.consume(TokenType::IDENTIFIER, "Expect property name after '.'")?;
 				expr = Expr::Get(ExprGet {name, object: Box::new(expr)})
+			} else if self.match_next(&[TokenType::LEFT_BRACKET]) {
+				let bracket = self.previous().clone();
+				let index = self.expression()?;
+				self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after index.")?;
+				expr = Expr::Index(ExprIndex {object: Box::new(expr), bracket, index: Box::new(index)})
 			} else {
 				break
 			}
@@ -224,16 +266,17 @@ impl Parser {
 	}
 
 	/// Finish parsing a call
-	pub fn finish_call(&mut self, callee: Expr) -> ParserResult<Expr> {
+	pub fn finish_call(&mut self, callee: Expr) -> LoxResult<Expr> {
 		let mut arguments = Vec::new();
 
-		if !self.check(TokenType::RIGHT_PAREN) {
+		if !self.check(&TokenType::RIGHT_PAREN) {
 			loop {
 				if arguments.len() >= 255 {
-					self.error(self.peek(), "Can't have more than 255 arguments");
+					self.record_error(self.peek().clone(), "Can't have more than 255 arguments");
 				}
 				arguments.push(self.expression()?);
-				if !self.match_next(vec![TokenType::COMMA]) {
+				// A trailing comma is allowed before the closing parenthesis
+				if !self.match_next(&[TokenType::COMMA]) || self.check(&TokenType::RIGHT_PAREN) {
 					break
 				}
 			}
@@ -241,18 +284,18 @@ impl Parser {
 
 		let paren = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments")?;
 
-		Ok(Expr::Call(ExprCall {callee: Box::new(callee), arguments, paren}))
+		Ok(Expr::Call(ExprCall {id: NodeId::next(), callee: Box::new(callee), arguments, paren}))
 
 
 	}
 
 	/// Parse a primary expression
-	pub fn primary(&mut self) -> ParserResult<Expr> {
-		if self.match_next(vec![TokenType::FALSE]) {return Ok(Expr::Literal(ExprLiteral::False))}
-		if self.match_next(vec![TokenType::TRUE]) {return Ok(Expr::Literal(ExprLiteral::True))}
-		if self.match_next(vec![TokenType::NIL]) {return Ok(Expr::Literal(ExprLiteral::Null))}
+	pub fn primary(&mut self) -> LoxResult<Expr> {
+		if self.match_next(&[TokenType::FALSE]) {return Ok(Expr::Literal(ExprLiteral::False))}
+		if self.match_next(&[TokenType::TRUE]) {return Ok(Expr::Literal(ExprLiteral::True))}
+		if self.match_next(&[TokenType::NIL]) {return Ok(Expr::Literal(ExprLiteral::Null))}
 
-		if self.match_next(vec![TokenType::NUMBER]) {
+		if self.match_next(&[TokenType::NUMBER]) {
 			let v = match self.previous().literal {
 				Literal::Float(x) => x,
 				_ => 0.0
@@ -260,47 +303,107 @@ impl Parser {
 			return Ok(Expr::Literal(ExprLiteral::NUMBER(v)))
 		}
 
-		if self.match_next(vec![TokenType::STRING]) {
-			let v = match self.previous().literal {
+		if self.match_next(&[TokenType::STRING]) {
+			let mut v = match self.previous().literal {
 				Literal::String(x) => x,
-				_ => "".to_string()
+				_ => Symbol::default()
 			};
+
+			// Adjacent strings are one string, so long text can be split across lines without a `+`
+			if self.check(&TokenType::STRING) {
+				let mut joined = v.to_string();
+
+				while self.match_next(&[TokenType::STRING]) {
+					if let Literal::String(x) = self.previous().literal {
+						joined.push_str(&x);
+					}
+				}
+
+				v = Symbol::intern(&joined);
+			}
+
 			return Ok(Expr::Literal(ExprLiteral::STRING(v)))
 		}
 
-		if self.match_next(vec![TokenType::THIS]) {
-			return Ok(Expr::This(ExprThis {keyword:  self.previous()}))
+		if self.match_next(&[TokenType::THIS]) {
+			return Ok(Expr::This(ExprThis {id: NodeId::next(), keyword:  self.previous().clone()}))
 		}
 
-		if self.match_next(vec![TokenType::IDENTIFIER]) {
-			return Ok(Expr::new_variable(self.previous()))
+		if self.match_next(&[TokenType::IDENTIFIER]) {
+			return Ok(Expr::new_variable(self.previous().clone()))
 		}
 
-		if self.match_next(vec![TokenType::LEFT_PAREN]) {
+		if self.match_next(&[TokenType::LEFT_PAREN]) {
 			let expr = self.expression()?;
 			self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression")?;
 			return Ok(Expr::new_grouping(expr));
 		}
 
-		return Ok(Expr::Literal(ExprLiteral::Null));
+		if self.match_next(&[TokenType::LEFT_BRACKET]) {
+			let bracket = self.previous().clone();
+			let mut elements = Vec::new();
+
+			if !self.check(&TokenType::RIGHT_BRACKET) {
+				loop {
+					elements.push(self.expression()?);
+					if !self.match_next(&[TokenType::COMMA]) || self.check(&TokenType::RIGHT_BRACKET) {
+						break
+					}
+				}
+			}
+
+			self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after array elements.")?;
+			return Ok(Expr::Array(ExprArray {bracket, elements}))
+		}
+
+		if self.match_next(&[TokenType::IF]) {
+			return self.if_expression()
+		}
+
+		if self.match_next(&[TokenType::LEFT_BRACE]) {
+			return self.block_expression()
+		}
 
+		Err(self.error(self.peek().clone(), "Expect expression."))
 	}
 
 	/// Expect a given token to be at the current position, throws an error otherwise
-	pub fn consume(&mut self, token_type: TokenType, message: &str) -> ParserResult<Token> {
-		if self.check(token_type) {
-			return Ok(self.advance())
+	pub fn consume(&mut self, token_type: TokenType, message: &str) -> LoxResult<Token> {
+		if self.check(&token_type) {
+			return Ok(self.advance().clone())
+		}
+
+		return Err(self.error(self.peek().clone(), message))
+	}
+
+	/// Expect a semicolon terminating a statement. In optional semicolons mode, a line break, a closing
+	/// brace or the end of the file also terminates the statement
+	pub fn consume_semicolon(&mut self, message: &str) -> LoxResult<()> {
+		if self.match_next(&[TokenType::SEMICOLON]) || self.at_implicit_semicolon() {
+			return Ok(())
 		}
 
-		return Err(self.error(self.peek(), message))
+		return Err(self.error(self.peek().clone(), message))
+	}
+
+	/// Checks if, in optional semicolons mode, the statement may end before the current token
+	pub fn at_implicit_semicolon(&self) -> bool {
+		if !self.optional_semicolons {
+			return false
+		}
+
+		self.is_at_end() || self.check(&TokenType::RIGHT_BRACE) || self.peek().line > self.previous().line
 	}
 
 	/// Generate a ParseeError
-	pub fn error(&mut self, token: Token, message: &str) -> ParserError {
-		self.had_error = true;
-		let error = ParserError::new(token, message);
-		error.error();
-		error
+	pub fn error(&mut self, token: Token, message: &str) -> LoxError {
+		LoxError::syntax(token, message)
+	}
+
+	/// Record an error that doesn't stop parsing, to be returned once parsing is done
+	pub fn record_error(&mut self, token: Token, message: &str) {
+		let error = self.error(token, message);
+		self.errors.push(error);
 	}
 
 	/// Synchronize the curr in the event of bad syntax
@@ -311,7 +414,7 @@ impl Parser {
 			if self.previous().token_type == TokenType::SEMICOLON {return}
 
 			match self.peek().token_type {
-				TokenType::CLASS | TokenType::FUN | TokenType::VAR
+				TokenType::CLASS | TokenType::FUN | TokenType::VAR | TokenType::CONST
 				| TokenType::FOR | TokenType::IF | TokenType::WHILE
 				| TokenType::PRINT | TokenType::RETURN  => return,
 				_ => {}
@@ -0,0 +1,185 @@
+use crate::{parser::expr::{Expr, ExprLiteral}, scanner::token::{Literal, Token}, statement::{BlockStatement, FunctionDecl, Statement, VarDeclaration}};
+
+use super::json::Json;
+
+/// Prints a program's statement-level syntax tree as JSON. Every node is an object with a `type`,
+/// plus a `line` when the node carries a token
+pub struct JsonAstPrinter;
+
+impl JsonAstPrinter {
+	/// Print a whole program
+	pub fn print(statements: &[Statement]) -> String {
+		Self::statements(statements).pretty()
+	}
+
+	fn statements(statements: &[Statement]) -> Json {
+		Json::Array(statements.iter().map(Self::statement).collect())
+	}
+
+	fn node(kind: &str, line: Option<usize>, mut members: Vec<(&str, Json)>) -> Json {
+		let mut all = vec![("type", Json::string(kind))];
+
+		if let Some(line) = line {
+			all.push(("line", Json::Number(line as f64)));
+		}
+
+		all.append(&mut members);
+		Json::object(all)
+	}
+
+	fn name(token: &Token) -> Json {
+		Json::string(&token.lexeme)
+	}
+
+	fn optional(expr: &Option<Expr>) -> Json {
+		expr.as_ref().map(Self::expr).unwrap_or(Json::Null)
+	}
+
+	fn block(block: &BlockStatement) -> Json {
+		Self::node("Block", None, vec![("statements", Self::statements(&block.statements))])
+	}
+
+	fn function(kind: &str, f: &FunctionDecl) -> Json {
+		Self::node(kind, Some(f.name.line), vec![
+			("name", Self::name(&f.name)),
+			("params", Json::Array(f.params.iter().map(Self::name).collect())),
+			("body", Self::statements(&f.body)),
+		])
+	}
+
+	fn var(v: &VarDeclaration) -> Json {
+		Self::node(if v.is_const { "Const" } else { "Var" }, Some(v.name.line), vec![
+			("name", Self::name(&v.name)),
+			("initializer", Self::optional(&v.initializer)),
+		])
+	}
+
+	fn statement(statement: &Statement) -> Json {
+		match statement {
+			Statement::Print(p) => Self::node("Print", None, vec![("expression", Self::expr(&p.0))]),
+			Statement::Expression(e) => Self::node("Expression", None, vec![("expression", Self::expr(&e.0))]),
+			Statement::Function(f) => Self::function("Function", f),
+			Statement::Class(c) => Self::node("Class", Some(c.name.line), vec![
+				("name", Self::name(&c.name)),
+				("methods", Json::Array(c.methods.iter().map(|m| Self::function("Method", m)).collect())),
+			]),
+			Statement::Return(r) => Self::node("Return", Some(r.keyword.line), vec![("value", Self::optional(&r.value))]),
+			Statement::If(i) => Self::node("If", Some(i.keyword.line), vec![
+				("condition", Self::expr(&i.condition)),
+				("then", Self::statement(&i.then_branch)),
+				("else", i.else_branch.as_ref().map(|e| Self::statement(e)).unwrap_or(Json::Null)),
+			]),
+			Statement::While(w) => Self::node("While", Some(w.keyword.line), vec![
+				("condition", Self::expr(&w.condition)),
+				("body", Self::statement(&w.body)),
+			]),
+			Statement::For(f) => Self::node("For", Some(f.keyword.line), vec![
+				("initializer", f.initializer.as_ref().map(|i| Self::statement(i)).unwrap_or(Json::Null)),
+				("condition", Self::optional(&f.condition)),
+				("increment", Self::optional(&f.increment)),
+				("body", Self::statement(&f.body)),
+			]),
+			Statement::ForIn(f) => Self::node("ForIn", Some(f.keyword.line), vec![
+				("name", Self::name(&f.name)),
+				("iterable", Self::expr(&f.iterable)),
+				("body", Self::statement(&f.body)),
+			]),
+			Statement::Switch(s) => Self::node("Switch", Some(s.keyword.line), vec![
+				("subject", Self::expr(&s.subject)),
+				("cases", Json::Array(s.cases.iter().map(|c| Self::node("Case", None, vec![
+					("values", Json::Array(c.values.iter().map(Self::expr).collect())),
+					("body", Self::block(&c.body)),
+				])).collect())),
+				("default", s.default.as_ref().map(Self::block).unwrap_or(Json::Null)),
+			]),
+			Statement::Break() => Self::node("Break", None, vec![]),
+			Statement::Continue() => Self::node("Continue", None, vec![]),
+			Statement::Var(v) => Self::var(v),
+			Statement::MultiVar(v) => Self::node("MultiVar", None, vec![
+				("declarations", Json::Array(v.iter().map(Self::var).collect())),
+			]),
+			Statement::Block(b) => Self::block(b),
+			Statement::Import(i) => Self::node("Import", Some(i.keyword.line), vec![
+				("name", Self::name(&i.name)),
+				("path", match &i.path.literal {
+					Literal::String(path) => Json::string(path),
+					_ => Self::name(&i.path)
+				}),
+			]),
+			Statement::Export(e) => Self::node("Export", Some(e.keyword.line), vec![
+				("declaration", Self::statement(&e.declaration)),
+			]),
+		}
+	}
+
+	fn expr(expr: &Expr) -> Json {
+		match expr {
+			Expr::Literal(l) => {
+				let value = match l {
+					ExprLiteral::NUMBER(n) => Json::Number(*n),
+					ExprLiteral::STRING(s) => Json::string(s),
+					ExprLiteral::True => Json::Bool(true),
+					ExprLiteral::False => Json::Bool(false),
+					ExprLiteral::Null => Json::Null,
+				};
+
+				Self::node("Literal", None, vec![("value", value)])
+			},
+			Expr::Call(c) => Self::node("Call", Some(c.paren.line), vec![
+				("callee", Self::expr(&c.callee)),
+				("arguments", Json::Array(c.arguments.iter().map(Self::expr).collect())),
+			]),
+			Expr::Binary(b) => Self::node("Binary", Some(b.operator.line), vec![
+				("operator", Self::name(&b.operator)),
+				("left", Self::expr(&b.left)),
+				("right", Self::expr(&b.right)),
+			]),
+			Expr::Logical(l) => Self::node("Logical", Some(l.operator.line), vec![
+				("operator", Self::name(&l.operator)),
+				("left", Self::expr(&l.left)),
+				("right", Self::expr(&l.right)),
+			]),
+			Expr::Unary(u) => Self::node("Unary", Some(u.operator.line), vec![
+				("operator", Self::name(&u.operator)),
+				("right", Self::expr(&u.right)),
+			]),
+			Expr::Grouping(g) => Self::node("Grouping", None, vec![("expression", Self::expr(&g.0))]),
+			Expr::Variable(v) => Self::node("Variable", Some(v.name.line), vec![("name", Self::name(&v.name))]),
+			Expr::Assignment(a) => Self::node("Assignment", Some(a.name.line), vec![
+				("name", Self::name(&a.name)),
+				("value", Self::expr(&a.value)),
+			]),
+			Expr::Get(g) => Self::node("Get", Some(g.name.line), vec![
+				("object", Self::expr(&g.object)),
+				("name", Self::name(&g.name)),
+			]),
+			Expr::Set(s) => Self::node("Set", Some(s.name.line), vec![
+				("object", Self::expr(&s.object)),
+				("name", Self::name(&s.name)),
+				("value", Self::expr(&s.value)),
+			]),
+			Expr::This(t) => Self::node("This", Some(t.keyword.line), vec![]),
+			Expr::Block(b) => Self::node("BlockExpression", None, vec![
+				("statements", Self::statements(&b.statements)),
+				("value", b.value.as_ref().map(|v| Self::expr(v)).unwrap_or(Json::Null)),
+			]),
+			Expr::If(i) => Self::node("IfExpression", Some(i.keyword.line), vec![
+				("condition", Self::expr(&i.condition)),
+				("then", Self::expr(&i.then_branch)),
+				("else", i.else_branch.as_ref().map(|e| Self::expr(e)).unwrap_or(Json::Null)),
+			]),
+			Expr::Array(a) => Self::node("Array", Some(a.bracket.line), vec![
+				("elements", Json::Array(a.elements.iter().map(Self::expr).collect())),
+			]),
+			Expr::Index(i) => Self::node("Index", Some(i.bracket.line), vec![
+				("object", Self::expr(&i.object)),
+				("index", Self::expr(&i.index)),
+			]),
+			Expr::IndexSet(i) => Self::node("IndexSet", Some(i.bracket.line), vec![
+				("object", Self::expr(&i.object)),
+				("index", Self::expr(&i.index)),
+				("value", Self::expr(&i.value)),
+			]),
+		}
+	}
+}
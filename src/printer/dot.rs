@@ -0,0 +1,312 @@
+use crate::{parser::expr::{Expr, ExprLiteral}, statement::{BlockStatement, FunctionDecl, Statement, VarDeclaration}, utils::number};
+
+/// Prints a program's syntax tree as a GraphViz DOT graph. Every node is labelled with its kind and
+/// edges are labelled with the role of the child, so desugared loops and operator precedence show up
+/// as the shape of the tree
+pub struct DotAstPrinter {
+	out: String,
+	next_id: usize
+}
+
+impl DotAstPrinter {
+	/// Print a whole program, rooted at a `Program` node
+	pub fn print(statements: &[Statement]) -> String {
+		let mut printer = Self { out: String::new(), next_id: 0 };
+
+		let root = printer.node("Program");
+		printer.children(root, "", statements);
+
+		format!("digraph ast {{\n  node [shape=box, fontname=\"monospace\"];\n{}}}", printer.out)
+	}
+
+	/// Add a node, returning its id
+	fn node(&mut self, label: &str) -> usize {
+		let id = self.next_id;
+		self.next_id += 1;
+
+		self.out.push_str(&format!("  n{} [label=\"{}\"];\n", id, escape(label)));
+		id
+	}
+
+	fn edge(&mut self, from: usize, to: usize, label: &str) {
+		if label.is_empty() {
+			self.out.push_str(&format!("  n{} -> n{};\n", from, to));
+		} else {
+			self.out.push_str(&format!("  n{} -> n{} [label=\"{}\"];\n", from, to, escape(label)));
+		}
+	}
+
+	/// Add statements as children of a node, numbering the edges when there is more than one
+	fn children(&mut self, parent: usize, label: &str, statements: &[Statement]) {
+		for (i, statement) in statements.iter().enumerate() {
+			let child = self.statement(statement);
+			let label = if statements.len() > 1 { format!("{}{}", label, i) } else { label.to_string() };
+			self.edge(parent, child, &label);
+		}
+	}
+
+	fn child_expr(&mut self, parent: usize, label: &str, expr: &Expr) {
+		let child = self.expr(expr);
+		self.edge(parent, child, label);
+	}
+
+	fn child_statement(&mut self, parent: usize, label: &str, statement: &Statement) {
+		let child = self.statement(statement);
+		self.edge(parent, child, label);
+	}
+
+	fn block(&mut self, label: &str, block: &BlockStatement) -> usize {
+		let id = self.node(label);
+		self.children(id, "", &block.statements);
+		id
+	}
+
+	fn function(&mut self, kind: &str, f: &FunctionDecl) -> usize {
+		let params: Vec<&str> = f.params.iter().map(|p| p.lexeme.as_str()).collect();
+		let id = self.node(&format!("{} {}({})", kind, f.name.lexeme, params.join(", ")));
+
+		self.children(id, "", &f.body);
+		id
+	}
+
+	fn var(&mut self, v: &VarDeclaration) -> usize {
+		let id = self.node(&format!("{} {}", if v.is_const { "Const" } else { "Var" }, v.name.lexeme));
+
+		if let Some(initializer) = &v.initializer {
+			self.child_expr(id, "initializer", initializer);
+		}
+
+		id
+	}
+
+	fn statement(&mut self, statement: &Statement) -> usize {
+		match statement {
+			Statement::Print(p) => {
+				let id = self.node("Print");
+				self.child_expr(id, "", &p.0);
+				id
+			},
+			Statement::Expression(e) => {
+				let id = self.node("Expression");
+				self.child_expr(id, "", &e.0);
+				id
+			},
+			Statement::Function(f) => self.function("Function", f),
+			Statement::Class(c) => {
+				let id = self.node(&format!("Class {}", c.name.lexeme));
+
+				for method in &c.methods {
+					let child = self.function("Method", method);
+					self.edge(id, child, "");
+				}
+
+				id
+			},
+			Statement::Return(r) => {
+				let id = self.node("Return");
+
+				if let Some(value) = &r.value {
+					self.child_expr(id, "", value);
+				}
+
+				id
+			},
+			Statement::If(i) => {
+				let id = self.node("If");
+				self.child_expr(id, "condition", &i.condition);
+				self.child_statement(id, "then", &i.then_branch);
+
+				if let Some(else_branch) = &i.else_branch {
+					self.child_statement(id, "else", else_branch);
+				}
+
+				id
+			},
+			Statement::While(w) => {
+				let id = self.node("While");
+				self.child_expr(id, "condition", &w.condition);
+				self.child_statement(id, "body", &w.body);
+				id
+			},
+			Statement::For(f) => {
+				let id = self.node("For");
+
+				if let Some(initializer) = &f.initializer {
+					self.child_statement(id, "initializer", initializer);
+				}
+
+				if let Some(condition) = &f.condition {
+					self.child_expr(id, "condition", condition);
+				}
+
+				if let Some(increment) = &f.increment {
+					self.child_expr(id, "increment", increment);
+				}
+
+				self.child_statement(id, "body", &f.body);
+				id
+			},
+			Statement::ForIn(f) => {
+				let id = self.node(&format!("ForIn {}", f.name.lexeme));
+				self.child_expr(id, "iterable", &f.iterable);
+				self.child_statement(id, "body", &f.body);
+				id
+			},
+			Statement::Switch(s) => {
+				let id = self.node("Switch");
+				self.child_expr(id, "subject", &s.subject);
+
+				for case in &s.cases {
+					let case_id = self.block("Case", &case.body);
+
+					for value in &case.values {
+						self.child_expr(case_id, "value", value);
+					}
+
+					self.edge(id, case_id, "");
+				}
+
+				if let Some(default) = &s.default {
+					let default_id = self.block("Default", default);
+					self.edge(id, default_id, "");
+				}
+
+				id
+			},
+			Statement::Break() => self.node("Break"),
+			Statement::Continue() => self.node("Continue"),
+			Statement::Var(v) => self.var(v),
+			Statement::MultiVar(declarations) => {
+				let id = self.node("MultiVar");
+
+				for v in declarations {
+					let child = self.var(v);
+					self.edge(id, child, "");
+				}
+
+				id
+			},
+			Statement::Block(b) => self.block("Block", b),
+			Statement::Import(i) => self.node(&format!("Import {} from {}", i.name.lexeme, i.path.lexeme)),
+			Statement::Export(e) => {
+				let id = self.node("Export");
+				self.child_statement(id, "", &e.declaration);
+				id
+			},
+		}
+	}
+
+	fn expr(&mut self, expr: &Expr) -> usize {
+		match expr {
+			Expr::Literal(l) => {
+				let value = match l {
+					ExprLiteral::NUMBER(n) => number::format(*n),
+					ExprLiteral::STRING(s) => format!("\"{}\"", s),
+					ExprLiteral::True => "true".to_string(),
+					ExprLiteral::False => "false".to_string(),
+					ExprLiteral::Null => "nil".to_string(),
+				};
+
+				self.node(&format!("Literal {}", value))
+			},
+			Expr::Call(c) => {
+				let id = self.node("Call");
+				self.child_expr(id, "callee", &c.callee);
+
+				for (i, argument) in c.arguments.iter().enumerate() {
+					self.child_expr(id, &format!("arg{}", i), argument);
+				}
+
+				id
+			},
+			Expr::Binary(b) => {
+				let id = self.node(&format!("Binary {}", b.operator.lexeme));
+				self.child_expr(id, "left", &b.left);
+				self.child_expr(id, "right", &b.right);
+				id
+			},
+			Expr::Logical(l) => {
+				let id = self.node(&format!("Logical {}", l.operator.lexeme));
+				self.child_expr(id, "left", &l.left);
+				self.child_expr(id, "right", &l.right);
+				id
+			},
+			Expr::Unary(u) => {
+				let id = self.node(&format!("Unary {}", u.operator.lexeme));
+				self.child_expr(id, "", &u.right);
+				id
+			},
+			Expr::Grouping(g) => {
+				let id = self.node("Grouping");
+				self.child_expr(id, "", &g.0);
+				id
+			},
+			Expr::Variable(v) => self.node(&format!("Variable {}", v.name.lexeme)),
+			Expr::Assignment(a) => {
+				let id = self.node(&format!("Assign {}", a.name.lexeme));
+				self.child_expr(id, "value", &a.value);
+				id
+			},
+			Expr::Get(g) => {
+				let id = self.node(&format!("Get .{}", g.name.lexeme));
+				self.child_expr(id, "object", &g.object);
+				id
+			},
+			Expr::Set(s) => {
+				let id = self.node(&format!("Set .{}", s.name.lexeme));
+				self.child_expr(id, "object", &s.object);
+				self.child_expr(id, "value", &s.value);
+				id
+			},
+			Expr::This(_) => self.node("This"),
+			Expr::Block(b) => {
+				let id = self.node("BlockExpression");
+				self.children(id, "", &b.statements);
+
+				if let Some(value) = &b.value {
+					self.child_expr(id, "value", value);
+				}
+
+				id
+			},
+			Expr::If(i) => {
+				let id = self.node("IfExpression");
+				self.child_expr(id, "condition", &i.condition);
+				self.child_expr(id, "then", &i.then_branch);
+
+				if let Some(else_branch) = &i.else_branch {
+					self.child_expr(id, "else", else_branch);
+				}
+
+				id
+			},
+			Expr::Array(a) => {
+				let id = self.node("Array");
+
+				for (i, element) in a.elements.iter().enumerate() {
+					self.child_expr(id, &i.to_string(), element);
+				}
+
+				id
+			},
+			Expr::Index(i) => {
+				let id = self.node("Index");
+				self.child_expr(id, "object", &i.object);
+				self.child_expr(id, "index", &i.index);
+				id
+			},
+			Expr::IndexSet(i) => {
+				let id = self.node("IndexSet");
+				self.child_expr(id, "object", &i.object);
+				self.child_expr(id, "index", &i.index);
+				self.child_expr(id, "value", &i.value);
+				id
+			},
+		}
+	}
+}
+
+/// Escape a label for a double-quoted DOT string
+fn escape(label: &str) -> String {
+	label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,103 @@
+use std::fmt::Write;
+
+use crate::utils::number;
+
+/// A JSON document, built up by the printers and rendered with [`Json::pretty`]
+#[derive(Clone, PartialEq)]
+pub enum Json {
+	Null,
+	Bool(bool),
+	Number(f64),
+	String(String),
+	Array(Vec<Json>),
+	/// Members keep their insertion order
+	Object(Vec<(String, Json)>)
+}
+
+impl Json {
+	/// Create an object from its members
+	pub fn object(members: Vec<(&str, Json)>) -> Self {
+		Json::Object(members.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+	}
+
+	/// Create a string
+	pub fn string(s: &str) -> Self {
+		Json::String(s.to_string())
+	}
+
+	/// Render the document on a single line
+	pub fn compact(&self) -> String {
+		let mut out = String::new();
+		self.write(&mut out, None, 0);
+		out
+	}
+
+	/// Render the document across lines, indented by two spaces per level
+	pub fn pretty(&self) -> String {
+		let mut out = String::new();
+		self.write(&mut out, Some(2), 0);
+		out
+	}
+
+	fn write(&self, out: &mut String, indent: Option<usize>, depth: usize) {
+		let newline = |out: &mut String, depth: usize| {
+			if let Some(width) = indent {
+				out.push('\n');
+				out.push_str(&" ".repeat(width * depth));
+			}
+		};
+
+		match self {
+			Json::Null => out.push_str("null"),
+			Json::Bool(b) => out.push_str(&b.to_string()),
+			Json::Number(n) if n.is_finite() => { out.push_str(&number::format(*n)); },
+			// JSON has no infinities or NaN
+			Json::Number(_) => out.push_str("null"),
+			Json::String(s) => write_string(out, s),
+			Json::Array(elements) if elements.is_empty() => out.push_str("[]"),
+			Json::Array(elements) => {
+				out.push('[');
+				for (i, element) in elements.iter().enumerate() {
+					if i > 0 { out.push(','); }
+					newline(out, depth + 1);
+					element.write(out, indent, depth + 1);
+				}
+				newline(out, depth);
+				out.push(']');
+			},
+			Json::Object(members) if members.is_empty() => out.push_str("{}"),
+			Json::Object(members) => {
+				out.push('{');
+				for (i, (key, value)) in members.iter().enumerate() {
+					if i > 0 { out.push(','); }
+					newline(out, depth + 1);
+					write_string(out, key);
+					out.push_str(if indent.is_some() { ": " } else { ":" });
+					value.write(out, indent, depth + 1);
+				}
+				newline(out, depth);
+				out.push('}');
+			}
+		}
+	}
+}
+
+/// Write a string as a quoted JSON string, escaping what JSON requires. `jsonStringify` writes its
+/// strings with this too
+pub fn write_string(out: &mut String, s: &str) {
+	out.push('"');
+
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			c if (c as u32) < 0x20 => { write!(out, "\\u{:04x}", c as u32).unwrap(); },
+			c => out.push(c)
+		}
+	}
+
+	out.push('"');
+}
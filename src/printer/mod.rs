@@ -0,0 +1,5 @@
+pub mod ast;
+pub mod dot;
+pub mod json;
+pub mod source;
+pub mod tokens;
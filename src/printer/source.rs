@@ -0,0 +1,218 @@
+use crate::scanner::token::{Token, TokenType};
+
+const INDENT: &str = "  ";
+
+/// An open brace, tracking whether it is a switch body and, if so, whether it is inside a case
+struct Brace {
+	switch: bool,
+	in_case: bool,
+	/// The paren and bracket depth outside the brace, restored when it closes
+	groups: usize
+}
+
+/// Prints tokens back to canonical Lox source: one statement per line, braces on the line that opens
+/// them, two spaces of indentation and single spaces around operators. Comments and single blank lines
+/// are kept, so the tokens should come from a scanner that keeps comments
+pub struct SourcePrinter<'a> {
+	/// The source the tokens were scanned from, which their spans point into
+	source: &'a str,
+	optional_semicolons: bool,
+	out: String,
+	braces: Vec<Brace>,
+	/// The depth of open parens and brackets, inside which `;` doesn't end a line
+	groups: usize,
+	/// A line break is owed before the next token
+	pending_break: bool,
+	/// The next brace opens a switch body
+	switch_pending: bool,
+	/// The next colon ends a case label
+	label_pending: bool,
+	/// The last token written, comments aside, was a prefix `-` or `!`
+	unary: bool
+}
+
+impl<'a> SourcePrinter<'a> {
+	/// Print a whole file. In optional semicolons mode, line breaks that may end a statement are kept
+	pub fn print(tokens: &[Token], source: &'a str, optional_semicolons: bool) -> String {
+		let mut printer = SourcePrinter {
+			source,
+			optional_semicolons,
+			out: String::new(),
+			braces: Vec::new(),
+			groups: 0,
+			pending_break: false,
+			switch_pending: false,
+			label_pending: false,
+			unary: false
+		};
+
+		let mut previous: Option<&Token> = None;
+		let mut last: Option<&Token> = None;
+
+		for token in tokens.iter().filter(|t| t.token_type != TokenType::EOF) {
+			printer.token(token, previous, last);
+
+			last = Some(token);
+			if token.token_type != TokenType::COMMENT {
+				previous = Some(token);
+			}
+		}
+
+		if !printer.out.is_empty() {
+			printer.out.push('\n');
+		}
+
+		printer.out
+	}
+
+	/// Write a token, preceded by a line break or space. `previous` is the last token that isn't a
+	/// comment and `last` is the last token of any kind
+	fn token(&mut self, token: &Token, previous: Option<&Token>, last: Option<&Token>) {
+		let kind = &token.token_type;
+		let previous_kind = previous.map(|p| p.token_type.clone());
+
+		match kind {
+			TokenType::RIGHT_BRACE => {
+				if let Some(brace) = self.braces.pop() {
+					self.groups = brace.groups;
+				}
+			},
+			TokenType::CASE | TokenType::DEFAULT => {
+				if let Some(brace) = self.braces.last_mut().filter(|b| b.switch) {
+					brace.in_case = false;
+					self.label_pending = true;
+					self.pending_break = true;
+				}
+			},
+			_ => {}
+		}
+
+		let line_break = match last {
+			None => false,
+			Some(last) => self.breaks_before(token, previous_kind.as_ref(), last)
+		};
+
+		if line_break {
+			let last = last.unwrap();
+
+			// Keep a single blank line, but never at the start or end of a block
+			let blank = token.line > end_line(last, self.source) + 1
+				&& last.token_type != TokenType::LEFT_BRACE
+				&& *kind != TokenType::RIGHT_BRACE;
+
+			self.out.push('\n');
+			if blank { self.out.push('\n'); }
+
+			let depth = self.braces.len() + self.braces.iter().filter(|b| b.in_case).count();
+			self.out.push_str(&INDENT.repeat(depth));
+		} else if last.is_some() && self.spaced(previous_kind.as_ref(), kind, last.unwrap()) {
+			self.out.push(' ');
+		}
+
+		self.out.push_str(token.text(self.source));
+
+		if *kind != TokenType::COMMENT {
+			self.pending_break = false;
+			self.unary = match kind {
+				TokenType::BANG => true,
+				TokenType::MINUS => !previous_kind.as_ref().is_some_and(ends_operand),
+				_ => false
+			};
+		}
+
+		match kind {
+			TokenType::LEFT_PAREN | TokenType::LEFT_BRACKET => self.groups += 1,
+			TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACKET => self.groups = self.groups.saturating_sub(1),
+			TokenType::LEFT_BRACE => {
+				self.braces.push(Brace { switch: self.switch_pending, in_case: false, groups: self.groups });
+				self.switch_pending = false;
+				self.groups = 0;
+				self.pending_break = true;
+			},
+			TokenType::RIGHT_BRACE => self.pending_break = true,
+			TokenType::SEMICOLON => self.pending_break = self.groups == 0,
+			TokenType::SWITCH => self.switch_pending = true,
+			TokenType::COLON if self.label_pending && self.groups == 0 => {
+				self.label_pending = false;
+				if let Some(brace) = self.braces.last_mut() {
+					brace.in_case = true;
+				}
+				self.pending_break = true;
+			},
+			TokenType::COMMENT if token.text(self.source).starts_with("//") => self.pending_break = true,
+			_ => {}
+		}
+	}
+
+	/// Whether a line break goes before the token
+	fn breaks_before(&self, token: &Token, previous: Option<&TokenType>, last: &Token) -> bool {
+		let kind = &token.token_type;
+		let on_new_line = token.line > end_line(last, self.source);
+
+		// Comments stay on the line they were written on
+		if *kind == TokenType::COMMENT {
+			return on_new_line
+		}
+
+		if last.token_type == TokenType::COMMENT && on_new_line {
+			return true
+		}
+
+		match (previous, kind) {
+			// Empty braces stay together
+			(Some(TokenType::LEFT_BRACE), TokenType::RIGHT_BRACE) => return last.token_type == TokenType::COMMENT,
+			(_, TokenType::RIGHT_BRACE) => return true,
+			(Some(TokenType::RIGHT_BRACE), TokenType::ELSE | TokenType::SEMICOLON | TokenType::COMMA
+				| TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACKET | TokenType::DOT) => return false,
+			_ => {}
+		}
+
+		if self.pending_break {
+			return true
+		}
+
+		// Without semicolons a line break may be what ends a statement, so keep those
+		self.optional_semicolons && on_new_line && self.groups == 0
+			&& previous.is_some_and(ends_statement)
+	}
+
+	/// Whether a space goes between two tokens on the same line
+	fn spaced(&self, previous: Option<&TokenType>, kind: &TokenType, last: &Token) -> bool {
+		if last.token_type == TokenType::COMMENT || *kind == TokenType::COMMENT {
+			return true
+		}
+
+		let Some(previous) = previous else { return false };
+
+		if self.unary || matches!(previous, TokenType::LEFT_PAREN | TokenType::LEFT_BRACKET | TokenType::DOT) {
+			return false
+		}
+
+		match kind {
+			TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACKET | TokenType::SEMICOLON | TokenType::COMMA
+			| TokenType::DOT | TokenType::COLON => false,
+			TokenType::LEFT_PAREN | TokenType::LEFT_BRACKET => !ends_operand(previous),
+			TokenType::RIGHT_BRACE => *previous != TokenType::LEFT_BRACE,
+			_ => true
+		}
+	}
+}
+
+/// The line a token ends on, which differs from its start for multi-line strings and comments
+fn end_line(token: &Token, source: &str) -> usize {
+	token.line + token.text(source).matches('\n').count()
+}
+
+/// Whether the token ends an operand, so that a following `(` or `[` is a call or index and a
+/// following `-` is binary
+fn ends_operand(kind: &TokenType) -> bool {
+	matches!(kind,
+		TokenType::IDENTIFIER | TokenType::NUMBER | TokenType::STRING | TokenType::TRUE | TokenType::FALSE
+		| TokenType::NIL | TokenType::THIS | TokenType::SUPER | TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACKET
+	)
+}
+
+/// Whether a statement may end after the token
+fn ends_statement(kind: &TokenType) -> bool {
+	ends_operand(kind) || matches!(kind, TokenType::BREAK | TokenType::CONTINUE | TokenType::RETURN)
+}
@@ -0,0 +1,38 @@
+use crate::scanner::token::{Literal, Token};
+
+use super::json::Json;
+
+/// Prints tokens as a JSON array of `{type, lexeme, literal, line, column}` records, one per line
+pub struct JsonTokenPrinter;
+
+impl JsonTokenPrinter {
+	pub fn print(tokens: &[Token]) -> String {
+		let records: Vec<String> = tokens.iter()
+			.map(|token| format!("  {}", Self::token(token).compact()))
+			.collect();
+
+		if records.is_empty() {
+			return "[]".to_string()
+		}
+
+		format!("[\n{}\n]", records.join(",\n"))
+	}
+
+	fn token(token: &Token) -> Json {
+		let literal = match &token.literal {
+			Literal::Null => Json::Null,
+			Literal::Integer(i) => Json::Number(*i as f64),
+			Literal::Float(f) => Json::Number(*f),
+			Literal::String(s) => Json::string(s),
+			Literal::Boolean(b) => Json::Bool(*b),
+		};
+
+		Json::object(vec![
+			("type", Json::String(token.token_type.to_string())),
+			("lexeme", Json::string(&token.lexeme)),
+			("literal", literal),
+			("line", Json::Number(token.line as f64)),
+			("column", Json::Number(token.column as f64)),
+		])
+	}
+}
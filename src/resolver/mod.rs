@@ -1,15 +1,26 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use error::{ResolverError, ResolverResult};
 
-use crate::{interpreter::Interpreter, parser::expr::{Expr, ExprAssignment, ExprBinary, ExprCall, ExprGet, ExprGrouping, ExprLiteral, ExprLogical, ExprSet, ExprThis, ExprUnary, ExprVariable}, scanner::token::Token, statement::{BlockStatement, ClassDecl, ExprStatement, FunctionDecl, IfStatement, PrintStatement, ReturnStatement, Statement, VarDeclaration, WhileStatement}};
+use crate::{error::LoxError, interpreter::Interpreter, parser::expr::{Expr, ExprArray, NodeId, ExprAssignment, ExprBinary, ExprBlock, ExprCall, ExprGet, ExprGrouping, ExprIf, ExprIndex, ExprIndexSet, ExprLiteral, ExprLogical, ExprSet, ExprThis, ExprUnary, ExprVariable}, scanner::token::{Symbol, Token}, statement::{declares, BlockStatement, ClassDecl, ExportStatement, ExprStatement, ForInStatement, ForStatement, FunctionDecl, IfStatement, ImportStatement, PrintStatement, ReturnStatement, Statement, SwitchStatement, VarDeclaration, WhileStatement}, visitor::{ExprVisitor, StmtVisitor}};
 
-pub mod error;
 pub struct Resolver {
 	pub interpreter: Interpreter,
-	pub scopes: Vec<HashMap<String, bool>>,
+	pub scopes: Vec<HashMap<Symbol, Binding>>,
 	current_function: FunctionType,
 	current_class: ClassType,
+	/// The number of scopes enclosing top-level code, 1 when resolving a module and 0 otherwise
+	top_level_depth: usize,
+	/// Errors found so far, returned once resolution is done
+	errors: Vec<LoxError>,
+}
+
+/// What the resolver knows about a name declared in a local scope
+#[derive(Clone, PartialEq)]
+pub struct Binding {
+	/// Whether the binding's initializer has finished resolving
+	pub defined: bool,
+	/// Whether the binding was declared with `const`, and so can't be reassigned
+	pub constant: bool,
 }
 
 #[derive(Clone, PartialEq)]
@@ -30,21 +41,50 @@ pub enum ClassType {
 
 impl Resolver {
 	pub fn new(interpreter: Interpreter) -> Self {
-		Self {interpreter, scopes: Vec::new(), current_function: FunctionType::NONE, current_class: ClassType::NONE}
+		Self {interpreter, scopes: Vec::new(), current_function: FunctionType::NONE, current_class: ClassType::NONE, top_level_depth: 0, errors: Vec::new()}
+	}
+
+	/// Resolve a program, or more of one: the REPL resolves each input against the scopes left by
+	/// those before it. Every statement is resolved even after an error, and all the errors found are
+	/// returned together
+	pub fn resolve_statements(&mut self, statements: &[Statement]) -> Result<(), Vec<LoxError>> {
+		self.resolve_all(statements);
+		self.finish()
+	}
+
+	/// Resolve the statements of a module. Top-level module bindings live in their own scope rather
+	/// than in the globals, so they are resolved as locals
+	pub fn resolve_module(&mut self, statements: &[Statement]) -> Result<(), Vec<LoxError>> {
+		self.begin_scope();
+		self.top_level_depth += 1;
+
+		self.resolve_all(statements);
+
+		self.top_level_depth -= 1;
+		self.end_scope();
+
+		self.finish()
+	}
+
+	/// Fail with the errors found since the last time, if there were any
+	fn finish(&mut self) -> Result<(), Vec<LoxError>> {
+		if self.errors.is_empty() {
+			Ok(())
+		} else {
+			Err(std::mem::take(&mut self.errors))
+		}
 	}
 
 	// region:    --- Statements
 	
 
-	pub fn resolve_statements(&mut self, statements: Vec<Statement>) -> ResolverResult<() >{
+	fn resolve_all(&mut self, statements: &[Statement]) {
 		for statement in statements {
-			self.resolve_statement(statement)?;
+			self.resolve_statement(statement);
 		}
-
-		Ok(())
 	}
 
-	fn resolve_func(&mut self, function: FunctionDecl,  function_type: FunctionType) -> ResolverResult<()> {
+	fn resolve_func(&mut self, function: &FunctionDecl,  function_type: FunctionType) {
 		let enclosing_function = self.current_function.clone();
 
 		self.current_function = function_type;
@@ -54,41 +94,111 @@ impl Resolver {
 		let FunctionDecl {name: _, body, params} = function;
 
 		for param in params {
-			self.declare(&param)?;
-			self.define(&param);
+			self.declare(param);
+			self.define(param);
 		}
 
-		self.resolve_statements(body)?;
+		self.resolve_all(body);
 
 		self.end_scope();
 
 		self.current_function = enclosing_function;
+	}
+
+	fn resolve_statement(&mut self, statement: &Statement) {
+		statement.accept(self)
+	}
 
-		Ok(())
+	// endregion: --- Statements
+
+	fn resolve_expr(&mut self, expr: &Expr) {
+		expr.accept(self)
 	}
 
+
+	// region:    --- Utils
+
 	
 
-	pub fn resolve_block_statement(&mut self, s: BlockStatement) -> ResolverResult<()> {
+	fn begin_scope(&mut self) {
+		self.scopes.push(HashMap::new());
+	}
+
+	fn end_scope(&mut self) {
+		self.scopes.pop();
+	}
+
+	fn declare(&mut self, name: &Token) {
+		if let Some(scope) = self.scopes.last_mut() {
+			if scope.contains_key(&name.lexeme) {
+				self.error(name.clone(), "Already a variable with this name in this scope".to_string());
+				return
+			}
+
+			scope.insert(name.lexeme, Binding { defined: false, constant: false });
+
+		}
+	}
+
+	fn define(&mut self, name: &Token) {
+		if let Some(scope) = self.scopes.last_mut() {
+			scope.insert(name.lexeme, Binding { defined: true, constant: false });
+		}
+	}
+
+	fn define_constant(&mut self, name: &Token) {
+		if let Some(scope) = self.scopes.last_mut() {
+			scope.insert(name.lexeme, Binding { defined: true, constant: true });
+		}
+	}
+
+	fn resolve_local(&mut self, id: NodeId, name: &Token) {
+		let n = self.scopes.len();
+		for i in (0..n).rev() {
+			if self.scopes[i].contains_key(&name.lexeme) {
+				self.interpreter.resolve_expr_depth(id, (n - 1) - i);
+				return
+			}
+		}
+
+		self.interpreter.resolve_expr_global(id, name.lexeme);
+	}
+
+	/// Record an error, leaving resolution to carry on
+	fn error(&mut self, token: Token, message: String) {
+		self.errors.push(LoxError::resolution(token, &message));
+	}
+	// endregion: --- Utils
+
+}
+
+impl StmtVisitor for Resolver {
+	type Output = ();
+
+	fn visit_block_statement(&mut self, s: &BlockStatement) {
+		// Without declarations the block needs no scope, and the interpreter gives it no environment
+		if !declares(&s.statements) {
+			self.interpreter.resolve_flat_block(s.id);
+			return self.resolve_all(&s.statements)
+		}
+
 		self.begin_scope();
-		self.resolve_statements(s.statements)?;
+		self.resolve_all(&s.statements);
 		self.end_scope();
-
-		Ok(())
 	}
 
-	pub fn resolve_class_decl(&mut self, s: ClassDecl) -> ResolverResult<()> {
+	fn visit_class_statement(&mut self, s: &ClassDecl) {
 		let enclosing_class = self.current_class.clone();
 		self.current_class = ClassType::CLASS;
 
-		self.declare(&s.name)?;
+		self.declare(&s.name);
 		self.define(&s.name);
 
 		self.begin_scope();
 
-		self.scopes.last_mut().and_then(|scope| scope.insert("this".to_string(), true));
+		self.scopes.last_mut().and_then(|scope| scope.insert(Symbol::this(), Binding { defined: true, constant: true }));
 
-		for method in s.methods {
+		for method in &s.methods {
 			let declaration = if method.name.lexeme == "init" {
 				FunctionType::INITIALIZER
 			} else {
@@ -96,263 +206,266 @@ impl Resolver {
 			};
 
 
-			self.resolve_func(method, declaration)?;
+			self.resolve_func(method, declaration);
 		}
 
 		self.end_scope();
 		
 		self.current_class = enclosing_class;
-
-		Ok(())
 	}
 
-	pub fn resolve_expression_statement(&mut self, ExprStatement(expression): ExprStatement) -> ResolverResult<()> {
-		self.resolve_expr(expression)?;
-
-		Ok(())
+	fn visit_expression_statement(&mut self, ExprStatement(expression): &ExprStatement) {
+		self.resolve_expr(expression);
 	}
 
-	pub fn resolve_func_statement(&mut self, s: FunctionDecl,) -> ResolverResult<()> {
+	fn visit_function_statement(&mut self, s: &Arc<FunctionDecl>) {
 		// Eagerly resolve name to allow recursion
-		self.declare(&s.name)?;
+		self.declare(&s.name);
 		self.define(&s.name);
 
-		self.resolve_func(s, FunctionType::FUNCTION)?;
-
-		Ok(())
+		self.resolve_func(s, FunctionType::FUNCTION);
 	}
 
-	pub fn resolve_if_statement(&mut self, statement: IfStatement,) -> ResolverResult<()> {
-		let IfStatement {condition, then_branch, else_branch} = statement;
-		self.resolve_expr(condition)?;
-		self.resolve_statement(*then_branch)?;
+	fn visit_if_statement(&mut self, statement: &IfStatement) {
+		let IfStatement {condition, then_branch, else_branch, ..} = statement;
+		self.resolve_expr(condition);
+		self.resolve_statement(then_branch);
 
 		if let Some(else_branch) = else_branch {
-			self.resolve_statement(*else_branch)?
+			self.resolve_statement(else_branch);
 		}
-
-		Ok(())
 	}
 
-	pub fn resolve_print_statement(&mut self, PrintStatement(expr): PrintStatement) -> ResolverResult<()> {
-		self.resolve_expr(expr)?;
+	fn visit_print_statement(&mut self, PrintStatement(expr, _): &PrintStatement) {
+		self.resolve_expr(expr);
 		
-		Ok(())
 	}
 
-	pub fn resolve_return_statement(&mut self, statement: ReturnStatement) -> ResolverResult<()> {
+	fn visit_return_statement(&mut self, statement: &ReturnStatement) {
 		if self.current_function == FunctionType::NONE {
-			return Err(self.error(statement.keyword, "Can't return from top-level code.".to_string()));
+			self.error(statement.keyword.clone(), "Can't return from top-level code.".to_string());
 		}
 
-		if let Some(value) = statement.value {
+		if let Some(value) = &statement.value {
 			if self.current_function == FunctionType::INITIALIZER {
-				return Err(self.error(statement.keyword, "Can't return from an initializer.".to_string()))
+				self.error(statement.keyword.clone(), "Can't return from an initializer.".to_string());
+			}
+
+			if let Some(call) = value.tail_call() {
+				self.interpreter.resolve_tail_call(call.id);
 			}
-			self.resolve_expr(value)?;
+
+			self.resolve_expr(value);
 		}
 		
-		Ok(())
 	}
 
-	pub fn resolve_var_statement(&mut self, s: VarDeclaration) -> ResolverResult<()> {
-		self.declare(&s.name)?;
+	fn visit_var_statement(&mut self, s: &VarDeclaration) {
+		self.declare(&s.name);
 
-		if let Some(initializer) = s.initializer {
-			self.resolve_expr(initializer)?;
+		if let Some(initializer) = &s.initializer {
+			self.resolve_expr(initializer);
 		}
 
-		self.define(&s.name);
+		if s.is_const {
+			self.define_constant(&s.name);
+		} else {
+			self.define(&s.name);
+		}
+	}
 
-		Ok(())
+	fn visit_multi_var_statement(&mut self, declarations: &[VarDeclaration]) {
+		for declaration in declarations {
+			self.visit_var_statement(declaration);
+		}
 	}
 
-	pub fn resolve_while_statement(&mut self, statement: WhileStatement) -> ResolverResult<()> {
-		self.resolve_expr(statement.condition)?;
-		self.resolve_statement(*statement.body)?;
+	fn visit_while_statement(&mut self, statement: &WhileStatement) {
+		self.resolve_expr(&statement.condition);
+		self.resolve_statement(&statement.body);
 		
-		Ok(())
 	}
 
-	pub fn resolve_expr_assignment(&mut self, expr: ExprAssignment) -> ResolverResult<()> {
-		let name = expr.name.clone();
-		self.resolve_expr(*expr.value.clone())?;
-		self.resolve_local(Expr::Assignment(expr), name);
+	fn visit_for_statement(&mut self, statement: &ForStatement) {
+		self.begin_scope();
+
+		if let Some(initializer) = &statement.initializer {
+			self.resolve_statement(initializer);
+		}
+
+		if let Some(condition) = &statement.condition {
+			self.resolve_expr(condition);
+		}
+
+		self.resolve_statement(&statement.body);
 
-		Ok(())
+		if let Some(increment) = &statement.increment {
+			self.resolve_expr(increment);
+		}
+
+		self.end_scope();
 	}
 
-	pub fn resolve_expr_binary(&mut self, expr: ExprBinary) -> ResolverResult<()> {
-		self.resolve_expr(*expr.left)?;
-		self.resolve_expr(*expr.right)?;
+	fn visit_for_in_statement(&mut self, statement: &ForInStatement) {
+		self.resolve_expr(&statement.iterable);
 
-		Ok(())
+		self.begin_scope();
+		self.declare(&statement.name);
+		self.define(&statement.name);
+		self.resolve_statement(&statement.body);
+		self.end_scope();
 	}
 
-	pub fn resolve_expr_call(&mut self, expr: ExprCall) -> ResolverResult<()> {
-		self.resolve_expr(*expr.callee)?;
+	fn visit_import_statement(&mut self, statement: &ImportStatement) {
+		self.declare(&statement.name);
+		self.define(&statement.name);
+	}
 
-		for argument in expr.arguments {
-			self.resolve_expr(argument)?;
+	fn visit_export_statement(&mut self, statement: &ExportStatement) {
+		if self.scopes.len() != self.top_level_depth || self.current_function != FunctionType::NONE {
+			self.error(statement.keyword.clone(), "Can only export top-level declarations.".to_string());
 		}
 
-		Ok(())
+		self.resolve_statement(&statement.declaration)
 	}
 
-	pub fn resolve_expr_get(&mut self, expr: ExprGet) -> ResolverResult<()> {
-		self.resolve_expr(*expr.object)?;
+	fn visit_switch_statement(&mut self, statement: &SwitchStatement) {
+		self.resolve_expr(&statement.subject);
 
-		Ok(())
-	}
+		for case in &statement.cases {
+			for value in &case.values {
+				self.resolve_expr(value);
+			}
 
-	pub fn resolve_expr_grouping(&mut self, ExprGrouping(expr): ExprGrouping) -> ResolverResult<()> {
-		self.resolve_expr(*expr)?;
+			self.visit_block_statement(&case.body);
+		}
 
-		Ok(())
+		if let Some(default) = &statement.default {
+			self.visit_block_statement(default);
+		}
 	}
 
-	pub fn resolve_expr_literal(&mut self, _: ExprLiteral) -> ResolverResult<()> {
-		Ok(())
+	fn visit_break_statement(&mut self) {
 	}
 
-	pub fn resolve_expr_logical(&mut self, expr: ExprLogical) -> ResolverResult<()> {
-		self.resolve_expr(*expr.left)?;
-		self.resolve_expr(*expr.right)?;
-
-		Ok(())
+	fn visit_continue_statement(&mut self) {
 	}
+}
 
-	pub fn resolve_expr_set(&mut self, expr: ExprSet) -> ResolverResult<()> {
-		self.resolve_expr(*expr.value)?;
-		self.resolve_expr(*expr.object)?;
+impl ExprVisitor for Resolver {
+	type Output = ();
 
-		Ok(())
-	}
+	fn visit_assignment_expr(&mut self, expr: &ExprAssignment) {
+		let name = &expr.name;
 
-	pub fn resolve_expr_this(&mut self, expr: ExprThis) -> ResolverResult<()> {
+		let binding = self.scopes.iter().rev().find_map(|scope| scope.get(&name.lexeme));
 
-		if self.current_class == ClassType::NONE {
-			return Err(self.error(expr.keyword.clone(), "Can't use 'this' outside of a class".to_string()))
+		if binding.is_some_and(|b| b.constant) {
+			self.error(name.clone(), format!("Can't assign to constant '{}'.", name.lexeme));
 		}
 
-		let expr_this = Expr::This(expr.clone());
-		let keyword = expr.keyword;
-
-		self.resolve_local(expr_this, keyword);
+		self.resolve_expr(&expr.value);
+		self.resolve_local(expr.id, name);
+	}
 
-		Ok(())
+	fn visit_binary_expr(&mut self, expr: &ExprBinary) {
+		self.resolve_expr(&expr.left);
+		self.resolve_expr(&expr.right);
 	}
 
-	pub fn resolve_expr_unary(&mut self, expr: ExprUnary) -> ResolverResult<()> {
-		self.resolve_expr(*expr.right)?;
+	fn visit_block_expr(&mut self, expr: &ExprBlock) {
+		self.begin_scope();
+		self.resolve_all(&expr.statements);
+
+		if let Some(value) = &expr.value {
+			self.resolve_expr(value);
+		}
 
-		Ok(())
+		self.end_scope();
 	}
 
-	pub fn resolve_expr_variable(&mut self, expr: ExprVariable) -> ResolverResult<()> {
-		if !self.scopes.is_empty() {
-			if let Some(scope) = self.scopes.last() {
-				if let Some(&v) = scope.get(&expr.name.lexeme) {
-					if !v {
-						return Err(self.error(expr.name, "Can't read local variable in its own initializer".to_string()))
-					}
-				}
-				// .expect("Unwrapped a scope entry and failed, this shouldn't happen").clone();
-			}
+	fn visit_if_expr(&mut self, expr: &ExprIf) {
+		self.resolve_expr(&expr.condition);
+		self.resolve_expr(&expr.then_branch);
+
+		if let Some(else_branch) = &expr.else_branch {
+			self.resolve_expr(else_branch);
 		}
+	}
 
-		let n = expr.name.clone();
-		self.resolve_local(Expr::Variable(expr), n);
-
-		Ok(())
-	}
-
-	fn resolve_statement(&mut self, statement: Statement) -> ResolverResult<()> {
-		match statement {
-			Statement::Block(s) => {self.resolve_block_statement(s)?},
-			Statement::Break() => {},
-			Statement::Continue() => {},
-			Statement::If(s) => {self.resolve_if_statement(s)?},
-			Statement::Print(s) => {self.resolve_print_statement(s)?},
-			Statement::Return(s) => {self.resolve_return_statement(s)?},
-			Statement::While(s) => {self.resolve_while_statement(s)?},
-			Statement::Function(s) => {self.resolve_func_statement(s)?},
-			Statement::Class(s) => {self.resolve_class_decl(s)?},
-			Statement::Expression(s) => {self.resolve_expression_statement(s)?},
-			Statement::Var(s) => {self.resolve_var_statement(s)?},
+	fn visit_array_expr(&mut self, expr: &ExprArray) {
+		for element in &expr.elements {
+			self.resolve_expr(element);
 		}
+	}
 
-		Ok(())
+	fn visit_index_expr(&mut self, expr: &ExprIndex) {
+		self.resolve_expr(&expr.object);
+		self.resolve_expr(&expr.index);
 	}
 
-	// endregion: --- Statements
+	fn visit_index_set_expr(&mut self, expr: &ExprIndexSet) {
+		self.resolve_expr(&expr.value);
+		self.resolve_expr(&expr.object);
+		self.resolve_expr(&expr.index);
+	}
 
-	fn resolve_expr(&mut self, expr: Expr) -> ResolverResult<()> {
-		match expr {
-			Expr::Assignment(expr) => {self.resolve_expr_assignment(expr)?},
-			Expr::Binary(expr) => {self.resolve_expr_binary(expr)?},
-			Expr::Unary(expr) => {self.resolve_expr_unary(expr)?},
-			Expr::Grouping(expr) => {self.resolve_expr_grouping(expr)?},
-			Expr::Literal(expr) => {self.resolve_expr_literal(expr)?},
-			Expr::Call(expr) => {self.resolve_expr_call(expr)?},
-			Expr::Get(expr) => {self.resolve_expr_get(expr)?},
-			Expr::Set(expr) => {self.resolve_expr_set(expr)?},
-			Expr::This(expr) => {self.resolve_expr_this(expr)?},
-			Expr::Variable(expr) => {self.resolve_expr_variable(expr)?},
-			Expr::Logical(expr) => {self.resolve_expr_logical(expr)?},
-		}
+	fn visit_call_expr(&mut self, expr: &ExprCall) {
+		self.resolve_expr(&expr.callee);
 
-		Ok(())
+		for argument in &expr.arguments {
+			self.resolve_expr(argument);
+		}
 	}
 
+	fn visit_get_expr(&mut self, expr: &ExprGet) {
+		self.resolve_expr(&expr.object);
+	}
 
-	// region:    --- Utils
-
-	
+	fn visit_grouping_expr(&mut self, ExprGrouping(expr): &ExprGrouping) {
+		self.resolve_expr(expr);
+	}
 
-	fn begin_scope(&mut self) {
-		self.scopes.push(HashMap::new());
+	fn visit_literal_expr(&mut self, _: &ExprLiteral) {
 	}
 
-	fn end_scope(&mut self) {
-		self.scopes.pop();
+	fn visit_logical_expr(&mut self, expr: &ExprLogical) {
+		self.resolve_expr(&expr.left);
+		self.resolve_expr(&expr.right);
 	}
 
-	fn declare(&mut self, name: &Token) -> ResolverResult<()> {
-		if let Some(scope) = self.scopes.last_mut() {
-			if scope.contains_key(&name.lexeme) {
-				return Err(self.error(name.clone(), "Already a variable with this name in this scope".to_string()))
-			}
+	fn visit_set_expr(&mut self, expr: &ExprSet) {
+		self.resolve_expr(&expr.value);
+		self.resolve_expr(&expr.object);
+	}
 
-			scope.insert(name.lexeme.clone(), false);
+	fn visit_this_expr(&mut self, expr: &ExprThis) {
 
+		if self.current_class == ClassType::NONE {
+			self.error(expr.keyword.clone(), "Can't use 'this' outside of a class".to_string());
+			return
 		}
 
-		Ok(())
+		self.resolve_local(expr.id, &expr.keyword);
 	}
 
-	fn define(&mut self, name: &Token) {
-		if let Some(scope) = self.scopes.last_mut() {
-			scope.insert(name.lexeme.clone(), true);
-		}
+	fn visit_unary_expr(&mut self, expr: &ExprUnary) {
+		self.resolve_expr(&expr.right);
 	}
 
-	fn resolve_local(&mut self, expr: Expr, name: Token) {
-		let n = self.scopes.len();
-		for i in (0..n).rev() {
-			if self.scopes[i].contains_key(&name.lexeme) {
-				self.interpreter.resolve_expr_depth(expr, (n - 1) - i);
-				return
+	fn visit_variable_expr(&mut self, expr: &ExprVariable) {
+		if !self.scopes.is_empty() {
+			if let Some(scope) = self.scopes.last() {
+				if let Some(v) = scope.get(&expr.name.lexeme) {
+					if !v.defined {
+						self.error(expr.name.clone(), "Can't read local variable in its own initializer".to_string());
+						return
+					}
+				}
+				// .expect("Unwrapped a scope entry and failed, this shouldn't happen").clone();
 			}
 		}
-	}
 
-	fn error(&self, token: Token, message: String) -> ResolverError {
-		let e = ResolverError::new(token, message);
-		e.error();
-
-		e
+		self.resolve_local(expr.id, &expr.name);
 	}
-	// endregion: --- Utils
-
-}
\ No newline at end of file
+}
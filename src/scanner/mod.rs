@@ -1,64 +1,103 @@
-use error::{ScannerError, ScannerResult};
-use token::{keywords, Literal, Token, TokenType};
+use std::collections::VecDeque;
 
-use crate::{char_at, utils::{is_alpha, is_alphanumeric, substring}};
+use token::{keyword, Literal, Location, Span, Symbol, Token, TokenType};
+
+use crate::{error::{LoxError, LoxResult}, utils::{is_alpha, is_alphanumeric}};
 
-pub mod error;
 pub mod token;
 
-/// Lox Scanner
+/// Lox Scanner. Tokens are produced on demand by iterating over it, with errors in place of the
+/// tokens that failed to scan
 pub struct Scanner {
 	source: String,
-	tokens: Vec<Token>,
+	/// Tokens and errors scanned but not yet taken
+	pending: VecDeque<LoxResult<Token>>,
+	/// Whether the EOF token has been produced
+	finished: bool,
 	start: usize,
   current: usize,
-  line: usize,
+	/// The line being scanned, starting at 1. The REPL carries it across inputs
+	pub line: usize,
+	/// The line the current token starts on, multi-line tokens are reported where they start
+	start_line: usize,
+	/// The byte offset and column of the last token, so columns are counted from there rather
+	/// than from the start of the line
+	column_cursor: (usize, usize),
+	/// Emit comments as `COMMENT` tokens instead of skipping them, for tools that print source back
+	pub keep_comments: bool,
 	pub had_error: bool
 }
 
 impl Scanner {
-	/// Create a new scanner
+	/// Create a new scanner. A leading UTF-8 byte order mark is stripped
 	pub fn new(source: String) -> Self {
+		let source = match source.strip_prefix('\u{FEFF}') {
+			Some(stripped) => stripped.to_string(),
+			None => source
+		};
+
 		Self {
 			source,
-			tokens: Vec::new(),
+			pending: VecDeque::new(),
+			finished: false,
 			start: 0,
 			current: 0,
 			line: 1,
+			start_line: 1,
+			column_cursor: (0, 1),
+			keep_comments: false,
 			had_error: false
 		}
 	}
 	
-	/// Scan and return all file tokens
-	pub fn scan_tokens(&mut self) -> ScannerResult<Vec<Token>> {
-		while !self.is_at_end() {
-			self.start = self.current;
-			self.scan_token()?
+	/// Scan and return all file tokens, along with the errors found on the way
+	pub fn scan_tokens(&mut self) -> (Vec<Token>, Vec<LoxError>) {
+		let mut tokens = Vec::new();
+		let mut errors = Vec::new();
+
+		for result in self.by_ref() {
+			match result {
+				Ok(token) => tokens.push(token),
+				Err(e) => errors.push(e)
+			}
 		}
 
-		self.tokens.push(Token::new(TokenType::EOF, "".to_string(), Literal::Null, self.line));
+		(tokens, errors)
+	}
 
-		Ok(self.tokens.clone())
+	/// The source being scanned, which token spans point into
+	pub fn source(&self) -> &str {
+		&self.source
 	}
 
-	pub fn error(&mut self, e: ScannerError) {
+	/// Record an error, to be produced in place of a token
+	pub fn error(&mut self, e: LoxError) {
 		self.had_error = true;
-		e.report("");
+		self.pending.push_back(Err(e));
+	}
+
+	/// Record an error about the text of the current token
+	fn lexical_error(&mut self, message: &str) {
+		let location = Location { line: self.start_line, column: self.column(), span: Span::new(self.start, self.current) };
+		self.error(LoxError::Lexical { line: self.line, message: message.to_string(), location });
 	}
 
 	/// Scan a file for a token
-	fn scan_token(&mut self) -> ScannerResult<()> {
+	fn scan_token(&mut self) -> LoxResult<()> {
     let c = self.advance();
     match c {
       '(' => self.add_token(TokenType::LEFT_PAREN),
       ')' => self.add_token(TokenType::RIGHT_PAREN),
       '{' => self.add_token(TokenType::LEFT_BRACE),
       '}' => self.add_token(TokenType::RIGHT_BRACE),
+      '[' => self.add_token(TokenType::LEFT_BRACKET),
+      ']' => self.add_token(TokenType::RIGHT_BRACKET),
       ',' => self.add_token(TokenType::COMMA),
       '.' => self.add_token(TokenType::DOT),
       '-' => self.add_token(TokenType::MINUS),
       '+' => self.add_token(TokenType::PLUS),
       ';' => self.add_token(TokenType::SEMICOLON),
+      ':' => self.add_token(TokenType::COLON),
       '*' => self.add_token(TokenType::STAR),
 			// Double symbols
       '!' => {
@@ -79,22 +118,36 @@ impl Scanner {
 			},
 			'/' => {
 				if self.match_char('/') {
-					while self.peek() != '\n' && !self.is_at_end() {
+					while self.peek() != '\n' && self.peek() != '\r' && !self.is_at_end() {
 						self.advance();
 					}
+
+					if self.keep_comments { self.add_token(TokenType::COMMENT) }
+				} else if self.match_char('*') {
+					self.block_comment();
+
+					if self.keep_comments { self.add_token(TokenType::COMMENT) }
 				} else {
 					self.add_token(TokenType::SLASH)
 				}
 			},
 			// Whitespace
 			' ' => {},
-			'\r' => {},
+			// `\r\n` and a lone `\r` are both a single line break
+			'\r' => {
+				self.match_char('\n');
+				self.line += 1
+			},
 			'\t' => {},
 			'\n' => {self.line += 1},
 			// String literals
 			'"' => {
 				self.string()
 			},
+			// Character literals
+			'\'' => {
+				self.character()
+			},
 			// Number literals
 			'1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' | '0' => {
 				self.number()
@@ -105,12 +158,7 @@ impl Scanner {
 					self.identifier()
 				} else {
 
-					self.error(
-						ScannerError {
-							line: self.line,
-							message: format!("Unexpected character: {}", c)
-						}
-					)
+					self.lexical_error(&format!("Unexpected character: {}", c))
 				}
 			}
     }
@@ -124,83 +172,252 @@ impl Scanner {
 			return false
 		}
 
-		let c = char_at(&self.source, self.current);
-
-		if c != expected {
+		if self.peek() != expected {
 			return false
 		}
 
-		self.current += 1;
+		self.current += expected.len_utf8();
 		return true
 
 	}
 
+	/// Skip a block comment, the opening `/*` has already been consumed. Block comments nest
+	fn block_comment(&mut self) {
+		let mut depth = 1;
+
+		while depth > 0 {
+			if self.is_at_end() {
+				self.lexical_error("Unterminated block comment.");
+				return;
+			}
+
+			match self.advance() {
+				'\n' => self.line += 1,
+				'\r' => {
+					self.match_char('\n');
+					self.line += 1
+				},
+				'/' if self.match_char('*') => depth += 1,
+				'*' if self.match_char('/') => depth -= 1,
+				_ => {}
+			}
+		}
+	}
+
 	/// Tokenize a string
 	fn string(&mut self) {
 		while self.peek() != '"' && !self.is_at_end() {
-			if self.peek() == '\n' { self.line += 1; }
+			if self.peek() == '\n' || (self.peek() == '\r' && self.peek_next() != '\n') { self.line += 1; }
 			self.advance();
 		}
 
 		if self.is_at_end() {
-			self.error(ScannerError { line: self.line, message: "Unterminated string.".to_string() });
+			self.lexical_error("Unterminated string.");
 			return;
 		}
 
 		self.advance();
 
-		let value = substring(&self.source, self.start + 1, self.current - 1);
-		self.add_token_to_list(TokenType::STRING, Literal::String(value.to_string()));
+		let value = &self.source[self.start + 1..self.current - 1];
+		self.add_token_to_list(TokenType::STRING, Literal::String(Symbol::intern(value)));
+	}
+
+	/// Tokenize a character literal, producing a one-character string
+	fn character(&mut self) {
+		let c = match self.peek() {
+			'\'' => {
+				self.advance();
+				self.lexical_error("Empty character literal.");
+				return;
+			},
+			'\n' | '\0' => {
+				self.lexical_error("Unterminated character literal.");
+				return;
+			},
+			'\\' => {
+				self.advance();
+
+				if self.is_at_end() {
+					self.lexical_error("Unterminated character literal.");
+					return;
+				}
+
+				let escaped = self.advance();
+
+				match escaped {
+					'n' => '\n',
+					't' => '\t',
+					'r' => '\r',
+					'0' => '\0',
+					'\\' | '\'' | '"' => escaped,
+					_ => {
+						self.skip_character_literal();
+						self.lexical_error(&format!("Invalid escape sequence '\\{}'.", escaped));
+						return;
+					}
+				}
+			},
+			_ => self.advance()
+		};
+
+		if !self.match_char('\'') {
+			if self.skip_character_literal() {
+				self.lexical_error("Character literal must contain exactly one character.");
+			} else {
+				self.lexical_error("Unterminated character literal.");
+			}
+			return;
+		}
+
+		self.add_token_to_list(TokenType::STRING, Literal::String(Symbol::intern(&c.to_string())));
+	}
+
+	/// Skip to the closing quote of a malformed character literal on this line, so the rest of the
+	/// literal isn't scanned as code. Returns whether a closing quote was found
+	fn skip_character_literal(&mut self) -> bool {
+		while self.peek() != '\'' && self.peek() != '\n' && !self.is_at_end() {
+			self.advance();
+		}
+
+		self.match_char('\'')
 	}
 
 	/// Tokenize a number
 	fn number(&mut self) {
-		while self.peek().is_digit(10) {
+		if self.source[self.start..].starts_with('0') {
+			let radix = match self.peek() {
+				'x' | 'X' => Some(16),
+				'b' | 'B' => Some(2),
+				'o' | 'O' => Some(8),
+				_ => None
+			};
+
+			if let Some(radix) = radix {
+				self.advance();
+				return self.radix_number(radix);
+			}
+		}
+
+		while self.peek().is_ascii_digit() {
+			self.advance();
+		}
+
+		if self.peek() == '.' && self.peek_next().is_ascii_digit() {
 			self.advance();
+
+			while self.peek().is_ascii_digit() { self.advance();}
+		}
+
+		// Exponent, e.g. `1e9`, `2.5e-3`, `1E+6`
+		if self.peek() == 'e' || self.peek() == 'E' {
+			let signed = self.peek_next() == '+' || self.peek_next() == '-';
+			let first_digit = if signed { self.peek_nth(2) } else { self.peek_next() };
+
+			if first_digit.is_ascii_digit() {
+				self.advance();
+				if signed { self.advance(); }
+
+				while self.peek().is_ascii_digit() { self.advance(); }
+			} else {
+				self.advance();
+				if signed { self.advance(); }
+
+				// Consume the rest of a malformed literal such as `1ex` so that it is reported once
+				while is_alphanumeric(self.peek()) { self.advance(); }
+
+				self.lexical_error("Expect digits in exponent.");
+				return;
+			}
+		}
+
+		let value = self.source[self.start..self.current].parse::<f64>().unwrap();
+
+		if value.is_infinite() {
+			self.lexical_error("Number literal is too large.");
+			return;
 		}
 
-		if self.peek() == '.' && self.peek_next().is_digit(10) {
+		self.add_token_to_list(TokenType::NUMBER, Literal::Float(value))
+
+	}
+
+	/// Tokenize a prefixed (`0x`, `0b`, `0o`) number, the prefix has already been consumed
+	fn radix_number(&mut self, radix: u32) {
+		let kind = match radix {
+			16 => "hexadecimal",
+			8 => "octal",
+			_ => "binary"
+		};
+
+		// Consume every alphanumeric so that a malformed literal is reported as a single error
+		while is_alphanumeric(self.peek()) {
 			self.advance();
+		}
 
-			while self.peek().is_digit(10) { self.advance();}
+		let digits = &self.source[self.start + 2..self.current];
+
+		if digits.is_empty() {
+			let prefix = self.source[self.start..self.current].to_string();
+			self.lexical_error(&format!("Expect {} digits after '{}'.", kind, prefix));
+			return;
 		}
 
-		self.add_token_to_list(TokenType::NUMBER, Literal::Float(substring(&self.source, self.start, self.current).parse::<f64>().unwrap()))
+		let mut value = 0.0;
+
+		for c in digits.chars() {
+			match c.to_digit(radix) {
+				Some(d) => value = value * radix as f64 + d as f64,
+				None => {
+					self.lexical_error(&format!("Invalid digit '{}' in {} literal.", c, kind));
+					return;
+				}
+			}
+		}
 
+		self.add_token_to_list(TokenType::NUMBER, Literal::Float(value))
 	}
 
 	/// Tokenize an identifier
 	fn identifier(&mut self) {
 		while is_alphanumeric(self.peek()) { self.advance();};
-		let text = substring(&self.source, self.start, self.current);
+		let text = &self.source[self.start..self.current];
 
-		let token_type = keywords().get(text).unwrap_or(&TokenType::IDENTIFIER).clone();
+		let token_type = keyword(text).unwrap_or(TokenType::IDENTIFIER);
 
 		self.add_token(token_type);
 	}
 
 	// Check the current character, without consuming
 	fn peek(&self) -> char {
-		if self.is_at_end() {
-			return '\0';
-		}
-		
-		return char_at(&self.source, self.current);
+		self.peek_nth(0)
 	}
 
 	/// Check the next character, without consuming
 	fn peek_next(&self) -> char {
-		if self.current + 1 >= self.source.len() {
-			return '\0'
-		} 
+		self.peek_nth(1)
+	}
+
+	/// Check the character `n` characters ahead, without consuming. Runs of ASCII are read as bytes,
+	/// only other characters are decoded
+	fn peek_nth(&self, n: usize) -> char {
+		let bytes = &self.source.as_bytes()[self.current..];
+
+		if bytes.iter().take(n + 1).all(u8::is_ascii) {
+			return bytes.get(n).map(|b| *b as char).unwrap_or('\0')
+		}
 
-		return char_at(&self.source, self.current + 1)
+		self.source[self.current..].chars().nth(n).unwrap_or('\0')
 	}
 
-	/// Consume and return the current character, move forward 1 step
+	/// Consume and return the current character, moving forward by its UTF-8 length. At the end of
+	/// the input this returns `'\0'` and stays put
 	fn advance(&mut self) -> char {
-		let c = char_at(&self.source, self.current);
-		self.current += 1;
+		if self.is_at_end() {
+			return '\0'
+		}
+
+		let c = self.peek();
+		self.current += c.len_utf8();
 
 		return c;
 	}
@@ -210,17 +427,39 @@ impl Scanner {
 		self.add_token_to_list(token_type, Literal::Null);
 	}
 	
-	/// Add a given token to the list, with a given literal
+	/// Add a given token to the list, with a given literal. Comments aren't interned, their text is
+	/// read back through their span
 	fn add_token_to_list(&mut self, token_type: TokenType, literal: Literal) {
-		let text = &self.source[(self.start as usize)..(self.current as usize)];
+		let span = Span::new(self.start, self.current);
+		let lexeme = match token_type {
+			TokenType::COMMENT => Symbol::default(),
+			_ => Symbol::intern(&self.source[span.start..span.end()])
+		};
+
 		let token = Token {
 			token_type,
-			lexeme: text.to_string(),
+			lexeme,
 			literal,
-			line: self.line, 
+			line: self.start_line,
+			column: self.column(),
+			span,
+		};
+
+		self.pending.push_back(Ok(token))
+	}
+
+	/// The column of the current token's first character, counting from 1
+	fn column(&mut self) -> usize {
+		let (offset, column) = self.column_cursor;
+		let between = &self.source[offset..self.start];
+
+		let column = match between.rfind(['\n', '\r']) {
+			Some(i) => between[i + 1..].chars().count() + 1,
+			None => column + between.chars().count()
 		};
 
-		self.tokens.push(token)
+		self.column_cursor = (self.start, column);
+		column
 	}
 
 	/// Check if is at end of source
@@ -229,5 +468,26 @@ impl Scanner {
 	}
 }
 
+impl Iterator for Scanner {
+	type Item = LoxResult<Token>;
 
+	/// Scan until a token or error is produced. The last token is always EOF
+	fn next(&mut self) -> Option<Self::Item> {
+		while self.pending.is_empty() && !self.finished {
+			self.start = self.current;
+			self.start_line = self.line;
+
+			if self.is_at_end() {
+				let mut eof = Token::new(TokenType::EOF, "", Literal::Null, self.line);
+				eof.column = self.column();
+				eof.span = Span::new(self.current, self.current);
+				self.pending.push_back(Ok(eof));
+				self.finished = true;
+			} else if let Err(e) = self.scan_token() {
+				self.error(e);
+			}
+		}
 
+		self.pending.pop_front()
+	}
+}
@@ -1,16 +1,61 @@
-use std::{collections::HashMap, hash::Hash, sync::OnceLock};
+use std::hash::Hash;
+
+use crate::utils::number;
+
+pub use symbol::Symbol;
+
+pub mod symbol;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Token {
 	pub token_type: TokenType,
-	pub lexeme: String,
+	pub lexeme: Symbol,
 	pub literal: Literal,
-	pub line: usize
+	pub line: usize,
+	/// The character column the token starts at, counting from 1. 0 for tokens that don't appear in source
+	pub column: usize,
+	/// Where the token is in the scanned source. Empty for tokens that don't appear in source
+	pub span: Span
 }
 
 impl Token {
-	pub fn new(token_type: TokenType, lexeme: String, literal: Literal, line: usize) -> Self {
-		Self { token_type, lexeme, literal, line }
+	pub fn new(token_type: TokenType, lexeme: &str, literal: Literal, line: usize) -> Self {
+		Self { token_type, lexeme: Symbol::intern(lexeme), literal, line, column: 0, span: Span::default() }
+	}
+
+	/// The token's text in the source it was scanned from
+	pub fn text<'a>(&self, source: &'a str) -> &'a str {
+		&source[self.span.start..self.span.end()]
+	}
+
+	pub fn location(&self) -> Location {
+		Location { line: self.line, column: self.column, span: self.span }
+	}
+}
+
+/// Where a diagnostic points in the source: the line and column it starts at, and the bytes it
+/// covers. A column of 0 means only the line is known
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+	pub line: usize,
+	pub column: usize,
+	pub span: Span
+}
+
+/// A range of bytes in a source
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+	pub start: usize,
+	pub len: usize
+}
+
+impl Span {
+	pub fn new(start: usize, end: usize) -> Self {
+		Self { start, len: end - start }
+	}
+
+	pub fn end(&self) -> usize {
+		self.start + self.len
 	}
 }
 
@@ -26,6 +71,7 @@ pub enum TokenType {
   // Single-character tokens.
   LEFT_PAREN, RIGHT_PAREN, LEFT_BRACE, RIGHT_BRACE,
   COMMA, DOT, MINUS, PLUS, SEMICOLON, SLASH, STAR,
+	COLON, LEFT_BRACKET, RIGHT_BRACKET,
 
   // One or two character tokens.
   BANG, BANG_EQUAL,
@@ -39,40 +85,50 @@ pub enum TokenType {
   // Keywords.
   AND, CLASS, ELSE, FALSE, FUN, FOR, IF, NIL, OR,
   PRINT, RETURN, SUPER, THIS, TRUE, VAR, WHILE, BREAK,
-	CONTINUE,
+	CONTINUE, SWITCH, CASE, DEFAULT, IN, CONST, IMPORT, EXPORT, FROM,
+
+	// Only produced when the scanner keeps comments
+	COMMENT,
 
   EOF
 }
 
-pub fn keywords() -> &'static HashMap<&'static str, TokenType> {
-	static HASHMAP: OnceLock<HashMap<&str, TokenType>> = OnceLock::new();
-	HASHMAP.get_or_init(|| {
-		let mut map = HashMap::new();
-			map.insert("and", TokenType::AND);
-			map.insert("break", TokenType::BREAK);
-			map.insert("class", TokenType::CLASS);
-			map.insert("continue", TokenType::CONTINUE);
-			map.insert("else", TokenType::ELSE);
-			map.insert("false", TokenType::FALSE);
-			map.insert("for", TokenType::FOR);
-			map.insert("fun", TokenType::FUN);
-			map.insert("if", TokenType::IF);
-			map.insert("nil", TokenType::NIL);
-			map.insert("or", TokenType::OR);
-			map.insert("print", TokenType::PRINT);
-			map.insert("return", TokenType::RETURN);
-			map.insert("super", TokenType::SUPER);
-			map.insert("this", TokenType::THIS);
-			map.insert("true", TokenType::TRUE);
-			map.insert("var", TokenType::VAR);
-			map.insert("while", TokenType::WHILE);
-
-		map
-	})
+/// The keyword an identifier spells, if any. Identifiers are the most common tokens, so rather than
+/// hashing each one, the first character picks the few keywords it could be
+pub fn keyword(text: &str) -> Option<TokenType> {
+	let token_type = match (text.as_bytes().first()?, text) {
+		(b'a', "and") => TokenType::AND,
+		(b'b', "break") => TokenType::BREAK,
+		(b'c', "case") => TokenType::CASE,
+		(b'c', "class") => TokenType::CLASS,
+		(b'c', "const") => TokenType::CONST,
+		(b'c', "continue") => TokenType::CONTINUE,
+		(b'd', "default") => TokenType::DEFAULT,
+		(b'e', "else") => TokenType::ELSE,
+		(b'e', "export") => TokenType::EXPORT,
+		(b'f', "false") => TokenType::FALSE,
+		(b'f', "for") => TokenType::FOR,
+		(b'f', "from") => TokenType::FROM,
+		(b'f', "fun") => TokenType::FUN,
+		(b'i', "if") => TokenType::IF,
+		(b'i', "import") => TokenType::IMPORT,
+		(b'i', "in") => TokenType::IN,
+		(b'n', "nil") => TokenType::NIL,
+		(b'o', "or") => TokenType::OR,
+		(b'p', "print") => TokenType::PRINT,
+		(b'r', "return") => TokenType::RETURN,
+		(b's', "super") => TokenType::SUPER,
+		(b's', "switch") => TokenType::SWITCH,
+		(b't', "this") => TokenType::THIS,
+		(b't', "true") => TokenType::TRUE,
+		(b'v', "var") => TokenType::VAR,
+		(b'w', "while") => TokenType::WHILE,
+		_ => return None
+	};
+
+	Some(token_type)
 }
 
-// static KEYWORDS: HashMap<&str, TokenType> = HashMap::from_iter([("and", TokenType::AND)]);
-
 impl std::fmt::Display for TokenType {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		write!(f, "{:?}", self)
@@ -83,7 +139,7 @@ impl std::fmt::Display for TokenType {
 pub enum Literal {
 	Null,
 	Integer(i64),
-	String(String),
+	String(Symbol),
 	Float(f64),
 	Boolean(bool)
 }
@@ -122,7 +178,7 @@ impl std::fmt::Display for Literal {
 			Self::Null => "null".to_string(),
 			Self::Integer(x) => x.to_string(),
 			Self::String(s) => s.to_string(),
-			Self::Float(f) => format!("{:?}", f),
+			Self::Float(f) => number::format_literal(*f),
 			Self::Boolean(b) => b.to_string()
 		};
 
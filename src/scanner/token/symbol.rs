@@ -0,0 +1,119 @@
+use std::{collections::HashSet, hash::{Hash, Hasher}, ops::Deref, sync::{Mutex, OnceLock}};
+
+/// An interned string. Each distinct string is stored once for the life of the program, so symbols
+/// compare and hash by address instead of by content, and copying one never allocates
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+fn interner() -> &'static Mutex<HashSet<&'static str>> {
+	static INTERNER: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+	INTERNER.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+impl Symbol {
+	/// The symbol for a string, storing it the first time it is seen
+	pub fn intern(s: &str) -> Self {
+		let mut strings = interner().lock().expect("The interner is never poisoned");
+
+		if let Some(existing) = strings.get(s) {
+			return Self(existing)
+		}
+
+		let leaked: &'static str = Box::leak(s.to_string().into_boxed_str());
+		strings.insert(leaked);
+		Self(leaked)
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		self.0
+	}
+
+	/// The symbol for `this`, bound whenever a method is bound to an instance
+	pub fn this() -> Self {
+		static THIS: OnceLock<Symbol> = OnceLock::new();
+		*THIS.get_or_init(|| Self::intern("this"))
+	}
+
+	/// The symbol for `init`, looked up whenever a class is called
+	pub fn init() -> Self {
+		static INIT: OnceLock<Symbol> = OnceLock::new();
+		*INIT.get_or_init(|| Self::intern("init"))
+	}
+}
+
+impl Default for Symbol {
+	fn default() -> Self {
+		Self::intern("")
+	}
+}
+
+impl Deref for Symbol {
+	type Target = str;
+
+	fn deref(&self) -> &str {
+		self.0
+	}
+}
+
+impl PartialEq for Symbol {
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self.0, other.0)
+	}
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+	fn eq(&self, other: &str) -> bool {
+		self.0 == other
+	}
+}
+
+impl PartialEq<&str> for Symbol {
+	fn eq(&self, other: &&str) -> bool {
+		self.0 == *other
+	}
+}
+
+/// Symbols order by their text, like the strings they stand for
+impl PartialOrd for Symbol {
+	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Symbol {
+	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+		self.0.cmp(other.0)
+	}
+}
+
+impl Hash for Symbol {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		std::ptr::hash(self.0, state)
+	}
+}
+
+impl From<&str> for Symbol {
+	fn from(s: &str) -> Self {
+		Self::intern(s)
+	}
+}
+
+impl From<String> for Symbol {
+	fn from(s: String) -> Self {
+		Self::intern(&s)
+	}
+}
+
+impl std::fmt::Display for Symbol {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.0)
+	}
+}
+
+impl std::fmt::Debug for Symbol {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{:?}", self.0)
+	}
+}
@@ -1,16 +1,21 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::{HashMap, HashSet}, rc::{Rc, Weak}};
 
-use error::{EnvironmentError, EnvironmentResult};
 
-use crate::{interpreter::values::{Value, ValueCell}, scanner::token::Token};
+use crate::{error::{LoxError, LoxResult}, interpreter::values::{Value, ValueCell}, scanner::token::{Symbol, Token}};
 
-pub mod error;
+pub mod pool;
 
 
 /// A struct representing an interpreter's environment
 #[derive(Default, Clone)]
 pub struct Environment {
-	pub values: HashMap<String, ValueCell>,
+	/// The index in `values` of each name known to this environment
+	pub slots: HashMap<Symbol, usize>,
+	/// The values by slot. A global's slot is reserved when the resolver first sees it referred to,
+	/// and stays empty until the global is defined
+	pub values: Vec<Option<ValueCell>>,
+	/// Names in `values` that were declared with `const`
+	pub constants: HashSet<Symbol>,
 	pub enclosing: Option<EnvCell>
 }
 
@@ -18,6 +23,26 @@ pub struct Environment {
 #[derive(Clone)]
 pub struct EnvCell(pub Rc<RefCell<Environment>>);
 
+thread_local! {
+	/// The environments captured by functions, the only way environments end up in a reference
+	/// cycle, for the garbage collector to search for cycles from
+	static ENVIRONMENTS: RefCell<Vec<Weak<RefCell<Environment>>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The number of environments tracked for the garbage collector, including any freed since it last ran
+pub fn tracked_count() -> usize {
+	ENVIRONMENTS.with(|environments| environments.borrow().len())
+}
+
+/// The environments still alive, forgetting those that have been freed
+pub fn tracked() -> Vec<Rc<RefCell<Environment>>> {
+	ENVIRONMENTS.with(|environments| {
+		let mut environments = environments.borrow_mut();
+		environments.retain(|e| e.strong_count() > 0);
+		environments.iter().filter_map(Weak::upgrade).collect()
+	})
+}
+
 impl EnvCell {
 	pub fn new() -> Self {
 		let e = Environment::default();
@@ -28,18 +53,42 @@ impl EnvCell {
 		Self(Rc::new(RefCell::new(environment)))
 	}
 
+	/// Track the environment for the garbage collector, done when a function captures it
+	pub fn track(&self) {
+		ENVIRONMENTS.with(|environments| {
+			let mut environments = environments.borrow_mut();
+
+			// Functions declared together capture the same environment
+			if environments.last().is_some_and(|e| std::ptr::eq(e.as_ptr(), Rc::as_ptr(&self.0))) {
+				return
+			}
+
+			environments.push(Rc::downgrade(&self.0));
+		})
+	}
+
+	/// Fails if a name is a constant of the inner environment, which can't be declared again
+	pub fn check_redeclaration(&self, name: &Token) -> LoxResult<()> {
+		self.0.borrow().check_redeclaration(name)
+	}
+
 	/// Define an environment entry, by mutably borrowing the inner environment
-	pub fn define(&mut self, name: String, value: Value) {
+	pub fn define(&mut self, name: Symbol, value: Value) {
 		self.0.borrow_mut().define(name, value);
 	}
 
+	/// Define an immutable environment entry
+	pub fn define_constant(&mut self, name: Symbol, value: Value) {
+		self.0.borrow_mut().define_constant(name, value);
+	}
+
 	/// Assigns a value to an entry in the given environment chain
-	pub fn assign(&mut self, name: Token, value: Value) -> EnvironmentResult<()> {
+	pub fn assign(&mut self, name: Token, value: Value) -> LoxResult<()> {
 		self.0.borrow_mut().assign(name, value)
 	}
 
 	/// Gets the value for a given entry inside the inner environment
-	pub fn get(&self, name: Token) -> EnvironmentResult<ValueCell> {
+	pub fn get(&self, name: Token) -> LoxResult<ValueCell> {
 		return self.0.borrow().get(name)
 	}
 
@@ -55,11 +104,40 @@ impl EnvCell {
 
 	}
 
-	pub fn get_at(&mut self, distance: usize, name: String) -> ValueCell {
-		self.ancestor(distance).0.borrow().values.get(&name).expect("Unwrap failed on EnvCell, get_at").clone()
+	pub fn get_at(&self, distance: usize, name: Symbol) -> ValueCell {
+		self.ancestor(distance).0.borrow().lookup(name).expect("Unwrap failed on EnvCell, get_at").clone()
+	}
+
+	/// The slot of a name in the inner environment, reserving an empty one if the name is new
+	pub fn slot(&mut self, name: Symbol) -> usize {
+		self.0.borrow_mut().slot(name)
+	}
+
+	/// Gets the value in a slot of the inner environment, failing if its name hasn't been defined yet
+	pub fn get_slot(&self, slot: usize, name: &Token) -> LoxResult<ValueCell> {
+		match &self.0.borrow().values[slot] {
+			Some(v) => Ok(v.clone()),
+			None => Err(LoxError::runtime(name.location(), &format!("Undefined variable '{}'.", name.lexeme)))
+		}
+	}
+
+	/// Assigns to a slot of the inner environment, failing if its name hasn't been defined yet or is a constant
+	pub fn assign_slot(&mut self, slot: usize, name: &Token, value: Value) -> LoxResult<()> {
+		let mut environment = self.0.borrow_mut();
+
+		if environment.values[slot].is_none() {
+			return Err(LoxError::runtime(name.location(), &format!("Undefined variable '{}'.", name.lexeme)))
+		}
+
+		if environment.constants.contains(&name.lexeme) {
+			return Err(LoxError::runtime(name.location(), &format!("Can't assign to constant '{}'.", name.lexeme)))
+		}
+
+		environment.values[slot] = Some(ValueCell::new(value));
+		Ok(())
 	}
 
-	pub fn ancestor(&mut self, distance: usize) -> Self {
+	pub fn ancestor(&self, distance: usize) -> Self {
 		let mut environment = self.clone();
 
 		for _ in 0..distance {
@@ -70,8 +148,24 @@ impl EnvCell {
 		environment
 	}
 
+	/// The number of environments in the chain, this one included
+	pub fn depth(&self) -> usize {
+		let mut depth = 1;
+		let mut environment = self.0.borrow().enclosing.clone();
+
+		while let Some(e) = environment {
+			depth += 1;
+			environment = e.0.borrow().enclosing.clone();
+		}
+
+		depth
+	}
+
 	pub fn assign_at(&mut self, distance: usize, name: &Token, value: Value) {
-		self.ancestor(distance).0.borrow_mut().values.insert(name.lexeme.clone(), ValueCell::new(value));
+		let environment = self.ancestor(distance);
+		let mut environment = environment.0.borrow_mut();
+		let slot = environment.slot(name.lexeme);
+		environment.values[slot] = Some(ValueCell::new(value));
 	}
 }
 
@@ -80,20 +174,74 @@ impl Environment {
 	/// Takes a given environment, mutates it changing it into its own child
 	pub fn with_enclosing(enclosing: EnvCell) -> Self {
 		Self {
-			values: HashMap::new(),
+			slots: HashMap::new(),
+			values: Vec::new(),
+			constants: HashSet::new(),
 			enclosing: Some(enclosing)
 		}
 	}
 
+	/// Forget every name and the enclosing environment, keeping the memory to reuse
+	pub fn clear(&mut self) {
+		self.slots.clear();
+		self.values.clear();
+		self.constants.clear();
+		self.enclosing = None;
+	}
+
+	/// Make room for this many more names without reallocating
+	pub fn reserve(&mut self, additional: usize) {
+		self.slots.reserve(additional);
+		self.values.reserve(additional);
+	}
+
+	/// The slot of a name, reserving an empty one if the name is new
+	pub fn slot(&mut self, name: Symbol) -> usize {
+		if let Some(&slot) = self.slots.get(&name) {
+			return slot
+		}
+
+		self.values.push(None);
+		self.slots.insert(name, self.values.len() - 1);
+		self.values.len() - 1
+	}
+
+	/// The value of a name defined in this environment
+	pub fn lookup(&self, name: Symbol) -> Option<&ValueCell> {
+		self.slots.get(&name).and_then(|&slot| self.values[slot].as_ref())
+	}
+
+	/// The names defined in this environment, with their values
+	pub fn entries(&self) -> impl Iterator<Item = (Symbol, &ValueCell)> {
+		self.slots.iter().filter_map(|(&name, &slot)| Some((name, self.values[slot].as_ref()?)))
+	}
+
+	/// Fails if a name is a constant of this environment, which can't be declared again
+	pub fn check_redeclaration(&self, name: &Token) -> LoxResult<()> {
+		if self.constants.contains(&name.lexeme) {
+			return Err(LoxError::runtime(name.location(), &format!("Can't redeclare constant '{}'.", name.lexeme)))
+		}
+
+		Ok(())
+	}
+
 	/// Defines/overwrites values for a new entry
-	pub fn define(&mut self, name: String, value: Value) {
-		self.values.insert(name, ValueCell::new(value));
+	pub fn define(&mut self, name: Symbol, value: Value) {
+		let slot = self.slot(name);
+		self.values[slot] = Some(ValueCell::new(value));
+	}
+
+	/// Defines/overwrites values for a new entry that can't be reassigned
+	pub fn define_constant(&mut self, name: Symbol, value: Value) {
+		let slot = self.slot(name);
+		self.values[slot] = Some(ValueCell::new(value));
+		self.constants.insert(name);
 	}
 
 	/// Gets the value for a given entry
-	pub fn get(&self, name: Token) -> EnvironmentResult<ValueCell> {
+	pub fn get(&self, name: Token) -> LoxResult<ValueCell> {
 		// Check current scope
-		if let Some(v) = self.values.get(&name.lexeme) {
+		if let Some(v) = self.lookup(name.lexeme) {
 			return Ok(v.clone())
 		}
 		
@@ -102,13 +250,18 @@ impl Environment {
 			return s.borrow().get(name)
 		}
 		
-		let l = name.lexeme.clone();
-		Err(EnvironmentError::new(name, &format!("Undefined variable '{}'.", l)))
+		let l = name.lexeme;
+		Err(LoxError::runtime(name.location(), &format!("Undefined variable '{}'.", l)))
 	}
 
 	/// Overwrites value for a given entry, repeatedly going up the environment chain. Panics if entry is not found
-	pub fn assign(&mut self, name: Token, value: Value) -> EnvironmentResult<()> {
-		if let Some(v) = self.values.get_mut(&name.lexeme) {
+	pub fn assign(&mut self, name: Token, value: Value) -> LoxResult<()> {
+		if let Some(v) = self.slots.get(&name.lexeme).and_then(|&slot| self.values[slot].as_mut()) {
+			if self.constants.contains(&name.lexeme) {
+				let l = name.lexeme;
+				return Err(LoxError::runtime(name.location(), &format!("Can't assign to constant '{}'.", l)))
+			}
+
 			*v = ValueCell::new(value);
 			return Ok(())
 		}
@@ -117,7 +270,7 @@ impl Environment {
 			return s.borrow_mut().assign(name, value)
 		}
 
-		let l = name.lexeme.clone();
-		Err(EnvironmentError::new(name, &format!("Undefined variable '{}'.", l)))
+		let l = name.lexeme;
+		Err(LoxError::runtime(name.location(), &format!("Undefined variable '{}'.", l)))
 	}
 }
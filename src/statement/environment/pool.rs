@@ -0,0 +1,38 @@
+use std::rc::Rc;
+
+use super::EnvCell;
+
+/// The most environments kept for reuse, enough for the blocks and calls of a deep recursion to
+/// find one waiting on the way back down
+const MAX_POOLED: usize = 256;
+
+/// Environments of finished blocks and calls, emptied and kept to be handed out again. Blocks and
+/// calls come and go constantly, and reusing an environment saves allocating it and its maps
+#[derive(Default)]
+pub struct EnvironmentPool {
+	free: Vec<EnvCell>
+}
+
+impl EnvironmentPool {
+	/// An empty environment inside `enclosing`, reused if one is free
+	pub fn take(&mut self, enclosing: &EnvCell) -> EnvCell {
+		match self.free.pop() {
+			Some(environment) => {
+				environment.0.borrow_mut().enclosing = Some(enclosing.clone());
+				environment
+			},
+			None => EnvCell::with_enclosing(enclosing)
+		}
+	}
+
+	/// Take back the environment of a block or call that has finished. One still referred to, by a
+	/// function that captured it or the garbage collector tracking it, is left alone
+	pub fn give(&mut self, environment: EnvCell) {
+		if self.free.len() >= MAX_POOLED || Rc::strong_count(&environment.0) > 1 || Rc::weak_count(&environment.0) > 0 {
+			return
+		}
+
+		environment.0.borrow_mut().clear();
+		self.free.push(environment);
+	}
+}
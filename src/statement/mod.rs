@@ -1,166 +1,305 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, rc::Rc, sync::Arc};
 
 use environment::EnvCell;
-use error::{StatementError, StatementResult};
 
-use crate::{interpreter::{error::{ValueError, ValueResult}, values::{LoxClass, LoxFunction, Value}, Interpreter}, parser::{ expr::{Expr, ExprLiteral}, Parser}, scanner::token::{Token, TokenType}};
+use crate::{error::{LoxError, LoxResult}, interpreter::{error::{ValueError, ValueResult}, flow::{ControlFlow, ExecResult, TailCall, Unwind}, tier::Tier, trace::Trace, values::{Arguments, LoxClass, LoxFunction, Value}, Interpreter}, parser::{expr::{Expr, ExprBlock, ExprIf, NodeId}, Parser}, scanner::token::{Literal, Token, TokenType}, utils::{is_alpha, is_alphanumeric}, visitor::StmtVisitor};
 
-pub mod error;
 pub mod environment;
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum Statement {
 	Print(PrintStatement),
 	Expression(ExprStatement),
-	Function(FunctionDecl),
+	Function(Arc<FunctionDecl>),
 	Class(ClassDecl),
 	Return(ReturnStatement),
 	If(IfStatement),
 	While(WhileStatement),
+	For(ForStatement),
+	ForIn(ForInStatement),
+	Switch(SwitchStatement),
 	Break(),
 	Continue(),
 	Var(VarDeclaration),
-	Block(BlockStatement)
+	MultiVar(Vec<VarDeclaration>),
+	Block(BlockStatement),
+	Import(ImportStatement),
+	Export(ExportStatement)
 }
 
 impl Statement {
-	fn new_var_statement(name: Token, initializer: Option<Expr>) -> Self{
-		return Statement::Var(VarDeclaration{name, initializer})
+	fn new_var_statement(name: Token, initializer: Option<Expr>, is_const: bool) -> Self{
+		return Statement::Var(VarDeclaration{name, initializer, is_const})
+	}
+
+	/// The line the statement starts on, if it keeps a token to tell
+	pub fn line(&self) -> Option<usize> {
+		match self {
+			Statement::Print(p) => Some(p.1.line),
+			Statement::Expression(e) => e.0.line(),
+			Statement::Function(f) => Some(f.name.line),
+			Statement::Class(c) => Some(c.name.line),
+			Statement::Return(r) => Some(r.keyword.line),
+			Statement::If(i) => Some(i.keyword.line),
+			Statement::While(w) => Some(w.keyword.line),
+			Statement::For(f) => Some(f.keyword.line),
+			Statement::ForIn(f) => Some(f.keyword.line),
+			Statement::Switch(s) => Some(s.keyword.line),
+			Statement::Break() | Statement::Continue() => None,
+			Statement::Var(v) => Some(v.name.line),
+			Statement::MultiVar(v) => v.first().map(|d| d.name.line),
+			Statement::Block(b) => Some(b.brace.line),
+			Statement::Import(i) => Some(i.keyword.line),
+			Statement::Export(e) => Some(e.keyword.line),
+		}
 	}
 }
 
-#[derive(Clone)]
-pub struct PrintStatement(pub Expr);
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PrintStatement(pub Expr, pub Token);
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ExprStatement(pub Expr);
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct FunctionDecl{pub name: Token, pub params: Vec<Token>, pub body: Vec<Statement> }
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ReturnStatement{ pub keyword: Token, pub value: Option<Expr> }
-#[derive(Clone)]
-pub struct IfStatement{ pub condition: Expr, pub then_branch: Box<Statement>, pub else_branch: Option<Box<Statement>> }
-#[derive(Clone)]
-pub struct WhileStatement{ pub condition: Expr, pub body: Box<Statement>}
-#[derive(Clone)]
-pub struct BlockStatement{ pub statements: Vec<Statement>}
-#[derive(Clone)]
-pub struct ClassDecl{ pub name: Token, pub methods: Vec<FunctionDecl>}
-#[derive(Clone)]
-pub struct VarDeclaration{ pub name: Token, pub initializer: Option<Expr> }
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct IfStatement{ pub keyword: Token, pub condition: Expr, pub then_branch: Box<Statement>, pub else_branch: Option<Box<Statement>> }
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct WhileStatement{ pub keyword: Token, pub condition: Expr, pub body: Box<Statement>}
+/// A C-style `for` loop. Any of its three clauses may be left out, and a missing condition loops forever
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ForStatement{ pub keyword: Token, pub initializer: Option<Box<Statement>>, pub condition: Option<Expr>, pub increment: Option<Expr>, pub body: Box<Statement>}
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ForInStatement{ pub name: Token, pub keyword: Token, pub iterable: Expr, pub body: Box<Statement>}
+/// A block of statements. `brace` is the opening brace, or the keyword or colon that starts a block
+/// without braces, such as a switch case
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct BlockStatement{ pub id: NodeId, pub brace: Token, pub statements: Vec<Statement>}
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct SwitchStatement{ pub keyword: Token, pub subject: Expr, pub cases: Vec<SwitchCase>, pub default: Option<BlockStatement> }
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct SwitchCase{ pub values: Vec<Expr>, pub body: BlockStatement }
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ClassDecl{ pub name: Token, pub methods: Vec<Arc<FunctionDecl>>}
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ImportStatement{ pub keyword: Token, pub path: Token, pub name: Token }
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ExportStatement{ pub keyword: Token, pub declaration: Box<Statement> }
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct VarDeclaration{ pub name: Token, pub initializer: Option<Expr>, pub is_const: bool }
+
+/// Whether any of the statements binds a name in the scope it runs in
+pub fn declares(statements: &[Statement]) -> bool {
+	statements.iter().any(|s| matches!(s, Statement::Var(_) | Statement::MultiVar(_) | Statement::Function(_) | Statement::Class(_) | Statement::Import(_) | Statement::Export(_)))
+}
 
-impl Interpreter {
-	/// Interpret a list of statements sequentially. Quits the program upon error
-	pub fn interpret_statements(&mut self, statements: Vec<Statement>) {
-		for s in statements {
-			let v = self.interpret_statement(s);
+impl ExportStatement {
+	/// The names bound by the exported declaration
+	pub fn names(&self) -> Vec<Token> {
+		match &*self.declaration {
+			Statement::Var(v) => vec![v.name.clone()],
+			Statement::MultiVar(v) => v.iter().map(|d| d.name.clone()).collect(),
+			Statement::Function(f) => vec![f.name.clone()],
+			Statement::Class(c) => vec![c.name.clone()],
+			_ => vec![]
+		}
+	}
+}
 
-			match v {
-				Ok(_) => {continue;},
-				Err(e) => {e.error(); std::process::exit(70);}
-			}
+impl Interpreter {
+	/// Interpret a list of statements sequentially, stopping at the first error
+	pub fn interpret_statements(&mut self, statements: Vec<Statement>) -> ValueResult<()> {
+		for s in &statements {
+			self.interpret_statement(s).map_err(Unwind::into_error)?;
 		}
+
+		Ok(())
 	}
 }
 
 impl Interpreter {
 	/// Interpret a given Lox Statement
-	pub fn interpret_statement(&mut self, s: Statement) -> ValueResult<()> {
-		match s {
-			Statement::Expression(e) => {self.interpret_expr_statement(e)},
-			Statement::Print(p) => {self.interpret_print_statement(p)},
-			Statement::Var(v) => {self.interpret_var_statement(v)},
-			Statement::Block(b) => {self.interpret_block_statement(b)},
-			Statement::If(i) => {self.interpret_if_statement(i)},
-			Statement::While(w) => {self.interpret_while_statement(w)},
-			Statement::Break() => {self.interpret_break_statement()},
-			Statement::Continue() => {self.interpret_continue_statement()},
-			Statement::Function(f) => {self.interpret_function_statement(f)},
-			Statement::Class(c) => {self.interpret_class_decl(c)},
-			Statement::Return(r) => {self.interpret_return_statement(r)},
+	pub fn interpret_statement(&mut self, s: &Statement) -> ExecResult<()> {
+		if self.trace != Trace::Off {
+			self.trace_statement(s);
+		}
+
+		if let (Some(coverage), Some(file)) = (&mut self.coverage, &self.current_file) {
+			if let Some(line) = s.line() {
+				coverage.hit(file, line);
+			}
 		}
+
+		if self.debugger.is_some() {
+			self.debug_statement(s)?;
+		}
+
+		self.count_statement();
+		s.accept(self)
 	}
 
-	/// Interpret an expression statement
-	pub fn interpret_expr_statement(&mut self, s: ExprStatement) -> ValueResult<()> {
-		self.interpret_expr(s.0)?;
+	/// Interpret statements sequentially, bubbling up errors to the top
+	pub fn execute_statements(&mut self, statements: &[Statement]) -> ExecResult<()> {
+		self.collect_garbage_if_needed();
 
+		for s in statements {
+			self.interpret_statement(s)?;
+		}
+		
+		
 		Ok(())
+
 	}
 
-	/// Interpret a print statement
-	pub fn interpret_print_statement(&mut self, s: PrintStatement) -> ValueResult<()> {
-		let v = self.interpret_expr(s.0)?;
+	/// Run inside a new environment enclosed by the current one, returning the current one to go back to
+	pub fn begin_environment(&mut self) -> EnvCell {
+		let previous = self.environment.clone();
+		self.environment = self.environment_pool.take(&previous);
+		self.count_environment();
+		previous
+	}
+
+	/// Go back to the environment a block or call ran inside, recycling the one it ran in
+	pub fn end_environment(&mut self, previous: EnvCell) {
+		let finished = std::mem::replace(&mut self.environment, previous);
+		self.environment_pool.give(finished);
+	}
+
+	/// Run a for loop inside the environment already made for its initializer
+	fn run_for(&mut self, s: &ForStatement) -> ExecResult<()> {
+		if let Some(initializer) = &s.initializer {
+			self.interpret_statement(initializer)?;
+		}
+
+		loop {
+			if let Some(condition) = &s.condition {
+				if !self.interpret_expr(condition)?.is_truthy() {
+					break
+				}
+			}
 
-		println!("{}", v.value());
+			match self.interpret_statement(&s.body) {
+				Err(Unwind::Flow(ControlFlow::Break)) => break,
+				Err(Unwind::Flow(ControlFlow::Continue)) | Ok(_) => {},
+				Err(e) => return Err(e)
+			}
+
+			if let Some(increment) = &s.increment {
+				self.interpret_expr(increment)?;
+			}
+		}
 
 		Ok(())
 	}
+}
 
-	/// Interpret a var statement
-	pub fn interpret_var_statement(&mut self, s: VarDeclaration) -> ValueResult<()> {
-		let mut value = Value::Nil;
+impl StmtVisitor for Interpreter {
+	type Output = ExecResult<()>;
+
+	/// Interpret a print statement
+	fn visit_print_statement(&mut self, s: &PrintStatement) -> ExecResult<()> {
+		let line = Some(s.1.line);
+		let v = self.interpret_expr(&s.0)?;
 
-		if let Some(e) = s.initializer {
-			value = self.interpret_expr(e)?.value();
+		if self.trace != Trace::Off {
+			self.trace_value(line, &v);
 		}
 
-		self.environment.define(s.name.lexeme, value);
+		println!("{}", v);
 
 		Ok(())
 	}
 
-	/// Interpret a block statement
-	pub fn interpret_block_statement(&mut self, s: BlockStatement) -> ValueResult<()> {
-		let previous = self.environment.clone();
-		self.environment = EnvCell::with_enclosing(&self.environment);
+	/// Interpret an expression statement
+	fn visit_expression_statement(&mut self, s: &ExprStatement) -> ExecResult<()> {
+		let line = s.0.line();
+		let v = self.interpret_expr(&s.0)?;
 
-		let statements = s.statements;
+		if self.trace != Trace::Off {
+			self.trace_value(line, &v);
+		}
 
-		for s in statements {
-			self.interpret_statement(s)?;
+		Ok(())
+	}
+
+	/// Interpret a function statement
+	fn visit_function_statement(&mut self, s: &Arc<FunctionDecl>) -> ExecResult<()> {
+		let function_name = s.name.lexeme;
+		let mut function = LoxFunction::new(s.clone(), self.environment.clone(), false);
+
+		// Only functions declared at the top level of the program were compiled, not those of modules
+		#[cfg(feature = "jit")]
+		if std::rc::Rc::ptr_eq(&self.environment.0, &self.globals.0) {
+			function.compiled = self.jit.as_ref().and_then(|jit| jit.get(s));
 		}
 
-		self.environment = previous;
+		// The compiled function finds the names the function doesn't declare among the globals, so only
+		// a function declared among them can move to the VM
+		if self.tier_up && std::rc::Rc::ptr_eq(&self.environment.0, &self.globals.0) {
+			function.tier = Some(Rc::new(Tier::default()));
+		}
+
+		self.environment.check_redeclaration(&s.name)?;
+		self.environment.define(function_name, Value::Function(Rc::new(function)));
+		self.count_value();
+
+
 		Ok(())
 	}
 
-	pub fn interpret_class_decl(&mut self, s: ClassDecl) -> ValueResult<()> {
-		self.environment.define(s.name.lexeme.clone(), Value::Nil);
+	fn visit_class_statement(&mut self, s: &ClassDecl) -> ExecResult<()> {
+		self.environment.check_redeclaration(&s.name)?;
+		self.environment.define(s.name.lexeme, Value::Nil);
 
 		let mut methods = HashMap::new();
 
-		for method in s.methods {
-			let name = method.name.lexeme.clone();
-			let function = LoxFunction::new(method, self.environment.clone(), name == "init");
+		for method in &s.methods {
+			let name = method.name.lexeme;
+			let function = LoxFunction::new(method.clone(), self.environment.clone(), name == "init");
 			methods.insert(name, function);
 		}
 
-		let class = Value::Class(LoxClass::new(s.name.lexeme.clone(), methods));
+		let class = Value::Class(Rc::new(LoxClass::new(s.name.lexeme.to_string(), methods)));
+		self.count_value();
 		self.environment.assign(s.name.clone(), class)?;
 
 		Ok(())
 	}
 
+	/// Interpret a return statement
+	fn visit_return_statement(&mut self, s: &ReturnStatement) -> ExecResult<()> {
+		let mut value = Value::Nil;
+		let _ = &s.keyword; // Just so we read the field, and prevent compiler warning
 
-	/// Interpret statements sequentially, bubbling up errors to the top
-	pub fn execute_statements(&mut self, statements: Vec<Statement>) -> ValueResult<()> {
+		// Tail calls are left to the function returning, unless every expression evaluated is traced
+		if let Some(call) = s.value.as_ref().and_then(Expr::tail_call) {
+			if self.tail_calls.contains(&call.id) && self.trace != Trace::Expressions {
+				let callee = self.interpret_expr(&call.callee)?;
+				let mut arguments = Arguments::new();
 
-		for s in statements {
-			self.interpret_statement(s)?;
+				for argument in &call.arguments {
+					arguments.push(self.interpret_expr(argument)?);
+				}
+
+				return Err(ControlFlow::TailCall(Box::new(TailCall { callee, arguments, paren: call.paren.clone() })).into())
+			}
 		}
-		
-		
-		Ok(())
 
+		if let Some(v) = &s.value {
+			value = self.interpret_expr(v)?;
+		}
+
+		Err(ControlFlow::Return(value).into())
 	}
 
 	/// Interpret if statement
-	pub fn interpret_if_statement(&mut self, s: IfStatement) -> ValueResult<()> {
-		if self.interpret_expr(s.condition)?.value().is_truthy() {
-			self.interpret_statement(*s.then_branch)?
+	fn visit_if_statement(&mut self, s: &IfStatement) -> ExecResult<()> {
+		if self.interpret_expr(&s.condition)?.is_truthy() {
+			self.interpret_statement(&s.then_branch)?
 		} else {
-			if let Some(statement) = s.else_branch {
-				self.interpret_statement(*statement)?
+			if let Some(statement) = &s.else_branch {
+				self.interpret_statement(statement)?
 			}
 		}
 
@@ -168,14 +307,14 @@ impl Interpreter {
 	}
 
 	/// Interpret a while statement
-	pub fn interpret_while_statement(&mut self, s: WhileStatement) -> ValueResult<()> {
-		while self.interpret_expr(s.condition.clone())?.value().is_truthy() {
-			let v = self.interpret_statement(*s.body.clone());
+	fn visit_while_statement(&mut self, s: &WhileStatement) -> ExecResult<()> {
+		while self.interpret_expr(&s.condition)?.is_truthy() {
+			let v = self.interpret_statement(&s.body);
 
 
 			match v {
-				Err(ValueError::Break) => break,
-				Err(ValueError::Continue) => continue,
+				Err(Unwind::Flow(ControlFlow::Break)) => break,
+				Err(Unwind::Flow(ControlFlow::Continue)) => continue,
 				k => k?
 			}
 
@@ -184,42 +323,133 @@ impl Interpreter {
 		Ok(())
 	}
 
+	/// Interpret a for statement. The initializer's variables live in an environment around the whole
+	/// loop, and the increment runs after every pass through the body, `continue` included
+	fn visit_for_statement(&mut self, s: &ForStatement) -> ExecResult<()> {
+		let previous = self.begin_environment();
+		let result = self.run_for(s);
+		self.end_environment(previous);
+
+		result
+	}
+
+	/// Interpret a for-in statement, binding each element of the iterable to a fresh loop variable
+	fn visit_for_in_statement(&mut self, s: &ForInStatement) -> ExecResult<()> {
+		let iterable = self.interpret_expr(&s.iterable)?;
+
+		let iterator = match iterable.iterate() {
+			Some(iterator) => iterator,
+			None => return Err(ValueError::new(s.keyword.clone(), "Can only iterate over strings, arrays and maps.").into())
+		};
+
+		for element in iterator {
+			let previous = self.begin_environment();
+			self.environment.define(s.name.lexeme, element);
+
+			let v = self.interpret_statement(&s.body);
+			self.end_environment(previous);
+
+			match v {
+				Err(Unwind::Flow(ControlFlow::Break)) => break,
+				Err(Unwind::Flow(ControlFlow::Continue)) => continue,
+				Err(e) => return Err(e),
+				Ok(_) => {}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Interpret a switch statement, running the body of the first case with a value equal to the subject.
+	/// Cases do not fall through, the default body runs only when no case matches
+	fn visit_switch_statement(&mut self, s: &SwitchStatement) -> ExecResult<()> {
+		let subject = self.interpret_expr(&s.subject)?;
+
+		for case in &s.cases {
+			for value in &case.values {
+				if self.interpret_expr(value)? == subject {
+					return self.visit_block_statement(&case.body)
+				}
+			}
+		}
+
+		if let Some(default) = &s.default {
+			self.visit_block_statement(default)?
+		}
+
+		Ok(())
+	}
+
 	/// Interpret a break statement
-	pub fn interpret_break_statement(&mut self) -> ValueResult<()> {
-		Err(ValueError::Break)
+	fn visit_break_statement(&mut self) -> ExecResult<()> {
+		Err(ControlFlow::Break.into())
 	}
 
 	/// Interpret a continue statement
-	pub fn interpret_continue_statement(&mut self) -> ValueResult<()> {
-		Err(ValueError::Continue)
+	fn visit_continue_statement(&mut self) -> ExecResult<()> {
+		Err(ControlFlow::Continue.into())
 	}
 
-	/// Interpret a function statement
-	pub fn interpret_function_statement(&mut self, s: FunctionDecl) -> ValueResult<()> {
-		let function_name = s.name.lexeme.clone();
-		let function = LoxFunction::new(s.clone(), self.environment.clone(), false);
-		self.environment.define(function_name.clone(), Value::Function(function.clone()));
+	/// Interpret a var statement
+	fn visit_var_statement(&mut self, s: &VarDeclaration) -> ExecResult<()> {
+		let mut value = Value::Nil;
 
+		if let Some(e) = &s.initializer {
+			value = self.interpret_expr(e)?;
+		}
+
+		if self.trace != Trace::Off {
+			self.trace_value(Some(s.name.line), &value);
+		}
+
+		self.environment.check_redeclaration(&s.name)?;
+
+		if s.is_const {
+			self.environment.define_constant(s.name.lexeme, value);
+		} else {
+			self.environment.define(s.name.lexeme, value);
+		}
 
 		Ok(())
 	}
 
-	/// Interpret a return statement
-	pub fn interpret_return_statement(&mut self, s: ReturnStatement) -> ValueResult<()> {
-		let mut value = Value::Nil;
-		let _ = s.keyword; // Just so we read the field, and prevent compiler warning
+	/// Interpret a var statement with several declarators, defining each in order
+	fn visit_multi_var_statement(&mut self, declarations: &[VarDeclaration]) -> ExecResult<()> {
+		for declaration in declarations {
+			self.visit_var_statement(declaration)?;
+		}
 
-		if let Some(v) = s.value {
-			value = self.interpret_expr(v)?.value();
+		Ok(())
+	}
+
+	/// Interpret a block statement
+	fn visit_block_statement(&mut self, s: &BlockStatement) -> ExecResult<()> {
+		// A block declaring nothing has nothing to keep in an environment of its own
+		if self.flat_blocks.contains(&s.id) {
+			return self.execute_statements(&s.statements)
 		}
 
-		Err(ValueError::Return(value))
+		let previous = self.begin_environment();
+
+		// Restore the enclosing environment even when a break, continue or return leaves the block early
+		let result = self.execute_statements(&s.statements);
+
+		self.end_environment(previous);
+		result
 	}
-}
 
-impl From<Expr> for PrintStatement {
-	fn from(value: Expr) -> Self {
-		PrintStatement(value)
+	/// Interpret an import statement, binding the module's namespace in the current environment
+	fn visit_import_statement(&mut self, s: &ImportStatement) -> ExecResult<()> {
+		let module = self.import_module(&s.path)?;
+		self.environment.check_redeclaration(&s.name)?;
+		self.environment.define(s.name.lexeme, Value::Module(Rc::new(module)));
+
+		Ok(())
+	}
+
+	/// Interpret an export statement, which runs the declaration it exports
+	fn visit_export_statement(&mut self, s: &ExportStatement) -> ExecResult<()> {
+		self.interpret_statement(&s.declaration)
 	}
 }
 
@@ -229,54 +459,78 @@ impl From<Expr> for ExprStatement {
 	}
 }
 
-
 impl Parser {
 	/// Parse a statement
-	pub fn parse_statement(&mut self) -> StatementResult<Vec<Statement>> {
+	pub fn parse_statement(&mut self) -> Result<Vec<Statement>, Vec<LoxError>> {
 		let mut statements = Vec::new();
 
 		while !self.is_at_end() {
-			match self.declaration() {
-				Ok(s) => statements.push(s),
-				Err(e) => { e.error(); std::process::exit(65)},
-			}
+			statements.extend(self.declaration_or_recover());
 		}
 
-		Ok(statements)
+		self.finish(Ok(statements))
+	}
+
+	/// Parse a declaration. On a syntax error, record it and skip to where the next statement likely
+	/// starts, so the rest of the program is still parsed and its errors found in the same run
+	fn declaration_or_recover(&mut self) -> Option<Statement> {
+		let loop_depth = self.loop_depth;
+
+		match self.declaration() {
+			Ok(s) => Some(s),
+			Err(e) => {
+				self.errors.push(e);
+				self.loop_depth = loop_depth;
+				self.synchronize();
+				None
+			}
+		}
 	}
 
 	/// Parse a declaration
-	fn declaration(&mut self) -> StatementResult<Statement>{
+	fn declaration(&mut self) -> LoxResult<Statement>{
 		
-		if self.match_next(vec![TokenType::CLASS]) {
+		if self.match_next(&[TokenType::CLASS]) {
 			return self.class_declaration()
 		}
 
-		if self.match_next(vec![TokenType::FUN]) {
+		if self.match_next(&[TokenType::FUN]) {
 			return self.function("function")
 		}
 
-		if self.match_next(vec![TokenType::VAR]) {
+		if self.match_next(&[TokenType::VAR]) {
 			return self.var_declaration()
 		}
 
+		if self.match_next(&[TokenType::CONST]) {
+			return self.const_declaration()
+		}
+
+		if self.match_next(&[TokenType::IMPORT]) {
+			return self.import_declaration()
+		}
+
+		if self.match_next(&[TokenType::EXPORT]) {
+			return self.export_declaration()
+		}
+
 		return self.statement()
 	}
 
-	fn class_declaration(&mut self) -> StatementResult<Statement> {
+	fn class_declaration(&mut self) -> LoxResult<Statement> {
 		let name = self.consume(TokenType::IDENTIFIER, "Expect class name.")?;
 
 		self.consume(TokenType::LEFT_BRACE, "Expect '{' before class body")?;
 
 		let mut methods = Vec::new();
 
-		while !self.check(TokenType::RIGHT_BRACE)  && !self.is_at_end() {
+		while !self.check(&TokenType::RIGHT_BRACE)  && !self.is_at_end() {
 			let s = self.function("method")?;
 
 			match s {
 				Statement::Function(s ) => {methods.push(s);},
 				_ => {
-					return Err(self.error(self.previous(), "Non-function statement found in class body").into())
+					return Err(self.error(self.previous().clone(), "Non-function statement found in class body"))
 				}
 			}
 		}
@@ -286,21 +540,22 @@ impl Parser {
 	}
 
 	/// Parse a function
-	fn function(&mut self, kind: &str) -> StatementResult<Statement>{
+	fn function(&mut self, kind: &str) -> LoxResult<Statement>{
 		let name = self.consume(TokenType::IDENTIFIER, &format!("Expect {} name.", kind))?;
 
 		self.consume(TokenType::LEFT_PAREN, &format!("Expect '(' after {} name.", kind))?;
 		let mut parameters = Vec::new();
 
-		if !self.check(TokenType::RIGHT_PAREN) {
+		if !self.check(&TokenType::RIGHT_PAREN) {
 			loop {
 				if parameters.len() >= 255 {
-					self.error(self.peek(), "Cant have more than 255 parameters");
+					self.record_error(self.peek().clone(), "Cant have more than 255 parameters");
 				}
 
 				parameters.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name")?);
 
-				if !self.match_next(vec![TokenType::COMMA]) {
+				// A trailing comma is allowed before the closing parenthesis
+				if !self.match_next(&[TokenType::COMMA]) || self.check(&TokenType::RIGHT_PAREN) {
 					break
 				}
 			}
@@ -314,60 +569,150 @@ impl Parser {
 
 		let body = match body {
 			Statement::Block(s) => s.statements,
-			_ => return Err(StatementError::new(self.previous(), &format!("Body not found inside after {}", kind)))
+			_ => return Err(LoxError::syntax(self.previous().clone(), &format!("Body not found inside after {}", kind)))
 		};
 
-		return Ok(Statement::Function(FunctionDecl {name, params: parameters, body}))
+		return Ok(Statement::Function(Arc::new(FunctionDecl {name, params: parameters, body})))
 
 	}
 
 	/// Parse a variable declaration
-	fn var_declaration(&mut self) -> StatementResult<Statement> {
+	fn var_declaration(&mut self) -> LoxResult<Statement> {
 		let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.")?;
-		
-		let mut initializer = None;
 
-		if self.match_next(vec![TokenType::EQUAL]) {
-			initializer = Some(self.expression()?);
-		}
+		self.var_initializer(name, false)
+	}
 
-		self.consume(TokenType::SEMICOLON, "Expect ';' after variable declaration.")?;
+	/// Parse an import, either `import "path.lox";` which binds the module under its file stem, or
+	/// `import name from "path.lox";`
+	fn import_declaration(&mut self) -> LoxResult<Statement> {
+		let keyword = self.previous().clone();
 
-		return Ok(Statement::new_var_statement(name, initializer))
+		let (name, path) = if self.match_next(&[TokenType::IDENTIFIER]) {
+			let name = self.previous().clone();
+			self.consume(TokenType::FROM, "Expect 'from' after import name.")?;
+			let path = self.consume(TokenType::STRING, "Expect module path string.")?;
+
+			(name, path)
+		} else {
+			let path = self.consume(TokenType::STRING, "Expect module path string.")?;
+
+			let stem = match &path.literal {
+				Literal::String(p) => std::path::Path::new(p.as_str()).file_stem().map(|s| s.to_string_lossy().to_string()),
+				_ => None
+			};
+
+			let stem = match stem {
+				Some(stem) if stem.chars().next().is_some_and(is_alpha) && stem.chars().all(is_alphanumeric) => stem,
+				_ => return Err(LoxError::syntax(path, "Can't derive a module name from this path, use 'import name from \"path\"'."))
+			};
+
+			let mut name = Token::new(TokenType::IDENTIFIER, &stem, Literal::Null, path.line);
+			name.column = path.column;
+			(name, path)
+		};
+
+		self.consume_semicolon("Expect ';' after import.")?;
+
+		Ok(Statement::Import(ImportStatement { keyword, path, name }))
 	}
 
+	/// Parse an exported declaration
+	fn export_declaration(&mut self) -> LoxResult<Statement> {
+		let keyword = self.previous().clone();
+
+		let declaration = if self.match_next(&[TokenType::VAR]) {
+			self.var_declaration()?
+		} else if self.match_next(&[TokenType::CONST]) {
+			self.const_declaration()?
+		} else if self.match_next(&[TokenType::FUN]) {
+			self.function("function")?
+		} else if self.match_next(&[TokenType::CLASS]) {
+			self.class_declaration()?
+		} else {
+			return Err(LoxError::syntax(self.peek().clone(), "Expect declaration after 'export'."))
+		};
+
+		Ok(Statement::Export(ExportStatement { keyword, declaration: Box::new(declaration) }))
+	}
+
+	/// Parse a constant declaration, which behaves like a variable declaration but requires an
+	/// initializer and can't be reassigned
+	fn const_declaration(&mut self) -> LoxResult<Statement> {
+		let name = self.consume(TokenType::IDENTIFIER, "Expect constant name.")?;
+
+		self.var_initializer(name, true)
+	}
+
+	/// Parse the rest of a variable declaration, after its name. Further declarators may follow
+	/// separated by commas, e.g. `var a = 1, b = 2, c;`
+	fn var_initializer(&mut self, name: Token, is_const: bool) -> LoxResult<Statement> {
+		let mut declarations = Vec::new();
+		let mut name = name;
+
+		loop {
+			let mut initializer = None;
+
+			if self.match_next(&[TokenType::EQUAL]) {
+				initializer = Some(self.expression()?);
+			} else if is_const {
+				return Err(LoxError::syntax(self.peek().clone(), "Expect '=' after constant name."))
+			}
+
+			declarations.push(VarDeclaration { name, initializer, is_const });
+
+			if !self.match_next(&[TokenType::COMMA]) {
+				break
+			}
+
+			name = self.consume(TokenType::IDENTIFIER, if is_const {"Expect constant name."} else {"Expect variable name."})?;
+		}
+
+		self.consume_semicolon("Expect ';' after variable declaration.")?;
+
+		if declarations.len() == 1 {
+			let VarDeclaration { name, initializer, is_const } = declarations.remove(0);
+			return Ok(Statement::new_var_statement(name, initializer, is_const))
+		}
+
+		return Ok(Statement::MultiVar(declarations))
+	}
 
 	/// Parse a statement
-	fn statement(&mut self) -> StatementResult<Statement> {
-		if self.match_next(vec![TokenType::PRINT]) {
+	fn statement(&mut self) -> LoxResult<Statement> {
+		if self.match_next(&[TokenType::PRINT]) {
 			return self.print_statement()
 		}
 
-		if self.match_next(vec![TokenType::RETURN]) {
+		if self.match_next(&[TokenType::RETURN]) {
 			return self.return_statement()
 		}
 
-		if self.match_next(vec![TokenType::IF]) {
+		if self.match_next(&[TokenType::IF]) {
 			return self.if_statement()
 		}
 
-		if self.match_next(vec![TokenType::WHILE]) {
+		if self.match_next(&[TokenType::WHILE]) {
 			return self.while_statement()
 		}
 
-		if self.match_next(vec![TokenType::FOR]) {
+		if self.match_next(&[TokenType::FOR]) {
 			return self.for_statement()
 		}
 
-		if self.match_next(vec![TokenType::BREAK]) {
+		if self.match_next(&[TokenType::SWITCH]) {
+			return self.switch_statement()
+		}
+
+		if self.match_next(&[TokenType::BREAK]) {
 			return self.break_statement()
 		}
 
-		if self.match_next(vec![TokenType::CONTINUE]) {
+		if self.match_next(&[TokenType::CONTINUE]) {
 			return self.continue_statement()
 		}
 
-		if self.match_next(vec![TokenType::LEFT_BRACE]) {
+		if self.match_next(&[TokenType::LEFT_BRACE]) {
 			return self.block_statement()
 		}
 
@@ -375,55 +720,51 @@ impl Parser {
 	}
 
 	/// Parse a print statement
-	fn print_statement(&mut self) -> StatementResult<Statement> {
+	fn print_statement(&mut self) -> LoxResult<Statement> {
+		let keyword = self.previous().clone();
 		let value = self.expression()?;
-
-		match &value {
-			Expr::Literal(ExprLiteral::Null) => {return Err(StatementError::new(self.previous(), "Expect expression after PRINT"))},
-			_ => {}
-		}
-
-
-		self.consume(TokenType::SEMICOLON, "Expect ';' after value.")?;
-		Ok(Statement::Print(value.into()))
+		self.consume_semicolon("Expect ';' after value.")?;
+		Ok(Statement::Print(PrintStatement(value, keyword)))
 	}
 
 	/// Parse a return statement
-	fn return_statement(&mut self) -> StatementResult<Statement> {
-		let keyword = self.previous();
+	fn return_statement(&mut self) -> LoxResult<Statement> {
+		let keyword = self.previous().clone();
 		let mut value = None;
 
-		if !self.check(TokenType::SEMICOLON) {
+		if !self.check(&TokenType::SEMICOLON) && !self.at_implicit_semicolon() {
 			value = Some(self.expression()?);
 		}
 
-		self.consume(TokenType::SEMICOLON, "Expect ';' after a return value.")?;
+		self.consume_semicolon("Expect ';' after a return value.")?;
 
 		return Ok(Statement::Return(ReturnStatement { keyword, value }));
 	}
 
 	/// Parse a block statement
-	fn block_statement(&mut self) -> StatementResult<Statement> {
+	fn block_statement(&mut self) -> LoxResult<Statement> {
+		let brace = self.previous().clone();
 		let mut statements = Vec::new();
 
-		while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
-			statements.push(self.declaration()?);
+		while !self.check(&TokenType::RIGHT_BRACE) && !self.is_at_end() {
+			statements.extend(self.declaration_or_recover());
 		}
 
 		self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.")?;
 
-		Ok(Statement::Block(BlockStatement{statements}))
+		Ok(Statement::Block(BlockStatement{id: NodeId::next(), brace, statements}))
 	}
 
 	/// Parse an expression statement
-	fn expression_statement(&mut self) -> StatementResult<Statement> {
+	fn expression_statement(&mut self) -> LoxResult<Statement> {
 		let value = self.expression()?;
-		self.consume(TokenType::SEMICOLON, "Expect ';' after value.")?;
+		self.consume_semicolon("Expect ';' after value.")?;
 		Ok(Statement::Expression(value.into()))
 	}
 
 	/// Parse an if statement
-	fn if_statement(&mut self) -> StatementResult<Statement> {
+	fn if_statement(&mut self) -> LoxResult<Statement> {
+		let keyword = self.previous().clone();
 		self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.")?;
 
 		let condition = self.expression()?;
@@ -433,21 +774,21 @@ impl Parser {
 		let then_branch = Box::new(self.statement()?);
 		let mut else_branch = None;
 
-		if self.match_next(vec![TokenType::ELSE]) {
+		if self.match_next(&[TokenType::ELSE]) {
 			else_branch = Some(Box::new(self.statement()?))
 		}
 
-		Ok(Statement::If(IfStatement {condition, then_branch, else_branch}))
+		Ok(Statement::If(IfStatement {keyword, condition, then_branch, else_branch}))
 	}
 
 	/// Parse a while statement
-	fn while_statement(&mut self) -> StatementResult<Statement> {
+	fn while_statement(&mut self) -> LoxResult<Statement> {
+		let keyword = self.previous().clone();
 		self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.")?;
 
 		let condition = self.expression()?;
 		self.consume(TokenType::RIGHT_PAREN, "Expect ')' after 'while' condition.")?;
 
-
 		// Pre parse
 		self.loop_depth += 1;
 
@@ -462,32 +803,38 @@ impl Parser {
 		// Post-parse
 		self.loop_depth -= 1;
 
-		Ok(Statement::While(WhileStatement {condition, body}))
+		Ok(Statement::While(WhileStatement {keyword, condition, body}))
 	}
 
 	/// Parse a for statement
-	fn for_statement(&mut self) -> StatementResult<Statement> {
+	fn for_statement(&mut self) -> LoxResult<Statement> {
+		let keyword = self.previous().clone();
 		self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.")?;
 
-		let initializer = if self.match_next(vec![TokenType::SEMICOLON]) {
+		let initializer = if self.match_next(&[TokenType::SEMICOLON]) {
 			None
-		} else if self.match_next(vec![TokenType::VAR]) {
-			Some(self.var_declaration()?)
+		} else if self.match_next(&[TokenType::VAR]) {
+			let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.")?;
+
+			if self.match_next(&[TokenType::IN]) {
+				return self.for_in_statement(name)
+			}
+
+			Some(self.var_initializer(name, false)?)
 		} else {
 			Some(self.expression_statement()?)
 		};
 
 		let mut condition = None;
 
-		if !self.check(TokenType::SEMICOLON) {
+		if !self.check(&TokenType::SEMICOLON) {
 			condition = Some(self.expression()?);
 		}
 		self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition")?;
 
-
 		let mut increment = None;
 
-		if !self.check(TokenType::RIGHT_PAREN) {
+		if !self.check(&TokenType::RIGHT_PAREN) {
 			increment = Some(self.expression()?)
 		}
 
@@ -496,56 +843,175 @@ impl Parser {
 		// Pre-parse
 		self.loop_depth += 1;
 
-		// Parsing the loop
+		let body = Box::new(self.statement()?);
+
+		// Post-parse
+		self.loop_depth -= 1;
+
+		Ok(Statement::For(ForStatement { keyword, initializer: initializer.map(Box::new), condition, increment, body }))
+	}
+
+	/// Parse the rest of a for-in statement, after `for (var name in`
+	fn for_in_statement(&mut self, name: Token) -> LoxResult<Statement> {
+		let keyword = self.previous().clone();
+		let iterable = self.expression()?;
+
+		self.consume(TokenType::RIGHT_PAREN, "Expect ')' after 'for' iterable.")?;
+
+		self.loop_depth += 1;
+		let body = Box::new(self.statement()?);
+		self.loop_depth -= 1;
+
+		Ok(Statement::ForIn(ForInStatement { name, keyword, iterable, body }))
+	}
+
+	/// Parse an if expression, after the `if` keyword. A branch is a block expression, or a single
+	/// statement which produces nil
+	pub fn if_expression(&mut self) -> LoxResult<Expr> {
+		let keyword = self.previous().clone();
 
-		let mut body = self.statement()?;
+		self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.")?;
+		let condition = Box::new(self.expression()?);
+		self.consume(TokenType::RIGHT_PAREN, "Expect ')' after 'if' condition")?;
+
+		let then_branch = Box::new(self.branch_expression()?);
+		let mut else_branch = None;
 
-		if let Some(increment) = increment {
-			body = Statement::Block(
-				BlockStatement {
-					statements: vec![body, Statement::Expression(ExprStatement(increment))]
-				}) 
+		if self.match_next(&[TokenType::ELSE]) {
+			if self.match_next(&[TokenType::IF]) {
+				else_branch = Some(Box::new(self.if_expression()?));
+			} else {
+				else_branch = Some(Box::new(self.branch_expression()?));
+			}
 		}
 
-		if condition.is_none() {
-			condition = Some(Expr::Literal(ExprLiteral::True))
+		Ok(Expr::If(ExprIf { keyword, condition, then_branch, else_branch }))
+	}
+
+	/// Parse the branch of an if expression
+	fn branch_expression(&mut self) -> LoxResult<Expr> {
+		if self.match_next(&[TokenType::LEFT_BRACE]) {
+			return self.block_expression()
 		}
 
-		body = Statement::While(WhileStatement { condition: condition.expect("Condition is 'None', this shouldn't happen"), body: Box::new(body) });
+		let statement = self.statement()?;
+		Ok(Expr::Block(ExprBlock { statements: vec![statement], value: None }))
+	}
+
+	/// Parse a block expression, after the opening brace. A trailing expression without a
+	/// semicolon becomes the value of the block
+	pub fn block_expression(&mut self) -> LoxResult<Expr> {
+		let mut statements = Vec::new();
+		let mut value = None;
 
-		if let Some(initializer) = initializer {
-			body = Statement::Block(
-				BlockStatement {
-					statements: vec![initializer, body]
-				}) 
+		while !self.check(&TokenType::RIGHT_BRACE) && !self.is_at_end() {
+			match self.peek().token_type {
+				TokenType::VAR | TokenType::CONST | TokenType::FUN | TokenType::CLASS
+				| TokenType::PRINT | TokenType::RETURN | TokenType::WHILE | TokenType::FOR
+				| TokenType::BREAK | TokenType::CONTINUE | TokenType::SWITCH => {
+					statements.push(self.declaration()?);
+					continue;
+				},
+				_ => {}
+			}
+
+			let expr = self.expression()?;
+
+			if self.check(&TokenType::RIGHT_BRACE) {
+				value = Some(Box::new(expr));
+			} else if self.match_next(&[TokenType::SEMICOLON]) || self.at_implicit_semicolon() {
+				statements.push(Statement::Expression(expr.into()));
+			} else if matches!(expr, Expr::Block(_) | Expr::If(_)) {
+				// Block-like expressions don't need a semicolon to be used as statements
+				statements.push(Statement::Expression(expr.into()));
+			} else {
+				return Err(self.error(self.peek().clone(), "Expect ';' after value."))
+			}
 		}
 
-		// End of parse
+		self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.")?;
+
+		Ok(Expr::Block(ExprBlock { statements, value }))
+	}
 
-		// Post-parse
-		self.loop_depth -= 1;
+	/// Parse a switch statement
+	fn switch_statement(&mut self) -> LoxResult<Statement> {
+		let keyword = self.previous().clone();
+
+		self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'switch'.")?;
+		let subject = self.expression()?;
+		self.consume(TokenType::RIGHT_PAREN, "Expect ')' after 'switch' subject.")?;
+
+		self.consume(TokenType::LEFT_BRACE, "Expect '{' before switch body.")?;
+
+		let mut cases = Vec::new();
+		let mut default = None;
+
+		while !self.check(&TokenType::RIGHT_BRACE) && !self.is_at_end() {
+			if self.match_next(&[TokenType::CASE]) {
+				let mut values = Vec::new();
+
+				loop {
+					values.push(self.expression()?);
+
+					if !self.match_next(&[TokenType::COMMA]) {
+						break
+					}
+				}
 
-		return Ok(body);
+				self.consume(TokenType::COLON, "Expect ':' after case value.")?;
+				let body = self.switch_case_body()?;
+
+				cases.push(SwitchCase { values, body });
+			} else if self.match_next(&[TokenType::DEFAULT]) {
+				let token = self.previous().clone();
+
+				if default.is_some() {
+					return Err(LoxError::syntax(token, "Only one 'default' case is allowed in a switch."))
+				}
+
+				self.consume(TokenType::COLON, "Expect ':' after 'default'.")?;
+				default = Some(self.switch_case_body()?);
+			} else {
+				return Err(LoxError::syntax(self.peek().clone(), "Expect 'case' or 'default' in switch body."))
+			}
+		}
+
+		self.consume(TokenType::RIGHT_BRACE, "Expect '}' after switch body.")?;
+
+		Ok(Statement::Switch(SwitchStatement { keyword, subject, cases, default }))
 	}
 
+	/// Parse the statements of a switch case, up to the next case, default, or end of the switch
+	fn switch_case_body(&mut self) -> LoxResult<BlockStatement> {
+		let brace = self.previous().clone();
+		let mut statements = Vec::new();
+
+		while !self.check(&TokenType::CASE) && !self.check(&TokenType::DEFAULT)
+			&& !self.check(&TokenType::RIGHT_BRACE) && !self.is_at_end() {
+			statements.push(self.declaration()?);
+		}
+
+		Ok(BlockStatement { id: NodeId::next(), brace, statements })
+	}
 
 	/// Parse a break statement
-	fn break_statement(&mut self) -> StatementResult<Statement> {
+	fn break_statement(&mut self) -> LoxResult<Statement> {
 		if self.loop_depth == 0 {
-			return Err(StatementError::new(self.previous(), "Must be inside a loop to use 'break'."))
+			return Err(LoxError::syntax(self.previous().clone(), "Must be inside a loop to use 'break'."))
 		}
 
-		self.consume(TokenType::SEMICOLON, "Expect ';' after 'break.")?;
+		self.consume_semicolon("Expect ';' after 'break.")?;
 		return Ok(Statement::Break())
 	}
 
 	/// Parse a continue statement
-	fn continue_statement(&mut self) -> StatementResult<Statement> {
+	fn continue_statement(&mut self) -> LoxResult<Statement> {
 		if self.loop_depth == 0 {
-			return Err(StatementError::new(self.previous(), "Must be inside a loop to use 'continue'."))
+			return Err(LoxError::syntax(self.previous().clone(), "Must be inside a loop to use 'continue'."))
 		}
 
-		self.consume(TokenType::SEMICOLON, "Expect ';' after 'continue.")?;
+		self.consume_semicolon("Expect ';' after 'continue.")?;
 		return Ok(Statement::Continue())
 	}
 
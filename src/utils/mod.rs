@@ -1,13 +1,13 @@
-use std::str::from_utf8;
-
-pub fn substring(string: &str, start: usize, end: usize) -> &str {
-	from_utf8(&string.as_bytes()[start..end]).expect("Unable to convert u8 slice to valid utf8")
-}
+pub mod number;
 
+/// Check if a character can start an identifier: an underscore, or any character with the Unicode
+/// XID_Start property, which covers the ASCII letters
 pub fn is_alpha(c: char) -> bool{
-	(c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+	c == '_' || unicode_ident::is_xid_start(c)
 }
 
+/// Check if a character can continue an identifier: any character with the Unicode XID_Continue
+/// property, which covers the ASCII letters and digits, the underscore and combining marks
 pub fn is_alphanumeric(c: char) -> bool {
-	c.is_digit(10) || is_alpha(c)
+	unicode_ident::is_xid_continue(c)
 }
\ No newline at end of file
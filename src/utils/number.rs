@@ -0,0 +1,26 @@
+/// Show a number the way Lox prints it: as its shortest form that reads back as the same number,
+/// with no fractional part on integers, and in exponent form when very large or small, e.g. `2`,
+/// `0.1` or `1e21`
+pub fn format(n: f64) -> String {
+	let mut s = format_literal(n);
+
+	if s.ends_with(".0") {
+		s.truncate(s.len() - 2);
+	}
+
+	s
+}
+
+/// Show a number the way `tokenize` and `parse` show a number literal, which always has a fractional
+/// part or an exponent, e.g. `2.0`
+pub fn format_literal(n: f64) -> String {
+	if n.is_nan() {
+		return "NaN".to_string()
+	}
+
+	if n.is_infinite() {
+		return if n > 0.0 { "inf" } else { "-inf" }.to_string()
+	}
+
+	ryu::Buffer::new().format_finite(n).to_string()
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use crate::{parser::expr::{Expr, ExprArray, ExprAssignment, ExprBinary, ExprBlock, ExprCall, ExprGet, ExprGrouping, ExprIf, ExprIndex, ExprIndexSet, ExprLiteral, ExprLogical, ExprSet, ExprThis, ExprUnary, ExprVariable}, statement::{BlockStatement, ClassDecl, ExportStatement, ExprStatement, ForInStatement, ForStatement, FunctionDecl, IfStatement, ImportStatement, PrintStatement, ReturnStatement, Statement, SwitchStatement, VarDeclaration, WhileStatement}};
+
+/// A pass over expressions, with a method for each kind. [`Expr::accept`] calls the one matching an
+/// expression, and each method decides whether and how to visit the expressions inside
+pub trait ExprVisitor {
+	type Output;
+
+	fn visit_literal_expr(&mut self, expr: &ExprLiteral) -> Self::Output;
+	fn visit_call_expr(&mut self, expr: &ExprCall) -> Self::Output;
+	fn visit_binary_expr(&mut self, expr: &ExprBinary) -> Self::Output;
+	fn visit_grouping_expr(&mut self, expr: &ExprGrouping) -> Self::Output;
+	fn visit_variable_expr(&mut self, expr: &ExprVariable) -> Self::Output;
+	fn visit_assignment_expr(&mut self, expr: &ExprAssignment) -> Self::Output;
+	fn visit_get_expr(&mut self, expr: &ExprGet) -> Self::Output;
+	fn visit_logical_expr(&mut self, expr: &ExprLogical) -> Self::Output;
+	fn visit_set_expr(&mut self, expr: &ExprSet) -> Self::Output;
+	fn visit_this_expr(&mut self, expr: &ExprThis) -> Self::Output;
+	fn visit_unary_expr(&mut self, expr: &ExprUnary) -> Self::Output;
+	fn visit_block_expr(&mut self, expr: &ExprBlock) -> Self::Output;
+	fn visit_if_expr(&mut self, expr: &ExprIf) -> Self::Output;
+	fn visit_array_expr(&mut self, expr: &ExprArray) -> Self::Output;
+	fn visit_index_expr(&mut self, expr: &ExprIndex) -> Self::Output;
+	fn visit_index_set_expr(&mut self, expr: &ExprIndexSet) -> Self::Output;
+}
+
+/// A pass over statements, with a method for each kind. [`Statement::accept`] calls the one matching
+/// a statement
+pub trait StmtVisitor {
+	type Output;
+
+	fn visit_print_statement(&mut self, statement: &PrintStatement) -> Self::Output;
+	fn visit_expression_statement(&mut self, statement: &ExprStatement) -> Self::Output;
+	fn visit_function_statement(&mut self, statement: &Arc<FunctionDecl>) -> Self::Output;
+	fn visit_class_statement(&mut self, statement: &ClassDecl) -> Self::Output;
+	fn visit_return_statement(&mut self, statement: &ReturnStatement) -> Self::Output;
+	fn visit_if_statement(&mut self, statement: &IfStatement) -> Self::Output;
+	fn visit_while_statement(&mut self, statement: &WhileStatement) -> Self::Output;
+	fn visit_for_statement(&mut self, statement: &ForStatement) -> Self::Output;
+	fn visit_for_in_statement(&mut self, statement: &ForInStatement) -> Self::Output;
+	fn visit_switch_statement(&mut self, statement: &SwitchStatement) -> Self::Output;
+	fn visit_break_statement(&mut self) -> Self::Output;
+	fn visit_continue_statement(&mut self) -> Self::Output;
+	fn visit_var_statement(&mut self, statement: &VarDeclaration) -> Self::Output;
+	fn visit_multi_var_statement(&mut self, statement: &[VarDeclaration]) -> Self::Output;
+	fn visit_block_statement(&mut self, statement: &BlockStatement) -> Self::Output;
+	fn visit_import_statement(&mut self, statement: &ImportStatement) -> Self::Output;
+	fn visit_export_statement(&mut self, statement: &ExportStatement) -> Self::Output;
+}
+
+impl Expr {
+	/// Visit the expression with the visitor method for its kind
+	pub fn accept<V: ExprVisitor + ?Sized>(&self, visitor: &mut V) -> V::Output {
+		match self {
+			Expr::Literal(e) => visitor.visit_literal_expr(e),
+			Expr::Call(e) => visitor.visit_call_expr(e),
+			Expr::Binary(e) => visitor.visit_binary_expr(e),
+			Expr::Grouping(e) => visitor.visit_grouping_expr(e),
+			Expr::Variable(e) => visitor.visit_variable_expr(e),
+			Expr::Assignment(e) => visitor.visit_assignment_expr(e),
+			Expr::Get(e) => visitor.visit_get_expr(e),
+			Expr::Logical(e) => visitor.visit_logical_expr(e),
+			Expr::Set(e) => visitor.visit_set_expr(e),
+			Expr::This(e) => visitor.visit_this_expr(e),
+			Expr::Unary(e) => visitor.visit_unary_expr(e),
+			Expr::Block(e) => visitor.visit_block_expr(e),
+			Expr::If(e) => visitor.visit_if_expr(e),
+			Expr::Array(e) => visitor.visit_array_expr(e),
+			Expr::Index(e) => visitor.visit_index_expr(e),
+			Expr::IndexSet(e) => visitor.visit_index_set_expr(e),
+		}
+	}
+}
+
+impl Statement {
+	/// Visit the statement with the visitor method for its kind
+	pub fn accept<V: StmtVisitor + ?Sized>(&self, visitor: &mut V) -> V::Output {
+		match self {
+			Statement::Print(s) => visitor.visit_print_statement(s),
+			Statement::Expression(s) => visitor.visit_expression_statement(s),
+			Statement::Function(s) => visitor.visit_function_statement(s),
+			Statement::Class(s) => visitor.visit_class_statement(s),
+			Statement::Return(s) => visitor.visit_return_statement(s),
+			Statement::If(s) => visitor.visit_if_statement(s),
+			Statement::While(s) => visitor.visit_while_statement(s),
+			Statement::For(s) => visitor.visit_for_statement(s),
+			Statement::ForIn(s) => visitor.visit_for_in_statement(s),
+			Statement::Switch(s) => visitor.visit_switch_statement(s),
+			Statement::Break() => visitor.visit_break_statement(),
+			Statement::Continue() => visitor.visit_continue_statement(),
+			Statement::Var(s) => visitor.visit_var_statement(s),
+			Statement::MultiVar(s) => visitor.visit_multi_var_statement(s),
+			Statement::Block(s) => visitor.visit_block_statement(s),
+			Statement::Import(s) => visitor.visit_import_statement(s),
+			Statement::Export(s) => visitor.visit_export_statement(s),
+		}
+	}
+}
@@ -0,0 +1,170 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{interpreter::values::Value, scanner::token::Symbol};
+
+/// The instructions of the bytecode VM. Operands follow the opcode byte, `u16` operands are big endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+	/// Push the constant at a `u16` index
+	Constant,
+	Nil,
+	True,
+	False,
+	Pop,
+	/// Push the local in a `u8` slot of the current frame
+	GetLocal,
+	/// Store the top of the stack in a `u8` slot, leaving it on the stack
+	SetLocal,
+	/// Push the global named by the string constant at a `u16` index
+	GetGlobal,
+	/// Pop a value into a new global named by a `u16` constant
+	DefineGlobal,
+	/// Like `DefineGlobal`, for a global that can't be reassigned
+	DefineConstGlobal,
+	/// Store the top of the stack in an existing global, leaving it on the stack
+	SetGlobal,
+	/// Replace an object with its property named by a `u16` constant
+	GetProperty,
+	Equal,
+	Greater,
+	GreaterEqual,
+	Less,
+	LessEqual,
+	Add,
+	Subtract,
+	Multiply,
+	Divide,
+	Not,
+	Negate,
+	Print,
+	/// Jump forward by a `u16` offset
+	Jump,
+	/// Jump forward by a `u16` offset if the top of the stack is falsy, leaving it on the stack
+	JumpIfFalse,
+	/// Jump backward by a `u16` offset
+	Loop,
+	/// Call the value below a `u8` count of arguments
+	Call,
+	/// Push a closure made from the function constant at a `u16` index. For each of the function's
+	/// upvalues, two bytes follow: 1 to capture a local slot of the current frame or 0 to share an
+	/// upvalue of the current closure, then the slot
+	Closure,
+	/// Return the top of the stack from the current call
+	Return,
+	/// Collect a `u16` count of values into an array
+	Array,
+	Index,
+	IndexSet,
+	/// Pop a `u8` count of values from beneath the top of the stack, ending a scope that has a value
+	PopBelow,
+	/// Push a copy of the top of the stack
+	Dup,
+	/// Push the variable captured in a `u8` upvalue slot of the current closure
+	GetUpvalue,
+	/// Store the top of the stack in a `u8` upvalue slot, leaving it on the stack
+	SetUpvalue,
+	/// Move the local on top of the stack into the upvalues capturing it, then pop it
+	CloseUpvalue,
+}
+
+impl OpCode {
+	const ALL: [OpCode; 38] = [
+		OpCode::Constant, OpCode::Nil, OpCode::True, OpCode::False, OpCode::Pop, OpCode::GetLocal,
+		OpCode::SetLocal, OpCode::GetGlobal, OpCode::DefineGlobal, OpCode::DefineConstGlobal, OpCode::SetGlobal,
+		OpCode::GetProperty, OpCode::Equal, OpCode::Greater, OpCode::GreaterEqual, OpCode::Less,
+		OpCode::LessEqual, OpCode::Add, OpCode::Subtract, OpCode::Multiply, OpCode::Divide, OpCode::Not,
+		OpCode::Negate, OpCode::Print, OpCode::Jump, OpCode::JumpIfFalse, OpCode::Loop, OpCode::Call,
+		OpCode::Closure, OpCode::Return, OpCode::Array, OpCode::Index, OpCode::IndexSet, OpCode::PopBelow,
+		OpCode::Dup, OpCode::GetUpvalue, OpCode::SetUpvalue, OpCode::CloseUpvalue,
+	];
+
+	/// The opcode encoded by a byte, if any
+	pub fn from_byte(byte: u8) -> Option<Self> {
+		Self::ALL.get(byte as usize).copied()
+	}
+}
+
+/// A constant referenced by the instructions of a chunk
+#[derive(Debug, Clone)]
+pub enum Constant {
+	Number(f64),
+	/// A string literal, or the name of a global or property
+	String(Symbol),
+	/// A function declared inside the chunk
+	Function(Rc<Function>),
+}
+
+/// A sequence of instructions with the constants they use
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+	pub code: Vec<u8>,
+	/// The source line of each byte in `code`, for runtime errors
+	pub lines: Vec<usize>,
+	pub constants: Vec<Constant>,
+}
+
+impl Chunk {
+	pub fn write(&mut self, byte: u8, line: usize) {
+		self.code.push(byte);
+		self.lines.push(line);
+	}
+
+	pub fn write_op(&mut self, op: OpCode, line: usize) {
+		self.write(op as u8, line);
+	}
+
+	pub fn write_u16(&mut self, value: u16, line: usize) {
+		self.write((value >> 8) as u8, line);
+		self.write(value as u8, line);
+	}
+
+	pub fn read_u16(&self, offset: usize) -> u16 {
+		(self.code[offset] as u16) << 8 | self.code[offset + 1] as u16
+	}
+
+	/// Add a constant, returning its index. Strings are shared by every use in the chunk
+	pub fn add_constant(&mut self, constant: Constant) -> usize {
+		if let Constant::String(s) = &constant {
+			let existing = self.constants.iter().position(|c| matches!(c, Constant::String(c) if c == s));
+
+			if let Some(index) = existing {
+				return index
+			}
+		}
+
+		self.constants.push(constant);
+		self.constants.len() - 1
+	}
+}
+
+/// A compiled function. The top level of a program is a function named `<script>` taking no arguments
+#[derive(Debug, Clone, Default)]
+pub struct Function {
+	pub name: String,
+	pub arity: usize,
+	/// How many variables of enclosing functions the function captures
+	pub upvalue_count: usize,
+	pub chunk: Chunk,
+}
+
+/// A variable captured by a closure. It stays on the VM stack while its scope is active and moves
+/// into the upvalue when the scope ends, so every closure sharing it sees the same variable
+pub enum Upvalue {
+	/// The absolute index of the captured local on the stack
+	Open(usize),
+	Closed(Value),
+}
+
+/// A function value created at runtime from a compiled function
+pub struct Closure {
+	pub function: Rc<Function>,
+	pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl PartialEq for Closure {
+	/// Closures are equal only to themselves
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self, other)
+	}
+}
@@ -0,0 +1,768 @@
+use std::rc::Rc;
+
+use crate::{error::{LoxError, LoxResult}, parser::expr::{Expr, ExprLiteral}, scanner::token::{Literal, Symbol, Token, TokenType}, statement::{BlockStatement, ForStatement, FunctionDecl, Statement, SwitchStatement, VarDeclaration}};
+
+use super::chunk::{Constant, Function, OpCode};
+
+/// A local variable, living in a stack slot of its function's frame
+struct Local {
+	name: Symbol,
+	depth: usize,
+	slot: usize,
+	/// Whether a nested function captures the local, so ending its scope must close it
+	captured: bool,
+}
+
+/// A variable captured by the function being compiled, as the `Closure` instruction encodes it
+#[derive(PartialEq)]
+struct UpvalueRef {
+	/// A local slot of the enclosing function, or an upvalue of the enclosing function
+	is_local: bool,
+	index: u8,
+}
+
+/// An enclosing loop, for `break` and `continue`
+struct Loop {
+	/// Where `continue` jumps back to
+	start: usize,
+	/// The stack height when the body starts, jumping out pops everything above it
+	height: usize,
+	/// The `break` jumps to patch once the end of the loop is known
+	breaks: Vec<usize>,
+}
+
+/// The function being compiled, one per level of nesting
+struct FunctionState {
+	function: Function,
+	locals: Vec<Local>,
+	upvalues: Vec<UpvalueRef>,
+	scope_depth: usize,
+	loops: Vec<Loop>,
+	/// The number of values on the stack of the frame at the current instruction, including the
+	/// callee in slot 0. Locals live at the height they were pushed at
+	height: usize,
+}
+
+impl FunctionState {
+	fn new(name: &str, arity: usize) -> Self {
+		Self {
+			function: Function { name: name.to_string(), arity, ..Default::default() },
+			locals: Vec::new(),
+			upvalues: Vec::new(),
+			scope_depth: 0,
+			loops: Vec::new(),
+			height: 1,
+		}
+	}
+}
+
+/// Compiles a resolved program to bytecode, clox style. Globals are looked up by name, locals are
+/// stack slots resolved at compile time, and locals of enclosing functions are captured as upvalues
+pub struct Compiler {
+	states: Vec<FunctionState>,
+	/// The line of the code being compiled, recorded with each instruction
+	line: usize,
+	/// Whether a call in tail position may be compiled. The VM grows its stack for each one, while the
+	/// tree-walker doesn't, so a function it hands over must keep them there
+	tail_calls: bool,
+}
+
+impl Compiler {
+	/// Compile a program into the function run as its top level
+	pub fn compile(statements: &[Statement]) -> LoxResult<Function> {
+		let mut compiler = Self { states: vec![FunctionState::new("<script>", 0)], line: 1, tail_calls: true };
+
+		for statement in statements {
+			compiler.statement(statement)?;
+		}
+
+		compiler.emit(OpCode::Nil);
+		compiler.emit(OpCode::Return);
+
+		let state = compiler.states.pop().expect("The script is always being compiled");
+		Ok(state.function)
+	}
+
+	/// Compile a function declared at the top level of a program on its own, for the tree-walker to
+	/// hand its calls over to the VM. Any name it doesn't declare is a global, so it has no upvalues.
+	/// A function making tail calls is rejected, as the VM would overflow where the tree-walker doesn't
+	pub fn compile_function(f: &FunctionDecl) -> LoxResult<Function> {
+		let mut compiler = Self { states: vec![FunctionState::new("<script>", 0)], line: f.name.line, tail_calls: false };
+		Ok(compiler.function_body(f)?.function)
+	}
+
+	fn state(&mut self) -> &mut FunctionState {
+		self.states.last_mut().expect("A function is always being compiled")
+	}
+
+	fn emit(&mut self, op: OpCode) {
+		let line = self.line;
+		let state = self.state();
+
+		state.function.chunk.write_op(op, line);
+		state.height = state.height.saturating_add_signed(stack_effect(op));
+	}
+
+	fn emit_byte(&mut self, byte: u8) {
+		let line = self.line;
+		self.state().function.chunk.write(byte, line);
+	}
+
+	fn emit_u16(&mut self, value: u16) {
+		let line = self.line;
+		self.state().function.chunk.write_u16(value, line);
+	}
+
+	fn constant(&mut self, token: &Token, constant: Constant) -> LoxResult<u16> {
+		let index = self.state().function.chunk.add_constant(constant);
+		u16::try_from(index).map_err(|_| LoxError::compile(token.clone(), "Too many constants in one chunk."))
+	}
+
+	fn emit_constant(&mut self, token: &Token, constant: Constant) -> LoxResult<()> {
+		let index = self.constant(token, constant)?;
+		self.emit(OpCode::Constant);
+		self.emit_u16(index);
+		Ok(())
+	}
+
+	/// Emit an instruction that takes a name, such as a global or property, as its operand
+	fn emit_named(&mut self, op: OpCode, name: &Token) -> LoxResult<()> {
+		let index = self.constant(name, Constant::String(name.lexeme))?;
+		self.emit(op);
+		self.emit_u16(index);
+		Ok(())
+	}
+
+	/// Emit a forward jump with a placeholder offset, returning where to patch it
+	fn emit_jump(&mut self, op: OpCode) -> usize {
+		self.emit(op);
+		self.emit_u16(u16::MAX);
+		self.state().function.chunk.code.len() - 2
+	}
+
+	/// Point a forward jump at the next instruction
+	fn patch_jump(&mut self, token: &Token, offset: usize) -> LoxResult<()> {
+		let chunk = &mut self.state().function.chunk;
+		let jump = chunk.code.len() - offset - 2;
+		let jump = u16::try_from(jump).map_err(|_| LoxError::compile(token.clone(), "Too much code to jump over."))?;
+
+		chunk.code[offset] = (jump >> 8) as u8;
+		chunk.code[offset + 1] = jump as u8;
+		Ok(())
+	}
+
+	fn emit_loop(&mut self, token: &Token, start: usize) -> LoxResult<()> {
+		self.emit(OpCode::Loop);
+
+		let offset = self.state().function.chunk.code.len() - start + 2;
+		let offset = u16::try_from(offset).map_err(|_| LoxError::compile(token.clone(), "Loop body too large."))?;
+		self.emit_u16(offset);
+		Ok(())
+	}
+
+	/// Emit pops until the stack is back to a height, without forgetting anything at compile time.
+	/// Used when jumping out of scopes
+	fn emit_pops_to(&mut self, height: usize) {
+		let current = self.state().height;
+
+		for slot in (height..current).rev() {
+			let captured = self.state().locals.iter().any(|l| l.slot == slot && l.captured);
+			self.emit(if captured { OpCode::CloseUpvalue } else { OpCode::Pop });
+		}
+
+		self.state().height = current;
+	}
+
+	fn begin_scope(&mut self) {
+		self.state().scope_depth += 1;
+	}
+
+	/// Close a scope, forgetting its locals. Returns them
+	fn end_scope(&mut self) -> Vec<Local> {
+		let state = self.state();
+		state.scope_depth -= 1;
+
+		let depth = state.scope_depth;
+		let count = state.locals.iter().rev().take_while(|l| l.depth > depth).count();
+		state.locals.split_off(state.locals.len() - count)
+	}
+
+	/// Close a scope at statement level, where its locals are the top of the stack
+	fn end_scope_popping(&mut self) {
+		for local in self.end_scope().into_iter().rev() {
+			self.emit(if local.captured { OpCode::CloseUpvalue } else { OpCode::Pop });
+		}
+	}
+
+	/// Make the value on top of the stack a local of the current scope
+	fn add_local(&mut self, name: &Token) -> LoxResult<()> {
+		let state = self.state();
+		let slot = state.height - 1;
+
+		if slot > u8::MAX as usize {
+			return Err(LoxError::compile(name.clone(), "Too many local variables in function."))
+		}
+
+		let depth = state.scope_depth;
+		state.locals.push(Local { name: name.lexeme, depth, slot, captured: false });
+		Ok(())
+	}
+
+	/// The slot of a local in one of the functions being compiled
+	fn resolve_local(&self, state: usize, name: &Token) -> Option<u8> {
+		self.states[state].locals.iter().rev()
+			.find(|l| l.name == name.lexeme)
+			.map(|l| l.slot as u8)
+	}
+
+	/// The upvalue slot through which a function reaches a local of an enclosing function, capturing
+	/// it in every function in between
+	fn resolve_upvalue(&mut self, state: usize, name: &Token) -> LoxResult<Option<u8>> {
+		if state == 0 {
+			return Ok(None)
+		}
+
+		let enclosing = state - 1;
+
+		if let Some(local) = self.states[enclosing].locals.iter_mut().rev().find(|l| l.name == name.lexeme) {
+			local.captured = true;
+			let index = local.slot as u8;
+			return self.add_upvalue(state, name, UpvalueRef { is_local: true, index }).map(Some)
+		}
+
+		match self.resolve_upvalue(enclosing, name)? {
+			Some(index) => self.add_upvalue(state, name, UpvalueRef { is_local: false, index }).map(Some),
+			None => Ok(None)
+		}
+	}
+
+	fn add_upvalue(&mut self, state: usize, name: &Token, upvalue: UpvalueRef) -> LoxResult<u8> {
+		let upvalues = &mut self.states[state].upvalues;
+
+		if let Some(index) = upvalues.iter().position(|u| *u == upvalue) {
+			return Ok(index as u8)
+		}
+
+		if upvalues.len() > u8::MAX as usize {
+			return Err(LoxError::compile(name.clone(), "Too many closure variables in function."))
+		}
+
+		upvalues.push(upvalue);
+		Ok((upvalues.len() - 1) as u8)
+	}
+
+	/// Emit an instruction reading or writing a variable, whether local, captured or global
+	fn emit_variable(&mut self, name: &Token, local: OpCode, upvalue: OpCode, global: OpCode) -> LoxResult<()> {
+		let state = self.states.len() - 1;
+
+		if let Some(slot) = self.resolve_local(state, name) {
+			self.emit(local);
+			self.emit_byte(slot);
+		} else if let Some(index) = self.resolve_upvalue(state, name)? {
+			self.emit(upvalue);
+			self.emit_byte(index);
+		} else {
+			self.emit_named(global, name)?;
+		}
+
+		Ok(())
+	}
+
+	/// A token for errors about code the AST keeps no token for, such as literals
+	fn synthetic(&self, token_type: TokenType, lexeme: &str) -> Token {
+		Token::new(token_type, lexeme, Literal::Null, self.line)
+	}
+
+	fn unsupported(token: &Token, what: &str) -> LoxError {
+		LoxError::compile(token.clone(), &format!("{} can't be compiled to bytecode yet, run the program with 'run --backend tree' instead.", what))
+	}
+
+	fn statement(&mut self, statement: &Statement) -> LoxResult<()> {
+		if let Some(line) = statement.line() {
+			self.line = line;
+		}
+
+		match statement {
+			Statement::Print(p) => {
+				self.expr(&p.0)?;
+				self.line = p.1.line;
+				self.emit(OpCode::Print);
+			},
+			Statement::Expression(e) => {
+				self.expr(&e.0)?;
+				self.emit(OpCode::Pop);
+			},
+			Statement::Var(v) => self.var(v)?,
+			Statement::MultiVar(declarations) => {
+				for v in declarations {
+					self.var(v)?;
+				}
+			},
+			Statement::Function(f) => {
+				if self.state().scope_depth == 0 {
+					self.function(f)?;
+					self.emit_named(OpCode::DefineGlobal, &f.name)?;
+				} else {
+					// Declared before the body is compiled, so the function can refer to itself
+					self.state().height += 1;
+					self.add_local(&f.name)?;
+					self.state().height -= 1;
+					self.function(f)?;
+				}
+			},
+			Statement::Return(r) => {
+				let height = self.state().height;
+
+				if let Some(call) = r.value.as_ref().and_then(Expr::tail_call).filter(|_| !self.tail_calls) {
+					return Err(Self::unsupported(&call.paren, "A tail call"))
+				}
+
+				match &r.value {
+					Some(value) => self.expr(value)?,
+					None => self.emit(OpCode::Nil)
+				}
+
+				self.line = r.keyword.line;
+				self.emit(OpCode::Return);
+				self.state().height = height;
+			},
+			Statement::If(i) => {
+				self.expr(&i.condition)?;
+				let height = self.state().height;
+
+				let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+				self.emit(OpCode::Pop);
+				self.statement(&i.then_branch)?;
+				let else_jump = self.emit_jump(OpCode::Jump);
+
+				self.patch_jump(&i.keyword, then_jump)?;
+				self.state().height = height;
+				self.emit(OpCode::Pop);
+
+				if let Some(else_branch) = &i.else_branch {
+					self.statement(else_branch)?;
+				}
+
+				self.patch_jump(&i.keyword, else_jump)?;
+			},
+			Statement::While(w) => {
+				let start = self.state().function.chunk.code.len();
+				self.expr(&w.condition)?;
+				let height = self.state().height;
+
+				let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+				self.emit(OpCode::Pop);
+
+				let body_height = self.state().height;
+				self.state().loops.push(Loop { start, height: body_height, breaks: Vec::new() });
+				self.statement(&w.body)?;
+				let lp = self.state().loops.pop().expect("The loop was just pushed");
+
+				self.emit_loop(&w.keyword, start)?;
+				self.patch_jump(&w.keyword, exit_jump)?;
+				self.state().height = height;
+				self.emit(OpCode::Pop);
+
+				for jump in lp.breaks {
+					self.patch_jump(&w.keyword, jump)?;
+				}
+			},
+			Statement::Break() => {
+				let Some(height) = self.state().loops.last().map(|l| l.height) else { return Ok(()) };
+
+				self.emit_pops_to(height);
+				let jump = self.emit_jump(OpCode::Jump);
+
+				if let Some(lp) = self.state().loops.last_mut() {
+					lp.breaks.push(jump);
+				}
+			},
+			Statement::Continue() => {
+				let Some((start, height)) = self.state().loops.last().map(|l| (l.start, l.height)) else { return Ok(()) };
+
+				self.emit_pops_to(height);
+
+				let keyword = self.synthetic(TokenType::CONTINUE, "continue");
+				self.emit_loop(&keyword, start)?;
+			},
+			Statement::Block(b) => self.block(b)?,
+			Statement::For(f) => self.for_statement(f)?,
+			Statement::Switch(s) => self.switch(s)?,
+			Statement::Class(c) => return Err(Self::unsupported(&c.name, "Classes")),
+			Statement::ForIn(f) => return Err(Self::unsupported(&f.keyword, "'for in'")),
+			Statement::Import(i) => return Err(Self::unsupported(&i.keyword, "Importing modules")),
+			Statement::Export(e) => return Err(Self::unsupported(&e.keyword, "Exporting from modules")),
+		}
+
+		Ok(())
+	}
+
+	/// Compile a for loop. The increment is placed before the body and jumped over on the way in, so
+	/// that the end of the body and `continue` can both loop back to it
+	fn for_statement(&mut self, f: &ForStatement) -> LoxResult<()> {
+		self.begin_scope();
+
+		if let Some(initializer) = &f.initializer {
+			self.statement(initializer)?;
+		}
+
+		let mut start = self.state().function.chunk.code.len();
+		let mut exit = None;
+
+		if let Some(condition) = &f.condition {
+			self.expr(condition)?;
+			exit = Some((self.state().height, self.emit_jump(OpCode::JumpIfFalse)));
+			self.emit(OpCode::Pop);
+		}
+
+		if let Some(increment) = &f.increment {
+			let body_jump = self.emit_jump(OpCode::Jump);
+			let increment_start = self.state().function.chunk.code.len();
+
+			self.expr(increment)?;
+			self.emit(OpCode::Pop);
+			self.emit_loop(&f.keyword, start)?;
+
+			start = increment_start;
+			self.patch_jump(&f.keyword, body_jump)?;
+		}
+
+		let body_height = self.state().height;
+		self.state().loops.push(Loop { start, height: body_height, breaks: Vec::new() });
+		self.statement(&f.body)?;
+		let lp = self.state().loops.pop().expect("The loop was just pushed");
+
+		self.emit_loop(&f.keyword, start)?;
+
+		if let Some((height, exit_jump)) = exit {
+			self.patch_jump(&f.keyword, exit_jump)?;
+			self.state().height = height;
+			self.emit(OpCode::Pop);
+		}
+
+		for jump in lp.breaks {
+			self.patch_jump(&f.keyword, jump)?;
+		}
+
+		self.end_scope_popping();
+		Ok(())
+	}
+
+	fn var(&mut self, v: &VarDeclaration) -> LoxResult<()> {
+		match &v.initializer {
+			Some(initializer) => self.expr(initializer)?,
+			None => self.emit(OpCode::Nil)
+		}
+
+		self.line = v.name.line;
+
+		if self.state().scope_depth > 0 {
+			return self.add_local(&v.name)
+		}
+
+		let op = if v.is_const { OpCode::DefineConstGlobal } else { OpCode::DefineGlobal };
+		self.emit_named(op, &v.name)
+	}
+
+	fn block(&mut self, block: &BlockStatement) -> LoxResult<()> {
+		self.begin_scope();
+
+		for statement in &block.statements {
+			self.statement(statement)?;
+		}
+
+		self.end_scope_popping();
+		Ok(())
+	}
+
+	/// Compile a function declaration, leaving the function on the stack
+	fn function(&mut self, f: &FunctionDecl) -> LoxResult<()> {
+		let state = self.function_body(f)?;
+
+		let index = self.constant(&f.name, Constant::Function(Rc::new(state.function)))?;
+		self.emit(OpCode::Closure);
+		self.emit_u16(index);
+
+		for upvalue in state.upvalues {
+			self.emit_byte(upvalue.is_local as u8);
+			self.emit_byte(upvalue.index);
+		}
+
+		Ok(())
+	}
+
+	/// Compile the parameters and body of a function, returning it with the upvalues it captures
+	fn function_body(&mut self, f: &FunctionDecl) -> LoxResult<FunctionState> {
+		if f.params.len() > u8::MAX as usize {
+			return Err(LoxError::compile(f.name.clone(), "Can't have more than 255 parameters."))
+		}
+
+		self.states.push(FunctionState::new(&f.name.lexeme, f.params.len()));
+		self.begin_scope();
+
+		for param in &f.params {
+			self.state().height += 1;
+			self.add_local(param)?;
+		}
+
+		for statement in &f.body {
+			self.statement(statement)?;
+		}
+
+		self.emit(OpCode::Nil);
+		self.emit(OpCode::Return);
+
+		let mut state = self.states.pop().expect("The function was just pushed");
+		self.line = f.name.line;
+		state.function.upvalue_count = state.upvalues.len();
+
+		Ok(state)
+	}
+
+	/// Compile a switch. The subject is kept in a hidden local that each case value is compared with
+	fn switch(&mut self, s: &SwitchStatement) -> LoxResult<()> {
+		self.begin_scope();
+		self.expr(&s.subject)?;
+
+		let state = self.state();
+		let slot = state.height - 1;
+		let depth = state.scope_depth;
+		// Not an identifier, so the program can't refer to it
+		state.locals.push(Local { name: " subject".into(), depth, slot, captured: false });
+
+		let mut end_jumps = Vec::new();
+
+		for case in &s.cases {
+			let mut matched = Vec::new();
+
+			for value in &case.values {
+				self.emit(OpCode::GetLocal);
+				self.emit_byte(slot as u8);
+				self.expr(value)?;
+				self.emit(OpCode::Equal);
+				let height = self.state().height;
+
+				let skip = self.emit_jump(OpCode::JumpIfFalse);
+				self.emit(OpCode::Pop);
+				matched.push(self.emit_jump(OpCode::Jump));
+
+				self.patch_jump(&s.keyword, skip)?;
+				self.state().height = height;
+				self.emit(OpCode::Pop);
+			}
+
+			let next_case = self.emit_jump(OpCode::Jump);
+
+			for jump in matched {
+				self.patch_jump(&s.keyword, jump)?;
+			}
+
+			self.block(&case.body)?;
+			end_jumps.push(self.emit_jump(OpCode::Jump));
+			self.patch_jump(&s.keyword, next_case)?;
+		}
+
+		if let Some(default) = &s.default {
+			self.block(default)?;
+		}
+
+		for jump in end_jumps {
+			self.patch_jump(&s.keyword, jump)?;
+		}
+
+		self.end_scope_popping();
+		Ok(())
+	}
+
+	fn expr(&mut self, expr: &Expr) -> LoxResult<()> {
+		match expr {
+			Expr::Literal(l) => match l {
+				ExprLiteral::NUMBER(n) => {
+					let token = self.synthetic(TokenType::NUMBER, &n.to_string());
+					self.emit_constant(&token, Constant::Number(*n))?;
+				},
+				ExprLiteral::STRING(s) => {
+					let token = self.synthetic(TokenType::STRING, s);
+					self.emit_constant(&token, Constant::String(*s))?;
+				},
+				ExprLiteral::True => self.emit(OpCode::True),
+				ExprLiteral::False => self.emit(OpCode::False),
+				ExprLiteral::Null => self.emit(OpCode::Nil),
+			},
+			Expr::Grouping(g) => self.expr(&g.0)?,
+			Expr::Unary(u) => {
+				self.expr(&u.right)?;
+				self.line = u.operator.line;
+
+				match u.operator.token_type {
+					TokenType::MINUS => self.emit(OpCode::Negate),
+					_ => self.emit(OpCode::Not),
+				}
+			},
+			Expr::Binary(b) => {
+				self.expr(&b.left)?;
+				self.expr(&b.right)?;
+				self.line = b.operator.line;
+
+				match b.operator.token_type {
+					TokenType::PLUS => self.emit(OpCode::Add),
+					TokenType::MINUS => self.emit(OpCode::Subtract),
+					TokenType::STAR => self.emit(OpCode::Multiply),
+					TokenType::SLASH => self.emit(OpCode::Divide),
+					TokenType::GREATER => self.emit(OpCode::Greater),
+					TokenType::GREATER_EQUAL => self.emit(OpCode::GreaterEqual),
+					TokenType::LESS => self.emit(OpCode::Less),
+					TokenType::LESS_EQUAL => self.emit(OpCode::LessEqual),
+					TokenType::EQUAL_EQUAL => self.emit(OpCode::Equal),
+					TokenType::BANG_EQUAL => {
+						self.emit(OpCode::Equal);
+						self.emit(OpCode::Not);
+					},
+					_ => return Err(LoxError::compile(b.operator.clone(), "Unknown binary operator."))
+				}
+			},
+			Expr::Logical(l) => {
+				self.expr(&l.left)?;
+				self.line = l.operator.line;
+
+				if l.operator.token_type == TokenType::OR {
+					let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+					let end_jump = self.emit_jump(OpCode::Jump);
+
+					self.patch_jump(&l.operator, else_jump)?;
+					self.emit(OpCode::Pop);
+					self.expr(&l.right)?;
+					self.patch_jump(&l.operator, end_jump)?;
+				} else {
+					let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+
+					self.emit(OpCode::Pop);
+					self.expr(&l.right)?;
+					self.patch_jump(&l.operator, end_jump)?;
+				}
+			},
+			Expr::Variable(v) => {
+				self.line = v.name.line;
+
+				self.emit_variable(&v.name, OpCode::GetLocal, OpCode::GetUpvalue, OpCode::GetGlobal)?;
+			},
+			Expr::Assignment(a) => {
+				self.expr(&a.value)?;
+				self.line = a.name.line;
+
+				self.emit_variable(&a.name, OpCode::SetLocal, OpCode::SetUpvalue, OpCode::SetGlobal)?;
+			},
+			Expr::Call(c) => {
+				self.expr(&c.callee)?;
+
+				for argument in &c.arguments {
+					self.expr(argument)?;
+				}
+
+				if c.arguments.len() > u8::MAX as usize {
+					return Err(LoxError::compile(c.paren.clone(), "Can't have more than 255 arguments."))
+				}
+
+				self.line = c.paren.line;
+				self.emit(OpCode::Call);
+				self.emit_byte(c.arguments.len() as u8);
+				self.state().height -= c.arguments.len();
+			},
+			Expr::Get(g) => {
+				self.expr(&g.object)?;
+				self.line = g.name.line;
+				self.emit_named(OpCode::GetProperty, &g.name)?;
+			},
+			Expr::Block(b) => {
+				self.begin_scope();
+
+				for statement in &b.statements {
+					self.statement(statement)?;
+				}
+
+				match &b.value {
+					Some(value) => self.expr(value)?,
+					None => self.emit(OpCode::Nil)
+				}
+
+				// The VM closes any captured locals as it pops them
+				let count = self.end_scope().len();
+
+				if count > 0 {
+					self.emit(OpCode::PopBelow);
+					self.emit_byte(count as u8);
+					self.state().height -= count;
+				}
+			},
+			Expr::If(i) => {
+				self.expr(&i.condition)?;
+				let height = self.state().height;
+
+				let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+				self.emit(OpCode::Pop);
+				self.expr(&i.then_branch)?;
+				let else_jump = self.emit_jump(OpCode::Jump);
+
+				self.patch_jump(&i.keyword, then_jump)?;
+				self.state().height = height;
+				self.emit(OpCode::Pop);
+
+				match &i.else_branch {
+					Some(else_branch) => self.expr(else_branch)?,
+					None => self.emit(OpCode::Nil)
+				}
+
+				self.patch_jump(&i.keyword, else_jump)?;
+			},
+			Expr::Array(a) => {
+				for element in &a.elements {
+					self.expr(element)?;
+				}
+
+				let count = u16::try_from(a.elements.len())
+					.map_err(|_| LoxError::compile(a.bracket.clone(), "Too many elements in an array literal."))?;
+
+				self.line = a.bracket.line;
+				self.emit(OpCode::Array);
+				self.emit_u16(count);
+
+				let state = self.state();
+				state.height = state.height - a.elements.len() + 1;
+			},
+			Expr::Index(i) => {
+				self.expr(&i.object)?;
+				self.expr(&i.index)?;
+				self.line = i.bracket.line;
+				self.emit(OpCode::Index);
+			},
+			Expr::IndexSet(i) => {
+				self.expr(&i.object)?;
+				self.expr(&i.index)?;
+				self.expr(&i.value)?;
+				self.line = i.bracket.line;
+				self.emit(OpCode::IndexSet);
+			},
+			Expr::Set(s) => return Err(Self::unsupported(&s.name, "Setting properties")),
+			Expr::This(t) => return Err(Self::unsupported(&t.keyword, "'this'")),
+		}
+
+		Ok(())
+	}
+}
+
+/// How an instruction changes the height of the stack, for those whose effect doesn't depend on
+/// their operand
+fn stack_effect(op: OpCode) -> isize {
+	match op {
+		OpCode::Constant | OpCode::Nil | OpCode::True | OpCode::False | OpCode::GetLocal | OpCode::GetGlobal
+			| OpCode::GetUpvalue | OpCode::Closure | OpCode::Dup => 1,
+		OpCode::Pop | OpCode::DefineGlobal | OpCode::DefineConstGlobal | OpCode::Equal | OpCode::Greater
+			| OpCode::GreaterEqual | OpCode::Less | OpCode::LessEqual | OpCode::Add | OpCode::Subtract
+			| OpCode::Multiply | OpCode::Divide | OpCode::Print | OpCode::Return | OpCode::Index
+			| OpCode::CloseUpvalue => -1,
+		OpCode::IndexSet => -2,
+		OpCode::SetLocal | OpCode::SetUpvalue | OpCode::SetGlobal | OpCode::GetProperty | OpCode::Not
+			| OpCode::Negate | OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop | OpCode::Call | OpCode::Array
+			| OpCode::PopBelow => 0,
+	}
+}
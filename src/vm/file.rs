@@ -0,0 +1,210 @@
+use std::rc::Rc;
+
+use crate::scanner::token::Symbol;
+
+use super::{chunk::{Chunk, Constant, Function}, verify::verify};
+
+/// The first bytes of every compiled file
+pub const MAGIC: &[u8; 4] = b"LOXC";
+
+/// The version of the `.loxc` format, bumped whenever the instruction set or layout changes
+pub const VERSION: u16 = 2;
+
+/// The last bytes of an executable made by `build`, after the program and its trailer
+pub const EMBED_MAGIC: &[u8; 8] = b"LOXBUILD";
+
+/// Set in the trailer of a built executable when scripts may run shell commands
+pub const EMBED_ALLOW_EXEC: u8 = 1;
+
+/// The size of what follows an embedded program: its flags, its length as a little-endian `u64`,
+/// and the magic
+pub const EMBED_TRAILER: usize = 1 + 8 + EMBED_MAGIC.len();
+
+const TAG_NUMBER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FUNCTION: u8 = 2;
+
+/// How deeply functions may be nested in a compiled file, so that reading one can't overflow the stack
+const MAX_NESTING: usize = 256;
+
+/// Serialize a compiled program. After the magic and version, the script function follows as its
+/// name, arity, upvalue count, code, lines and constants, with nested functions written in place. Integers are
+/// little endian, lengths are `u32`
+pub fn encode(function: &Function) -> Vec<u8> {
+	let mut out = Vec::new();
+
+	out.extend_from_slice(MAGIC);
+	out.extend_from_slice(&VERSION.to_le_bytes());
+	write_function(&mut out, function);
+
+	out
+}
+
+/// Deserialize a compiled program, checking that it was written by this version and that its code
+/// is safe to run, see [`verify`]
+pub fn decode(bytes: &[u8]) -> Result<Function, String> {
+	let mut reader = Reader { bytes, position: 0 };
+
+	if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+		return Err("Not a compiled Lox file.".to_string())
+	}
+
+	let version = reader.u16()?;
+
+	if version != VERSION {
+		return Err(format!("Compiled with format version {}, this interpreter reads version {}. Compile the file again.", version, VERSION))
+	}
+
+	let function = reader.function(0)?;
+
+	if reader.position != bytes.len() {
+		return Err("Unexpected data after the compiled program.".to_string())
+	}
+
+	// The script is run without arguments or upvalues
+	if function.arity != 0 || function.upvalue_count != 0 {
+		return Err("The compiled program doesn't start with a script.".to_string())
+	}
+
+	verify(&function)?;
+	Ok(function)
+}
+
+/// Append a compiled program to an executable, so that it runs the program when started
+pub fn embed(executable: &[u8], program: &[u8], flags: u8) -> Vec<u8> {
+	let mut out = Vec::with_capacity(executable.len() + program.len() + EMBED_TRAILER);
+
+	out.extend_from_slice(executable);
+	out.extend_from_slice(program);
+	out.push(flags);
+	out.extend_from_slice(&(program.len() as u64).to_le_bytes());
+	out.extend_from_slice(EMBED_MAGIC);
+
+	out
+}
+
+/// Read the trailer of an executable, returning the flags and length of the program embedded before
+/// it, if there is one
+pub fn embedded_trailer(trailer: &[u8; EMBED_TRAILER]) -> Option<(u8, u64)> {
+	let (rest, magic) = trailer.split_at(EMBED_TRAILER - EMBED_MAGIC.len());
+
+	if magic != EMBED_MAGIC.as_slice() {
+		return None
+	}
+
+	let len = u64::from_le_bytes(rest[1..].try_into().expect("The trailer holds 8 length bytes"));
+	Some((rest[0], len))
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) {
+	out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+	write_u32(out, s.len());
+	out.extend_from_slice(s.as_bytes());
+}
+
+fn write_function(out: &mut Vec<u8>, function: &Function) {
+	write_string(out, &function.name);
+	write_u32(out, function.arity);
+	write_u32(out, function.upvalue_count);
+
+	write_u32(out, function.chunk.code.len());
+	out.extend_from_slice(&function.chunk.code);
+
+	for line in &function.chunk.lines {
+		write_u32(out, *line);
+	}
+
+	write_u32(out, function.chunk.constants.len());
+
+	for constant in &function.chunk.constants {
+		match constant {
+			Constant::Number(n) => {
+				out.push(TAG_NUMBER);
+				out.extend_from_slice(&n.to_le_bytes());
+			},
+			Constant::String(s) => {
+				out.push(TAG_STRING);
+				write_string(out, s);
+			},
+			Constant::Function(f) => {
+				out.push(TAG_FUNCTION);
+				write_function(out, f);
+			},
+		}
+	}
+}
+
+/// Reads values from a compiled file, failing on truncated input
+struct Reader<'a> {
+	bytes: &'a [u8],
+	position: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+		let end = self.position.checked_add(len).filter(|end| *end <= self.bytes.len())
+			.ok_or_else(|| "The compiled file is truncated.".to_string())?;
+
+		let slice = &self.bytes[self.position..end];
+		self.position = end;
+		Ok(slice)
+	}
+
+	fn u8(&mut self) -> Result<u8, String> {
+		Ok(self.take(1)?[0])
+	}
+
+	fn u16(&mut self) -> Result<u16, String> {
+		let bytes = self.take(2)?;
+		Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+	}
+
+	fn u32(&mut self) -> Result<usize, String> {
+		let bytes = self.take(4)?;
+		Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+	}
+
+	fn string(&mut self) -> Result<String, String> {
+		let len = self.u32()?;
+		let bytes = self.take(len)?;
+
+		String::from_utf8(bytes.to_vec()).map_err(|_| "The compiled file contains an invalid string.".to_string())
+	}
+
+	/// Read a function, nested `depth` functions deep
+	fn function(&mut self, depth: usize) -> Result<Function, String> {
+		if depth > MAX_NESTING {
+			return Err("The compiled file nests functions too deeply.".to_string())
+		}
+
+		let name = self.string()?;
+		let arity = self.u32()?;
+		let upvalue_count = self.u32()?;
+
+		let len = self.u32()?;
+		let code = self.take(len)?.to_vec();
+		let lines = (0..len).map(|_| self.u32()).collect::<Result<Vec<_>, _>>()?;
+
+		let count = self.u32()?;
+		let mut constants = Vec::new();
+
+		for _ in 0..count {
+			let constant = match self.u8()? {
+				TAG_NUMBER => {
+					let bytes = self.take(8)?;
+					Constant::Number(f64::from_le_bytes(bytes.try_into().expect("Took exactly 8 bytes")))
+				},
+				TAG_STRING => Constant::String(Symbol::intern(&self.string()?)),
+				TAG_FUNCTION => Constant::Function(Rc::new(self.function(depth + 1)?)),
+				tag => return Err(format!("Unknown constant tag {} in the compiled file.", tag))
+			};
+
+			constants.push(constant);
+		}
+
+		Ok(Function { name, arity, upvalue_count, chunk: Chunk { code, lines, constants } })
+	}
+}
@@ -0,0 +1,584 @@
+use std::{cell::RefCell, rc::Rc};
+
+use chunk::{Closure, Constant, Function, OpCode, Upvalue};
+
+use crate::{error::{LoxError, LoxResult}, interpreter::{error::{check_index, check_key, ValueError}, values::{Arguments, LoxInstance, Value}, CallFrame, CallKind, Interpreter}, scanner::token::{Literal, Location, Symbol, Token, TokenType}};
+
+pub mod chunk;
+pub mod compiler;
+pub mod file;
+pub mod verify;
+
+/// An active call of a compiled function
+struct Frame {
+	closure: Rc<Closure>,
+	/// The offset of the next instruction in the function's chunk
+	ip: usize,
+	/// Where the frame's slots start on the stack, slot 0 holds the callee
+	base: usize,
+	/// Whether the call is listed in the interpreter's frames, the top level of a script isn't
+	traced: bool,
+}
+
+/// The values of the VM and the upvalues still pointing into them. It lives in the interpreter
+/// between runs, so a compiled function called back from a native runs on the same stack as its
+/// caller and finds the locals it captured where it left them
+#[derive(Default)]
+pub struct Stack {
+	values: Vec<Value>,
+	/// Upvalues of locals still on the stack, ordered by their index
+	open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+/// A stack machine running compiled Lox. Globals and natives are shared with the interpreter it
+/// borrows, so both backends see the same global environment
+pub struct Vm<'a> {
+	interpreter: &'a mut Interpreter,
+	stack: Stack,
+	frames: Vec<Frame>,
+}
+
+impl Drop for Vm<'_> {
+	/// Hand the stack back to the interpreter, for the VM that called into it
+	fn drop(&mut self) {
+		self.interpreter.vm_stack = std::mem::take(&mut self.stack);
+	}
+}
+
+impl<'a> Vm<'a> {
+	pub fn new(interpreter: &'a mut Interpreter) -> Self {
+		let stack = std::mem::take(&mut interpreter.vm_stack);
+		Self { interpreter, stack, frames: Vec::new() }
+	}
+
+	/// Run a compiled program to completion
+	pub fn run_script(&mut self, function: Function) -> LoxResult<()> {
+		let closure = Rc::new(Closure { function: Rc::new(function), upvalues: Vec::new() });
+
+		self.enter(|vm, depth| {
+			let base = vm.stack.values.len();
+			vm.stack.values.push(Value::Closure(closure.clone()));
+			vm.frames.push(Frame { closure, ip: 0, base, traced: false });
+			vm.run(depth)
+		}).map(|_| ())
+	}
+
+	/// Call a compiled function from outside the VM, such as from a native that takes a callback
+	pub fn call(&mut self, closure: Rc<Closure>, arguments: Arguments) -> LoxResult<Value> {
+		self.call_from(closure, arguments, true)
+	}
+
+	/// Call the compiled form of a tree-walked function, in place of walking it. The interpreter has
+	/// already listed the call in its frames
+	pub fn call_tiered(&mut self, closure: Rc<Closure>, arguments: Arguments) -> LoxResult<Value> {
+		self.call_from(closure, arguments, false)
+	}
+
+	fn call_from(&mut self, closure: Rc<Closure>, arguments: Arguments, traced: bool) -> LoxResult<Value> {
+		let count = arguments.len();
+
+		self.enter(|vm, depth| {
+			vm.stack.values.push(Value::Closure(closure.clone()));
+			vm.stack.values.extend(arguments);
+			vm.call_closure(closure, count, 0, traced)?;
+			vm.run(depth)
+		})
+	}
+
+	/// Run from outside the VM, until the frames are back to how many there were. After an error,
+	/// the frames and values the run left behind are dropped, so a caller that recovers finds the
+	/// stack as it was
+	fn enter(&mut self, run: impl FnOnce(&mut Self, usize) -> LoxResult<Value>) -> LoxResult<Value> {
+		let depth = self.frames.len();
+		let base = self.stack.values.len();
+		let calls = self.interpreter.frames.len();
+
+		let mut result = run(self, depth);
+
+		if let Err(e) = &mut result {
+			if let LoxError::Runtime { location, trace, .. } = e {
+				if trace.is_empty() {
+					*trace = self.interpreter.error_trace(location.line).into();
+				}
+			}
+
+			self.interpreter.frames.truncate(calls);
+			self.frames.truncate(depth);
+			self.close_upvalues(base);
+			self.stack.values.truncate(base);
+		}
+
+		result
+	}
+
+	fn frame(&self) -> &Frame {
+		self.frames.last().expect("The VM always runs inside a frame")
+	}
+
+	/// The line of the instruction being executed
+	fn line(&self) -> usize {
+		// A call from a native fails before its frame exists, the native reports its own line
+		self.frames.last()
+			.and_then(|frame| frame.closure.function.chunk.lines.get(frame.ip.saturating_sub(1)).copied())
+			.unwrap_or(0)
+	}
+
+	fn error(&self, message: &str) -> LoxError {
+		LoxError::runtime(Location { line: self.line(), ..Location::default() }, message)
+	}
+
+	/// Place an error raised by the interpreter, such as from a native, at the current line
+	fn value_error(&self, error: ValueError) -> LoxError {
+		match error {
+			ValueError::Error(e) => *e,
+			ValueError::Native { message } => self.error(&message),
+			ValueError::Quit => self.error("Quit."),
+		}
+	}
+
+	/// A token at the current line, for the interpreter's helpers that report errors at one
+	fn token(&self, token_type: TokenType, lexeme: &str) -> Token {
+		Token::new(token_type, lexeme, Literal::Null, self.line())
+	}
+
+	fn read_byte(&mut self) -> LoxResult<u8> {
+		let frame = self.frames.last_mut().expect("The VM always runs inside a frame");
+		let byte = frame.closure.function.chunk.code.get(frame.ip).copied();
+		frame.ip += 1;
+
+		byte.ok_or_else(|| self.error("Ran past the end of the compiled code."))
+	}
+
+	fn read_u16(&mut self) -> LoxResult<u16> {
+		Ok((self.read_byte()? as u16) << 8 | self.read_byte()? as u16)
+	}
+
+	fn read_constant(&mut self) -> LoxResult<Constant> {
+		let index = self.read_u16()? as usize;
+		let constant = self.frame().closure.function.chunk.constants.get(index).cloned();
+
+		constant.ok_or_else(|| self.error("Invalid constant in the compiled code."))
+	}
+
+	fn read_name(&mut self) -> LoxResult<Symbol> {
+		match self.read_constant()? {
+			Constant::String(name) => Ok(name),
+			_ => Err(self.error("Expected a name in the compiled code."))
+		}
+	}
+
+	fn push(&mut self, value: Value) {
+		self.stack.values.push(value);
+	}
+
+	fn pop(&mut self) -> Value {
+		self.stack.values.pop().expect("The compiler keeps the stack balanced")
+	}
+
+	fn peek(&self, distance: usize) -> &Value {
+		&self.stack.values[self.stack.values.len() - 1 - distance]
+	}
+
+	fn slot(&self, slot: u8) -> LoxResult<usize> {
+		let index = self.frame().base + slot as usize;
+
+		if index >= self.stack.values.len() {
+			return Err(self.error("Invalid local slot in the compiled code."))
+		}
+
+		Ok(index)
+	}
+
+	fn numbers(&mut self) -> LoxResult<(f64, f64)> {
+		let right = self.pop();
+		let left = self.pop();
+
+		match (left, right) {
+			(Value::Double(l), Value::Double(r)) => Ok((l, r)),
+			_ => Err(self.error("Operands must be a numbers."))
+		}
+	}
+
+	/// The upvalue at the slot given by the next byte, in the running closure
+	fn upvalue(&mut self) -> LoxResult<Rc<RefCell<Upvalue>>> {
+		let index = self.read_byte()? as usize;
+		self.frame().closure.upvalues.get(index).cloned().ok_or_else(|| self.error("Invalid upvalue in the compiled code."))
+	}
+
+	/// The upvalue for the local at a stack index, shared with any closure that already captured it
+	fn capture_upvalue(&mut self, index: usize) -> Rc<RefCell<Upvalue>> {
+		let open = &mut self.stack.open_upvalues;
+		let position = open.partition_point(|u| matches!(*u.borrow(), Upvalue::Open(i) if i < index));
+
+		if let Some(upvalue) = open.get(position) {
+			if matches!(*upvalue.borrow(), Upvalue::Open(i) if i == index) {
+				return upvalue.clone()
+			}
+		}
+
+		let upvalue = Rc::new(RefCell::new(Upvalue::Open(index)));
+		open.insert(position, upvalue.clone());
+		upvalue
+	}
+
+	/// Move the locals at or above a stack index into the upvalues capturing them
+	fn close_upvalues(&mut self, from: usize) {
+		while let Some(upvalue) = self.stack.open_upvalues.last() {
+			let index = match *upvalue.borrow() {
+				Upvalue::Open(index) if index >= from => index,
+				_ => break
+			};
+
+			let value = self.stack.values.get(index).cloned().unwrap_or(Value::Nil);
+			*upvalue.borrow_mut() = Upvalue::Closed(value);
+			self.stack.open_upvalues.pop();
+		}
+	}
+
+	/// Start running a compiled function whose arguments are on the stack. A traced call is listed in
+	/// the interpreter's frames until it returns
+	fn call_closure(&mut self, closure: Rc<Closure>, count: usize, line: usize, traced: bool) -> LoxResult<()> {
+		if count != closure.function.arity {
+			return Err(self.error(&format!("Expected {} arguments but got {}.", closure.function.arity, count)))
+		}
+
+		// Counted in the interpreter's frames, which also hold the tree-walked calls this one is inside
+		if self.interpreter.frames.len() >= self.interpreter.max_call_depth {
+			return Err(self.error("Stack overflow."))
+		}
+
+		if traced {
+			self.interpreter.frames.push(CallFrame { name: closure.function.name.clone(), kind: CallKind::Function, line });
+		}
+
+		let base = self.stack.values.len() - count - 1;
+		self.frames.push(Frame { closure, ip: 0, base, traced });
+		Ok(())
+	}
+
+	/// Call the value below `count` arguments on the stack
+	fn call_value(&mut self, count: usize) -> LoxResult<()> {
+		let line = self.line();
+		let callee = self.peek(count).clone();
+
+		if let Value::Closure(closure) = callee {
+			return self.call_closure(closure, count, line, true)
+		}
+
+		// A tree-walked function that has been compiled runs here, without leaving the VM
+		if let Some(closure) = match &callee { Value::Function(f) => f.tiered(), _ => None } {
+			return self.call_closure(closure, count, line, true)
+		}
+
+		let arguments: Arguments = self.stack.values.drain(self.stack.values.len() - count..).collect();
+		self.pop();
+
+		// Lend the stack back to the interpreter, the callee may call compiled functions
+		self.interpreter.frames.push(CallFrame::new(&callee, line));
+		self.interpreter.vm_stack = std::mem::take(&mut self.stack);
+		let result = self.interpreter.call_value(callee, arguments);
+		self.stack = std::mem::take(&mut self.interpreter.vm_stack);
+		self.interpreter.frames.pop();
+
+		let value = result.map_err(|e| self.value_error(e))?;
+		self.push(value);
+		Ok(())
+	}
+
+	/// Execute instructions until the frame count drops back to `depth`, returning the value the
+	/// last frame returned
+	fn run(&mut self, depth: usize) -> LoxResult<Value> {
+		loop {
+			let byte = self.read_byte()?;
+			let op = OpCode::from_byte(byte).ok_or_else(|| self.error(&format!("Unknown opcode {}.", byte)))?;
+
+			match op {
+				OpCode::Constant => {
+					let value = match self.read_constant()? {
+						Constant::Number(n) => Value::Double(n),
+						Constant::String(s) => Value::String((*s).into()),
+						Constant::Function(_) => return Err(self.error("Functions are loaded with 'Closure'."))
+					};
+
+					self.push(value);
+				},
+				OpCode::Nil => self.push(Value::Nil),
+				OpCode::True => self.push(Value::Boolean(true)),
+				OpCode::False => self.push(Value::Boolean(false)),
+				OpCode::Pop => { self.pop(); },
+				OpCode::Dup => self.push(self.peek(0).clone()),
+				OpCode::PopBelow => {
+					let count = self.read_byte()? as usize;
+					let top = self.pop();
+					let len = self.stack.values.len() - count;
+					self.close_upvalues(len);
+					self.stack.values.truncate(len);
+					self.push(top);
+				},
+				OpCode::GetLocal => {
+					let slot = self.read_byte()?;
+					let index = self.slot(slot)?;
+					self.push(self.stack.values[index].clone());
+				},
+				OpCode::SetLocal => {
+					let slot = self.read_byte()?;
+					let index = self.slot(slot)?;
+					self.stack.values[index] = self.peek(0).clone();
+				},
+				OpCode::GetGlobal => {
+					let name = self.read_name()?;
+					let value = self.interpreter.globals.0.borrow().lookup(name).map(|v| v.value());
+
+					match value {
+						Some(value) => self.push(value),
+						None => return Err(self.error(&format!("Undefined variable '{}'.", name)))
+					}
+				},
+				OpCode::DefineGlobal => {
+					let name = self.read_name()?;
+					let token = self.token(TokenType::IDENTIFIER, &name);
+					self.interpreter.globals.check_redeclaration(&token)?;
+
+					let value = self.pop();
+					self.interpreter.globals.define(name, value);
+				},
+				OpCode::DefineConstGlobal => {
+					let name = self.read_name()?;
+					let token = self.token(TokenType::IDENTIFIER, &name);
+					self.interpreter.globals.check_redeclaration(&token)?;
+
+					let value = self.pop();
+					self.interpreter.globals.define_constant(name, value);
+				},
+				OpCode::SetGlobal => {
+					let name = self.read_name()?;
+					let token = self.token(TokenType::IDENTIFIER, &name);
+					let value = self.peek(0).clone();
+
+					self.interpreter.globals.assign(token, value)?;
+				},
+				OpCode::GetProperty => {
+					let name = self.read_name()?;
+					let object = self.pop();
+
+					let value = match object {
+						// Instances and modules made by the tree-walker, when it hands a function over
+						Value::Instance(instance) => {
+							let token = self.token(TokenType::IDENTIFIER, &name);
+							LoxInstance::get(&instance, token).map_err(|e| self.value_error(e))?
+						},
+						Value::Module(module) => {
+							let token = self.token(TokenType::IDENTIFIER, &name);
+							module.get(&token).map_err(|e| self.value_error(e))?
+						},
+						Value::Array(array) if name == "length" => Value::Double(array.borrow().len() as f64),
+						Value::Map(map) if name == "length" => Value::Double(map.borrow().len() as f64),
+						Value::String(s) if name == "length" => Value::Double(s.chars().count() as f64),
+						_ => return Err(self.error("Only instances have properties"))
+					};
+
+					self.push(value);
+				},
+				OpCode::Equal => {
+					let right = self.pop();
+					let left = self.pop();
+					self.push(Value::Boolean(left == right));
+				},
+				OpCode::Greater => {
+					let (l, r) = self.numbers()?;
+					self.push(Value::Boolean(l > r));
+				},
+				OpCode::GreaterEqual => {
+					let (l, r) = self.numbers()?;
+					self.push(Value::Boolean(l >= r));
+				},
+				OpCode::Less => {
+					let (l, r) = self.numbers()?;
+					self.push(Value::Boolean(l < r));
+				},
+				OpCode::LessEqual => {
+					let (l, r) = self.numbers()?;
+					self.push(Value::Boolean(l <= r));
+				},
+				OpCode::Add => {
+					let right = self.pop();
+					let left = self.pop();
+
+					let value = match (left, right) {
+						(Value::Double(l), Value::Double(r)) => Value::Double(l + r),
+						(Value::String(l), Value::String(r)) => Value::String(l.concat(&r)),
+						_ => return Err(self.error("Operands can only be numbers or strings"))
+					};
+
+					self.push(value);
+				},
+				OpCode::Subtract => {
+					let (l, r) = self.numbers()?;
+					self.push(Value::Double(l - r));
+				},
+				OpCode::Multiply => {
+					let (l, r) = self.numbers()?;
+					self.push(Value::Double(l * r));
+				},
+				OpCode::Divide => {
+					let (l, r) = self.numbers()?;
+
+					if r == 0.0 {
+						return Err(self.error("Denominator cannot be 0"))
+					}
+
+					self.push(Value::Double(l / r));
+				},
+				OpCode::Not => {
+					let value = self.pop();
+					self.push(Value::Boolean(!value.is_truthy()));
+				},
+				OpCode::Negate => match self.pop() {
+					Value::Double(n) => self.push(Value::Double(-n)),
+					_ => return Err(self.error("Operand must be a number."))
+				},
+				OpCode::Print => {
+					let value = self.pop();
+					println!("{}", value);
+				},
+				OpCode::Jump => {
+					let offset = self.read_u16()? as usize;
+					self.frames.last_mut().expect("The VM always runs inside a frame").ip += offset;
+				},
+				OpCode::JumpIfFalse => {
+					let offset = self.read_u16()? as usize;
+
+					if !self.peek(0).is_truthy() {
+						self.frames.last_mut().expect("The VM always runs inside a frame").ip += offset;
+					}
+				},
+				OpCode::Loop => {
+					let offset = self.read_u16()? as usize;
+					let frame = self.frames.last_mut().expect("The VM always runs inside a frame");
+					frame.ip = frame.ip.saturating_sub(offset);
+				},
+				OpCode::Call => {
+					let count = self.read_byte()? as usize;
+					self.call_value(count)?;
+				},
+				OpCode::Closure => {
+					let Constant::Function(function) = self.read_constant()? else {
+						return Err(self.error("Expected a function in the compiled code."))
+					};
+
+					let mut upvalues = Vec::with_capacity(function.upvalue_count);
+
+					for _ in 0..function.upvalue_count {
+						let is_local = self.read_byte()? == 1;
+						let index = self.read_byte()?;
+
+						let upvalue = if is_local {
+							self.capture_upvalue(self.frame().base + index as usize)
+						} else {
+							self.frame().closure.upvalues.get(index as usize).cloned()
+								.ok_or_else(|| self.error("Invalid upvalue in the compiled code."))?
+						};
+
+						upvalues.push(upvalue);
+					}
+
+					self.push(Value::Closure(Rc::new(Closure { function, upvalues })));
+				},
+				OpCode::GetUpvalue => {
+					let upvalue = self.upvalue()?;
+
+					let value = match &*upvalue.borrow() {
+						Upvalue::Open(index) => self.stack.values[*index].clone(),
+						Upvalue::Closed(value) => value.clone(),
+					};
+
+					self.push(value);
+				},
+				OpCode::SetUpvalue => {
+					let upvalue = self.upvalue()?;
+					let value = self.peek(0).clone();
+					let mut upvalue = upvalue.borrow_mut();
+
+					match &mut *upvalue {
+						Upvalue::Open(index) => self.stack.values[*index] = value,
+						Upvalue::Closed(closed) => *closed = value,
+					}
+				},
+				OpCode::CloseUpvalue => {
+					self.close_upvalues(self.stack.values.len() - 1);
+					self.pop();
+				},
+				OpCode::Return => {
+					let result = self.pop();
+					let frame = self.frames.pop().expect("The VM always runs inside a frame");
+
+					if frame.traced {
+						self.interpreter.frames.pop();
+					}
+
+					self.close_upvalues(frame.base);
+					self.stack.values.truncate(frame.base);
+
+					if self.frames.len() == depth {
+						return Ok(result)
+					}
+
+					self.push(result);
+				},
+				OpCode::Array => {
+					let count = self.read_u16()? as usize;
+					let elements = self.stack.values.split_off(self.stack.values.len() - count);
+					self.push(Value::Array(Rc::new(RefCell::new(elements))));
+				},
+				OpCode::Index => {
+					let index = self.pop();
+					let object = self.pop();
+					let bracket = self.token(TokenType::LEFT_BRACKET, "[");
+
+					let value = match object {
+						Value::Array(array) => {
+							let array = array.borrow();
+							let i = check_index(&bracket, &index, array.len()).map_err(|e| self.value_error(e))?;
+							array[i].clone()
+						},
+						Value::String(s) => {
+							let chars: Vec<char> = s.chars().collect();
+							let i = check_index(&bracket, &index, chars.len()).map_err(|e| self.value_error(e))?;
+							Value::String(chars[i].to_string().into())
+						},
+						// Missing keys read as nil
+						Value::Map(map) => {
+							let key = check_key(&bracket, &index).map_err(|e| self.value_error(e))?;
+							map.borrow().get(&key).cloned().unwrap_or(Value::Nil)
+						},
+						_ => return Err(self.error("Only arrays, maps and strings can be indexed."))
+					};
+
+					self.push(value);
+				},
+				OpCode::IndexSet => {
+					let value = self.pop();
+					let index = self.pop();
+					let object = self.pop();
+					let bracket = self.token(TokenType::LEFT_BRACKET, "[");
+
+					match object {
+						Value::Array(array) => {
+							let mut array = array.borrow_mut();
+							let i = check_index(&bracket, &index, array.len()).map_err(|e| self.value_error(e))?;
+							array[i] = value.clone();
+						},
+						Value::Map(map) => {
+							let key = check_key(&bracket, &index).map_err(|e| self.value_error(e))?;
+							map.borrow_mut().insert(key, value.clone());
+						},
+						Value::String(_) => return Err(self.error("Strings are immutable.")),
+						_ => return Err(self.error("Only arrays and maps can be assigned by index."))
+					}
+
+					self.push(value);
+				},
+			}
+		}
+	}
+}
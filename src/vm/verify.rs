@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use super::chunk::{Constant, Function, OpCode};
+
+/// Check the code of a function loaded from a file, and of every function nested in it, so that
+/// running it can't read outside the chunk or the stack. Every opcode must be known with all its
+/// operands present, every constant, local and upvalue index in range, every jump must land on an
+/// instruction, and the stack must never drop below the frame's base, with the same height wherever
+/// two paths meet
+pub fn verify(function: &Function) -> Result<(), String> {
+	let instructions = decode_instructions(function)?;
+
+	// The height of the stack before each reachable instruction, counted from the frame's base
+	let mut heights: HashMap<usize, usize> = HashMap::new();
+	let mut pending = vec![(0, function.arity + 1)];
+
+	while let Some((offset, height)) = pending.pop() {
+		match heights.get(&offset) {
+			Some(&seen) if seen == height => continue,
+			Some(_) => return Err(invalid(function, offset, "the stack height differs between paths")),
+			None => { heights.insert(offset, height); },
+		}
+
+		let instruction = instructions.get(&offset)
+			.ok_or_else(|| invalid(function, offset, "a jump lands outside the code or inside an instruction"))?;
+
+		let height = instruction.effect(function, height).map_err(|e| invalid(function, offset, &e))?;
+		pending.extend(instruction.successors().into_iter().map(|next| (next, height)));
+	}
+
+	for constant in &function.chunk.constants {
+		if let Constant::Function(f) = constant {
+			verify(f)?;
+		}
+	}
+
+	Ok(())
+}
+
+fn invalid(function: &Function, offset: usize, reason: &str) -> String {
+	format!("Invalid compiled code in '{}' at offset {}: {}.", function.name, offset, reason)
+}
+
+/// A decoded instruction with its operands
+struct Instruction {
+	op: OpCode,
+	/// The `u8` or `u16` operand, if the opcode has one
+	operand: usize,
+	/// Each upvalue of a `Closure`: whether it captures a local, and the slot
+	captures: Vec<(bool, usize)>,
+	/// The offset of the next instruction
+	next: usize,
+}
+
+/// Read the code of a function as a sequence of instructions, keyed by their offset
+fn decode_instructions(function: &Function) -> Result<HashMap<usize, Instruction>, String> {
+	let code = &function.chunk.code;
+	let mut instructions = HashMap::new();
+	let mut offset = 0;
+
+	while offset < code.len() {
+		let op = OpCode::from_byte(code[offset])
+			.ok_or_else(|| invalid(function, offset, &format!("unknown opcode {}", code[offset])))?;
+
+		let operand_size = match op {
+			OpCode::Constant | OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::DefineConstGlobal | OpCode::SetGlobal
+			| OpCode::GetProperty | OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop | OpCode::Closure | OpCode::Array => 2,
+			OpCode::GetLocal | OpCode::SetLocal | OpCode::Call | OpCode::PopBelow | OpCode::GetUpvalue | OpCode::SetUpvalue => 1,
+			_ => 0
+		};
+
+		let truncated = || invalid(function, offset, "an operand runs past the end of the code");
+		let operands = code.get(offset + 1..offset + 1 + operand_size).ok_or_else(truncated)?;
+		let operand = operands.iter().fold(0, |value, byte| value << 8 | *byte as usize);
+		let mut next = offset + 1 + operand_size;
+
+		let mut captures = Vec::new();
+
+		if op == OpCode::Closure {
+			let Some(Constant::Function(f)) = function.chunk.constants.get(operand) else {
+				return Err(invalid(function, offset, "'Closure' doesn't refer to a function constant"))
+			};
+
+			let bytes = f.upvalue_count.checked_mul(2)
+				.and_then(|len| next.checked_add(len))
+				.and_then(|end| code.get(next..end))
+				.ok_or_else(truncated)?;
+
+			captures = bytes.chunks(2).map(|pair| (pair[0] == 1, pair[1] as usize)).collect();
+			next += bytes.len();
+		}
+
+		instructions.insert(offset, Instruction { op, operand, captures, next });
+		offset = next;
+	}
+
+	Ok(instructions)
+}
+
+impl Instruction {
+	/// The stack height after the instruction, given the height before it, failing if the instruction
+	/// pops more than is there or refers to something that doesn't exist
+	fn effect(&self, function: &Function, height: usize) -> Result<usize, String> {
+		let constants = &function.chunk.constants;
+		let name = || match constants.get(self.operand) {
+			Some(Constant::String(_)) => Ok(()),
+			_ => Err("a name isn't a string constant".to_string())
+		};
+
+		// How many values the instruction needs on the stack, and the height after it
+		let (needs, after) = match self.op {
+			OpCode::Constant => match constants.get(self.operand) {
+				Some(Constant::Number(_) | Constant::String(_)) => (0, height + 1),
+				_ => return Err("'Constant' doesn't refer to a number or string constant".to_string())
+			},
+			OpCode::Nil | OpCode::True | OpCode::False => (0, height + 1),
+			OpCode::GetLocal => {
+				if self.operand >= height {
+					return Err("a local slot is out of range".to_string())
+				}
+				(0, height + 1)
+			},
+			OpCode::SetLocal => {
+				if self.operand >= height {
+					return Err("a local slot is out of range".to_string())
+				}
+				(1, height)
+			},
+			OpCode::GetGlobal => { name()?; (0, height + 1) },
+			OpCode::DefineGlobal | OpCode::DefineConstGlobal => { name()?; (1, height.saturating_sub(1)) },
+			OpCode::SetGlobal | OpCode::GetProperty => { name()?; (1, height) },
+			OpCode::Equal | OpCode::Greater | OpCode::GreaterEqual | OpCode::Less | OpCode::LessEqual | OpCode::Add
+			| OpCode::Subtract | OpCode::Multiply | OpCode::Divide | OpCode::Index => (2, height.saturating_sub(1)),
+			OpCode::IndexSet => (3, height.saturating_sub(2)),
+			OpCode::Not | OpCode::Negate | OpCode::JumpIfFalse => (1, height),
+			OpCode::Pop | OpCode::Print | OpCode::CloseUpvalue => (1, height.saturating_sub(1)),
+			OpCode::Dup => (1, height + 1),
+			OpCode::Jump | OpCode::Loop => (0, height),
+			OpCode::Call => (self.operand + 1, height.saturating_sub(self.operand)),
+			OpCode::PopBelow => (self.operand + 1, height.saturating_sub(self.operand)),
+			OpCode::Array => (self.operand, (height + 1).saturating_sub(self.operand)),
+			OpCode::Return => (1, height),
+			OpCode::GetUpvalue | OpCode::SetUpvalue => {
+				if self.operand >= function.upvalue_count {
+					return Err("an upvalue slot is out of range".to_string())
+				}
+				(usize::from(self.op == OpCode::SetUpvalue), height + usize::from(self.op == OpCode::GetUpvalue))
+			},
+			OpCode::Closure => {
+				for &(is_local, index) in &self.captures {
+					if (is_local && index >= height) || (!is_local && index >= function.upvalue_count) {
+						return Err("a captured variable is out of range".to_string())
+					}
+				}
+				(0, height + 1)
+			},
+		};
+
+		if needs > height {
+			return Err("it pops more values than are on the stack".to_string())
+		}
+
+		Ok(after)
+	}
+
+	/// The offsets execution can continue at after the instruction
+	fn successors(&self) -> Vec<usize> {
+		match self.op {
+			OpCode::Return => vec![],
+			OpCode::Jump => vec![self.next + self.operand],
+			OpCode::JumpIfFalse => vec![self.next, self.next + self.operand],
+			// A loop jumping before the start of the code lands nowhere valid, it is caught as such
+			OpCode::Loop => vec![self.next.checked_sub(self.operand).unwrap_or(usize::MAX)],
+			_ => vec![self.next],
+		}
+	}
+}
@@ -0,0 +1,108 @@
+//! Compiled `.loxc` files must read back as they were written, and a corrupted one must be rejected
+//! when it is loaded rather than crash the VM running it
+
+use codecrafters_interpreter::{parser::Parser, scanner::Scanner, vm::{chunk::{Chunk, Constant, Function, OpCode}, compiler::Compiler, file}};
+
+const PROGRAM: &str = "
+var total = 0;
+for (var i = 0; i < 10; i = i + 1) {
+	if (i == 3) continue;
+	total = total + i;
+}
+fun counter() { var n = 0; fun inc() { n = n + 1; return n; } return inc; }
+var c = counter();
+print [total, c(), c()];
+";
+
+fn compile(source: &str) -> Function {
+	let (tokens, errors) = Scanner::new(source.to_string()).scan_tokens();
+	assert!(errors.is_empty(), "The program scans");
+
+	let statements = Parser::new(tokens).parse_statement().unwrap_or_else(|_| panic!("The program parses"));
+	Compiler::compile(&statements).unwrap_or_else(|_| panic!("The program compiles"))
+}
+
+/// A script running the given code, with the given constants
+fn script(code: &[u8], constants: Vec<Constant>) -> Function {
+	let chunk = Chunk { code: code.to_vec(), lines: vec![1; code.len()], constants };
+	Function { name: "<script>".to_string(), arity: 0, upvalue_count: 0, chunk }
+}
+
+fn rejected(function: &Function) -> String {
+	file::decode(&file::encode(function)).expect_err("The corrupted program is rejected")
+}
+
+#[test]
+fn round_trips_through_encode_and_decode() {
+	let bytes = file::encode(&compile(PROGRAM));
+	let decoded = file::decode(&bytes).expect("The compiled program loads");
+
+	assert_eq!(file::encode(&decoded), bytes);
+}
+
+#[test]
+fn rejects_unknown_opcodes() {
+	let message = rejected(&script(&[200], vec![]));
+	assert!(message.contains("unknown opcode 200"), "{}", message);
+}
+
+#[test]
+fn rejects_truncated_operands() {
+	let message = rejected(&script(&[OpCode::Constant as u8, 0], vec![Constant::Number(1.0)]));
+	assert!(message.contains("past the end"), "{}", message);
+}
+
+#[test]
+fn rejects_constants_out_of_range() {
+	let message = rejected(&script(&[OpCode::Constant as u8, 0, 7, OpCode::Return as u8], vec![Constant::Number(1.0)]));
+	assert!(message.contains("'Constant'"), "{}", message);
+}
+
+#[test]
+fn rejects_locals_out_of_range() {
+	let message = rejected(&script(&[OpCode::GetLocal as u8, 9, OpCode::Return as u8], vec![]));
+	assert!(message.contains("local slot"), "{}", message);
+}
+
+#[test]
+fn rejects_jumps_outside_the_code() {
+	let message = rejected(&script(&[OpCode::Jump as u8, 1, 0, OpCode::Nil as u8, OpCode::Return as u8], vec![]));
+	assert!(message.contains("jump lands outside"), "{}", message);
+}
+
+#[test]
+fn rejects_popping_an_empty_stack() {
+	let message = rejected(&script(&[OpCode::Array as u8, 0, 5, OpCode::Return as u8], vec![]));
+	assert!(message.contains("pops more values"), "{}", message);
+}
+
+#[test]
+fn rejects_mismatched_stack_heights() {
+	// One path pushes a value before the join, the other doesn't
+	let code = [OpCode::True as u8, OpCode::JumpIfFalse as u8, 0, 1, OpCode::Nil as u8, OpCode::Return as u8];
+	let message = rejected(&script(&code, vec![]));
+	assert!(message.contains("stack height differs"), "{}", message);
+}
+
+#[test]
+fn rejects_huge_upvalue_counts() {
+	let closed = Function { name: "f".to_string(), arity: 0, upvalue_count: u32::MAX as usize, chunk: Chunk::default() };
+	let code = [OpCode::Closure as u8, 0, 0, OpCode::Return as u8];
+	let message = rejected(&script(&code, vec![Constant::Function(closed.into())]));
+	assert!(message.contains("past the end"), "{}", message);
+}
+
+#[test]
+fn loading_corrupted_bytes_never_panics() {
+	let bytes = file::encode(&compile(PROGRAM));
+
+	for i in 0..bytes.len() {
+		for flip in [0x01, 0x80, 0xff] {
+			let mut corrupted = bytes.clone();
+			corrupted[i] ^= flip;
+			let _ = file::decode(&corrupted);
+		}
+
+		let _ = file::decode(&bytes[..i]);
+	}
+}
@@ -0,0 +1,14 @@
+//! A character literal cut short by the end of the input is reported, not scanned past the end
+
+mod common;
+
+use common::run;
+
+#[test]
+fn escape_at_end_of_input_is_unterminated() {
+	let output = run("unterminated_escape.lox", &["tokenize"]);
+	let stderr = String::from_utf8_lossy(&output.stderr);
+
+	assert_eq!(output.status.code(), Some(65));
+	assert!(stderr.contains("[line 1] Error: Unterminated character literal."), "{}", stderr);
+}
@@ -0,0 +1,9 @@
+//! Helpers shared by the integration tests
+
+use std::{path::Path, process::{Command, Output}};
+
+/// Run the interpreter with the given arguments on a program in `tests/programs`
+pub fn run(program: &str, args: &[&str]) -> Output {
+	let program = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs").join(program);
+	Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter")).args(args).arg(program).output().expect("The interpreter runs")
+}
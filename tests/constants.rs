@@ -0,0 +1,13 @@
+//! A constant can't be assigned to, nor declared again to get around that
+
+mod common;
+
+use common::run;
+
+#[test]
+fn redeclaring_a_constant_is_a_runtime_error() {
+	let output = run("const_redeclaration.lox", &["run"]);
+
+	assert_eq!(output.status.code(), Some(70));
+	assert!(String::from_utf8_lossy(&output.stderr).contains("Can't redeclare constant 'c'."));
+}
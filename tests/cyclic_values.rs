@@ -0,0 +1,23 @@
+//! Arrays and maps that contain themselves must print, compare and convert to JSON without
+//! recursing forever
+
+mod common;
+
+use common::run;
+
+#[test]
+fn self_containing_arrays_print_and_compare() {
+	let output = run("cyclic_values.lox", &["run"]);
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "[1, [...]]\ntrue\nfalse\n");
+}
+
+#[test]
+fn self_containing_arrays_fail_to_convert_to_json() {
+	let output = run("cyclic_json.lox", &["run"]);
+	let stderr = String::from_utf8_lossy(&output.stderr);
+
+	assert_eq!(output.status.code(), Some(70));
+	assert!(stderr.contains("Can't convert a cyclic structure to JSON."), "{}", stderr);
+}
@@ -0,0 +1,13 @@
+//! `for-in` loops go through strings, arrays, maps and ranges, with ranges counted out lazily
+
+mod common;
+
+use common::run;
+
+#[test]
+fn lazy_ranges_count_out_their_numbers() {
+	let output = run("lazy_range.lox", &["run"]);
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "<range 0..3>\n0\n1\n2\n5\n");
+}
@@ -0,0 +1,22 @@
+//! The garbage collector must only free what nothing outside a reference cycle can reach,
+//! including values the interpreter is holding on to while evaluating an expression
+
+mod common;
+
+use common::run;
+
+#[test]
+fn closures_held_by_call_arguments_survive_collection() {
+	let output = run("gc_temporaries.lox", &["run"]);
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "b\n");
+}
+
+#[test]
+fn closures_made_by_hot_functions_are_collected() {
+	let output = run("gc_tiered_closures.lox", &["run"]);
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "true\n");
+}
@@ -0,0 +1,14 @@
+//! Classes, instances and functions are shared by reference, so every alias sees the same object
+//! and is equal to it
+
+mod common;
+
+use common::run;
+
+#[test]
+fn classes_instances_and_functions_compare_by_identity() {
+	let output = run("references.lox", &["run"]);
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "true\nfalse\ntrue\nfalse\ntrue\nfalse\n1\n");
+}
@@ -0,0 +1,33 @@
+//! Tail calls must keep working once a function is hot enough to tier up, as the VM doesn't
+//! eliminate them
+
+mod common;
+
+use common::run;
+
+#[test]
+fn deep_tail_recursion_runs_with_tier_up() {
+	let output = run("tail_calls.lox", &["run"]);
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "100000\n");
+}
+
+/// Native code jumps back to the start of a function for a call to itself in tail position, rather
+/// than bailing out to the interpreter once the calls get too deep
+#[cfg(feature = "jit")]
+#[test]
+fn deep_tail_recursion_runs_compiled() {
+	let output = run("tail_calls.lox", &["run", "--jit"]);
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "100000\n");
+}
+
+#[test]
+fn tail_called_native_keeps_the_caller_frame() {
+	let output = run("tail_call_native.lox", &["run"]);
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "at f (line 2)\nat <script> (line 3)\n");
+}
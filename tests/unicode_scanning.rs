@@ -0,0 +1,24 @@
+//! The scanner reads whole characters, so non-ASCII text in strings, comments and identifiers
+//! scans cleanly and a stray multi-byte character is reported rather than panicking
+
+mod common;
+
+use common::run;
+
+#[test]
+fn non_ascii_strings_comments_and_identifiers_scan() {
+	let output = run("unicode.lox", &["run"]);
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "héllo 🌍 мир\n日本語!\n");
+}
+
+#[test]
+fn stray_multi_byte_character_is_reported() {
+	let output = run("unexpected_unicode.lox", &["run"]);
+	let stderr = String::from_utf8_lossy(&output.stderr);
+
+	assert_eq!(output.status.code(), Some(65));
+	assert!(stderr.contains("[line 1] Error: Unexpected character: ¬"), "{}", stderr);
+	assert!(stderr.contains("[line 2] Error: Unexpected character: 🌍"), "{}", stderr);
+}